@@ -166,11 +166,18 @@ fn main() {
                 .to_ascii_lowercase(),
         ),
         Err(GetOneError::None) => None,
-        Err(GetOneError::Multiple) => panic!("Multiple ch32xx Cargo features enabled"),
+        Err(GetOneError::Multiple) => panic!("Multiple time-driver-xxx Cargo features enabled"),
     };
 
     let time_driver_singleton = match time_driver.as_ref().map(|x| x.as_ref()) {
         None => "",
+        Some("systick") => {
+            if qingke_ver == "qingke_v3" {
+                panic!("time-driver-systick requires a QingKe V2 or V4 core, use a time-driver-timX feature instead");
+            }
+            println!("cargo:rustc-cfg=time_driver_systick");
+            ""
+        }
         Some("tim1") => "TIM1",
         Some("tim2") => "TIM2",
         Some("tim3") => "TIM3",
@@ -245,6 +252,7 @@ fn main() {
 
     // ========
     // Generate RccPeripheral and RemapPeripheral impls
+    let mut disable_unused = TokenStream::new();
     for p in METADATA.peripherals {
         if !singletons.contains(&p.name.to_string()) {
             continue;
@@ -271,24 +279,62 @@ fn main() {
             let en_reg = format_ident!("{}", en.register.to_ascii_lowercase());
             let set_en_field = format_ident!("set_{}", en.field.to_ascii_lowercase());
 
-            let clk = format_ident!("{}", rcc.bus_clock.to_ascii_lowercase());
+            let bus_clock = rcc.bus_clock.to_ascii_lowercase();
+            let kind = p.registers.as_ref().map(|r| r.kind);
+            let clk = match kind {
+                Some("adc") => format_ident!("adcclk"),
+                // timers run at twice the APB clock if it's divided
+                Some("timer") if bus_clock.starts_with("pclk") => format_ident!("{}_tim", bus_clock),
+                _ => format_ident!("{}", bus_clock),
+            };
+
+            // Number of drivers using the peripheral, the clock is only turned off by the last one
+            let refcount = format_ident!("REFCOUNT_{}", p.name);
 
             g.extend(quote! {
+                static mut #refcount: u8 = 0;
+
                 impl crate::peripheral::SealedRccPeripheral for peripherals::#pname {
-                    fn frequency() -> crate::time::Hertz {
-                        crate::rcc::clocks().#clk
-                    }
                     fn enable_and_reset_with_cs(_cs: critical_section::CriticalSection) {
-                        crate::pac::RCC.#en_reg().modify(|w| w.#set_en_field(true));
-                        #rst
+                        unsafe {
+                            if #refcount == 0 {
+                                crate::pac::RCC.#en_reg().modify(|w| w.#set_en_field(true));
+                                #rst
+                            }
+                            #refcount = #refcount.checked_add(1).expect("RCC: too many drivers for one peripheral");
+                        }
+                    }
+                    fn enable_with_cs(_cs: critical_section::CriticalSection) {
+                        unsafe {
+                            crate::pac::RCC.#en_reg().modify(|w| w.#set_en_field(true));
+                            #refcount = #refcount.checked_add(1).expect("RCC: too many drivers for one peripheral");
+                        }
                     }
                     fn disable_with_cs(_cs: critical_section::CriticalSection) {
-                        crate::pac::RCC.#en_reg().modify(|w| w.#set_en_field(false));
+                        unsafe {
+                            #refcount = #refcount.saturating_sub(1);
+                            if #refcount == 0 && cfg!(not(feature = "keep-clocks-on")) {
+                                crate::pac::RCC.#en_reg().modify(|w| w.#set_en_field(false));
+                            }
+                        }
                     }
                 }
 
-                impl crate::peripheral::RccPeripheral for peripherals::#pname {}
+                impl crate::peripheral::RccPeripheral for peripherals::#pname {
+                    fn frequency() -> crate::time::Hertz {
+                        crate::rcc::clocks().#clk
+                    }
+                }
             });
+
+            // GPIO and DMA are enabled at init, PWR, BKP and AFIO are used without a driver
+            if !matches!(kind, Some("gpio" | "dma")) && !matches!(p.name, "PWR" | "BKP" | "AFIO") {
+                disable_unused.extend(quote! {
+                    if #refcount == 0 {
+                        crate::pac::RCC.#en_reg().modify(|w| w.#set_en_field(false));
+                    }
+                });
+            }
         }
 
         if let Some(remap) = &p.remap {
@@ -318,6 +364,16 @@ fn main() {
         }
     }
 
+    g.extend(quote! {
+        /// Turn off the clock of every peripheral that no driver uses.
+        #[allow(unused)]
+        pub(crate) fn disable_unused(_cs: critical_section::CriticalSection) {
+            unsafe {
+                #disable_unused
+            }
+        }
+    });
+
     // ========
     // Generate fns to enable GPIO, DMA in RCC
     for kind in ["dma", "gpio"] {
@@ -361,6 +417,8 @@ fn main() {
         /*(("spi", "I2S_MCK"), quote!(crate::spi::MckPin)),
         (("spi", "I2S_CK"), quote!(crate::spi::CkPin)),
         (("spi", "I2S_WS"), quote!(crate::spi::WsPin)), */
+        (("can", "RX"), quote!(crate::can::RxPin)),
+        (("can", "TX"), quote!(crate::can::TxPin)),
         (("i2c", "SDA"), quote!(crate::i2c::SdaPin)),
         (("i2c", "SCL"), quote!(crate::i2c::SclPin)),
         (("timer", "CH1"), quote!(crate::timer::Channel1Pin)),
@@ -469,6 +527,7 @@ fn main() {
         (("timer", "CH3"), quote!(crate::timer::Ch3Dma)),
         (("timer", "CH4"), quote!(crate::timer::Ch4Dma)),
         (("sdio", "SDIO"), quote!(crate::sdio::SdioDma)),
+        (("adc", "ADC"), quote!(crate::adc::RxDma)),
     ]
     .into();
 
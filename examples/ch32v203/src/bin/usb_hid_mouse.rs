@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+#![feature(impl_trait_in_assoc_type)]
+
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_time::Timer;
+use embassy_usb::class::hid::{self, HidWriter, State};
+use embassy_usb::Builder;
+use hal::usbd::Driver;
+use hal::{bind_interrupts, peripherals, usbd, Config};
+use {ch32_hal as hal, panic_halt as _};
+
+bind_interrupts!(struct Irqs {
+    USB_LP_CAN1_RX0 => usbd::InterruptHandler<peripherals::USBD>;
+});
+
+/// Boot protocol mouse: 3 buttons, relative X and Y.
+const MOUSE_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x02, // Usage (Mouse)
+    0xA1, 0x01, // Collection (Application)
+    0x09, 0x01, //   Usage (Pointer)
+    0xA1, 0x00, //   Collection (Physical)
+    0x05, 0x09, //     Usage Page (Buttons)
+    0x19, 0x01, //     Usage Minimum (1)
+    0x29, 0x03, //     Usage Maximum (3)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x95, 0x03, //     Report Count (3)
+    0x75, 0x01, //     Report Size (1)
+    0x81, 0x02, //     Input (Data, Variable, Absolute)
+    0x95, 0x01, //     Report Count (1)
+    0x75, 0x05, //     Report Size (5)
+    0x81, 0x01, //     Input (Constant), padding
+    0x05, 0x01, //     Usage Page (Generic Desktop)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
+    0x15, 0x81, //     Logical Minimum (-127)
+    0x25, 0x7F, //     Logical Maximum (127)
+    0x75, 0x08, //     Report Size (8)
+    0x95, 0x02, //     Report Count (2)
+    0x81, 0x06, //     Input (Data, Variable, Relative)
+    0xC0, //       End Collection
+    0xC0, //     End Collection
+];
+
+#[embassy_executor::main(entry = "qingke_rt::entry")]
+async fn main(_spawner: Spawner) -> ! {
+    // USB needs 48 MHz, the prescaler is derived from the 144 MHz PLL
+    let cfg = Config {
+        rcc: hal::rcc::Config::SYSCLK_FREQ_144MHZ_HSI,
+        ..Default::default()
+    };
+    let p = hal::init(cfg);
+
+    let driver = Driver::new(p.USBD, Irqs, p.PA12, p.PA11);
+
+    let mut config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    config.manufacturer = Some("Embassy");
+    config.product = Some("HID mouse jiggler");
+    config.serial_number = Some("12345678");
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut state = State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [], // no msos descriptors
+        &mut control_buf,
+    );
+
+    // SET_IDLE, GET_REPORT and friends are answered by the class
+    let hid_config = hid::Config {
+        report_descriptor: MOUSE_REPORT_DESCRIPTOR,
+        request_handler: None,
+        poll_ms: 10,
+        max_packet_size: 8,
+    };
+    let mut writer = HidWriter::<_, 3>::new(&mut builder, &mut state, hid_config);
+
+    let mut usb = builder.build();
+
+    let usb_fut = usb.run();
+
+    let jiggle_fut = async {
+        writer.ready().await;
+        let mut dx: i8 = 4;
+        loop {
+            Timer::after_secs(1).await;
+            // buttons, x, y
+            let _ = writer.write(&[0, dx as u8, 0]).await;
+            dx = -dx;
+        }
+    };
+
+    join(usb_fut, jiggle_fut).await;
+    unreachable!()
+}
@@ -83,8 +83,8 @@ async fn main(spawner: Spawner) -> ! {
                 freq: Hertz::mhz(12),
                 mode: HseMode::Oscillator,
             }),
-            pll_src: PllSource::HSE,
             pll: Some(Pll {
+                src: PllSource::HSE,
                 prediv: PllPreDiv::DIV1,
                 mul: PllMul::MUL12, // 12 * 12 = max 144MHz, or else overclock
             }),
@@ -34,8 +34,8 @@ async fn main(spawner: Spawner) -> ! {
                 freq: Hertz::mhz(12),
                 mode: HseMode::Oscillator,
             }),
-            pll_src: PllSource::HSE,
             pll: Some(Pll {
+                src: PllSource::HSE,
                 prediv: PllPreDiv::DIV1,
                 // 12 * 12 = max 144MHz
                 // use MUL16 to overclock to 192MHz
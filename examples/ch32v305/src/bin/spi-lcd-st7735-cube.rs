@@ -271,8 +271,8 @@ async fn main(_spawner: Spawner) -> ! {
                 freq: Hertz::mhz(12),
                 mode: HseMode::Oscillator,
             }),
-            pll_src: PllSource::HSI,
             pll: Some(Pll {
+                src: PllSource::HSI,
                 prediv: PllPreDiv::DIV1,
                 mul: PllMul::MUL12, // 12 * 12 = max 144MHz, or else overclock
             }),
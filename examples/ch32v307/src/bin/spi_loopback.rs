@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+#![feature(impl_trait_in_assoc_type)]
+
+//! Checks each blocking `SpiBus` method, with MOSI (PA7) wired to MISO (PA6).
+
+use ch32_hal::spi;
+use embassy_executor::Spawner;
+use embassy_time::Timer;
+use embedded_hal::spi::SpiBus;
+use hal::println;
+use hal::time::Hertz;
+use {ch32_hal as hal, panic_halt as _};
+
+fn check(name: &str, ok: bool) {
+    println!("{}: {}", name, if ok { "ok" } else { "FAILED" });
+}
+
+#[embassy_executor::main(entry = "ch32_hal::entry")]
+async fn main(_spawner: Spawner) -> ! {
+    hal::debug::SDIPrint::enable();
+    let p = hal::init(Default::default());
+
+    let (sck, miso, mosi) = (p.PA5, p.PA6, p.PA7);
+
+    let mut spi_config = spi::Config::default();
+    spi_config.frequency = Hertz::mhz(1);
+    spi_config.dummy_byte = 0xA5;
+    let mut spi = spi::Spi::new_blocking(p.SPI1, sck, mosi, miso, spi_config);
+
+    loop {
+        // Received bytes of a write are discarded, the next read must not see them
+        SpiBus::<u8>::write(&mut spi, &[0x11, 0x22, 0x33]).unwrap();
+        let mut buf = [0u8; 4];
+        SpiBus::read(&mut spi, &mut buf).unwrap();
+        check("write, read", buf == [0xA5; 4]);
+
+        let mut buf = [0u8; 4];
+        SpiBus::transfer(&mut spi, &mut buf, &[1, 2, 3, 4]).unwrap();
+        check("transfer", buf == [1, 2, 3, 4]);
+
+        // Shorter write: padded with the dummy byte
+        let mut buf = [0u8; 4];
+        SpiBus::transfer(&mut spi, &mut buf, &[1, 2]).unwrap();
+        check("transfer, short write", buf == [1, 2, 0xA5, 0xA5]);
+
+        // Shorter read: the rest is written, received bytes discarded
+        let mut buf = [0u8; 2];
+        SpiBus::transfer(&mut spi, &mut buf, &[5, 6, 7, 8]).unwrap();
+        check("transfer, short read", buf == [5, 6]);
+
+        let mut buf = [0xde, 0xad, 0xbe, 0xef];
+        SpiBus::transfer_in_place(&mut spi, &mut buf).unwrap();
+        check("transfer_in_place", buf == [0xde, 0xad, 0xbe, 0xef]);
+
+        let mut buf = [0u16; 2];
+        SpiBus::transfer(&mut spi, &mut buf, &[0x1234, 0xABCD]).unwrap();
+        check("transfer, u16", buf == [0x1234, 0xABCD]);
+
+        SpiBus::<u8>::flush(&mut spi).unwrap();
+
+        Timer::after_millis(2000).await;
+    }
+}
@@ -2,13 +2,19 @@
 
 #![macro_use]
 
+use core::future::poll_fn;
 use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::Poll;
 
 use embassy_sync::waitqueue::AtomicWaker;
 
+use crate::dma::{ReadableRingBuffer, TransferOptions};
+use crate::interrupt::typelevel::{Binding, Interrupt};
+use crate::mode::{Async, Blocking, Mode};
 use crate::pac::adc::vals;
 pub use crate::pac::adc::vals::SampleTime;
-use crate::{into_ref, peripherals, Peripheral};
+use crate::{interrupt, into_ref, peripherals, Peripheral};
 
 /// ADC bit resolution
 #[cfg(any(adc_v0, adc_ch641))]
@@ -19,6 +25,9 @@ pub const ADC_MAX: u32 = (1 << 12) - 1;
 // No calibration data, voltage should be 1.2V (1.16 to 1.24)
 pub const VREF_INT: u32 = 1200;
 
+/// Trigger of the regular group (EXTSEL), for [`Adc::start_timer_triggered`].
+pub use crate::pac::adc::vals::Extsel as ExternalTrigger;
+
 pub struct Config {
     /// Div1 to Div16
     // raw values are 0 to 0b111
@@ -39,6 +48,16 @@ impl Default for Config {
     }
 }
 
+/// ADC error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The DMA overwrote samples that were not read yet
+    Overrun,
+    /// A conversion did not finish within twice its expected time
+    Timeout,
+}
+
 pub struct State {
     pub waker: AtomicWaker,
 }
@@ -50,19 +69,75 @@ impl State {
     }
 }
 
+/// ADC interrupt handler, for [`Adc::new_async`].
+pub struct InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let r = T::regs();
+
+        // The flags are cleared by the waiting task
+        let cr1 = r.ctlr1().read();
+        let sr = r.statr().read();
+        if cr1.awdie() && sr.awd() {
+            r.ctlr1().modify(|w| w.set_awdie(false));
+            T::state().waker.wake();
+        }
+        if cr1.jeocie() && sr.jeoc() {
+            r.ctlr1().modify(|w| w.set_jeocie(false));
+            T::state().waker.wake();
+        }
+    }
+}
+
 /// Analog to Digital driver.
-pub struct Adc<'d, T: Instance> {
+pub struct Adc<'d, T: Instance, M: Mode = Blocking> {
     #[allow(unused)]
     adc: crate::PeripheralRef<'d, T>,
+    /// Result of the last calibration
+    #[cfg(any(adc_v0, adc_v1, adc_v3, adc_l1))]
+    calibration: u16,
+    _mode: PhantomData<M>,
 }
 
-impl<'d, T: Instance> Adc<'d, T> {
-    #[allow(unused)]
+impl<'d, T: Instance> Adc<'d, T, Blocking> {
     pub fn new(adc: impl Peripheral<P = T> + 'd, config: Config) -> Self {
+        Self::new_inner(adc, config)
+    }
+}
+
+impl<'d, T: Instance> Adc<'d, T, Async> {
+    /// Create an ADC driver with its interrupt bound, for
+    /// [`wait_for_threshold`](Self::wait_for_threshold) and
+    /// [`wait_for_injected`](Self::wait_for_injected).
+    pub fn new_async(
+        adc: impl Peripheral<P = T> + 'd,
+        _irq: impl Binding<T::Interrupt, InterruptHandler<T>>,
+        config: Config,
+    ) -> Self {
+        let this = Self::new_inner(adc, config);
+
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+
+        this
+    }
+}
+
+impl<'d, T: Instance, M: Mode> Adc<'d, T, M> {
+    #[allow(unused)]
+    fn new_inner(adc: impl Peripheral<P = T> + 'd, config: Config) -> Self {
         into_ref!(adc);
         T::enable_and_reset();
 
-        // TODO: ADCPRE
+        // ADCPRE is set up by RCC
+        #[cfg(any(ch32v1, ch32l1, ch32v2, ch32v3, ch32f2))]
+        assert!(
+            T::frequency() <= crate::rcc::ADC_MAX_FREQUENCY,
+            "ADC clock above 14 MHz, divide APB2 further or set `adc_pre` in `rcc::Config`"
+        );
         // CTLR3 not avaiable to CH3V0, CH32V1
         #[cfg(any(adc_v3, adc_x0))]
         T::regs().ctlr3().modify(|w| w.set_clk_div(config.clkdiv));
@@ -87,7 +162,49 @@ impl<'d, T: Instance> Adc<'d, T> {
         // ADC ON
         T::regs().ctlr2().modify(|w| w.set_adon(true));
 
-        Self { adc }
+        #[cfg(any(adc_v0, adc_v1, adc_v3, adc_l1))]
+        {
+            let mut this = Self {
+                adc,
+                calibration: 0,
+                _mode: PhantomData,
+            };
+            // wait for the ADC to stabilize after power-up, before calibrating
+            crate::delay::Delay.delay_us(1);
+            this.calibrate();
+            this
+        }
+        #[cfg(not(any(adc_v0, adc_v1, adc_v3, adc_l1)))]
+        Self {
+            adc,
+            _mode: PhantomData,
+        }
+    }
+
+    /// Run the ADC self-calibration, and return the calibration value.
+    ///
+    /// The calibration register is reset first, then a calibration cycle is run. The hardware
+    /// applies the result to all following conversions, this only needs to be repeated after
+    /// large changes in supply voltage or temperature. Called by [`Adc::new`].
+    #[cfg(any(adc_v0, adc_v1, adc_v3, adc_l1))]
+    pub fn calibrate(&mut self) -> u16 {
+        let r = T::regs();
+
+        r.ctlr2().modify(|w| w.set_rstcal(true));
+        while r.ctlr2().read().rstcal() {}
+
+        r.ctlr2().modify(|w| w.set_cal(true));
+        while r.ctlr2().read().cal() {}
+
+        // the calibration code is left in the data register
+        self.calibration = r.rdatar().read().data();
+        self.calibration
+    }
+
+    /// Calibration value measured by the last [`calibrate`](Self::calibrate).
+    #[cfg(any(adc_v0, adc_v1, adc_v3, adc_l1))]
+    pub fn calibration_value(&self) -> u16 {
+        self.calibration
     }
 
     // regular conversion
@@ -95,18 +212,10 @@ impl<'d, T: Instance> Adc<'d, T> {
         channel.set_as_analog();
 
         let channel = channel.channel();
-
-        // sample time config
-        if channel < 10 {
-            T::regs().samptr2().modify(|w| w.set_smp(channel as usize, sample_time));
-        } else {
-            T::regs()
-                .samptr1()
-                .modify(|w| w.set_smp((channel - 10) as usize, sample_time));
-        }
+        set_sample_time::<T>(channel, sample_time);
 
         // regular sequence config
-        assert!(rank < 17 || rank > 0);
+        assert!(rank < 17 && rank > 0);
         if rank < 7 {
             T::regs()
                 .rsqr3()
@@ -124,17 +233,523 @@ impl<'d, T: Instance> Adc<'d, T> {
 
     // Get_ADC_Val
     pub fn convert(&mut self, channel: &mut impl AdcChannel<T>, sample_time: SampleTime) -> u16 {
+        self.try_convert(channel, sample_time)
+            .expect("ADC: conversion timed out")
+    }
+
+    /// Convert a single channel, like [`convert`](Self::convert).
+    ///
+    /// Returns [`Error::Timeout`] if the conversion takes more than twice its expected time at
+    /// the ADC clock, for example if the ADC is not powered.
+    pub fn try_convert(&mut self, channel: &mut impl AdcChannel<T>, sample_time: SampleTime) -> Result<u16, Error> {
         self.configure_channel(channel, 1, sample_time);
 
+        let timeout = conversion_timeout_ticks(sample_time);
+        let start = crate::delay::counter();
+
         T::regs().ctlr2().modify(|w| w.set_swstart(true));
 
         // while not end of conversion
-        while !T::regs().statr().read().eoc() {}
+        while !T::regs().statr().read().eoc() {
+            if crate::delay::counter().wrapping_sub(start) > timeout {
+                return Err(Error::Timeout);
+            }
+        }
+
+        Ok(T::regs().rdatar().read().data())
+    }
+
+    /// Oversample a channel in software: the rounded mean of `samples` conversions.
+    ///
+    /// The ADCs of these chips have no hardware oversampler. With `discard_extremes`, the lowest
+    /// and highest conversion are left out of the mean (if there are more than 2). This takes
+    /// `samples` times the time of [`convert`](Self::convert), averaging 4^n samples adds up to n
+    /// bits of resolution for noisy signals. Sums are kept in a `u32`, so any `u16` count works.
+    ///
+    /// Returns [`Error::Timeout`] if a conversion times out, like [`try_convert`](Self::try_convert).
+    pub fn read_averaged(
+        &mut self,
+        channel: &mut impl AdcChannel<T>,
+        sample_time: SampleTime,
+        samples: u16,
+        discard_extremes: bool,
+    ) -> Result<u16, Error> {
+        assert!(samples > 0, "ADC: no samples to average");
+
+        let mut acc = Averager::new();
+        for _ in 0..samples {
+            acc.add(self.try_convert(channel, sample_time)?);
+        }
+        Ok(acc.mean(discard_extremes))
+    }
 
-        T::regs().rdatar().read().data()
+    /// Continuously convert a sequence of channels into a circular DMA buffer.
+    ///
+    /// Each channel of `sequence` gets its own sample time, and is converted in order (up to 16
+    /// channels). Conversions restart at the beginning of the sequence once it is finished, so
+    /// `dma_buf` holds interleaved samples in sequence order. Its length should be a multiple of
+    /// twice the sequence length, so that each half of the buffer starts with the first channel.
+    ///
+    /// Conversion stops when the returned [`ContinuousDma`] is dropped.
+    pub fn start_continuous_dma<'a>(
+        &'a mut self,
+        dma: impl Peripheral<P = impl RxDma<T>> + 'a,
+        sequence: impl ExactSizeIterator<Item = (&'a mut AnyAdcChannel<T>, SampleTime)>,
+        dma_buf: &'a mut [u16],
+    ) -> ContinuousDma<'a, T> {
+        self.start_dma(dma, sequence, dma_buf, ExternalTrigger::SWSTART)
+    }
+
+    /// Convert a sequence of channels on each `trigger` into a circular DMA buffer.
+    ///
+    /// Like [`start_continuous_dma`](Self::start_continuous_dma), but the whole sequence is
+    /// converted once per trigger, at a fixed sample rate. The trigger is a timer event: the
+    /// TRGO of a timer set up with
+    /// [`Timer::set_trgo_update`](crate::timer::low_level::Timer::set_trgo_update) samples at the
+    /// timer frequency, and compare events (CCx) of a PWM channel sample at a fixed point of the
+    /// PWM period. Which timers and events can trigger depends on the chip, see the reference
+    /// manual for EXTSEL. The sequence must be converted within one trigger period.
+    pub fn start_timer_triggered<'a>(
+        &'a mut self,
+        dma: impl Peripheral<P = impl RxDma<T>> + 'a,
+        sequence: impl ExactSizeIterator<Item = (&'a mut AnyAdcChannel<T>, SampleTime)>,
+        dma_buf: &'a mut [u16],
+        trigger: ExternalTrigger,
+    ) -> ContinuousDma<'a, T> {
+        self.start_dma(dma, sequence, dma_buf, trigger)
+    }
+
+    fn start_dma<'a>(
+        &'a mut self,
+        dma: impl Peripheral<P = impl RxDma<T>> + 'a,
+        sequence: impl ExactSizeIterator<Item = (&'a mut AnyAdcChannel<T>, SampleTime)>,
+        dma_buf: &'a mut [u16],
+        trigger: ExternalTrigger,
+    ) -> ContinuousDma<'a, T> {
+        assert!(
+            (1..=16).contains(&sequence.len()),
+            "ADC: sequence must have 1 to 16 channels"
+        );
+        into_ref!(dma);
+
+        let r = T::regs();
+
+        r.rsqr1().modify(|w| w.set_l(sequence.len() as u8 - 1));
+        for (i, (channel, sample_time)) in sequence.enumerate() {
+            self.configure_channel(channel, i as u8 + 1, sample_time);
+        }
+
+        // Wake on half and full buffer
+        let opts = TransferOptions {
+            half_transfer_ir: true,
+            ..Default::default()
+        };
+        let request = dma.request();
+        let mut ring_buf =
+            unsafe { ReadableRingBuffer::new(dma, request, r.rdatar().as_ptr() as *mut u16, dma_buf, opts) };
+        ring_buf.start();
+
+        // Software trigger: convert continuously. Otherwise once per trigger.
+        let software = trigger == ExternalTrigger::SWSTART;
+        r.ctlr1().modify(|w| w.set_scan(true));
+        r.ctlr2().modify(|w| {
+            w.set_extsel(trigger);
+            w.set_cont(software);
+            w.set_dma(true);
+        });
+        compiler_fence(Ordering::SeqCst);
+        if software {
+            r.ctlr2().modify(|w| w.set_swstart(true));
+        }
+
+        ContinuousDma {
+            _phantom: PhantomData,
+            ring_buf,
+        }
+    }
+}
+
+/// Clear the STATR flags `f` sets to 0. They are rc_w0, writing 1 leaves the others unchanged
+/// where a read-modify-write could clear one set in between.
+fn clear_flag<T: Instance>(f: impl FnOnce(&mut crate::pac::adc::regs::Statr)) {
+    T::regs().statr().write(|w| {
+        w.0 = !0;
+        f(w);
+    });
+}
+
+fn set_sample_time<T: Instance>(channel: u8, sample_time: SampleTime) {
+    if channel < 10 {
+        T::regs().samptr2().modify(|w| w.set_smp(channel as usize, sample_time));
+    } else {
+        T::regs()
+            .samptr1()
+            .modify(|w| w.set_smp((channel - 10) as usize, sample_time));
+    }
+}
+
+impl<'d, T: Instance, M: Mode> Adc<'d, T, M> {
+    /// Watch a single channel with the analog watchdog, also while it's converted in a scan.
+    ///
+    /// The watchdog flags conversions of the channel below `low` or above `high`.
+    pub fn enable_watchdog(&mut self, channel: &mut impl AdcChannel<T>, low: u16, high: u16) {
+        let channel = channel.channel();
+        self.set_watchdog_thresholds(low, high);
+        T::regs().ctlr1().modify(|w| {
+            w.set_awdch(channel & 0b11111);
+            w.set_awdsgl(true);
+            w.set_awden(true);
+        });
+    }
+
+    /// Watch all regular channels with the analog watchdog, see [`enable_watchdog`](Self::enable_watchdog).
+    pub fn enable_watchdog_all(&mut self, low: u16, high: u16) {
+        self.set_watchdog_thresholds(low, high);
+        T::regs().ctlr1().modify(|w| {
+            w.set_awdsgl(false);
+            w.set_awden(true);
+        });
+    }
+
+    /// Stop the analog watchdog.
+    pub fn disable_watchdog(&mut self) {
+        T::regs().ctlr1().modify(|w| {
+            w.set_awden(false);
+            w.set_awdie(false);
+        });
+        clear_flag::<T>(|w| w.set_awd(false));
+    }
+
+    fn set_watchdog_thresholds(&mut self, low: u16, high: u16) {
+        T::regs().wdhtr().write(|w| w.set_ht(high.min(ADC_MAX as u16)));
+        T::regs().wdltr().write(|w| w.set_lt(low.min(ADC_MAX as u16)));
     }
 }
 
+impl<'d, T: Instance> Adc<'d, T, Async> {
+    /// Wait until a watched conversion is outside the thresholds, on the ADC interrupt.
+    ///
+    /// Conversions only happen while the ADC is converting, e.g. with
+    /// [`start_continuous_dma`](Self::start_continuous_dma). The flag is cleared on return, so
+    /// waiting again completes on the next conversion outside the thresholds.
+    pub async fn wait_for_threshold(&mut self) {
+        let r = T::regs();
+
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            if r.statr().read().awd() {
+                clear_flag::<T>(|w| w.set_awd(false));
+                Poll::Ready(())
+            } else {
+                r.ctlr1().modify(|w| w.set_awdie(true));
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<'d, T: Instance, M: Mode> Adc<'d, T, M> {
+    /// Set up the injected group: up to 4 channels, converted in order on each `trigger`.
+    ///
+    /// Each channel has its own sample time, and an offset that is subtracted from its results.
+    /// An injected group triggered during a regular conversion or scan (also a continuous one,
+    /// like [`start_continuous_dma`](Self::start_continuous_dma)) preempts it: the regular
+    /// conversion in progress is stopped, the injected channels are converted, and the regular
+    /// group restarts with the interrupted channel. Regular results aren't lost, they come a
+    /// little later.
+    ///
+    /// With `vals::Jextsel::JSWSTART`, [`start_injected`](Self::start_injected) triggers the
+    /// group. Otherwise it's started by the selected timer event, e.g. in sync with PWM.
+    pub fn configure_injected<'a>(
+        &mut self,
+        sequence: impl ExactSizeIterator<Item = (&'a mut AnyAdcChannel<T>, SampleTime, u16)>,
+        trigger: vals::Jextsel,
+    ) {
+        let len = sequence.len();
+        assert!(
+            (1..=4).contains(&len),
+            "ADC: injected sequence must have 1 to 4 channels"
+        );
+
+        let r = T::regs();
+        let mut isqr = crate::pac::adc::regs::Isqr(0);
+        isqr.set_jl((len - 1) as u8);
+        for (i, (channel, sample_time, offset)) in sequence.enumerate() {
+            let channel = channel.channel();
+            set_sample_time::<T>(channel, sample_time);
+
+            // Shorter sequences end in JSQ4, results start in IDATAR1 either way
+            let slot = 4 - len + i;
+            isqr.set_jsq(slot, channel & 0b11111);
+            r.iofr(i).write(|w| w.set_joffset(offset.min(ADC_MAX as u16)));
+        }
+        r.isqr().write_value(isqr);
+
+        if len > 1 {
+            r.ctlr1().modify(|w| w.set_scan(true));
+        }
+        r.ctlr2().modify(|w| {
+            w.set_jextsel(trigger);
+            w.set_jexttrig(true);
+        });
+        clear_flag::<T>(|w| w.set_jeoc(false));
+    }
+
+    /// Start the injected group, for `vals::Jextsel::JSWSTART` as trigger.
+    pub fn start_injected(&mut self) {
+        T::regs().ctlr2().modify(|w| w.set_jswstart(true));
+    }
+
+    /// The result of the injected conversion at `rank` (1 to 4), minus its offset.
+    ///
+    /// The result is negative if the conversion was below the offset.
+    pub fn read_injected(&mut self, rank: u8) -> i16 {
+        assert!((1..=4).contains(&rank), "ADC: injected rank must be 1 to 4");
+        // Sign-extended to 16 bits
+        T::regs().idatar(rank as usize - 1).read().jdata() as i16
+    }
+}
+
+impl<'d, T: Instance> Adc<'d, T, Async> {
+    /// Wait until the injected group has been converted (JEOC), on the ADC interrupt.
+    ///
+    /// The flag is cleared on return, read the results with [`read_injected`](Self::read_injected)
+    /// before the next trigger converts the group again.
+    pub async fn wait_for_injected(&mut self) {
+        let r = T::regs();
+
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            if r.statr().read().jeoc() {
+                clear_flag::<T>(|w| w.set_jeoc(false));
+                Poll::Ready(())
+            } else {
+                r.ctlr1().modify(|w| w.set_jeocie(true));
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// Running sum for [`Adc::read_averaged`].
+struct Averager {
+    sum: u32,
+    count: u32,
+    min: u16,
+    max: u16,
+}
+
+impl Averager {
+    fn new() -> Self {
+        Self {
+            sum: 0,
+            count: 0,
+            min: u16::MAX,
+            max: 0,
+        }
+    }
+
+    fn add(&mut self, sample: u16) {
+        self.sum += sample as u32;
+        self.count += 1;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+    }
+
+    fn mean(&self, discard_extremes: bool) -> u16 {
+        let (sum, count) = if discard_extremes && self.count > 2 {
+            (self.sum - self.min as u32 - self.max as u32, self.count - 2)
+        } else {
+            (self.sum, self.count)
+        };
+        ((sum + count / 2) / count.max(1)) as u16
+    }
+}
+
+/// Time for the temperature sensor and internal reference to start up after TSVREFE is set.
+#[cfg(any(adc_v1, adc_v3, adc_l1))]
+const TSVREF_STARTUP_US: u32 = 20;
+
+// Temperature sensor, datasheet typical values
+#[cfg(any(adc_v1, adc_v3))]
+const TS_V25_MV: i32 = 1400;
+#[cfg(adc_l1)]
+const TS_V25_MV: i32 = 1450;
+/// Slope in 0.1 mV/°C, negative if the sensor voltage drops with temperature
+#[cfg(any(adc_v1, adc_v3))]
+const TS_SLOPE: i32 = -43;
+#[cfg(adc_l1)]
+const TS_SLOPE: i32 = 42;
+
+/// Factory calibration of the temperature sensor, CH32V20x/CH32V30x only.
+/// Low half-word is the sensor voltage in mV, high half-word the temperature in °C it was taken at.
+///
+/// Not in the reference manuals, this is the word `TempSensor_Volt_To_Temper()` reads in the WCH
+/// peripheral libraries (`ch32v20x_adc.c`, `ch32v30x_adc.c`). The other families' libraries only
+/// have the datasheet typical values.
+#[cfg(any(ch32v2, ch32v3))]
+const ESIG_TS_CAL: *const u32 = 0x1FFFF720 as *const u32;
+
+#[cfg(any(adc_v1, adc_v3, adc_l1, adc_v0))]
+impl<'d, T: Instance, M: Mode> Adc<'d, T, M> {
+    /// Convert the internal reference voltage, returns the raw ADC value.
+    ///
+    /// The reference is nominally [`VREF_INT`] mV, see [`read_vdda`](Self::read_vdda).
+    pub fn read_vref(&mut self) -> u16 {
+        #[cfg(not(adc_v0))]
+        {
+            self.enable_tsvref();
+            self.convert(&mut VrefInt, SampleTime::CYCLES239_5)
+        }
+        #[cfg(adc_v0)]
+        self.convert(&mut Vref, SampleTime::CYCLES73)
+    }
+
+    /// Supply voltage of the ADC in mV, computed from the internal reference.
+    ///
+    /// There is no factory calibration of the reference, so the result is only as accurate as
+    /// the reference itself (1.16 V to 1.24 V).
+    pub fn read_vdda(&mut self) -> u32 {
+        let vref = (self.read_vref() as u32).max(1);
+        VREF_INT * ADC_MAX / vref
+    }
+}
+
+#[cfg(any(adc_v1, adc_v3, adc_l1))]
+impl<'d, T: Instance, M: Mode> Adc<'d, T, M> {
+    /// Set TSVREFE, and wait for the sensor and reference to start up if they were off.
+    fn enable_tsvref(&mut self) {
+        if !T::regs().ctlr2().read().tsvrefe() {
+            T::regs().ctlr2().modify(|w| w.set_tsvrefe(true));
+            crate::delay::Delay.delay_us(TSVREF_STARTUP_US);
+        }
+    }
+
+    /// Read the die temperature in °C.
+    ///
+    /// The sensor voltage is measured against VDDA, which is measured with the internal reference
+    /// first. On CH32V20x/CH32V30x the factory calibration from the signature area is used,
+    /// other families use the datasheet typical values:
+    ///
+    /// `T = T_cal + (V_sense - V_cal) / slope`
+    ///
+    /// The sensor is meant for measuring temperature changes, typical values can be off by
+    /// several tens of degrees between parts.
+    pub fn read_temperature(&mut self) -> i32 {
+        let vdda = self.read_vdda() as i32;
+
+        self.enable_tsvref();
+        let raw = self.convert(&mut Temperature, SampleTime::CYCLES239_5) as i32;
+        let vsense = raw * vdda / ADC_MAX as i32;
+
+        let (v_cal, t_cal) = ts_calibration();
+
+        // in 0.1 mV, rounded to the nearest degree
+        let dv = (vsense - v_cal) * 10;
+        let half = TS_SLOPE.abs() / 2;
+        let dv = if dv < 0 { dv - half } else { dv + half };
+        t_cal + dv / TS_SLOPE
+    }
+}
+
+/// Temperature sensor voltage in mV and the temperature in °C it corresponds to.
+#[cfg(any(adc_v1, adc_v3, adc_l1))]
+fn ts_calibration() -> (i32, i32) {
+    #[cfg(any(ch32v2, ch32v3))]
+    {
+        let cal = unsafe { core::ptr::read_volatile(ESIG_TS_CAL) };
+        let (v_cal, t_cal) = ((cal & 0xFFFF) as i32, (cal >> 16) as i32);
+        // Erased or left unprogrammed on a part: fall back to the typical values
+        if (1000..=2000).contains(&v_cal) && (0..=100).contains(&t_cal) {
+            return (v_cal, t_cal);
+        }
+    }
+
+    (TS_V25_MV, 25)
+}
+
+/// Continuous scan conversion into a circular DMA buffer.
+///
+/// Created with [`Adc::start_continuous_dma`].
+pub struct ContinuousDma<'a, T: Instance> {
+    _phantom: PhantomData<&'a mut T>,
+    ring_buf: ReadableRingBuffer<'a, u16>,
+}
+
+impl<'a, T: Instance> ContinuousDma<'a, T> {
+    /// Wait until `measurements` can be filled, and copy the oldest samples into it.
+    ///
+    /// The DMA wakes the task when it reaches the middle and the end of the buffer, so a
+    /// `measurements` of half the buffer length returns once per half buffer.
+    /// Returns the number of samples that are left available for immediate reading.
+    ///
+    /// [`Error::Overrun`] is returned if the DMA overwrote samples that were not read in time.
+    /// Reading can continue afterwards, but sample order relative to the sequence is lost.
+    pub async fn read(&mut self, measurements: &mut [u16]) -> Result<usize, Error> {
+        self.ring_buf.read_exact(measurements).await.map_err(|_| {
+            self.ring_buf.clear();
+            Error::Overrun
+        })
+    }
+
+    /// Length of the DMA buffer.
+    pub const fn capacity(&self) -> usize {
+        self.ring_buf.capacity()
+    }
+}
+
+impl<'d, T: Instance, M: Mode> Drop for Adc<'d, T, M> {
+    fn drop(&mut self) {
+        T::regs().ctlr2().modify(|w| w.set_adon(false));
+        T::disable();
+    }
+}
+
+impl<'a, T: Instance> Drop for ContinuousDma<'a, T> {
+    fn drop(&mut self) {
+        let r = T::regs();
+
+        r.ctlr2().modify(|w| {
+            w.set_cont(false);
+            w.set_dma(false);
+            w.set_extsel(ExternalTrigger::SWSTART);
+        });
+        r.ctlr1().modify(|w| w.set_scan(false));
+        r.rsqr1().modify(|w| w.set_l(0));
+        // the ring buffer stops the DMA channel when dropped
+    }
+}
+
+/// ADC clock cycles for sampling plus conversion, rounded up
+#[cfg(any(adc_v1, adc_v3, adc_l1))]
+fn conversion_cycles(sample_time: SampleTime) -> u32 {
+    // 1.5, 7.5, 13.5, 28.5, 41.5, 55.5, 71.5, 239.5 cycles of sampling, plus 12.5 for conversion
+    const CYCLES: [u32; 8] = [14, 20, 26, 41, 54, 68, 84, 252];
+    CYCLES[sample_time.to_bits() as usize & 0b111]
+}
+
+/// ADC clock cycles for sampling plus conversion, the longest sample time of all families
+#[cfg(not(any(adc_v1, adc_v3, adc_l1)))]
+fn conversion_cycles(_sample_time: SampleTime) -> u32 {
+    256
+}
+
+/// Delay counter ticks to wait for a conversion before giving up, twice the expected time
+fn conversion_timeout_ticks(sample_time: SampleTime) -> u32 {
+    // ADC_CTLR3 divides the ADC clock by up to 16 more
+    #[cfg(any(adc_v3, adc_x0))]
+    let cycles = conversion_cycles(sample_time) * 16;
+    #[cfg(not(any(adc_v3, adc_x0)))]
+    let cycles = conversion_cycles(sample_time);
+
+    let adcclk = crate::rcc::clocks().adcclk.0.max(1) as u64;
+    (2 * cycles as u64 * crate::delay::counter_frequency() as u64 / adcclk + 1) as u32
+}
+
 #[allow(unused)]
 trait SealedInstance {
     fn regs() -> crate::pac::adc::Adc;
@@ -148,6 +763,8 @@ pub(crate) trait SealedAdcChannel<T: Instance> {
     fn channel(&self) -> u8;
 }
 
+dma_trait!(RxDma, Instance);
+
 #[allow(private_bounds)]
 pub trait Instance: SealedInstance + crate::Peripheral<P = Self> + crate::peripheral::RccPeripheral {
     type Interrupt: crate::interrupt::typelevel::Interrupt;
@@ -396,3 +1013,40 @@ mod ch_internal {
 }
 
 pub use ch_internal::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mean(samples: &[u16], discard_extremes: bool) -> u16 {
+        let mut acc = Averager::new();
+        for &s in samples {
+            acc.add(s);
+        }
+        acc.mean(discard_extremes)
+    }
+
+    #[test]
+    fn rounded_mean() {
+        assert_eq!(mean(&[1000], false), 1000);
+        assert_eq!(mean(&[1000, 1001], false), 1001);
+        assert_eq!(mean(&[1000, 1000, 1001], false), 1000);
+    }
+
+    #[test]
+    fn discard_extremes() {
+        assert_eq!(mean(&[0, 2000, 2002, 4095], true), 2001);
+        assert_eq!(mean(&[0, 2000, 2002, 4095], false), 2024);
+        // Too few samples to drop any
+        assert_eq!(mean(&[0, 4095], true), 2048);
+    }
+
+    #[test]
+    fn no_overflow() {
+        let mut acc = Averager::new();
+        for _ in 0..u16::MAX {
+            acc.add(4095);
+        }
+        assert_eq!(acc.mean(true), 4095);
+    }
+}
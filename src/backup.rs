@@ -0,0 +1,56 @@
+//! Backup data registers (BKP)
+//!
+//! 16-bit registers in the backup domain. They keep their value across resets and in Standby,
+//! and with a battery on VBAT also while VDD is off. A backup domain reset clears them, which
+//! happens when the RTC clock source changes and it's allowed by
+//! [`rcc::LsConfig::reset_backup_domain`](crate::rcc::LsConfig::reset_backup_domain).
+
+use crate::pac::{BKP, PWR, RCC};
+
+/// Number of backup data registers.
+#[cfg(any(ch32v1, ch32l1, d6))]
+pub const COUNT: usize = 10;
+/// Number of backup data registers.
+#[cfg(not(any(ch32v1, ch32l1, d6)))]
+pub const COUNT: usize = 42;
+
+/// Register address of BKP_DATAR`index + 1`.
+fn datar(index: usize) -> *mut u32 {
+    assert!(index < COUNT, "BKP: backup register index out of range");
+    // DATAR1..10 at 0x04, DATAR11..42 at 0x40
+    let offset = if index < 10 {
+        0x04 + 4 * index
+    } else {
+        0x40 + 4 * (index - 10)
+    };
+    unsafe { (BKP.as_ptr() as *mut u8).add(offset) as *mut u32 }
+}
+
+fn enable_clocks() {
+    critical_section::with(|_| {
+        RCC.apb1pcenr().modify(|w| {
+            w.set_pwren(true);
+            w.set_bkpen(true);
+        });
+    });
+}
+
+/// Read backup register `index`, from 0 to [`COUNT`] - 1 (BKP_DATAR1 is index 0).
+///
+/// Panics if `index` is out of range.
+pub fn read(index: usize) -> u16 {
+    let reg = datar(index);
+    enable_clocks();
+    unsafe { reg.read_volatile() as u16 }
+}
+
+/// Write backup register `index`, from 0 to [`COUNT`] - 1 (BKP_DATAR1 is index 0).
+///
+/// This enables write access to the backup domain (PWR_CTLR.DBP), and leaves it enabled. Panics
+/// if `index` is out of range.
+pub fn write(index: usize, value: u16) {
+    let reg = datar(index);
+    enable_clocks();
+    PWR.ctlr().modify(|w| w.set_dbp(true));
+    unsafe { reg.write_volatile(value as u32) }
+}
@@ -0,0 +1,700 @@
+//! Controller Area Network (CAN)
+//!
+//! The controller is bxCAN compatible: 3 transmit mailboxes, 2 receive FIFOs of 3 frames, and
+//! 14 filter banks per instance. On chips with two controllers, CAN2 uses banks 14 to 27, which
+//! live in CAN1, so a CAN2 driver keeps CAN1's clock on too.
+//!
+//! After [`Can::new`], the first filter bank accepts every frame into FIFO 0. Use
+//! [`Can::set_filter`] to narrow it down.
+//!
+//! On CH32V20x, the CAN1 TX and RX0 interrupts are shared with USBD (`USB_HP_CAN1_TX` and
+//! `USB_LP_CAN1_RX0`). To use both drivers, bind both handlers to the shared interrupts, like
+//! `USB_LP_CAN1_RX0 => usbd::InterruptHandler<USBD>, can::Rx0InterruptHandler<CAN1>;`.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::gpio::{AFType, Pull, Speed};
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::can::{regs, Can as Regs};
+use crate::time::Hertz;
+use crate::{interrupt, into_ref, Peripheral};
+
+// IDE bit of the mailbox identifier and filter registers
+const MIR_IDE: u32 = 1 << 2;
+
+/// Filter banks per instance.
+pub const FILTER_BANKS: usize = 14;
+
+/// Sample point the bit timing aims for, in per mille.
+const SAMPLE_POINT: u32 = 875;
+
+/// Bit times to wait for entering or leaving initialization mode.
+///
+/// Entering waits for the frame on the bus to end, leaving for 11 recessive bits.
+const INIT_TIMEOUT_BITS: u64 = 512;
+
+/// TX interrupt handler.
+pub struct TxInterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::TxInterrupt> for TxInterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        // Acknowledge finished requests, this also clears the interrupt
+        T::regs().tstatr().write(|w| {
+            for mailbox in 0..3 {
+                w.set_rqcp(mailbox, true);
+            }
+        });
+        T::state().tx_waker.wake();
+    }
+}
+
+/// RX FIFO 0 interrupt handler.
+pub struct Rx0InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Rx0Interrupt> for Rx0InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        // FMP stays set until the frames are read, mask it until the task runs
+        T::regs().intenr().modify(|w| w.set_fmpie0(false));
+        T::state().rx_waker.wake();
+    }
+}
+
+/// RX FIFO 1 interrupt handler.
+pub struct Rx1InterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Rx1Interrupt> for Rx1InterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        T::regs().intenr().modify(|w| w.set_fmpie1(false));
+        T::state().rx_waker.wake();
+    }
+}
+
+/// CAN identifier.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Id {
+    /// 11-bit standard identifier
+    Standard(u16),
+    /// 29-bit extended identifier
+    Extended(u32),
+}
+
+impl Id {
+    /// Identifier in the layout of the mailbox and filter registers.
+    fn to_reg(self) -> u32 {
+        match self {
+            Id::Standard(id) => (id as u32 & 0x7FF) << 21,
+            Id::Extended(id) => ((id & 0x1FFF_FFFF) << 3) | MIR_IDE,
+        }
+    }
+
+    fn from_reg(reg: u32) -> Self {
+        if reg & MIR_IDE != 0 {
+            Id::Extended(reg >> 3)
+        } else {
+            Id::Standard((reg >> 21) as u16)
+        }
+    }
+}
+
+/// CAN frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    id: Id,
+    remote: bool,
+    dlc: u8,
+    data: [u8; 8],
+}
+
+impl Frame {
+    /// Create a data frame, `None` if `data` is longer than 8 bytes.
+    pub fn new(id: Id, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        let mut buf = [0; 8];
+        buf[..data.len()].copy_from_slice(data);
+        Some(Self {
+            id,
+            remote: false,
+            dlc: data.len() as u8,
+            data: buf,
+        })
+    }
+
+    /// Create a remote frame requesting `dlc` bytes, `None` if `dlc` is more than 8.
+    pub fn new_remote(id: Id, dlc: u8) -> Option<Self> {
+        (dlc <= 8).then(|| Self {
+            id,
+            remote: true,
+            dlc,
+            data: [0; 8],
+        })
+    }
+
+    /// Identifier.
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns `true` for a remote frame.
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Data length code.
+    pub fn dlc(&self) -> u8 {
+        self.dlc
+    }
+
+    /// Data bytes, empty for remote frames.
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..self.dlc as usize]
+        }
+    }
+}
+
+/// Receive FIFO.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Fifo {
+    Fifo0 = 0,
+    Fifo1 = 1,
+}
+
+/// Acceptance filter, in 32-bit scale.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Filter {
+    /// Accept every frame
+    AcceptAll,
+    /// Accept frames of the same kind as `id` (standard or extended) whose ID bits set in `mask`
+    /// match `id`. Both data and remote frames are accepted
+    Mask { id: Id, mask: u32 },
+    /// Accept data frames with one of the two IDs
+    List([Id; 2]),
+}
+
+impl Filter {
+    /// Filter register values (FR1, FR2), and `true` for list mode.
+    fn to_regs(self) -> (u32, u32, bool) {
+        match self {
+            Filter::AcceptAll => (0, 0, false),
+            Filter::Mask { id, mask } => {
+                let mask = match id {
+                    Id::Standard(_) => Id::Standard(mask as u16),
+                    Id::Extended(_) => Id::Extended(mask),
+                };
+                // Also compare IDE, so that only the kind of `id` matches
+                (id.to_reg(), mask.to_reg() | MIR_IDE, false)
+            }
+            Filter::List([a, b]) => (a.to_reg(), b.to_reg(), true),
+        }
+    }
+}
+
+/// Bit timing, as programmed into CAN_BTIMR.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BitTiming {
+    /// Time quanta are `prescaler` clock cycles, 1 to 1024
+    pub prescaler: u16,
+    /// Time quanta before the sample point, after the sync segment, 1 to 16
+    pub seg1: u8,
+    /// Time quanta after the sample point, 1 to 8
+    pub seg2: u8,
+    /// Resynchronization jump width, 1 to 4
+    pub sjw: u8,
+}
+
+impl BitTiming {
+    /// Find the bit timing for `bitrate` from the peripheral clock `pclk`, with a sample point as
+    /// close as possible to 87.5%. `None` if `bitrate` can't be hit exactly.
+    pub fn from_bitrate(pclk: Hertz, bitrate: u32) -> Option<Self> {
+        if bitrate == 0 {
+            return None;
+        }
+
+        let mut best: Option<(u32, Self)> = None;
+        // 1 + seg1 + seg2 quanta per bit, prefer more quanta on ties
+        for quanta in (4..=25u32).rev() {
+            let Some(cycles) = bitrate.checked_mul(quanta) else {
+                continue;
+            };
+            if pclk.0 % cycles != 0 {
+                continue;
+            }
+            let prescaler = pclk.0 / cycles;
+            if !(1..=1024).contains(&prescaler) {
+                continue;
+            }
+
+            for seg2 in 1..=8u32.min(quanta - 2) {
+                let seg1 = quanta - 1 - seg2;
+                if seg1 > 16 {
+                    continue;
+                }
+                let sample_point = 1000 * (1 + seg1) / quanta;
+                let error = sample_point.abs_diff(SAMPLE_POINT);
+                if best.map_or(true, |(e, _)| error < e) {
+                    let timing = Self {
+                        prescaler: prescaler as u16,
+                        seg1: seg1 as u8,
+                        seg2: seg2 as u8,
+                        sjw: seg2.min(4) as u8,
+                    };
+                    best = Some((error, timing));
+                }
+            }
+        }
+
+        best.map(|(_, timing)| timing)
+    }
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Config {
+    pub bitrate: u32,
+    /// Receive the own transmitted frames internally, and don't listen to the bus
+    pub loopback: bool,
+    /// Don't drive the bus, for listening only
+    pub silent: bool,
+    /// Retry transmission until it succeeds (cleared: CAN_CTLR.NART set)
+    pub auto_retransmit: bool,
+    /// Leave bus-off automatically after 128 * 11 recessive bits
+    pub auto_bus_off_recovery: bool,
+}
+
+impl Default for Config {
+    /// 500 kbit/s
+    fn default() -> Self {
+        Self {
+            bitrate: 500_000,
+            loopback: false,
+            silent: false,
+            auto_retransmit: true,
+            auto_bus_off_recovery: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// No bit timing gives exactly the requested bitrate from the APB clock
+    BitrateUnreachable,
+    /// The controller didn't enter or leave initialization mode in time, the bus may be stuck
+    /// dominant or have no transceiver
+    Timeout,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A FIFO was full and frames were lost
+    Overrun,
+}
+
+/// CAN driver.
+pub struct Can<'d, T: Instance> {
+    _phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Can<'d, T> {
+    /// Create a new CAN driver.
+    ///
+    /// Blocks until the controller has synchronized to the bus, which needs 11 recessive bits.
+    /// Returns [`ConfigError::Timeout`] if that doesn't happen, without a transceiver or with a
+    /// dominant bus use loopback and silent mode.
+    pub fn new<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::TxInterrupt, TxInterruptHandler<T>>
+            + interrupt::typelevel::Binding<T::Rx0Interrupt, Rx0InterruptHandler<T>>
+            + interrupt::typelevel::Binding<T::Rx1Interrupt, Rx1InterruptHandler<T>>
+            + 'd,
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        into_ref!(peri, rx, tx);
+
+        rx.set_as_input(Pull::Up);
+        tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        T::set_remap(REMAP);
+
+        T::enable_and_reset();
+        // The filter banks of CAN2 are in CAN1, which may not have a driver
+        T::FilterInstance::enable();
+
+        let mut this = Self { _phantom: PhantomData };
+        this.set_config(&config)?;
+        this.set_filter(0, Filter::AcceptAll, Fifo::Fifo0);
+
+        T::regs().intenr().write(|w| w.set_tmeie(true));
+        T::TxInterrupt::unpend();
+        T::Rx0Interrupt::unpend();
+        T::Rx1Interrupt::unpend();
+        unsafe {
+            T::TxInterrupt::enable();
+            T::Rx0Interrupt::enable();
+            T::Rx1Interrupt::enable();
+        }
+
+        Ok(this)
+    }
+
+    /// Reconfigure the driver
+    ///
+    /// Pending transmissions are aborted. Returns [`ConfigError::Timeout`] if the controller
+    /// can't be synchronized to the bus, see [`new`](Self::new).
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        let timing = BitTiming::from_bitrate(T::frequency(), config.bitrate).ok_or(ConfigError::BitrateUnreachable)?;
+
+        let r = T::regs();
+        let timeout = (INIT_TIMEOUT_BITS * crate::delay::counter_frequency() as u64 / config.bitrate as u64) as u32;
+
+        // Enter initialization mode
+        r.ctlr().modify(|w| {
+            w.set_sleep(false);
+            w.set_inrq(true);
+        });
+        wait_inak::<T>(true, timeout)?;
+
+        r.btimr().write(|w| {
+            w.set_brp(timing.prescaler - 1);
+            w.set_ts1(timing.seg1 - 1);
+            w.set_ts2(timing.seg2 - 1);
+            w.set_sjw(timing.sjw - 1);
+            w.set_lbkm(config.loopback);
+            w.set_silm(config.silent);
+        });
+
+        r.ctlr().modify(|w| {
+            // Send in request order
+            w.set_txfp(true);
+            w.set_nart(!config.auto_retransmit);
+            w.set_abom(config.auto_bus_off_recovery);
+            w.set_inrq(false);
+        });
+        wait_inak::<T>(false, timeout)
+    }
+
+    /// Configure filter bank `bank`, 0 to [`FILTER_BANKS`] - 1, to put matching frames into `fifo`.
+    pub fn set_filter(&mut self, bank: usize, filter: Filter, fifo: Fifo) {
+        assert!(bank < FILTER_BANKS, "CAN: filter bank out of range");
+        let bank = T::FILTER_START + bank;
+        let (fr1, fr2, list) = filter.to_regs();
+
+        with_filter_init::<T>(|r| {
+            r.fwr().modify(|w| w.set_fact(bank, false));
+            r.fmcfgr().modify(|w| w.set_fbm(bank, list));
+            // 32-bit scale
+            r.fscfgr().modify(|w| w.set_fsc(bank, true));
+            r.fafifor().modify(|w| w.set_ffa(bank, fifo == Fifo::Fifo1));
+            r.fxr1(bank).write_value(fr1);
+            r.fxr2(bank).write_value(fr2);
+            r.fwr().modify(|w| w.set_fact(bank, true));
+        });
+    }
+
+    /// Deactivate filter bank `bank`.
+    pub fn disable_filter(&mut self, bank: usize) {
+        assert!(bank < FILTER_BANKS, "CAN: filter bank out of range");
+        let bank = T::FILTER_START + bank;
+        with_filter_init::<T>(|r| r.fwr().modify(|w| w.set_fact(bank, false)));
+    }
+
+    /// Queue `frame` if a transmit mailbox is free, returns `false` otherwise.
+    pub fn try_transmit(&mut self, frame: &Frame) -> bool {
+        let r = T::regs();
+        let tstatr = r.tstatr().read();
+        let Some(mailbox) = (0..3).find(|&i| tstatr.tme(i)) else {
+            return false;
+        };
+
+        let data = &frame.data;
+        r.txmdtr(mailbox).write(|w| w.set_dlc(frame.dlc));
+        r.txmdlr(mailbox)
+            .write_value(regs::Txmdlr(u32::from_le_bytes([data[0], data[1], data[2], data[3]])));
+        r.txmdhr(mailbox)
+            .write_value(regs::Txmdhr(u32::from_le_bytes([data[4], data[5], data[6], data[7]])));
+
+        let mut mir = regs::Txmir(frame.id.to_reg());
+        mir.set_rtr(frame.remote);
+        mir.set_txrq(true);
+        r.txmir(mailbox).write_value(mir);
+        true
+    }
+
+    /// Queue `frame`, waiting for a free transmit mailbox.
+    pub fn blocking_transmit(&mut self, frame: &Frame) {
+        while !self.try_transmit(frame) {}
+    }
+
+    /// Queue `frame`, waiting for a free transmit mailbox.
+    pub async fn transmit(&mut self, frame: &Frame) {
+        poll_fn(|cx| {
+            T::state().tx_waker.register(cx.waker());
+            if self.try_transmit(frame) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Read a received frame, FIFO 0 first, `None` if both FIFOs are empty.
+    ///
+    /// After frames were lost in a full FIFO, returns [`Error::Overrun`] once, and then the
+    /// frames that were kept.
+    pub fn try_receive(&mut self) -> Option<Result<Frame, Error>> {
+        let r = T::regs();
+        [0, 1].into_iter().find_map(|fifo| {
+            let status = r.rfifo(fifo).read();
+            if status.fovr() {
+                // Write 1 to clear
+                r.rfifo(fifo).write(|w| w.set_fovr(true));
+                return Some(Err(Error::Overrun));
+            }
+            if status.fmp() == 0 {
+                return None;
+            }
+
+            let mir = r.rxmir(fifo).read();
+            let dlc = r.rxmdtr(fifo).read().dlc().min(8);
+            let low = r.rxmdlr(fifo).read().0.to_le_bytes();
+            let high = r.rxmdhr(fifo).read().0.to_le_bytes();
+            r.rfifo(fifo).write(|w| w.set_rfom(true));
+
+            let mut data = [0; 8];
+            data[..4].copy_from_slice(&low);
+            data[4..].copy_from_slice(&high);
+            Some(Ok(Frame {
+                id: Id::from_reg(mir.0),
+                remote: mir.rtr(),
+                dlc,
+                data,
+            }))
+        })
+    }
+
+    /// Wait for a received frame.
+    pub fn blocking_receive(&mut self) -> Result<Frame, Error> {
+        loop {
+            if let Some(r) = self.try_receive() {
+                return r;
+            }
+        }
+    }
+
+    /// Wait for a received frame.
+    pub async fn receive(&mut self) -> Result<Frame, Error> {
+        poll_fn(|cx| {
+            T::state().rx_waker.register(cx.waker());
+            if let Some(r) = self.try_receive() {
+                return Poll::Ready(r);
+            }
+            // Masked by the interrupt handlers
+            critical_section::with(|_| {
+                T::regs().intenr().modify(|w| {
+                    w.set_fmpie0(true);
+                    w.set_fmpie1(true);
+                })
+            });
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl<'d, T: Instance> Drop for Can<'d, T> {
+    fn drop(&mut self) {
+        T::TxInterrupt::disable();
+        T::Rx0Interrupt::disable();
+        T::Rx1Interrupt::disable();
+        // Back to sleep mode, as after reset
+        T::regs().ctlr().write(|w| w.set_sleep(true));
+        T::FilterInstance::disable();
+        T::disable();
+    }
+}
+
+/// Wait until STATR.INAK is `inak`, for up to `timeout` delay counter ticks.
+fn wait_inak<T: Instance>(inak: bool, timeout: u32) -> Result<(), ConfigError> {
+    let start = crate::delay::counter();
+    while T::regs().statr().read().inak() != inak {
+        if crate::delay::counter().wrapping_sub(start) > timeout {
+            return Err(ConfigError::Timeout);
+        }
+    }
+    Ok(())
+}
+
+/// Run `f` with the filters in initialization mode.
+fn with_filter_init<T: Instance>(f: impl FnOnce(Regs)) {
+    let r = T::filter_regs();
+    critical_section::with(|_| {
+        r.fctlr().modify(|w| w.set_finit(true));
+        f(r);
+        r.fctlr().modify(|w| w.set_finit(false));
+    });
+}
+
+struct State {
+    tx_waker: AtomicWaker,
+    rx_waker: AtomicWaker,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            tx_waker: AtomicWaker::new(),
+            rx_waker: AtomicWaker::new(),
+        }
+    }
+}
+
+trait SealedInstance: crate::peripheral::RccPeripheral + crate::peripheral::RemapPeripheral {
+    /// First filter bank of this instance.
+    const FILTER_START: usize;
+    /// Instance holding the filter banks.
+    type FilterInstance: crate::peripheral::SealedRccPeripheral;
+
+    fn regs() -> Regs;
+    /// Instance holding the filter banks.
+    fn filter_regs() -> Regs;
+    fn state() -> &'static State;
+}
+
+/// CAN peripheral instance
+#[allow(private_bounds)]
+pub trait Instance: Peripheral<P = Self> + SealedInstance + 'static + Send {
+    /// TX interrupt for this instance
+    type TxInterrupt: interrupt::typelevel::Interrupt;
+    /// RX FIFO 0 interrupt for this instance
+    type Rx0Interrupt: interrupt::typelevel::Interrupt;
+    /// RX FIFO 1 interrupt for this instance
+    type Rx1Interrupt: interrupt::typelevel::Interrupt;
+}
+
+macro_rules! impl_instance {
+    ($inst:ident, $filter_inst:ident, $filter_start:expr) => {
+        impl SealedInstance for crate::peripherals::$inst {
+            const FILTER_START: usize = $filter_start;
+            type FilterInstance = crate::peripherals::$filter_inst;
+
+            fn regs() -> Regs {
+                crate::pac::$inst
+            }
+
+            fn filter_regs() -> Regs {
+                crate::pac::$filter_inst
+            }
+
+            fn state() -> &'static State {
+                static STATE: State = State::new();
+                &STATE
+            }
+        }
+
+        impl Instance for crate::peripherals::$inst {
+            type TxInterrupt = crate::_generated::peripheral_interrupts::$inst::TX;
+            type Rx0Interrupt = crate::_generated::peripheral_interrupts::$inst::RX0;
+            type Rx1Interrupt = crate::_generated::peripheral_interrupts::$inst::RX1;
+        }
+    };
+}
+
+foreach_peripheral!(
+    (can, CAN2) => {
+        impl_instance!(CAN2, CAN1, FILTER_BANKS);
+    };
+    (can, $inst:ident) => {
+        impl_instance!($inst, $inst, 0);
+    };
+);
+
+pin_trait!(RxPin, Instance);
+pin_trait!(TxPin, Instance);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_timing() {
+        let pclk = Hertz(36_000_000);
+        // 8 quanta, sample point at 7/8
+        assert_eq!(
+            BitTiming::from_bitrate(pclk, 500_000),
+            Some(BitTiming {
+                prescaler: 9,
+                seg1: 6,
+                seg2: 1,
+                sjw: 1
+            })
+        );
+        // 16 quanta preferred over 8 at the same sample point
+        assert_eq!(
+            BitTiming::from_bitrate(pclk, 250_000),
+            Some(BitTiming {
+                prescaler: 9,
+                seg1: 13,
+                seg2: 2,
+                sjw: 2
+            })
+        );
+        // 18 quanta, sample point at 16/18
+        assert_eq!(
+            BitTiming::from_bitrate(pclk, 1_000_000),
+            Some(BitTiming {
+                prescaler: 2,
+                seg1: 15,
+                seg2: 2,
+                sjw: 2
+            })
+        );
+    }
+
+    #[test]
+    fn bit_timing_unreachable() {
+        assert_eq!(BitTiming::from_bitrate(Hertz(36_000_000), 0), None);
+        assert_eq!(BitTiming::from_bitrate(Hertz(36_000_000), 10_000_000), None);
+        // 36 MHz / 7 kbit/s isn't an integer number of quanta
+        assert_eq!(BitTiming::from_bitrate(Hertz(36_000_000), 7_000), None);
+    }
+
+    #[test]
+    fn filter_regs() {
+        assert_eq!(Filter::AcceptAll.to_regs(), (0, 0, false));
+        assert_eq!(
+            Filter::Mask {
+                id: Id::Standard(0x123),
+                mask: 0x7F0
+            }
+            .to_regs(),
+            (0x123 << 21, (0x7F0 << 21) | MIR_IDE, false)
+        );
+        assert_eq!(
+            Filter::List([Id::Extended(0x1234_5678), Id::Standard(0x7FF)]).to_regs(),
+            ((0x1234_5678 << 3) | MIR_IDE, 0x7FF << 21, true)
+        );
+    }
+}
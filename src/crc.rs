@@ -0,0 +1,145 @@
+//! CRC calculation unit (CRC)
+//!
+//! CRC-32 with the Ethernet polynomial 0x04C11DB7, initial value 0xFFFFFFFF, no reflection and
+//! no final XOR (CRC-32/MPEG-2). Polynomial and initial value are fixed in hardware.
+//!
+//! The unit takes 32-bit words, the most significant bit first. [`Crc::feed_bytes`] packs bytes
+//! big-endian into words, so the result is the CRC-32/MPEG-2 of the byte stream. Up to 3 bytes
+//! that don't fill a word are kept until the next feed, and added in software by
+//! [`Crc::finalize`].
+
+use crate::pac::CRC;
+use crate::peripheral::SealedRccPeripheral;
+use crate::{into_ref, peripherals, Peripheral, PeripheralRef};
+
+const POLY: u32 = 0x04C1_1DB7;
+
+/// CRC driver.
+pub struct Crc<'d> {
+    _peri: PeripheralRef<'d, peripherals::CRC>,
+    /// Bytes not fed to the hardware yet, big-endian in the low `pending_len` bytes
+    pending: u32,
+    pending_len: usize,
+}
+
+impl<'d> Crc<'d> {
+    /// Create a new CRC driver, reset to the initial value.
+    pub fn new(crc: impl Peripheral<P = peripherals::CRC> + 'd) -> Self {
+        into_ref!(crc);
+        peripherals::CRC::enable_and_reset();
+
+        let mut this = Self {
+            _peri: crc,
+            pending: 0,
+            pending_len: 0,
+        };
+        this.reset();
+        this
+    }
+
+    /// Restart the calculation from the initial value.
+    pub fn reset(&mut self) {
+        CRC.ctlr().write(|w| w.set_reset(true));
+        self.pending = 0;
+        self.pending_len = 0;
+    }
+
+    /// Feed a 32-bit word, most significant bit first.
+    ///
+    /// Bytes from [`feed_bytes`](Self::feed_bytes) that don't fill a word go first.
+    pub fn feed_word(&mut self, word: u32) {
+        if self.pending_len == 0 {
+            CRC.datar().write_value(word);
+        } else {
+            self.feed_bytes(&word.to_be_bytes());
+        }
+    }
+
+    /// Feed a slice of 32-bit words.
+    pub fn feed_words(&mut self, words: &[u32]) {
+        for &word in words {
+            self.feed_word(word);
+        }
+    }
+
+    /// Feed bytes, packed big-endian into words.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        let mut bytes = bytes;
+
+        // Complete the pending word first
+        while self.pending_len != 0 && !bytes.is_empty() {
+            self.pending = (self.pending << 8) | bytes[0] as u32;
+            self.pending_len += 1;
+            bytes = &bytes[1..];
+            if self.pending_len == 4 {
+                CRC.datar().write_value(self.pending);
+                self.pending = 0;
+                self.pending_len = 0;
+            }
+        }
+
+        let mut words = bytes.chunks_exact(4);
+        for word in &mut words {
+            CRC.datar()
+                .write_value(u32::from_be_bytes([word[0], word[1], word[2], word[3]]));
+        }
+        for &b in words.remainder() {
+            self.pending = (self.pending << 8) | b as u32;
+            self.pending_len += 1;
+        }
+    }
+
+    /// Get the CRC of everything fed since the last reset.
+    ///
+    /// The calculation can go on after this, feeding more data continues from the same state.
+    pub fn finalize(&self) -> u32 {
+        let crc = CRC.datar().read();
+        let pending = self.pending.to_be_bytes();
+        pending[4 - self.pending_len..]
+            .iter()
+            .fold(crc, |crc, &b| crc32_byte(crc, b))
+    }
+}
+
+impl<'d> Drop for Crc<'d> {
+    fn drop(&mut self) {
+        peripherals::CRC::disable();
+    }
+}
+
+/// Add one byte to `crc` in software, as the hardware would for the top byte of a word.
+fn crc32_byte(crc: u32, byte: u8) -> u32 {
+    let mut crc = crc ^ ((byte as u32) << 24);
+    for _ in 0..8 {
+        crc = if crc & 0x8000_0000 != 0 {
+            (crc << 1) ^ POLY
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Software model of the hardware, one word MSB first.
+    fn crc32_word(crc: u32, word: u32) -> u32 {
+        word.to_be_bytes().iter().fold(crc, |crc, &b| crc32_byte(crc, b))
+    }
+
+    #[test]
+    fn check_value() {
+        // CRC-32/MPEG-2 check value
+        let crc = b"123456789".iter().fold(0xFFFF_FFFF, |crc, &b| crc32_byte(crc, b));
+        assert_eq!(crc, 0x0376_E6E7);
+    }
+
+    #[test]
+    fn word_matches_bytes() {
+        // Known hardware result for the single word 0x12345678 from reset
+        assert_eq!(crc32_word(0xFFFF_FFFF, 0x1234_5678), 0xDF8A_8A2B);
+        assert_eq!(crc32_word(0xFFFF_FFFF, 0), 0xC704_DD7B);
+    }
+}
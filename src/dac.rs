@@ -3,7 +3,7 @@
 
 use core::marker::PhantomData;
 
-use crate::dma::NoDma;
+use crate::dma::{NoDma, Transfer, TransferOptions};
 pub use crate::pac::dac::vals::TrigSel as TriggerSel;
 use crate::peripheral::RccPeripheral;
 use crate::{into_ref, peripherals, Peripheral, PeripheralRef};
@@ -48,6 +48,16 @@ pub enum ValueArray<'a> {
     Bit12Right(&'a [u16]),
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+/// Built-in waveform generator, DAC_CTLR.WAVE and MAMP.
+pub enum Waveform {
+    /// Pseudo-random noise from an LFSR, with the lowest `bits` bits (1 to 12) varying
+    Noise { bits: u8 },
+    /// Triangle from 0 to `2^bits - 1` (`bits` 1 to 12), added to the DHR value
+    Triangle { bits: u8 },
+}
+
 /// Driver for a single DAC channel.
 ///
 /// If you want to use both channels, either together or independently,
@@ -132,6 +142,44 @@ impl<'d, T: Instance, const N: u8, DMA> DacChannel<'d, T, N, DMA> {
         });
     }
 
+    /// Enable or disable the output buffer.
+    ///
+    /// The buffer can drive low impedance loads, but doesn't reach the supply rails. Without it
+    /// the output is rail to rail, for high impedance loads only.
+    ///
+    /// This method disables the channel, so you may need to re-enable afterwards.
+    pub fn set_output_buffer(&mut self, enable: bool) {
+        critical_section::with(|_| {
+            T::regs().cr().modify(|reg| {
+                reg.set_en(Self::IDX, false);
+                reg.set_boff(Self::IDX, !enable);
+            });
+        });
+    }
+
+    /// Enable the built-in waveform generator, or disable it with `None`.
+    ///
+    /// The waveform advances one step on each trigger, so triggering has to be set up with
+    /// [`set_trigger`](Self::set_trigger) and [`set_triggering`](Self::set_triggering).
+    ///
+    /// This method disables the channel, so you may need to re-enable afterwards.
+    pub fn set_waveform(&mut self, waveform: Option<Waveform>) {
+        let (wave, bits) = match waveform {
+            None => (0b00, 1),
+            Some(Waveform::Noise { bits }) => (0b01, bits),
+            Some(Waveform::Triangle { bits }) => (0b10, bits),
+        };
+        assert!((1..=12).contains(&bits));
+
+        critical_section::with(|_| {
+            T::regs().cr().modify(|reg| {
+                reg.set_en(Self::IDX, false);
+                reg.set_wave(Self::IDX, wave);
+                reg.set_mamp(Self::IDX, bits - 1);
+            });
+        });
+    }
+
     /// Software trigger this channel.
     pub fn trigger(&mut self) {
         T::regs().swtrigr().write(|reg| {
@@ -177,18 +225,60 @@ macro_rules! impl_dma_methods {
                     w.set_dmaen(Self::IDX, true);
                 });
 
-                let tx_request = self.dma.request();
-                let dma_channel = &mut self.dma;
-
-                let tx_options = crate::dma::TransferOptions {
+                let tx_options = TransferOptions {
                     circular,
                     half_transfer_ir: false,
                     complete_transfer_ir: !circular,
                     ..Default::default()
                 };
 
+                let tx_f = self.start_transfer(data, tx_options);
+
+                tx_f.await;
+
+                T::regs().cr().modify(|w| {
+                    w.set_en(Self::IDX, false);
+                    w.set_dmaen(Self::IDX, false);
+                });
+            }
+
+            /// Output `data` continuously, one sample on each trigger from `trigger`.
+            ///
+            /// The trigger is usually the TRGO of a timer, set up with
+            /// [`Timer::set_trgo_update`](crate::timer::low_level::Timer::set_trgo_update), so the
+            /// timer frequency is the sample rate. `data` is repeated by a circular DMA transfer
+            /// until the returned [`WaveDma`] is dropped.
+            #[cfg(not(gpdma))]
+            pub fn start_wave_dma<'a>(&'a mut self, data: ValueArray<'a>, trigger: TriggerSel) -> WaveDma<'a, T, $n> {
+                self.set_trigger(trigger);
+                self.set_triggering(true);
+                T::regs().cr().modify(|w| w.set_dmaen(Self::IDX, true));
+
+                let tx_options = TransferOptions {
+                    circular: true,
+                    half_transfer_ir: false,
+                    complete_transfer_ir: false,
+                    ..Default::default()
+                };
+
+                let transfer = self.start_transfer(data, tx_options);
+
+                // the channel was disabled by `set_trigger`
+                T::regs().cr().modify(|w| w.set_en(Self::IDX, true));
+
+                WaveDma {
+                    _transfer: transfer,
+                    phantom: PhantomData,
+                }
+            }
+
+            #[cfg(not(gpdma))]
+            fn start_transfer<'a>(&'a mut self, data: ValueArray<'a>, tx_options: TransferOptions) -> Transfer<'a> {
+                let tx_request = self.dma.request();
+                let dma_channel = &mut self.dma;
+
                 // Initiate the correct type of DMA transfer depending on what data is passed
-                let tx_f = match data {
+                match data {
                     ValueArray::Bit8(buf) => unsafe {
                         crate::dma::Transfer::new_write(
                             dma_channel,
@@ -216,14 +306,7 @@ macro_rules! impl_dma_methods {
                             tx_options,
                         )
                     },
-                };
-
-                tx_f.await;
-
-                T::regs().cr().modify(|w| {
-                    w.set_en(Self::IDX, false);
-                    w.set_dmaen(Self::IDX, false);
-                });
+                }
             }
         }
     };
@@ -232,6 +315,24 @@ macro_rules! impl_dma_methods {
 impl_dma_methods!(1, DacDma1);
 impl_dma_methods!(2, DacDma2);
 
+/// Waveform output by DMA, see [`DacChannel::start_wave_dma`].
+///
+/// Dropping it stops the output, the channel keeps the last sample.
+pub struct WaveDma<'a, T: Instance, const N: u8> {
+    _transfer: Transfer<'a>,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Instance, const N: u8> Drop for WaveDma<'a, T, N> {
+    fn drop(&mut self) {
+        // the DMA transfer is stopped when dropped after this
+        T::regs().cr().modify(|w| {
+            w.set_dmaen((N - 1) as usize, false);
+            w.set_ten((N - 1) as usize, false);
+        });
+    }
+}
+
 impl<'d, T: Instance, const N: u8, DMA> Drop for DacChannel<'d, T, N, DMA> {
     fn drop(&mut self) {
         T::disable();
@@ -1,13 +1,168 @@
-#[cfg(any(qingke_v2, qingke_v4))]
+//! Blocking delays based on the SysTick counter
+//!
+//! [`Delay`] polls the SysTick counter, which runs at HCLK (QingKe V2/V4) or HCLK/8 (QingKe V3,
+//! or when SysTick is the embassy time driver), so it works before the executor is running and
+//! doesn't depend on `TICK_HZ`. Delays shorter than 1 µs use a busy loop calibrated against the
+//! counter at init.
+//!
+//! The counter frequency is cached at init. Call [`init`] again after changing HCLK at runtime.
+//!
+//! Delays are never shorter than requested, but can be longer: interrupts taken during the delay
+//! add to it, and the call and tick conversion add a fixed overhead of some tens of cycles, which
+//! matters for delays of a few µs at low HCLK. Busy-loop delays below 1 µs are only as precise
+//! as the calibration, they also get longer when running from flash with wait states.
+//!
+//! With the `embassy` feature, [`Delay`] also implements the async `DelayNs`, which waits on an
+//! embassy timer.
+
+#[cfg(all(any(qingke_v2, qingke_v4), not(time_driver_systick)))]
 #[path = "./impl_qingke_v2_v4.rs"]
 mod delay_impl;
 
-#[cfg(qingke_v3)]
+// The SysTick time driver keeps SysTick free-running at HCLK/8, so the delay can only read it.
+#[cfg(any(qingke_v3, time_driver_systick))]
 #[path = "./impl_qingke_v3.rs"]
 mod delay_impl;
 
-pub use delay_impl::*;
+/// Counter ticks per second.
+static mut TICK_FREQ: u32 = 0;
+/// Calibrated [`spin`] iterations per microsecond, in 24.8 fixed point.
+static mut LOOPS_PER_US_Q8: u32 = 0;
+
+/// Number of [`spin`] iterations measured for calibration.
+const CALIBRATION_LOOPS: u32 = 1024;
+
+/// A delay provided by the SysTick core peripheral
+///
+/// This requires SysTick to be set up and running, which is done by [`crate::init`].
+pub struct Delay;
+
+impl Delay {
+    /// Delay for at least `ns` nanoseconds.
+    pub fn delay_ns(&mut self, ns: u32) {
+        if ns < 1_000 {
+            let loops = (ns as u64 * unsafe { LOOPS_PER_US_Q8 } as u64).div_ceil(256 * 1_000);
+            spin(loops as u32);
+        } else {
+            delay_ticks((ns as u64 * unsafe { TICK_FREQ } as u64).div_ceil(1_000_000_000));
+        }
+    }
+
+    /// Delay for at least `us` microseconds.
+    pub fn delay_us(&mut self, us: u32) {
+        delay_ticks((us as u64 * unsafe { TICK_FREQ } as u64).div_ceil(1_000_000));
+    }
+
+    /// Delay for at least `ms` milliseconds.
+    pub fn delay_ms(&mut self, ms: u32) {
+        delay_ticks((ms as u64 * unsafe { TICK_FREQ } as u64).div_ceil(1_000));
+    }
+}
+
+/// Wait for `ticks` counter ticks.
+///
+/// Only the low 32 bits of the counter are read. Elapsed ticks are accumulated across polls, so
+/// delays longer than a 32-bit wrap work as well.
+fn delay_ticks(ticks: u64) {
+    let mut last = delay_impl::counter();
+    let mut elapsed: u64 = 0;
+
+    while elapsed < ticks {
+        let now = delay_impl::counter();
+        elapsed += now.wrapping_sub(last) as u64;
+        last = now;
+    }
+}
+
+/// Busy loop for `loops` iterations.
+///
+/// Not inlined, so that the calibration measures exactly the code used for delays.
+#[inline(never)]
+fn spin(loops: u32) {
+    for _ in 0..loops {
+        qingke::riscv::asm::nop();
+    }
+}
+
+/// Current counter value, for measuring other clocks against it.
+pub(crate) fn counter() -> u32 {
+    delay_impl::counter()
+}
+
+/// Nominal counter frequency, from the configured clocks.
+pub(crate) fn counter_frequency() -> u32 {
+    unsafe { TICK_FREQ }
+}
 
+/// Start the SysTick counter and calibrate the busy loop.
+///
+/// # Safety
+///
+/// Must not be called concurrently with a running delay.
 pub unsafe fn init() {
-    Delay::init();
+    let tick_freq = delay_impl::init();
+    TICK_FREQ = tick_freq;
+
+    let start = delay_impl::counter();
+    spin(CALIBRATION_LOOPS);
+    let ticks = delay_impl::counter().wrapping_sub(start).max(1);
+
+    LOOPS_PER_US_Q8 = (CALIBRATION_LOOPS as u64 * 256 * tick_freq as u64 / (ticks as u64 * 1_000_000)) as u32;
+}
+
+impl embedded_hal::delay::DelayNs for Delay {
+    #[inline]
+    fn delay_ns(&mut self, ns: u32) {
+        Delay::delay_ns(self, ns)
+    }
+
+    #[inline]
+    fn delay_us(&mut self, us: u32) {
+        Delay::delay_us(self, us)
+    }
+
+    #[inline]
+    fn delay_ms(&mut self, ms: u32) {
+        Delay::delay_ms(self, ms)
+    }
 }
+
+/// Async delays yield to the executor, using the embassy time driver instead of SysTick polling.
+///
+/// They have the resolution of `TICK_HZ`, shorter delays are rounded up to a tick.
+#[cfg(feature = "embassy")]
+impl embedded_hal_async::delay::DelayNs for Delay {
+    async fn delay_ns(&mut self, ns: u32) {
+        embassy_time::Timer::after_nanos(ns as u64).await
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        embassy_time::Timer::after_micros(us as u64).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        embassy_time::Timer::after_millis(ms as u64).await
+    }
+}
+
+macro_rules! impl_delay_02 {
+    ($($t:ty),*) => {
+        $(
+            impl embedded_hal_02::blocking::delay::DelayUs<$t> for Delay {
+                #[inline]
+                fn delay_us(&mut self, us: $t) {
+                    Delay::delay_us(self, us as u32)
+                }
+            }
+
+            impl embedded_hal_02::blocking::delay::DelayMs<$t> for Delay {
+                #[inline]
+                fn delay_ms(&mut self, ms: $t) {
+                    Delay::delay_ms(self, ms as u32)
+                }
+            }
+        )*
+    };
+}
+
+impl_delay_02!(u8, u16, u32);
@@ -106,12 +106,17 @@ mod bdma_only {
 pub(crate) struct ChannelState {
     waker: AtomicWaker,
     complete_count: AtomicUsize,
+    half_count: AtomicUsize,
+    /// Called once from the interrupt on transfer complete, with its argument
+    complete_hook: CriticalSectionMutex<Cell<Option<(fn(usize), usize)>>>,
 }
 
 impl ChannelState {
     pub(crate) const NEW: Self = Self {
         waker: AtomicWaker::new(),
         complete_count: AtomicUsize::new(0),
+        half_count: AtomicUsize::new(0),
+        complete_hook: CriticalSectionMutex::new(Cell::new(None)),
     };
 }
 
@@ -143,6 +148,10 @@ impl AnyChannel {
                 if isr.htif(info.num) && cr.read().htie() {
                     // Acknowledge half transfer complete interrupt
                     r.ifcr().write(|w| w.set_htif(info.num, true));
+                    critical_section::with(|_| {
+                        let cnt = state.half_count.load(Ordering::Acquire);
+                        state.half_count.store(cnt + 1, Ordering::Release);
+                    });
                 } else if isr.tcif(info.num) && cr.read().tcie() {
                     // Acknowledge transfer complete interrupt
                     r.ifcr().write(|w| w.set_tcif(info.num, true));
@@ -152,6 +161,9 @@ impl AnyChannel {
                         let cnt = state.complete_count.load(Ordering::Acquire);
                         state.complete_count.store(cnt + 1, Ordering::Release);
                     });
+                    if let Some((hook, arg)) = state.complete_hook.lock(|h| h.take()) {
+                        hook(arg);
+                    }
                 } else {
                     return;
                 }
@@ -181,7 +193,12 @@ impl AnyChannel {
                 let state: &ChannelState = &STATE[self.id as usize];
                 let ch = r.ch(info.num);
 
+                // The address and count registers can't be written while the channel is enabled,
+                // a transfer that completed without being stopped leaves EN set.
+                ch.cr().write(|w| w.set_en(false));
+
                 state.complete_count.store(0, Ordering::Release);
+                state.half_count.store(0, Ordering::Release);
                 self.clear_irqs();
 
                 ch.par().write_value(peri_addr as u32); // PADDR
@@ -203,6 +220,26 @@ impl AnyChannel {
         }
     }
 
+    /// Turn a configured channel into a memory to memory transfer, the peripheral address is
+    /// incremented as well.
+    fn set_mem2mem(&self) {
+        let info = self.info();
+        match self.info().dma {
+            DmaInfo::Dma(r) => r.ch(info.num).cr().modify(|w| {
+                w.set_pinc(true);
+                w.set_mem2mem(true);
+            }),
+        }
+    }
+
+    /// Number of half transfer and transfer complete events since the channel was configured.
+    fn event_count(&self) -> usize {
+        let state: &ChannelState = &STATE[self.id as usize];
+        critical_section::with(|_| {
+            state.half_count.load(Ordering::Acquire) + state.complete_count.load(Ordering::Acquire)
+        })
+    }
+
     fn start(&self) {
         let info = self.info();
         match self.info().dma {
@@ -239,6 +276,27 @@ impl AnyChannel {
         }
     }
 
+    fn request_pause(&self) {
+        let info = self.info();
+        match self.info().dma {
+            DmaInfo::Dma(r) => {
+                // Disable the channel, keeping its configuration so it can be restarted.
+                r.ch(info.num).cr().modify(|w| w.set_en(false));
+            }
+        }
+    }
+
+    /// Rewind a disabled channel to the start of its buffer.
+    fn rewind(&self, mem_len: usize) {
+        let info = self.info();
+        match self.info().dma {
+            DmaInfo::Dma(r) => {
+                self.clear_irqs();
+                r.ch(info.num).ndtr().write(|w| w.set_ndt(mem_len as u16)); // CNTR
+            }
+        }
+    }
+
     fn is_running(&self) -> bool {
         let info = self.info();
         match self.info().dma {
@@ -385,6 +443,45 @@ impl<'a> Transfer<'a> {
         )
     }
 
+    /// Create a new memory to memory DMA transfer, copying `src` to `dst`.
+    ///
+    /// Both addresses are incremented, and the channel copies as fast as the bus allows, without
+    /// a peripheral request. So any channel can be used. Circular mode is not available for memory
+    /// to memory transfers.
+    pub unsafe fn new_mem_to_mem<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        src: &'a [W],
+        dst: &'a mut [W],
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+
+        assert!(src.len() == dst.len());
+        assert!(
+            !options.circular,
+            "DMA: circular mode is not available for memory to memory"
+        );
+        let len = src.len();
+        assert!(len > 0 && len <= 0xFFFF);
+
+        let channel: PeripheralRef<'a, AnyChannel> = channel.map_into();
+        // MEM2MEM reads from the peripheral address
+        channel.configure(
+            (),
+            Dir::PeripheralToMemory,
+            src.as_ptr() as *const u32,
+            dst.as_mut_ptr() as *mut u32,
+            len,
+            true,
+            W::size(),
+            options,
+        );
+        channel.set_mem2mem();
+        channel.start();
+
+        Self { channel }
+    }
+
     unsafe fn new_inner(
         channel: PeripheralRef<'a, AnyChannel>,
         _request: Request,
@@ -432,14 +529,24 @@ impl<'a> Transfer<'a> {
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
         fence(Ordering::SeqCst);
 
+        self.set_complete_hook(None);
         core::mem::forget(self);
     }
+
+    /// Call `hook(arg)` from the DMA interrupt when the transfer completes, before the task is
+    /// woken. For peripherals that must be stopped right after the last word.
+    ///
+    /// The hook is removed when the transfer is dropped. The transfer must not have completed yet.
+    pub(crate) fn set_complete_hook(&mut self, hook: Option<(fn(usize), usize)>) {
+        STATE[self.channel.id as usize].complete_hook.lock(|h| h.set(hook));
+    }
 }
 
 impl<'a> Drop for Transfer<'a> {
     fn drop(&mut self) {
         self.request_stop();
         while self.is_running() {}
+        self.set_complete_hook(None);
 
         // "Subsequent reads and writes cannot be moved ahead of preceding reads."
         fence(Ordering::SeqCst);
@@ -447,6 +554,155 @@ impl<'a> Drop for Transfer<'a> {
 }
 
 impl<'a> Unpin for Transfer<'a> {}
+
+/// Half of a circular DMA buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Half {
+    /// First half of the buffer
+    First,
+    /// Second half of the buffer
+    Second,
+}
+
+/// Circular DMA transfer, for ping-pong buffering.
+///
+/// The DMA goes over the buffer again and again, and raises an event each time it is done with
+/// one half. [`wait_half`](Self::wait_half) returns the half that was just finished, which can be
+/// processed (peripheral to memory) or refilled (memory to peripheral) while the DMA works on the
+/// other one.
+///
+/// The channel is borrowed for the lifetime of the transfer, so it can't be reconfigured while it
+/// runs. Dropping the transfer stops the channel.
+pub struct CircularTransfer<'a> {
+    channel: PeripheralRef<'a, AnyChannel>,
+    /// Events seen by `wait_half`
+    seen: usize,
+}
+
+impl<'a> CircularTransfer<'a> {
+    /// Create a new circular read (peripheral to memory).
+    ///
+    /// The buffer is accessed by the DMA through the raw pointer until the transfer is dropped.
+    /// Its length must be even.
+    pub unsafe fn new_read<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        request: Request,
+        peri_addr: *mut W,
+        buf: *mut [W],
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+        Self::new_inner(
+            channel.map_into(),
+            request,
+            Dir::PeripheralToMemory,
+            peri_addr as *const u32,
+            buf,
+            options,
+        )
+    }
+
+    /// Create a new circular write (memory to peripheral).
+    ///
+    /// The buffer is accessed by the DMA through the raw pointer until the transfer is dropped.
+    /// Its length must be even.
+    pub unsafe fn new_write<W: Word>(
+        channel: impl Peripheral<P = impl Channel> + 'a,
+        request: Request,
+        buf: *mut [W],
+        peri_addr: *mut W,
+        options: TransferOptions,
+    ) -> Self {
+        into_ref!(channel);
+        Self::new_inner(
+            channel.map_into(),
+            request,
+            Dir::MemoryToPeripheral,
+            peri_addr as *const u32,
+            buf,
+            options,
+        )
+    }
+
+    unsafe fn new_inner<W: Word>(
+        channel: PeripheralRef<'a, AnyChannel>,
+        request: Request,
+        dir: Dir,
+        peri_addr: *const u32,
+        buf: *mut [W],
+        mut options: TransferOptions,
+    ) -> Self {
+        let (ptr, len) = super::slice_ptr_parts_mut(buf);
+        assert!(len >= 2 && len % 2 == 0 && len <= 0xFFFF);
+
+        options.circular = true;
+        options.half_transfer_ir = true;
+        options.complete_transfer_ir = true;
+
+        channel.configure(request, dir, peri_addr, ptr as *mut u32, len, true, W::size(), options);
+        channel.start();
+
+        Self { channel, seen: 0 }
+    }
+
+    /// Wait until the DMA is done with a half of the buffer, and return which one.
+    ///
+    /// Halves are returned in order. If the DMA finished more than one half since the last call,
+    /// the data was overwritten (or repeated) before it was handled. [`OverrunError`] is returned
+    /// then, and the following call waits for the next half again.
+    pub async fn wait_half(&mut self) -> Result<Half, OverrunError> {
+        let state: &ChannelState = &STATE[self.channel.id as usize];
+
+        let events = poll_fn(|cx| {
+            state.waker.register(cx.waker());
+            compiler_fence(Ordering::SeqCst);
+
+            let events = self.channel.event_count();
+            if events == self.seen {
+                Poll::Pending
+            } else {
+                Poll::Ready(events)
+            }
+        })
+        .await;
+
+        let missed = events - self.seen > 1;
+        self.seen = events;
+        if missed {
+            return Err(OverrunError);
+        }
+        // half transfer and transfer complete events alternate, starting with half transfer
+        Ok(if events % 2 == 1 { Half::First } else { Half::Second })
+    }
+
+    /// Request the transfer to stop.
+    ///
+    /// This doesn't immediately stop the transfer, you have to wait until [`is_running`](Self::is_running) returns false.
+    pub fn request_stop(&mut self) {
+        self.channel.request_stop()
+    }
+
+    /// Return whether this transfer is still running.
+    pub fn is_running(&mut self) -> bool {
+        self.channel.is_running()
+    }
+
+    /// Gets the remaining transfers until the end of the buffer
+    pub fn get_remaining_transfers(&self) -> u16 {
+        self.channel.get_remaining_transfers()
+    }
+}
+
+impl<'a> Drop for CircularTransfer<'a> {
+    fn drop(&mut self) {
+        self.request_stop();
+        while self.is_running() {}
+
+        // "Subsequent reads and writes cannot be moved ahead of preceding reads."
+        fence(Ordering::SeqCst);
+    }
+}
 impl<'a> Future for Transfer<'a> {
     type Output = ();
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -540,7 +796,12 @@ impl<'a, W: Word> ReadableRingBuffer<'a, W> {
     }
 
     /// Clear all data in the ring buffer.
+    ///
+    /// If the DMA is stopped or paused, it is also rewound to the start of the buffer.
     pub fn clear(&mut self) {
+        if !self.channel.is_running() {
+            self.channel.rewind(self.ringbuf.cap());
+        }
         self.ringbuf.clear(&mut DmaCtrlImpl(self.channel.reborrow()));
     }
 
@@ -587,6 +848,14 @@ impl<'a, W: Word> ReadableRingBuffer<'a, W> {
         self.channel.request_stop()
     }
 
+    /// Request DMA to pause.
+    ///
+    /// Unlike [`request_stop`](Self::request_stop), the channel configuration is kept, so the
+    /// ring buffer can be started again after a [`clear`](Self::clear).
+    pub fn request_pause(&mut self) {
+        self.channel.request_pause()
+    }
+
     /// Return whether DMA is still running.
     ///
     /// If this returns `false`, it can be because either the transfer finished, or
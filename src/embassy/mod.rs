@@ -1,8 +1,13 @@
 ///! The time driver for Embassy framework.
 ///
 /// This module provides the time driver for the Embassy framework.
+///
+/// The driver is selected with one of the `time-driver-*` features:
+///
+/// - `time-driver-systick`: SysTick, QingKe V2 and V4 cores only
+/// - `time-driver-timX` / `time-driver-any`: a general-purpose or advanced timer, see `time_driver_tim.rs`
 
-#[cfg(all(qingke_v4, not(time_driver_timer)))]
+#[cfg(time_driver_systick)]
 #[path = "time_driver_systick.rs"]
 pub mod time_driver_impl;
 
@@ -23,9 +28,24 @@ pub mod time_driver_impl;
 /// The WCH QingKe RISC-V core deviates from standard RISC-V specification:
 /// - `WFI` instruction will not wake up from disabled interrupts
 /// - Either `WFITOWFE` or `SEVONPEND` must be enabled for proper wake-up behavior
-pub unsafe fn init() {
+pub unsafe fn init(time_interrupt_priority: crate::interrupt::Priority) {
     crate::pac::PFIC.sctlr().modify(|w| w.set_sevonpend(true));
 
-    #[cfg(any(qingke_v4, time_driver_timer))]
-    critical_section::with(|cs| time_driver_impl::init(cs));
+    #[cfg(any(time_driver_systick, time_driver_timer))]
+    critical_section::with(|cs| time_driver_impl::init(cs, time_interrupt_priority));
+    #[cfg(not(any(time_driver_systick, time_driver_timer)))]
+    let _ = time_interrupt_priority;
+}
+
+/// Notify the time driver that the system clocks changed.
+///
+/// Call this after changing HCLK/PCLK at runtime and updating [`crate::rcc::clocks()`].
+/// `now()` stays monotonic across the change and pending alarms are rescheduled for the
+/// new clock rate.
+///
+/// With a TIMx time driver, the new prescaler takes effect right away. The ticks counted between
+/// the clock change and this call are counted at the wrong rate, so call it right after.
+pub fn on_clock_change() {
+    #[cfg(any(time_driver_systick, time_driver_timer))]
+    critical_section::with(|cs| time_driver_impl::on_clock_change(cs));
 }
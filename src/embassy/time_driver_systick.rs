@@ -1,7 +1,13 @@
 //! SysTick-based time driver.
+//!
+//! QingKe V4 cores have a 64-bit SysTick. QingKe V2 (CH32V003) only has a 32-bit one, which wraps
+//! every 2^32 counts (about 12 minutes at 48 MHz HCLK/8). It is extended to 64 bits in software:
+//! the alarm is never armed more than half a wrap ahead, so the counter is read at least twice
+//! per wrap and a wrap is seen as the counter going backwards.
+
+use core::cell::{Cell, RefCell};
+use core::task::Waker;
 
-use core::cell::RefCell;
-use core::sync::atomic::{AtomicU32, Ordering};
 use critical_section::CriticalSection;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
@@ -18,45 +24,296 @@ fn SysTick() {
     DRIVER.on_interrupt();
 }
 
+/// SysTick time driver.
+///
+/// Pending wakeups are kept in an `embassy_time_queue_utils::Queue`, so any number of
+/// concurrent timers can be armed. The compare register is always programmed to the
+/// earliest outstanding expiration.
 pub struct SystickDriver {
-    cnt_per_tick: AtomicU32,
+    systick: Systick,
+    timebase: Mutex<CriticalSectionRawMutex, Cell<Timebase>>,
+    // Deadlines are stored in ticks, so they stay valid across clock changes.
     queue: Mutex<CriticalSectionRawMutex, RefCell<Queue>>,
 }
 
 embassy_time_driver::time_driver_impl!(static DRIVER: SystickDriver = SystickDriver {
-    cnt_per_tick: AtomicU32::new(1), // avoid div by zero
+    systick: Systick::new(),
+    timebase: Mutex::const_new(CriticalSectionRawMutex::new(), Cell::new(Timebase {
+        cnt: 0,
+        ticks: 0,
+        cnt_per_second: 1, // avoid div by zero
+    })),
     queue: Mutex::new(RefCell::new(Queue::new()))
 });
 
+/// Mapping between the raw SysTick counter and embassy ticks.
+///
+/// `ticks` is the tick count at raw counter value `cnt`, from where the counter advances at
+/// `cnt_per_second` (HCLK/8). This is rebased on every clock change so `now()` stays monotonic.
+///
+/// The frequency is kept as a ratio against TICK_HZ instead of a truncated counts-per-tick
+/// value, so the conversion has no systematic drift.
+#[derive(Clone, Copy)]
+struct Timebase {
+    cnt: u64,
+    ticks: u64,
+    cnt_per_second: u64,
+}
+
+impl Timebase {
+    fn cnt_to_ticks(&self, cnt: u64) -> u64 {
+        self.ticks + cnt_to_ticks(cnt.saturating_sub(self.cnt), self.cnt_per_second)
+    }
+
+    fn ticks_to_cnt(&self, ticks: u64) -> u64 {
+        self.cnt
+            .saturating_add(ticks_to_cnt(ticks.saturating_sub(self.ticks), self.cnt_per_second))
+    }
+}
+
+/// Convert a raw SysTick count to embassy ticks, rounding down.
+///
+/// The count is split into whole seconds and a remainder so every intermediate product fits
+/// in a `u64`, which makes the conversion exact over the full counter range.
+#[inline]
+fn cnt_to_ticks(cnt: u64, cnt_per_second: u64) -> u64 {
+    let secs = cnt / cnt_per_second;
+    let rem = cnt % cnt_per_second;
+    secs * embassy_time_driver::TICK_HZ + rem * embassy_time_driver::TICK_HZ / cnt_per_second
+}
+
+/// Convert embassy ticks to a raw SysTick count, rounding up so alarms never fire early.
+///
+/// Saturates at `u64::MAX` for timestamps that can't be represented.
+#[inline]
+fn ticks_to_cnt(ticks: u64, cnt_per_second: u64) -> u64 {
+    let secs = ticks / embassy_time_driver::TICK_HZ;
+    let rem = ticks % embassy_time_driver::TICK_HZ;
+    let sub = (rem * cnt_per_second).div_ceil(embassy_time_driver::TICK_HZ);
+    secs.saturating_mul(cnt_per_second).saturating_add(sub)
+}
+
+/// The SysTick registers the alarm logic uses, simulated in the tests.
+trait Counter {
+    /// Current counter value.
+    fn cnt(&self, cs: CriticalSection) -> u64;
+    /// Raise the SysTick interrupt when the counter reaches `cmp`, or earlier.
+    fn arm(&self, cs: CriticalSection, cmp: u64);
+    /// Mask the SysTick interrupt.
+    fn disarm(&self);
+}
+
+/// The timer queue, `embassy_time_queue_utils::Queue` in the driver.
+trait TimerQueue {
+    /// Add `waker` at `at`, returns `true` if the next expiration may have changed.
+    fn schedule_wake(&mut self, at: u64, waker: &Waker) -> bool;
+    /// Wake the expired wakers, returns the next expiration or `u64::MAX`.
+    fn next_expiration(&mut self, now: u64) -> u64;
+}
+
+impl TimerQueue for Queue {
+    fn schedule_wake(&mut self, at: u64, waker: &Waker) -> bool {
+        Queue::schedule_wake(self, at, waker)
+    }
+
+    fn next_expiration(&mut self, now: u64) -> u64 {
+        Queue::next_expiration(self, now)
+    }
+}
+
+fn schedule_wake(
+    counter: &impl Counter,
+    cs: CriticalSection,
+    timebase: Timebase,
+    queue: &mut impl TimerQueue,
+    at: u64,
+    waker: &Waker,
+) {
+    if queue.schedule_wake(at, waker) {
+        trigger_alarm(counter, cs, timebase, queue);
+    }
+}
+
+/// Wake the expired wakers, and arm the alarm for the earliest one left.
+fn trigger_alarm(counter: &impl Counter, cs: CriticalSection, timebase: Timebase, queue: &mut impl TimerQueue) {
+    let mut next = queue.next_expiration(timebase.cnt_to_ticks(counter.cnt(cs)));
+    while !set_alarm(counter, cs, timebase, next) {
+        next = queue.next_expiration(timebase.cnt_to_ticks(counter.cnt(cs)));
+    }
+}
+
+/// Program the compare register for the alarm at `timestamp` ticks.
+///
+/// Returns `false` if the deadline is already in the past, or passed while the compare
+/// register was being written. Callers must then call `next_expiration` again, which wakes
+/// the expired wakers immediately instead of waiting for an interrupt that may never come.
+fn set_alarm(counter: &impl Counter, cs: CriticalSection, timebase: Timebase, timestamp: u64) -> bool {
+    let next_alarm_cnt = match timestamp {
+        u64::MAX => u64::MAX,
+        _ => timebase.ticks_to_cnt(timestamp),
+    };
+
+    if next_alarm_cnt <= counter.cnt(cs) {
+        // If alarm timestamp has passed the alarm will not fire.
+        // Disarm the alarm and return `false` to indicate that.
+        return false;
+    }
+
+    counter.arm(cs, next_alarm_cnt);
+
+    if next_alarm_cnt <= counter.cnt(cs) {
+        // If alarm timestamp has passed the alarm will not fire.
+        // Disarm the alarm and return `false` to indicate that.
+        counter.disarm();
+        return false;
+    }
+
+    true
+}
+
+/// Extend a 32-bit counter value `lo` to 64 bits, given the previous extended value `prev`.
+///
+/// Correct as long as the counter advanced by less than a wrap since `prev` was read.
+#[cfg_attr(not(qingke_v2), allow(dead_code))]
+fn extend_cnt(prev: u64, lo: u32) -> u64 {
+    let cnt = (prev & !0xffff_ffff) | lo as u64;
+    if cnt < prev {
+        cnt + (1 << 32)
+    } else {
+        cnt
+    }
+}
+
+/// The SysTick registers.
+struct Systick {
+    /// Last counter value read, extended to 64 bits
+    #[cfg(qingke_v2)]
+    last_cnt: Mutex<CriticalSectionRawMutex, Cell<u64>>,
+}
+
+impl Systick {
+    const fn new() -> Self {
+        Self {
+            #[cfg(qingke_v2)]
+            last_cnt: Mutex::const_new(CriticalSectionRawMutex::new(), Cell::new(0)),
+        }
+    }
+
+    /// Write the 64-bit compare register without ever exposing a torn, earlier value.
+    ///
+    /// The high half is parked at `u32::MAX` (unreachable for all practical purposes) while the
+    /// low half is updated.
+    #[cfg(not(qingke_v2))]
+    #[inline]
+    fn write_cmp(&self, _cs: CriticalSection, cmp: u64) {
+        let r = crate::pac::SYSTICK;
+        r.cmph().write_value(u32::MAX);
+        r.cmpl().write_value(cmp as u32);
+        r.cmph().write_value((cmp >> 32) as u32);
+    }
+
+    /// Write the 32-bit compare register, at most half a wrap ahead so the counter extension
+    /// sees every wrap.
+    #[cfg(qingke_v2)]
+    #[inline]
+    fn write_cmp(&self, cs: CriticalSection, cmp: u64) {
+        let cmp = cmp.min(self.last_cnt.borrow(cs).get() + (1 << 31));
+        crate::pac::SYSTICK.cmpl().write_value(cmp as u32);
+    }
+}
+
+impl Counter for Systick {
+    /// Read the 64-bit counter.
+    ///
+    /// The counter is read as two 32-bit halves, so re-read the high half to detect a carry from
+    /// the low half in between, which would otherwise make `now()` jump.
+    #[cfg(not(qingke_v2))]
+    #[inline]
+    fn cnt(&self, _cs: CriticalSection) -> u64 {
+        let r = crate::pac::SYSTICK;
+        loop {
+            let hi = r.cnth().read();
+            let lo = r.cntl().read();
+            if r.cnth().read() == hi {
+                return ((hi as u64) << 32) | lo as u64;
+            }
+        }
+    }
+
+    /// Read the 32-bit counter, extended to 64 bits.
+    #[cfg(qingke_v2)]
+    #[inline]
+    fn cnt(&self, cs: CriticalSection) -> u64 {
+        let last_cnt = self.last_cnt.borrow(cs);
+        let cnt = extend_cnt(last_cnt.get(), crate::pac::SYSTICK.cntl().read());
+        last_cnt.set(cnt);
+        cnt
+    }
+
+    fn arm(&self, cs: CriticalSection, cmp: u64) {
+        let r = &crate::pac::SYSTICK;
+        // Counter interrupt enable control bit
+        self.write_cmp(cs, cmp);
+        r.ctlr().modify(|w| w.set_stie(true));
+        r.sr().write(|w| w.set_cntif(false));
+    }
+
+    fn disarm(&self) {
+        let r = &crate::pac::SYSTICK;
+        r.ctlr().modify(|w| w.set_stie(false));
+        r.sr().write(|w| w.set_cntif(false));
+    }
+}
+
 impl SystickDriver {
     fn init(&'static self, _cs: critical_section::CriticalSection) {
         let r = &crate::pac::SYSTICK;
         let hclk = crate::rcc::clocks().hclk.0 as u64;
 
         let cnt_per_second = hclk / 8; // HCLK/8
-        let cnt_per_tick = cnt_per_second / embassy_time_driver::TICK_HZ;
 
-        self.cnt_per_tick.store(cnt_per_tick as u32, Ordering::Relaxed);
-
-        r.ctlr().write(|w| {
-            // Everything else is set to default (0)
-            w.set_init(true); // Initialize counter
-            w.set_ste(true); // Enable counter
+        self.timebase.borrow(_cs).set(Timebase {
+            cnt: 0,
+            ticks: 0,
+            cnt_per_second,
         });
 
-        // Write 0 to both halves of the compare register
-        r.cmph().write_value(0);
-        r.cmpl().write_value(0);
+        #[cfg(not(qingke_v2))]
+        {
+            r.ctlr().write(|w| {
+                // Everything else is set to default (0)
+                w.set_init(true); // Initialize counter
+                w.set_ste(true); // Enable counter
+            });
+
+            // Write 0 to both halves of the compare register
+            r.cmph().write_value(0);
+            r.cmpl().write_value(0);
+        }
+
+        // QingKe V2 has no INIT bit and only counts up
+        #[cfg(qingke_v2)]
+        {
+            r.ctlr().write(|w| w.set_ste(true)); // Enable counter
+            r.cntl().write_value(0);
+            r.cmpl().write_value(0);
+        }
 
         // Count value compare flag
         r.sr().write(|w| w.set_cntif(false)); // clear
 
         // Configration: Upcount, No reload, HCLK/8 as clock source
         r.ctlr().modify(|w| {
+            #[cfg(not(qingke_v2))]
             w.set_mode(vals::Mode::UPCOUNT); // Counter mode
             w.set_stre(false); // Auto reload count enable bit
             w.set_stclk(vals::Stclk::HCLK_DIV8); // Counter system clock sourse selection bit
         });
+
+        // The counter extension needs the interrupt at least every half wrap, even without
+        // timers
+        #[cfg(qingke_v2)]
+        self.trigger_alarm(_cs);
     }
 
     fn on_interrupt(&self) {
@@ -69,75 +326,340 @@ impl SystickDriver {
         });
     }
 
-    #[inline]
-    fn raw_cnt(&self) -> u64 {
-        let r = crate::pac::SYSTICK;
-        return r.cnt().read();
-    }
-
     fn trigger_alarm(&self, cs: CriticalSection) {
-        let mut next = self.queue.borrow(cs).borrow_mut().next_expiration(self.raw_cnt());
-        while !self.set_alarm(cs, next) {
-            next = self.queue.borrow(cs).borrow_mut().next_expiration(self.raw_cnt());
-        }
+        let timebase = self.timebase.borrow(cs).get();
+        trigger_alarm(&self.systick, cs, timebase, &mut *self.queue.borrow(cs).borrow_mut());
     }
 
-    fn set_alarm(&self, _cs: CriticalSection, next_alarm_cnt: u64) -> bool {
-        let r = &crate::pac::SYSTICK;
-
-        // TODO move this to schedule_wake
-        if next_alarm_cnt <= self.raw_cnt() {
-            // If alarm timestamp has passed the alarm will not fire.
-            // Disarm the alarm and return `false` to indicate that.
-            return false;
-        }
-
-        // Counter interrupt enable control bit
-        r.cmph().write_value((next_alarm_cnt >> 32) as u32);
-        r.cmpl().write_value((next_alarm_cnt) as u32);
-        r.ctlr().modify(|w| w.set_stie(true));
-        r.sr().write(|w| w.set_cntif(false));
+    fn on_clock_change(&self, cs: CriticalSection) {
+        let hclk = crate::rcc::clocks().hclk.0 as u64;
 
-        if next_alarm_cnt <= self.raw_cnt() {
-            // If alarm timestamp has passed the alarm will not fire.
-            // Disarm the alarm and return `false` to indicate that.
-            r.ctlr().modify(|w| w.set_stie(false));
-            r.sr().write(|w| w.set_cntif(false));
-            return false;
-        }
+        // Rebase at the current counter value, so ticks elapsed so far are kept as-is and only
+        // the rate of future ticks changes.
+        let cnt = self.systick.cnt(cs);
+        let ticks = self.timebase.borrow(cs).get().cnt_to_ticks(cnt);
+        self.timebase.borrow(cs).set(Timebase {
+            cnt,
+            ticks,
+            cnt_per_second: hclk / 8,
+        });
 
-        true
+        // The compare register holds a raw count computed with the old rate.
+        self.trigger_alarm(cs);
     }
 }
 
 impl Driver for SystickDriver {
     fn now(&self) -> u64 {
-        let cnt_per_tick = self.cnt_per_tick.load(Ordering::Relaxed) as u64;
-        self.raw_cnt() / cnt_per_tick
+        critical_section::with(|cs| self.timebase.borrow(cs).get().cnt_to_ticks(self.systick.cnt(cs)))
     }
 
-    fn schedule_wake(&self, ticks: u64, waker: &core::task::Waker) {
-        let cnt_per_tick = self.cnt_per_tick.load(Ordering::Relaxed) as u64;
+    fn schedule_wake(&self, ticks: u64, waker: &Waker) {
         critical_section::with(|cs| {
+            let timebase = self.timebase.borrow(cs).get();
             let mut queue = self.queue.borrow(cs).borrow_mut();
-
-            if queue.schedule_wake(ticks * cnt_per_tick, waker) {
-                let mut next = queue.next_expiration(self.raw_cnt());
-                while !self.set_alarm(cs, next) {
-                    next = queue.next_expiration(self.raw_cnt());
-                }
-            }
+            schedule_wake(&self.systick, cs, timebase, &mut *queue, ticks, waker);
         })
     }
 }
 
-pub(crate) fn init(cs: CriticalSection) {
+pub(crate) fn init(cs: CriticalSection, priority: Priority) {
     DRIVER.init(cs);
     use qingke_rt::CoreInterrupt;
 
     // enable interrupt
     unsafe {
-        qingke::pfic::set_priority(CoreInterrupt::SysTick as u8, Priority::P15 as u8);
+        qingke::pfic::set_priority(CoreInterrupt::SysTick as u8, priority as u8);
         qingke::pfic::enable_interrupt(CoreInterrupt::SysTick as u8);
     }
 }
+
+pub(crate) fn on_clock_change(cs: CriticalSection) {
+    DRIVER.on_clock_change(cs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::task::Wake;
+    use std::vec::Vec;
+
+    use embassy_time_driver::TICK_HZ;
+
+    use super::*;
+
+    /// HCLK/8 at 8, 48 and 144 MHz
+    const RATES: [u64; 3] = [1_000_000, 6_000_000, 18_000_000];
+
+    #[test]
+    fn no_drift() {
+        for cps in RATES {
+            assert_eq!(cnt_to_ticks(cps, cps), TICK_HZ);
+            // A day
+            assert_eq!(cnt_to_ticks(cps * 86_400, cps), TICK_HZ * 86_400);
+            assert_eq!(ticks_to_cnt(TICK_HZ * 86_400, cps), cps * 86_400);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for cps in RATES {
+            for ticks in [0, 1, TICK_HZ - 1, TICK_HZ, TICK_HZ + 1, u64::MAX / cps] {
+                assert_eq!(cnt_to_ticks(ticks_to_cnt(ticks, cps), cps), ticks);
+            }
+            for cnt in [0, 1, cps - 1, cps, cps + 1, u64::MAX - 1, u64::MAX] {
+                // Rounded down to ticks, and back up: never after `cnt`
+                assert!(ticks_to_cnt(cnt_to_ticks(cnt, cps), cps) <= cnt);
+            }
+        }
+    }
+
+    #[test]
+    fn counter_wrap() {
+        for cps in RATES {
+            // Monotonic up to the end of the counter range, without overflow
+            assert!(cnt_to_ticks(u64::MAX - 1, cps) <= cnt_to_ticks(u64::MAX, cps));
+            let expected = u64::MAX / cps * TICK_HZ + u64::MAX % cps * TICK_HZ / cps;
+            assert_eq!(cnt_to_ticks(u64::MAX, cps), expected);
+            // Timestamps past the counter range saturate
+            assert_eq!(ticks_to_cnt(u64::MAX, cps), u64::MAX);
+        }
+    }
+
+    #[test]
+    fn timebase_rebase() {
+        let timebase = Timebase {
+            cnt: 1_000,
+            ticks: 500,
+            cnt_per_second: 6_000_000,
+        };
+        assert_eq!(timebase.cnt_to_ticks(1_000), 500);
+        // A counter value before the rebase point maps to the rebase tick, not before it
+        assert_eq!(timebase.cnt_to_ticks(0), 500);
+        assert_eq!(timebase.ticks_to_cnt(0), 1_000);
+        assert_eq!(timebase.ticks_to_cnt(500 + TICK_HZ), 1_000 + 6_000_000);
+        // Saturates instead of wrapping
+        assert_eq!(timebase.ticks_to_cnt(u64::MAX), u64::MAX);
+    }
+
+    /// SysTick whose counter advances by `step` on every read, and only moves otherwise when
+    /// [`run`] jumps it to the armed compare value.
+    struct FakeSystick {
+        cnt: Cell<u64>,
+        step: u64,
+        cmp: Cell<Option<u64>>,
+    }
+
+    impl FakeSystick {
+        fn new(step: u64) -> Self {
+            Self {
+                cnt: Cell::new(0),
+                step,
+                cmp: Cell::new(None),
+            }
+        }
+    }
+
+    impl Counter for FakeSystick {
+        fn cnt(&self, _cs: CriticalSection) -> u64 {
+            let cnt = self.cnt.get();
+            self.cnt.set(cnt + self.step);
+            cnt
+        }
+
+        fn arm(&self, _cs: CriticalSection, cmp: u64) {
+            self.cmp.set(Some(cmp));
+        }
+
+        fn disarm(&self) {
+            self.cmp.set(None);
+        }
+    }
+
+    /// One entry per waker, like the embassy queue.
+    #[derive(Default)]
+    struct FakeQueue {
+        timers: Vec<(u64, Waker)>,
+        /// Deadline and time of each wake
+        fired: Vec<(u64, u64)>,
+    }
+
+    impl TimerQueue for FakeQueue {
+        fn schedule_wake(&mut self, at: u64, waker: &Waker) -> bool {
+            match self.timers.iter_mut().find(|(_, w)| w.will_wake(waker)) {
+                Some((t, _)) if at < *t => *t = at,
+                Some(_) => return false,
+                None => self.timers.push((at, waker.clone())),
+            }
+            true
+        }
+
+        fn next_expiration(&mut self, now: u64) -> u64 {
+            let (expired, pending) = self.timers.drain(..).partition::<Vec<_>, _>(|&(at, _)| at <= now);
+            self.timers = pending;
+            for (at, waker) in expired {
+                self.fired.push((at, now));
+                waker.wake();
+            }
+            self.timers.iter().map(|&(at, _)| at).min().unwrap_or(u64::MAX)
+        }
+    }
+
+    struct Task {
+        id: usize,
+        log: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Wake for Task {
+        fn wake(self: Arc<Self>) {
+            self.log.lock().unwrap().push(self.id);
+        }
+    }
+
+    fn task(id: usize, log: &Arc<Mutex<Vec<usize>>>) -> Waker {
+        Arc::new(Task { id, log: log.clone() }).into()
+    }
+
+    const TIMEBASE: Timebase = Timebase {
+        cnt: 0,
+        ticks: 0,
+        cnt_per_second: 6_000_000,
+    };
+
+    fn cs() -> CriticalSection<'static> {
+        unsafe { CriticalSection::new() }
+    }
+
+    /// Take the interrupt at each armed compare value, until no alarm is armed.
+    fn run(systick: &FakeSystick, queue: &mut FakeQueue) {
+        while let Some(cmp) = systick.cmp.take().filter(|&cmp| cmp != u64::MAX) {
+            systick.cnt.set(systick.cnt.get().max(cmp));
+            trigger_alarm(systick, cs(), TIMEBASE, queue);
+        }
+    }
+
+    /// Every wake happened at or after its deadline.
+    fn assert_not_early(queue: &FakeQueue) {
+        for &(at, now) in &queue.fired {
+            assert!(now >= at, "woken at {now} for {at}");
+        }
+    }
+
+    #[test]
+    fn overlapping_alarms_fire_in_order() {
+        let systick = FakeSystick::new(0);
+        let mut queue = FakeQueue::default();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        schedule_wake(&systick, cs(), TIMEBASE, &mut queue, 200, &task(0, &log));
+        assert_eq!(systick.cmp.get(), Some(TIMEBASE.ticks_to_cnt(200)));
+        // An earlier alarm moves the compare register, the later one stays queued
+        schedule_wake(&systick, cs(), TIMEBASE, &mut queue, 100, &task(1, &log));
+        assert_eq!(systick.cmp.get(), Some(TIMEBASE.ticks_to_cnt(100)));
+        schedule_wake(&systick, cs(), TIMEBASE, &mut queue, 150, &task(2, &log));
+        assert_eq!(systick.cmp.get(), Some(TIMEBASE.ticks_to_cnt(100)));
+
+        run(&systick, &mut queue);
+        assert_eq!(*log.lock().unwrap(), [1, 2, 0]);
+        assert_not_early(&queue);
+    }
+
+    #[test]
+    fn many_alarms() {
+        let systick = FakeSystick::new(0);
+        let mut queue = FakeQueue::default();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        // Deadlines 10 to 160 in scrambled order, two alarms per deadline
+        for i in 0..32 {
+            let at = (i * 7 % 16 + 1) as u64 * 10;
+            schedule_wake(&systick, cs(), TIMEBASE, &mut queue, at, &task(i, &log));
+        }
+        run(&systick, &mut queue);
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 32);
+        let deadlines: Vec<u64> = log.iter().map(|&i| (i * 7 % 16 + 1) as u64 * 10).collect();
+        assert!(deadlines.windows(2).all(|w| w[0] <= w[1]));
+        assert_not_early(&queue);
+    }
+
+    #[test]
+    fn rescheduled_waker_fires_once() {
+        let systick = FakeSystick::new(0);
+        let mut queue = FakeQueue::default();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let waker = task(0, &log);
+
+        schedule_wake(&systick, cs(), TIMEBASE, &mut queue, 300, &waker);
+        schedule_wake(&systick, cs(), TIMEBASE, &mut queue, 100, &waker);
+        assert_eq!(systick.cmp.get(), Some(TIMEBASE.ticks_to_cnt(100)));
+
+        run(&systick, &mut queue);
+        assert_eq!(*log.lock().unwrap(), [0]);
+        assert_eq!(queue.fired, [(100, 100)]);
+    }
+
+    /// Deadlines of 0 and 1 ticks from now, with the counter moving while they are armed.
+    #[test]
+    fn zero_and_one_tick_deadlines() {
+        let cnt_per_tick = TIMEBASE.ticks_to_cnt(1);
+        // From a still counter to one that moves a tick between two reads
+        for step in [0, 1, cnt_per_tick / 2, cnt_per_tick] {
+            let systick = FakeSystick::new(step);
+            let mut queue = FakeQueue::default();
+            let log = Arc::new(Mutex::new(Vec::new()));
+
+            for i in 0..5000 {
+                let now = TIMEBASE.cnt_to_ticks(systick.cnt.get());
+                let at = now + (i % 2) as u64;
+                schedule_wake(&systick, cs(), TIMEBASE, &mut queue, at, &task(i, &log));
+                if at == now {
+                    // Already expired: woken right away, not left to an interrupt
+                    assert_eq!(log.lock().unwrap().last(), Some(&i));
+                }
+                // Nothing due is left without an armed alarm
+                if let Some(&(at, _)) = queue.timers.iter().min_by_key(|(at, _)| *at) {
+                    assert!(systick.cmp.get().is_some_and(|cmp| cmp <= TIMEBASE.ticks_to_cnt(at)));
+                }
+            }
+            run(&systick, &mut queue);
+
+            let mut log = log.lock().unwrap().clone();
+            log.sort();
+            assert_eq!(log, (0..5000).collect::<Vec<_>>(), "step {step}");
+            assert_not_early(&queue);
+        }
+    }
+
+    /// The deadline passes between arming the compare register and checking the counter again.
+    #[test]
+    fn deadline_passes_while_arming() {
+        let systick = FakeSystick::new(1);
+        let mut queue = FakeQueue::default();
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        // Reads: `now` for the queue, before arming (one count early) and after arming (at it)
+        let at = 1;
+        systick.cnt.set(TIMEBASE.ticks_to_cnt(at) - 2);
+        schedule_wake(&systick, cs(), TIMEBASE, &mut queue, at, &task(0, &log));
+
+        assert_eq!(*log.lock().unwrap(), [0]);
+        assert_eq!(systick.cmp.get(), Some(u64::MAX));
+        assert_not_early(&queue);
+    }
+
+    #[test]
+    fn counter_extension() {
+        assert_eq!(extend_cnt(0, 0), 0);
+        assert_eq!(extend_cnt(0, 0x8000_0000), 0x8000_0000);
+        assert_eq!(extend_cnt(0xffff_fff0, 0xffff_ffff), 0xffff_ffff);
+        // Wrapped since the last read
+        assert_eq!(extend_cnt(0xffff_fff0, 0), 1 << 32);
+        assert_eq!(extend_cnt(0xffff_fff0, 5), (1 << 32) + 5);
+        let prev = (3 << 32) + 0x8000_0000;
+        assert_eq!(extend_cnt(prev, 0x7fff_ffff), (4 << 32) + 0x7fff_ffff);
+        // Counter not moved
+        assert_eq!(extend_cnt((7 << 32) + 42, 42), (7 << 32) + 42);
+    }
+}
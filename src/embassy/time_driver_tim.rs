@@ -1,5 +1,21 @@
 //! Time driver implementation for the TIM peripheral.
 //!
+//! Selected with one of the `time-driver-timX` features (or `time-driver-any`), which leaves
+//! SysTick untouched. The timer runs as a 16-bit counter at `TICK_HZ`, a software period
+//! counter extends it to 64 bits, CC1 marks the half-overflow point and CC2 is used for the alarm.
+//!
+//! Eligible timers, when present on the chip:
+//!
+//! - GPTM: TIM2, TIM3, TIM4, TIM5
+//! - ADTM: TIM1, TIM8, TIM9, TIM10
+//!
+//! CH32V0 only has TIM1 and TIM2, CH32V1/CH32V2/CH32L1 have TIM1 to TIM4, CH32V3 adds TIM5
+//! and TIM8 to TIM10 on the larger parts. CH32V0 (QingKe V2) has no SysTick time driver, so a
+//! timer driver is the only option there.
+//!
+//! BCTM timers (TIM6, TIM7) have no capture/compare channels and can not be used.
+//! `time-driver-any` picks the first available of TIM5, TIM4, TIM3, TIM2, TIM10, TIM9, TIM8, TIM1.
+//!
 //! Register renaming:
 //!
 //! - dmaintenr -> dier
@@ -134,6 +150,14 @@ fn calc_now(period: u32, counter: u16) -> u64 {
     ((period as u64) << 15) + ((counter as u32 ^ ((period & 1) << 15)) as u64)
 }
 
+fn calc_psc(timer_freq: u32) -> u16 {
+    let psc = timer_freq / TICK_HZ as u32 - 1;
+    match psc.try_into() {
+        Err(_) => panic!("psc division overflow: {}", psc),
+        Ok(n) => n,
+    }
+}
+
 struct AlarmState {
     timestamp: Cell<u64>,
 }
@@ -163,9 +187,10 @@ embassy_time_driver::time_driver_impl!(static DRIVER: RtcDriver = RtcDriver {
 });
 
 impl RtcDriver {
-    fn init(&'static self, cs: critical_section::CriticalSection) {
+    fn init(&'static self, cs: critical_section::CriticalSection, priority: crate::interrupt::Priority) {
         let r = regs_gp16();
 
+        // Counted like a driver that is never dropped, `rcc::disable_unused` leaves it running
         <T as SealedRccPeripheral>::enable_and_reset_with_cs(cs);
 
         let timer_freq = T::frequency();
@@ -173,13 +198,7 @@ impl RtcDriver {
         r.ctlr1().modify(|w| w.set_cen(false)); // Counter enable
         r.cnt().write_value(0);
 
-        let psc = timer_freq.0 / TICK_HZ as u32 - 1;
-        let psc: u16 = match psc.try_into() {
-            Err(_) => panic!("psc division overflow: {}", psc),
-            Ok(n) => n,
-        };
-
-        r.psc().write_value(psc); // prescaler
+        r.psc().write_value(calc_psc(timer_freq.0)); // prescaler
         r.atrlr().write_value(u16::MAX); // auto-reload register
 
         // Set URS, generate update and clear URS
@@ -196,10 +215,11 @@ impl RtcDriver {
             w.set_ccie(0, true); // Capture/Compare 1 interrupt enable
         });
 
+        <T as GeneralInstance16bit>::CaptureCompareInterrupt::set_priority_with_cs(cs, priority);
         <T as GeneralInstance16bit>::CaptureCompareInterrupt::unpend();
         unsafe { <T as GeneralInstance16bit>::CaptureCompareInterrupt::enable() };
 
-        r.ctlr1().modify(|w| w.set_cen(true));//Counter enable
+        r.ctlr1().modify(|w| w.set_cen(true)); // Counter enable
     }
 
     fn on_interrupt(&self) {
@@ -231,6 +251,54 @@ impl RtcDriver {
         })
     }
 
+    fn on_clock_change(&self, cs: CriticalSection) {
+        let r = regs_gp16();
+
+        r.ctlr1().modify(|w| w.set_cen(false)); // Counter enable
+        let t = self.now();
+
+        // PSC is preloaded, load it right away with an update event. With URS set it doesn't
+        // raise an interrupt. It clears the counter, which `restart_at` sets again.
+        r.psc().write_value(calc_psc(T::frequency().0)); // prescaler
+        r.ctlr1().modify(|w| w.set_urs(vals::Urs::COUNTERONLY)); // Update request source
+        r.swevgr().write(|w| w.set_ug(true)); // Update generation
+        r.ctlr1().modify(|w| w.set_urs(vals::Urs::ANYEVENT)); // Update request source
+
+        self.restart_at(cs, t);
+    }
+
+    /// Advance the timebase by `slept` ticks that passed while the timer was stopped.
+    #[cfg(feature = "low-power")]
+    fn on_wakeup(&self, cs: CriticalSection, slept: u64) {
+        let r = regs_gp16();
+
+        r.ctlr1().modify(|w| w.set_cen(false)); // Counter enable
+
+        self.restart_at(cs, self.now() + slept);
+    }
+
+    /// Restart the stopped counter at timestamp `t`, and reschedule the alarm.
+    fn restart_at(&self, cs: CriticalSection, mut t: u64) {
+        let r = regs_gp16();
+
+        // A counter of 0 or 0x8000 would raise a period interrupt for a period we already count.
+        if t & 0x7fff == 0 {
+            t += 1;
+        }
+        let period = (t >> 15) as u32;
+        self.period.store(period, Ordering::Relaxed);
+        compiler_fence(Ordering::Release);
+        r.cnt().write_value((t as u16 & 0x7fff) | ((period & 1) << 15) as u16);
+
+        // Flags raised before the stop belong to the old timebase, pending periods are counted
+        // in `t`. Counter is stopped, so clearing them all can't miss anything.
+        r.intfr().write_value(regs::Intfr(0));
+
+        r.ctlr1().modify(|w| w.set_cen(true)); // Counter enable
+
+        self.trigger_alarm(cs);
+    }
+
     fn next_period(&self) {
         let r = regs_gp16();
 
@@ -326,6 +394,15 @@ impl Driver for RtcDriver {
     }
 }
 
-pub(crate) fn init(cs: CriticalSection) {
-    DRIVER.init(cs)
+pub(crate) fn init(cs: CriticalSection, priority: crate::interrupt::Priority) {
+    DRIVER.init(cs, priority)
+}
+
+pub(crate) fn on_clock_change(cs: CriticalSection) {
+    DRIVER.on_clock_change(cs)
+}
+
+#[cfg(feature = "low-power")]
+pub(crate) fn on_wakeup(cs: CriticalSection, slept: u64) {
+    DRIVER.on_wakeup(cs, slept)
 }
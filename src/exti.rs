@@ -1,8 +1,21 @@
+//! External interrupts (EXTI) and async GPIO edge/level waiting.
+//!
+//! Each EXTI line `n` can be routed to pin `n` of exactly one GPIO port at a time (e.g. PA3 and PB3
+//! share EXTI3). [`ExtiInput::new`] takes the line's `EXTIn` singleton and selects the port in
+//! AFIO_EXTICR. It also panics if another `ExtiInput` already uses the line, which can only happen
+//! with stolen singletons.
+//!
+//! Several lines share an interrupt vector (EXTI9_5 and EXTI15_10 on CH32V1/V2/V3/L1, EXTI7_0
+//! on CH32V003, EXTI7_0/EXTI15_8/EXTI25_16 on CH32X035). The HAL defines all of these handlers,
+//! and each of them services every armed line that is pending.
+
+use core::cell::Cell;
 use core::future::Future;
 use core::marker::PhantomData;
 use core::pin::Pin;
 use core::task::{Context, Poll};
 
+use critical_section::Mutex;
 use embassy_sync::waitqueue::AtomicWaker;
 use qingke_rt::interrupt;
 
@@ -12,14 +25,21 @@ use crate::{impl_peripheral, into_ref, peripherals, Peripheral};
 const EXTI_COUNT: usize = 24;
 const NEW_AW: AtomicWaker = AtomicWaker::new();
 static EXTI_WAKERS: [AtomicWaker; EXTI_COUNT] = [NEW_AW; EXTI_COUNT];
+/// Lines used by an `ExtiInput`, bit `n` for line `n`
+static EXTI_USED: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+/// EXTI lines connected to GPIO pins, the lines above are internal (PVD, RTC alarm, USB wakeup).
+#[cfg(any(ch32x0, ch643))]
+const PIN_LINES_MASK: u32 = 0x00FF_FFFF;
+#[cfg(not(any(ch32x0, ch643)))]
+const PIN_LINES_MASK: u32 = 0x0000_FFFF;
 
 pub unsafe fn on_irq() {
     let exti = &crate::pac::EXTI;
 
-    let bits = exti.intfr().read();
-
-    // We don't handle or change any EXTI lines above 24.
-    let bits = bits.0 & 0x00FFFFFF;
+    // Only pin lines armed by an `ExtiInputFuture`. Pending bits of lines used for something
+    // else (events, or the RTC alarm on line 17) are left alone.
+    let bits = exti.intfr().read().0 & exti.intenr().read().0 & PIN_LINES_MASK;
 
     // Clear pending - Clears the EXTI's line pending bits.
     exti.intfr().write(|w| w.0 = bits);
@@ -48,7 +68,10 @@ impl Iterator for BitIter {
     }
 }
 
-/// EXTI input driver
+/// EXTI input driver.
+///
+/// Wraps an [`Input`] pin together with its EXTI line, and allows asynchronously waiting for
+/// edges or levels on it.
 pub struct ExtiInput<'d> {
     pin: Input<'d>,
 }
@@ -56,6 +79,9 @@ pub struct ExtiInput<'d> {
 impl<'d> Unpin for ExtiInput<'d> {}
 
 impl<'d> ExtiInput<'d> {
+    /// Create an EXTI input.
+    ///
+    /// `ch` must be the EXTI line of `pin`, i.e. `EXTIn` for pin `Pxn`.
     pub fn new<T: GpioPin>(
         pin: impl Peripheral<P = T> + 'd,
         ch: impl Peripheral<P = T::ExtiChannel> + 'd,
@@ -65,49 +91,189 @@ impl<'d> ExtiInput<'d> {
         // Needed if using AnyPin+AnyChannel.
         assert_eq!(pin.pin(), ch.number());
 
+        let line = pin.pin();
+        critical_section::with(|cs| {
+            let used = EXTI_USED.borrow(cs);
+            if used.get() & (1 << line) != 0 {
+                panic!("EXTI: line {} is already used by another pin", line);
+            }
+            used.set(used.get() | (1 << line));
+            select_port(line as usize, pin.port());
+        });
+
         Self {
             pin: Input::new(pin, pull),
         }
     }
 
+    fn line(&self) -> u8 {
+        self.pin.pin.pin.pin()
+    }
+
+    /// Get whether the pin is high.
     pub fn is_high(&self) -> bool {
         self.pin.is_high()
     }
 
+    /// Get whether the pin is low.
     pub fn is_low(&self) -> bool {
         self.pin.is_low()
     }
 
+    /// Get the pin level.
     pub fn get_level(&self) -> Level {
         self.pin.get_level()
     }
 
+    /// Asynchronously wait until the pin is high.
+    ///
+    /// This returns immediately if the pin is already high.
     pub async fn wait_for_high<'a>(&'a mut self) {
-        let fut = ExtiInputFuture::new(self.pin.pin.pin.pin(), self.pin.pin.pin.port(), true, false);
+        let fut = ExtiInputFuture::new(self.line(), true, false);
         if self.is_high() {
             return;
         }
         fut.await
     }
 
+    /// Asynchronously wait until the pin is low.
+    ///
+    /// This returns immediately if the pin is already low.
     pub async fn wait_for_low<'a>(&'a mut self) {
-        let fut = ExtiInputFuture::new(self.pin.pin.pin.pin(), self.pin.pin.pin.port(), false, true);
+        let fut = ExtiInputFuture::new(self.line(), false, true);
         if self.is_low() {
             return;
         }
         fut.await
     }
 
+    /// Asynchronously wait until the pin sees a rising edge.
     pub async fn wait_for_rising_edge<'a>(&'a mut self) {
-        ExtiInputFuture::new(self.pin.pin.pin.pin(), self.pin.pin.pin.port(), true, false).await
+        ExtiInputFuture::new(self.line(), true, false).await
     }
 
+    /// Asynchronously wait until the pin sees a falling edge.
     pub async fn wait_for_falling_edge<'a>(&'a mut self) {
-        ExtiInputFuture::new(self.pin.pin.pin.pin(), self.pin.pin.pin.port(), false, true).await
+        ExtiInputFuture::new(self.line(), false, true).await
     }
 
+    /// Asynchronously wait until the pin sees any edge (either rising or falling).
     pub async fn wait_for_any_edge<'a>(&'a mut self) {
-        ExtiInputFuture::new(self.pin.pin.pin.pin(), self.pin.pin.pin.port(), true, true).await
+        ExtiInputFuture::new(self.line(), true, true).await
+    }
+
+    /// Asynchronously wait until the pin has been at `level` for `debounce`, ignoring bounces.
+    ///
+    /// Each time the pin leaves `level` before `debounce` is over, the wait starts again. The
+    /// edge away from `level` is armed before the level is checked, so no change is missed.
+    #[cfg(feature = "embassy")]
+    pub async fn wait_for_stable(&mut self, level: Level, debounce: embassy_time::Duration) {
+        use embassy_futures::select::{select, Either};
+
+        // Only the edge leaving the level is needed
+        let (rising, falling) = match level {
+            Level::High => (false, true),
+            Level::Low => (true, false),
+        };
+
+        loop {
+            match level {
+                Level::High => self.wait_for_high().await,
+                Level::Low => self.wait_for_low().await,
+            }
+
+            let left = ExtiInputFuture::new(self.line(), rising, falling);
+            if self.get_level() != level {
+                continue;
+            }
+            if let Either::Second(()) = select(left, embassy_time::Timer::after(debounce)).await {
+                return;
+            }
+        }
+    }
+}
+
+impl<'d> Drop for ExtiInput<'d> {
+    fn drop(&mut self) {
+        let line = self.line();
+        critical_section::with(|cs| {
+            let used = EXTI_USED.borrow(cs);
+            used.set(used.get() & !(1 << line));
+        });
+    }
+}
+
+impl<'d> embedded_hal::digital::ErrorType for ExtiInput<'d> {
+    type Error = core::convert::Infallible;
+}
+
+impl<'d> embedded_hal::digital::InputPin for ExtiInput<'d> {
+    #[inline]
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok((*self).is_high())
+    }
+
+    #[inline]
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok((*self).is_low())
+    }
+}
+
+impl<'d> embedded_hal_async::digital::Wait for ExtiInput<'d> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_high().await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_low().await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_rising_edge().await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_falling_edge().await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_any_edge().await;
+        Ok(())
+    }
+}
+
+/// Route EXTI line `pin` to `port`, in AFIO_EXTICR.
+///
+/// EXTI0-EXTI23 Px0-Px23（x=A/B/C）
+fn select_port(pin: usize, port: u8) {
+    let afio = &crate::pac::AFIO;
+
+    #[cfg(afio_v0)]
+    {
+        // AFIO_EXTICR
+        // stride: 2, len: 15, 8 lines
+        afio.exticr().modify(|w| w.set_exti(pin, port));
+    }
+    // V1, V2, V3, L1
+    #[cfg(any(afio_v3, afio_l1))]
+    {
+        // AFIO_EXTICRx
+        // stride: 4, len: 4, 16 lines
+        afio.exticr(pin / 4).modify(|w| w.set_exti(pin % 4, port));
+    }
+    #[cfg(afio_x0)]
+    {
+        // stride: 2, len: 15, 24 lines
+        afio.exticr(pin / 16).modify(|w| w.set_exti(pin % 16, port));
+    }
+    #[cfg(afio_ch641)]
+    {
+        // single register
+        afio.exticr().modify(|w| w.set_exti(pin, port != 0));
     }
 }
 
@@ -117,45 +283,21 @@ struct ExtiInputFuture<'a> {
     phantom: PhantomData<&'a mut AnyPin>,
 }
 
-// EXTI0-EXTI23 Px0-Px23（x=A/B/C）
 impl<'a> ExtiInputFuture<'a> {
-    fn new(pin: u8, port: u8, rising: bool, falling: bool) -> Self {
+    fn new(pin: u8, rising: bool, falling: bool) -> Self {
         critical_section::with(|_| {
             let exti = &crate::pac::EXTI;
-            let afio = &crate::pac::AFIO;
-
-            let port = port as u8;
             let pin = pin as usize;
 
-            #[cfg(afio_v0)]
-            {
-                // AFIO_EXTICR
-                // stride: 2, len: 15, 8 lines
-                afio.exticr().modify(|w| w.set_exti(pin, port));
-            }
-            // V1, V2, V3, L1
-            #[cfg(any(afio_v3, afio_l1))]
-            {
-                // AFIO_EXTICRx
-                // stride: 4, len: 4, 16 lines
-                afio.exticr(pin / 4).modify(|w| w.set_exti(pin % 4, port));
-            }
-            #[cfg(afio_x0)]
-            {
-                // stride: 2, len: 15, 24 lines
-                afio.exticr(pin / 16).modify(|w| w.set_exti(pin % 16, port));
-            }
-            #[cfg(afio_ch641)]
-            {
-                // single register
-                afio.exticr().modify(|w| w.set_exti(pin, port != 0));
-            }
-
             // See-also: 7.4.3
-            exti.intenr().modify(|w| w.set_mr(pin, true)); // enable interrupt
-
             exti.rtenr().modify(|w| w.set_tr(pin, rising));
             exti.ftenr().modify(|w| w.set_tr(pin, falling));
+
+            // Clear a stale pending bit before arming, otherwise an edge from before this
+            // future was created would make it complete immediately.
+            exti.intfr().write(|w| w.0 = 1 << pin);
+
+            exti.intenr().modify(|w| w.set_mr(pin, true)); // enable interrupt
         });
 
         Self {
@@ -0,0 +1,424 @@
+//! Flash memory (FLASH)
+//!
+//! Erase and program the on-chip code flash, to keep data across power cycles. Erase and program
+//! use the fast page mode, a page is [`PAGE_SIZE`] bytes.
+//!
+//! [`Flash`] only writes inside a storage region given at creation, so the program can't be
+//! overwritten by mistake. The option bytes are outside the main flash and never written here.
+//! The region is best reserved in `memory.x`, for example the last 4K of a 64K part:
+//!
+//! ```text
+//! MEMORY
+//! {
+//!     FLASH : ORIGIN = 0x00000000, LENGTH = 60K
+//!     RAM : ORIGIN = 0x20000000, LENGTH = 20K
+//! }
+//! __storage_start = 60K;
+//! __storage_end = 64K;
+//! ```
+//!
+//! The symbol values are the offsets. Declare them as `extern "C" { static __storage_start: u8; }`
+//! and pass `&__storage_start as *const u8 as u32` (and the same for the end) to [`Flash::new`].
+//!
+//! ## Option bytes
+//!
+//! The user option bytes, data bytes and write protection are changed with
+//! [`Flash::program_option_bytes`], which keeps the read-out protection as it is. Read-out
+//! protection is only enabled by [`Flash::enable_read_protection`]. New option bytes are loaded
+//! at the next reset, see [`reload_option_bytes`].
+
+use core::ops::Range;
+
+use crate::pac::FLASH;
+use crate::{into_ref, peripherals, Peripheral, PeripheralRef};
+
+/// Address of the main flash, offsets are relative to it.
+pub const FLASH_BASE: u32 = 0x0800_0000;
+
+/// Size of a fast erase and program page, in bytes.
+#[cfg(any(ch32v0, ch641))]
+pub const PAGE_SIZE: usize = 64;
+/// Size of a fast erase and program page, in bytes.
+#[cfg(ch32v1)]
+pub const PAGE_SIZE: usize = 128;
+/// Size of a fast erase and program page, in bytes.
+#[cfg(not(any(ch32v0, ch641, ch32v1)))]
+pub const PAGE_SIZE: usize = 256;
+
+/// Words written to the page buffer per BUFLOAD.
+#[cfg(any(ch32v1, ch32v2, ch32v3))]
+const WORDS_PER_LOAD: usize = 4;
+#[cfg(not(any(ch32v1, ch32v2, ch32v3)))]
+const WORDS_PER_LOAD: usize = 1;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+/// Option bytes, each one is a halfword with the complement in the upper byte.
+const OB_BASE: u32 = 0x1FFF_F800;
+/// USER bits with a field in [`OptionBytes`], the others are kept as they are
+const USER_IWDG_SW: u8 = 1 << 0;
+const USER_STOP_RST: u8 = 1 << 1;
+const USER_STANDBY_RST: u8 = 1 << 2;
+
+/// Flash error.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlashError {
+    /// The unlock key sequence was rejected, flash stays locked until the next reset.
+    Locked,
+    /// Offset or length is not a multiple of [`PAGE_SIZE`].
+    Unaligned,
+    /// The access is outside the flash, or a write outside the storage region.
+    OutOfRange,
+    /// The page is write-protected (FLASH_WPR).
+    WriteProtected,
+}
+
+/// Read-out protection level.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReadProtection {
+    /// Flash can be read and written by the debug interface.
+    Disabled,
+    /// The debug interface can't access flash.
+    Enabled,
+}
+
+/// User option bytes.
+///
+/// The default value matches erased option bytes (all 0xFF): no hardware watchdog, no reset on
+/// Stop or Standby, data bytes 0xFF and no write protection. Read-out protection is separate,
+/// see [`Flash::enable_read_protection`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OptionBytes {
+    /// The independent watchdog is started by hardware at reset (USER.IWDG_SW cleared).
+    pub iwdg_hardware: bool,
+    /// Entering Stop mode resets the chip (USER.STOP_RST cleared).
+    pub reset_on_stop: bool,
+    /// Entering Standby mode resets the chip (USER.STANDY_RST cleared).
+    pub reset_on_standby: bool,
+    /// User data bytes, Data0 and Data1.
+    pub data: [u8; 2],
+    /// Write protection, a set bit protects the matching group of sectors (WRPR0 to WRPR3
+    /// cleared).
+    pub write_protection: u32,
+}
+
+impl Default for OptionBytes {
+    fn default() -> Self {
+        Self {
+            iwdg_hardware: false,
+            reset_on_stop: false,
+            reset_on_standby: false,
+            data: [0xFF; 2],
+            write_protection: 0,
+        }
+    }
+}
+
+/// Flash driver.
+pub struct Flash<'d> {
+    _peri: PeripheralRef<'d, peripherals::FLASH>,
+    storage: Range<u32>,
+}
+
+impl<'d> Flash<'d> {
+    /// Create a new flash driver, allowing erase and write in `storage`.
+    ///
+    /// `storage` is a range of offsets from [`FLASH_BASE`]. Panics if it is not page aligned or
+    /// doesn't fit in the flash.
+    pub fn new(flash: impl Peripheral<P = peripherals::FLASH> + 'd, storage: Range<u32>) -> Self {
+        into_ref!(flash);
+
+        assert!(
+            storage.start % PAGE_SIZE as u32 == 0 && storage.end % PAGE_SIZE as u32 == 0,
+            "FLASH: storage region is not page aligned"
+        );
+        assert!(
+            storage.start <= storage.end && storage.end <= size(),
+            "FLASH: storage region is outside the flash"
+        );
+
+        Self { _peri: flash, storage }
+    }
+
+    /// The storage region, as offsets from [`FLASH_BASE`].
+    pub fn storage(&self) -> Range<u32> {
+        self.storage.clone()
+    }
+
+    /// Read `buf.len()` bytes at `offset`.
+    ///
+    /// Any part of the flash can be read, not only the storage region.
+    pub fn read(&self, offset: u32, buf: &mut [u8]) -> Result<(), FlashError> {
+        let end = offset.checked_add(buf.len() as u32).ok_or(FlashError::OutOfRange)?;
+        if end > size() {
+            return Err(FlashError::OutOfRange);
+        }
+
+        let src = (FLASH_BASE + offset) as *const u8;
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = unsafe { src.add(i).read_volatile() };
+        }
+        Ok(())
+    }
+
+    /// Erase page number `page`, the page at offset `page * PAGE_SIZE`.
+    pub fn erase(&mut self, page: u32) -> Result<(), FlashError> {
+        let offset = page.checked_mul(PAGE_SIZE as u32).ok_or(FlashError::OutOfRange)?;
+        self.check_storage(offset, PAGE_SIZE)?;
+
+        with_unlocked(|| {
+            FLASH.ctlr().modify(|w| w.set_fter(true));
+            FLASH.addr().write_value(FLASH_BASE + offset);
+            FLASH.ctlr().modify(|w| w.set_strt(true));
+            let res = wait_ready();
+            FLASH.ctlr().modify(|w| w.set_fter(false));
+            res
+        })
+    }
+
+    /// Program whole pages at `offset`, the pages must be erased.
+    ///
+    /// `offset` and `data.len()` must be multiples of [`PAGE_SIZE`].
+    pub fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        if offset % PAGE_SIZE as u32 != 0 || data.len() % PAGE_SIZE != 0 {
+            return Err(FlashError::Unaligned);
+        }
+        self.check_storage(offset, data.len())?;
+
+        with_unlocked(|| {
+            for (i, page) in data.chunks_exact(PAGE_SIZE).enumerate() {
+                program_page(FLASH_BASE + offset + (i * PAGE_SIZE) as u32, page)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Write `data` at `offset`, erasing the pages it touches.
+    ///
+    /// No alignment is needed, the rest of partially written pages is kept.
+    pub fn blocking_write(&mut self, offset: u32, data: &[u8]) -> Result<(), FlashError> {
+        self.check_storage(offset, data.len())?;
+
+        let mut buf = [0u8; PAGE_SIZE];
+        let mut offset = offset;
+        let mut data = data;
+        while !data.is_empty() {
+            let page_start = offset - offset % PAGE_SIZE as u32;
+            let start = (offset - page_start) as usize;
+            let len = data.len().min(PAGE_SIZE - start);
+
+            self.read(page_start, &mut buf)?;
+            buf[start..start + len].copy_from_slice(&data[..len]);
+            self.erase(page_start / PAGE_SIZE as u32)?;
+            self.write(page_start, &buf)?;
+
+            offset += len as u32;
+            data = &data[len..];
+        }
+        Ok(())
+    }
+
+    /// Get the current read-out protection level, FLASH_OBR.RDPRT.
+    pub fn read_protection(&self) -> ReadProtection {
+        if FLASH.obr().read().rdprt() {
+            ReadProtection::Enabled
+        } else {
+            ReadProtection::Disabled
+        }
+    }
+
+    /// Get the option bytes as programmed.
+    ///
+    /// These are the values loaded at the next reset, they differ from the ones in use after
+    /// [`program_option_bytes`](Self::program_option_bytes).
+    pub fn option_bytes(&self) -> OptionBytes {
+        let user = ob_read(1);
+        OptionBytes {
+            iwdg_hardware: user & USER_IWDG_SW == 0,
+            reset_on_stop: user & USER_STOP_RST == 0,
+            reset_on_standby: user & USER_STANDBY_RST == 0,
+            data: [ob_read(2), ob_read(3)],
+            write_protection: !u32::from_le_bytes([ob_read(4), ob_read(5), ob_read(6), ob_read(7)]),
+        }
+    }
+
+    /// Get the write protection in use, a set bit for a protected group of sectors.
+    pub fn write_protection(&self) -> u32 {
+        !FLASH.wpr().read()
+    }
+
+    /// Erase and program the option bytes, keeping the current read-out protection.
+    ///
+    /// The new values apply after a reset, see [`reload_option_bytes`]. Losing power during the
+    /// update leaves erased option bytes, which enable read-out protection.
+    pub fn program_option_bytes(&mut self, option_bytes: &OptionBytes) -> Result<(), FlashError> {
+        program_option_bytes(ob_read(0), option_bytes)
+    }
+
+    /// **Dangerous:** enable read-out protection.
+    ///
+    /// After the next reset, the debug interface can no longer read flash. Debugging and flashing
+    /// need read-out protection to be disabled again first, which **erases the whole code flash**.
+    /// This is done over the debug interface, for example with `wlink unprotect`, or the
+    /// "unprotect" action of WCH-LinkUtility. Disabling it from firmware isn't supported here.
+    ///
+    /// The other option bytes are kept.
+    pub fn enable_read_protection(&mut self) -> Result<(), FlashError> {
+        let option_bytes = self.option_bytes();
+        program_option_bytes(0x00, &option_bytes)
+    }
+
+    fn check_storage(&self, offset: u32, len: usize) -> Result<(), FlashError> {
+        match offset.checked_add(len as u32) {
+            Some(end) if offset >= self.storage.start && end <= self.storage.end => Ok(()),
+            _ => Err(FlashError::OutOfRange),
+        }
+    }
+}
+
+/// Reset the chip, to load new option bytes.
+///
+/// Option bytes are only loaded by a reset.
+pub fn reload_option_bytes() -> ! {
+    // PFIC_CFGR, KEY3 and SYSRESET
+    const PFIC_CFGR: *mut u32 = 0xE000_E048 as *mut u32;
+    unsafe { PFIC_CFGR.write_volatile(0xBEEF_0000 | (1 << 7)) };
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Size of the main flash in bytes.
+fn size() -> u32 {
+    crate::signature::flash_size_kb() as u32 * 1024
+}
+
+/// Wait for the end of an operation, and check for a write protection error.
+fn wait_ready() -> Result<(), FlashError> {
+    while FLASH.statr().read().bsy() {}
+
+    let statr = FLASH.statr().read();
+    // Both flags are cleared by writing 1
+    FLASH.statr().write(|w| {
+        w.set_wrprterr(statr.wrprterr());
+        w.set_eop(statr.eop());
+    });
+    if statr.wrprterr() {
+        Err(FlashError::WriteProtected)
+    } else {
+        Ok(())
+    }
+}
+
+/// Unlock flash and fast mode around `f`, and lock both again after.
+fn with_unlocked<R>(f: impl FnOnce() -> Result<R, FlashError>) -> Result<R, FlashError> {
+    if FLASH.ctlr().read().lock() {
+        FLASH.keyr().write_value(KEY1);
+        FLASH.keyr().write_value(KEY2);
+    }
+    if FLASH.ctlr().read().flock() {
+        FLASH.modekeyr().write_value(KEY1);
+        FLASH.modekeyr().write_value(KEY2);
+    }
+    // A wrong key sequence locks flash until reset
+    let ctlr = FLASH.ctlr().read();
+    if ctlr.lock() || ctlr.flock() {
+        return Err(FlashError::Locked);
+    }
+
+    let res = f();
+    FLASH.ctlr().modify(|w| {
+        w.set_lock(true);
+        w.set_flock(true);
+    });
+    res
+}
+
+/// Read option byte `index`, without its complement.
+fn ob_read(index: u32) -> u8 {
+    unsafe { ((OB_BASE + 2 * index) as *const u16).read_volatile() as u8 }
+}
+
+fn program_option_bytes(rdpr: u8, option_bytes: &OptionBytes) -> Result<(), FlashError> {
+    let mut user = ob_read(1) | USER_IWDG_SW | USER_STOP_RST | USER_STANDBY_RST;
+    if option_bytes.iwdg_hardware {
+        user &= !USER_IWDG_SW;
+    }
+    if option_bytes.reset_on_stop {
+        user &= !USER_STOP_RST;
+    }
+    if option_bytes.reset_on_standby {
+        user &= !USER_STANDBY_RST;
+    }
+    let wrpr = (!option_bytes.write_protection).to_le_bytes();
+    let values = [
+        rdpr,
+        user,
+        option_bytes.data[0],
+        option_bytes.data[1],
+        wrpr[0],
+        wrpr[1],
+        wrpr[2],
+        wrpr[3],
+    ];
+
+    with_unlocked(|| {
+        FLASH.obkeyr().write_value(KEY1);
+        FLASH.obkeyr().write_value(KEY2);
+        if !FLASH.ctlr().read().obwre() {
+            return Err(FlashError::Locked);
+        }
+
+        // Erase all option bytes, then program them, the complements are written by hardware
+        FLASH.ctlr().modify(|w| w.set_ober(true));
+        FLASH.ctlr().modify(|w| w.set_strt(true));
+        let res = wait_ready();
+        FLASH.ctlr().modify(|w| w.set_ober(false));
+
+        let res = res.and_then(|_| {
+            FLASH.ctlr().modify(|w| w.set_obpg(true));
+            let res = values.iter().enumerate().try_for_each(|(i, &value)| {
+                unsafe { ((OB_BASE + 2 * i as u32) as *mut u16).write_volatile(value as u16) };
+                wait_ready()
+            });
+            FLASH.ctlr().modify(|w| w.set_obpg(false));
+            res
+        });
+        FLASH.ctlr().modify(|w| w.set_obwre(false));
+        res
+    })
+}
+
+/// Fast program one page at `addr` from `data`, through the page buffer.
+fn program_page(addr: u32, data: &[u8]) -> Result<(), FlashError> {
+    FLASH.ctlr().modify(|w| w.set_ftpg(true));
+    FLASH.ctlr().modify(|w| w.set_bufrst(true));
+    let res = wait_ready();
+    FLASH.ctlr().modify(|w| w.set_ftpg(false));
+    res?;
+
+    for (i, load) in data.chunks_exact(4 * WORDS_PER_LOAD).enumerate() {
+        let load_addr = addr + (i * 4 * WORDS_PER_LOAD) as u32;
+
+        FLASH.ctlr().modify(|w| w.set_ftpg(true));
+        for (j, word) in load.chunks_exact(4).enumerate() {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            unsafe { ((load_addr + 4 * j as u32) as *mut u32).write_volatile(word) };
+        }
+        FLASH.ctlr().modify(|w| w.set_bufload(true));
+        let res = wait_ready();
+        FLASH.ctlr().modify(|w| w.set_ftpg(false));
+        res?;
+    }
+
+    FLASH.ctlr().modify(|w| w.set_ftpg(true));
+    FLASH.addr().write_value(addr);
+    FLASH.ctlr().modify(|w| w.set_strt(true));
+    let res = wait_ready();
+    FLASH.ctlr().modify(|w| w.set_ftpg(false));
+    res
+}
@@ -10,8 +10,17 @@
 //! - Alternate Function (input or output)
 //!
 //! Power On: Floating Input except for some Alternate Function
+//!
+//! The drivers ([`Flex`], [`Input`], [`Output`], [`OutputOpenDrain`]) hold a type-erased
+//! [`AnyPin`], so pins of different ports can be kept together, e.g. in a `[Output<'static>; 8]`.
+//! Typed pins are converted with [`Pin::degrade`] or `into()`.
+//!
+//! `AnyPin` is a single byte, port * 32 + pin. Each operation computes the port register block
+//! from it, a shift and an add, instead of using a constant address. The drivers are not generic
+//! over the pin type, so their code exists only once in flash, whatever the number of pins used.
 
 use core::convert::Infallible;
+use core::marker::PhantomData;
 
 use critical_section::CriticalSection;
 use pac::gpio::vals;
@@ -19,6 +28,11 @@ use pac::gpio::vals;
 use crate::{exti, impl_peripheral, into_ref, pac, peripherals, Peripheral, PeripheralRef};
 
 /// Speed, for output mode
+///
+/// This is the maximum output frequency the driver is sized for, set in the MODE bits of
+/// CFGLR/CFGHR. Slower settings have a gentler slew rate and less EMI. The encoding is the same
+/// on all families, but on CH32V003 `High` is 30 MHz, and CH32X035 has a single output speed,
+/// so all values give the same one there.
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Speed {
@@ -26,7 +40,7 @@ pub enum Speed {
     Medium = 0b01,
     /// 2MHz
     Low = 0b10,
-    /// 50MHz
+    /// 50MHz (30MHz on CH32V003)
     #[default]
     High = 0b11,
 }
@@ -52,6 +66,10 @@ impl From<Speed> for vals::Mode {
 }
 
 /// Pull setting for an input.
+///
+/// Any input mode enables the digital input path of the pad, analog mode is only used by the ADC,
+/// DAC and OPA drivers, which switch their pins to it. This is the case on CH32X035 too, whose
+/// pins come out of reset as floating inputs like the other families.
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Pull {
@@ -136,11 +154,15 @@ impl<'d> Flex<'d> {
     }
 
     /// Put the pin into input mode.
+    ///
+    /// The pull direction is the output level bit, so with [`Pull::Up`] or [`Pull::Down`] the
+    /// remembered output level changes.
     #[inline]
     pub fn set_as_input(&mut self, pull: Pull) {
         critical_section::with(|_| {
-            self.pin.set_mode_cnf(vals::Mode::INPUT, pull.into());
+            // Pull direction first, so that a pin driven low doesn't briefly pull down
             self.pin.set_pull(pull);
+            self.pin.set_mode_cnf(vals::Mode::INPUT, pull.into());
         });
     }
 
@@ -155,6 +177,27 @@ impl<'d> Flex<'d> {
         });
     }
 
+    /// Put the pin into output mode, driving `level` from the start.
+    ///
+    /// The level is set before the mode, so the pin doesn't glitch to the previous level.
+    #[inline]
+    pub fn set_as_output_with_level(&mut self, speed: Speed, level: Level) {
+        critical_section::with(|_| {
+            self.set_level(level);
+            self.pin.set_as_output(speed);
+        });
+    }
+
+    /// Put the pin into bidirectional mode, an open drain output that can also be read.
+    ///
+    /// The input reads the actual pin level, so a high output can be pulled low by another
+    /// device, as on a 1-Wire bus. Same as
+    /// [`set_as_output_open_drain`](Self::set_as_output_open_drain).
+    #[inline]
+    pub fn set_as_input_output(&mut self, speed: Speed) {
+        self.set_as_output_open_drain(speed);
+    }
+
     /// Put the pin into output mode with open drain.
     ///
     /// Pull resistor is disabled in this mode.
@@ -174,6 +217,14 @@ impl<'d> Flex<'d> {
         });
     }
 
+    /// Change the speed (slew rate) of the pin, if it is in output mode.
+    ///
+    /// In input mode, this does nothing. The speed is given again when switching to output.
+    #[inline]
+    pub fn set_speed(&mut self, speed: Speed) {
+        critical_section::with(|_| self.pin.set_speed(speed));
+    }
+
     #[inline]
     pub fn is_high(&self) -> bool {
         self.pin.block().indr().read().idr(self.pin.pin() as usize)
@@ -250,6 +301,15 @@ impl<'d> Input<'d> {
         Self { pin }
     }
 
+    /// Change the pull resistor.
+    ///
+    /// The pull direction is selected by the output register bit, and the pull is enabled by the
+    /// CNF bits. Both are rewritten, so this works from any previous pull.
+    #[inline]
+    pub fn set_pull(&mut self, pull: Pull) {
+        self.pin.set_as_input(pull);
+    }
+
     /// Get whether the pin input level is high.
     #[inline]
     pub fn is_high(&self) -> bool {
@@ -291,6 +351,12 @@ impl<'d> Output<'d> {
         Self { pin }
     }
 
+    /// Change the output speed.
+    #[inline]
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.pin.set_speed(speed);
+    }
+
     /// Set the output as high.
     #[inline]
     pub fn set_high(&mut self) {
@@ -336,29 +402,45 @@ impl<'d> Output<'d> {
 
 /// GPIO output open-drain driver.
 ///
+/// The CH32 pin configuration has no pull resistors in output mode. With [`Pull::Up`], the pin is
+/// instead switched between open-drain output driving low and input with pull-up (OUTDR = 1) for
+/// high, which behaves like an open-drain output with an internal pull-up.
+///
 /// Note that pins will **return to their floating state** when `OutputOpenDrain` is dropped.
 /// If pins should retain their state indefinitely, either keep ownership of the
 /// `OutputOpenDrain`, or pass it to [`core::mem::forget`].
 pub struct OutputOpenDrain<'d> {
     pub(crate) pin: Flex<'d>,
+    speed: Speed,
+    pull_up: bool,
 }
 
 impl<'d> OutputOpenDrain<'d> {
     /// Create a new GPIO open drain output driver for a [Pin] with the provided [Level] and [Speed], [Pull] configuration.
-    #[inline]
-    pub fn new(pin: impl Peripheral<P = impl Pin> + 'd, initial_output: Level, speed: Speed) -> Self {
-        let mut pin = Flex::new(pin);
-
-        match initial_output {
-            Level::High => pin.set_high(),
-            Level::Low => pin.set_low(),
+    ///
+    /// Only [`Pull::None`] and [`Pull::Up`] are supported, a pull-down would hold a released line low.
+    #[inline]
+    pub fn new(pin: impl Peripheral<P = impl Pin> + 'd, initial_output: Level, speed: Speed, pull: Pull) -> Self {
+        assert!(pull != Pull::Down, "GPIO: open-drain output can't have a pull-down");
+
+        let mut this = Self {
+            pin: Flex::new(pin),
+            speed,
+            pull_up: pull == Pull::Up,
+        };
+        if this.pull_up {
+            this.set_level(initial_output);
+        } else {
+            this.pin.set_level(initial_output);
+            this.pin.set_as_output_open_drain(speed);
         }
-
-        pin.set_as_output_open_drain(speed);
-        Self { pin }
+        this
     }
 
     /// Get whether the pin input level is high.
+    ///
+    /// This is the level on the pin, it is low if another device pulls the line low while the
+    /// output is set high.
     #[inline]
     pub fn is_high(&self) -> bool {
         !self.pin.is_low()
@@ -376,25 +458,38 @@ impl<'d> OutputOpenDrain<'d> {
         self.pin.get_level()
     }
 
-    /// Set the output as high.
+    /// Set the output as high (released).
     #[inline]
     pub fn set_high(&mut self) {
-        self.pin.set_high();
+        if self.pull_up {
+            // OUTDR = 1 selects the pull-up in input mode
+            self.pin.set_as_input(Pull::Up);
+        } else {
+            self.pin.set_high();
+        }
     }
 
     /// Set the output as low.
     #[inline]
     pub fn set_low(&mut self) {
         self.pin.set_low();
+        if self.pull_up {
+            self.pin.set_as_output_open_drain(self.speed);
+        }
     }
 
     /// Set the output level.
     #[inline]
     pub fn set_level(&mut self, level: Level) {
-        self.pin.set_level(level);
+        match level {
+            Level::High => self.set_high(),
+            Level::Low => self.set_low(),
+        }
     }
 
     /// Get whether the output level is set to high.
+    ///
+    /// This is the output latch, see [`is_high`](Self::is_high) for the level on the pin.
     #[inline]
     pub fn is_set_high(&self) -> bool {
         self.pin.is_set_high()
@@ -415,7 +510,196 @@ impl<'d> OutputOpenDrain<'d> {
     /// Toggle pin output
     #[inline]
     pub fn toggle(&mut self) {
-        self.pin.toggle()
+        if self.is_set_low() {
+            self.set_high()
+        } else {
+            self.set_low()
+        }
+    }
+
+    /// Change the output speed.
+    #[inline]
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        // While released with the pull-up the pin is an input, the speed applies on the next set_low
+        self.pin.set_speed(speed);
+    }
+}
+
+/// Pins of one GPIO port, used as a group by [`OutputGroup`] and [`InputGroup`].
+///
+/// Implemented for tuples of 1 to 16 pins. A tuple with pins of different ports, or with a pin
+/// above 15, fails to build.
+#[allow(private_bounds)]
+pub trait PinGroup<'d>: SealedPinGroup<'d> {}
+
+pub(crate) trait SealedPinGroup<'d> {
+    /// Port number of all pins
+    const PORT: u8;
+    /// Bit `n` set for pin `n`
+    const MASK: u16;
+
+    fn for_each_pin(self, f: impl FnMut(AnyPin));
+}
+
+/// Pin with a port and pin number known at compile time.
+pub trait PortPin: Pin {
+    /// Port, 0 for GPIOA.
+    const PORT: u8;
+    /// Pin number within the port.
+    const PIN: u8;
+}
+
+macro_rules! impl_pin_group {
+    ($(($T:ident, $P:ident, $p:ident)),+) => {
+        impl<'d, $($T: Peripheral<P = $P> + 'd, $P: PortPin,)+> SealedPinGroup<'d> for ($($T,)+) {
+            const PORT: u8 = [$($P::PORT),+][0];
+            const MASK: u16 = {
+                let ports = [$($P::PORT),+];
+                let pins = [$($P::PIN),+];
+                let mut mask = 0;
+                let mut i = 0;
+                while i < pins.len() {
+                    assert!(ports[i] == ports[0], "GPIO: pins of a group must be on the same port");
+                    assert!(pins[i] < 16, "GPIO: pins of a group must be pin 0 to 15");
+                    mask |= 1 << pins[i];
+                    i += 1;
+                }
+                mask
+            };
+
+            fn for_each_pin(self, mut f: impl FnMut(AnyPin)) {
+                let ($($p,)+) = self;
+                $(
+                    f(unsafe { AnyPin::steal($p.into_ref().pin_port()) });
+                )+
+            }
+        }
+
+        impl<'d, $($T: Peripheral<P = $P> + 'd, $P: PortPin,)+> PinGroup<'d> for ($($T,)+) {}
+    };
+}
+
+/// Implement for all tuples made of a tail of the list.
+macro_rules! impl_pin_groups {
+    ($first:tt $(, $rest:tt)*) => {
+        impl_pin_group!($first $(, $rest)*);
+        impl_pin_groups!($($rest),*);
+    };
+    () => {};
+}
+
+impl_pin_groups!(
+    (T0, P0, p0),
+    (T1, P1, p1),
+    (T2, P2, p2),
+    (T3, P3, p3),
+    (T4, P4, p4),
+    (T5, P5, p5),
+    (T6, P6, p6),
+    (T7, P7, p7),
+    (T8, P8, p8),
+    (T9, P9, p9),
+    (T10, P10, p10),
+    (T11, P11, p11),
+    (T12, P12, p12),
+    (T13, P13, p13),
+    (T14, P14, p14),
+    (T15, P15, p15)
+);
+
+/// Group of push-pull outputs on one port, written with a single register store.
+///
+/// Values and masks have bit `n` for pin `n` of the port, bits of pins outside the group are
+/// ignored.
+pub struct OutputGroup<'d> {
+    port: u8,
+    mask: u16,
+    _phantom: PhantomData<&'d mut AnyPin>,
+}
+
+impl<'d> OutputGroup<'d> {
+    /// Create an output group from a tuple of pins on the same port, driving `initial_output`.
+    pub fn new<G: PinGroup<'d>>(pins: G, initial_output: u16, speed: Speed) -> Self {
+        let mut this = Self {
+            port: G::PORT,
+            mask: G::MASK,
+            _phantom: PhantomData,
+        };
+        // Level first, so that the pins don't glitch
+        this.write(initial_output, u16::MAX);
+        critical_section::with(|_| pins.for_each_pin(|pin| pin.set_as_output(speed)));
+        this
+    }
+
+    /// Pins of the group, bit `n` set for pin `n`.
+    #[inline]
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    /// Set the pins in `mask` to the level of their bit in `value`, all at the same time.
+    ///
+    /// This is one write of OUTDR set and reset bits (BSHR), so it doesn't affect other pins of the
+    /// port and needs no critical section.
+    #[inline]
+    pub fn write(&mut self, value: u16, mask: u16) {
+        let mask = (mask & self.mask) as u32;
+        let value = value as u32;
+        let bits = (value & mask) | ((!value & mask) << 16);
+        pac::GPIO(self.port as _).bshr().write(|w| w.0 = bits);
+    }
+
+    /// Drive the pins in `set` high and the pins in `clear` low, all at the same time.
+    ///
+    /// Like [`write`](Self::write), this is a single BSHR write. A pin in both masks goes high.
+    #[inline]
+    pub fn set_and_clear(&mut self, set: u16, clear: u16) {
+        let bits = (set & self.mask) as u32 | ((clear & self.mask) as u32) << 16;
+        pac::GPIO(self.port as _).bshr().write(|w| w.0 = bits);
+    }
+
+    /// Get the output levels of the group, from the output register.
+    #[inline]
+    pub fn output(&self) -> u16 {
+        pac::GPIO(self.port as _).outdr().read().0 as u16 & self.mask
+    }
+
+    /// Get the levels on the pins of the group.
+    #[inline]
+    pub fn read(&self) -> u16 {
+        pac::GPIO(self.port as _).indr().read().0 as u16 & self.mask
+    }
+}
+
+/// Group of inputs on one port, read with a single register load.
+pub struct InputGroup<'d> {
+    port: u8,
+    mask: u16,
+    _phantom: PhantomData<&'d mut AnyPin>,
+}
+
+impl<'d> InputGroup<'d> {
+    /// Create an input group from a tuple of pins on the same port.
+    pub fn new<G: PinGroup<'d>>(pins: G, pull: Pull) -> Self {
+        critical_section::with(|_| pins.for_each_pin(|pin| pin.set_as_input(pull)));
+        Self {
+            port: G::PORT,
+            mask: G::MASK,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Pins of the group, bit `n` set for pin `n`.
+    #[inline]
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    /// Get the levels on the pins of the group, bit `n` for pin `n`.
+    #[inline]
+    pub fn read(&self) -> u16 {
+        pac::GPIO(self.port as _).indr().read().0 as u16 & self.mask
     }
 }
 
@@ -540,6 +824,35 @@ pub(crate) trait SealedPin {
         }
     }
 
+    /// Change the MODE bits of an output, keep the CNF bits. Does nothing in input mode.
+    #[inline]
+    fn set_speed(&self, speed: Speed) {
+        let pin = self._pin() as usize;
+        let block = self.block();
+        let mode: vals::Mode = speed.into();
+
+        match pin / 8 {
+            0 => block.cfglr().modify(|w| {
+                if w.mode(pin % 8) != vals::Mode::INPUT {
+                    w.set_mode(pin % 8, mode);
+                }
+            }),
+            #[cfg(not(gpio_v0))]
+            1 => block.cfghr().modify(|w| {
+                if w.mode(pin % 8) != vals::Mode::INPUT {
+                    w.set_mode(pin % 8, mode);
+                }
+            }),
+            #[cfg(gpio_x0)]
+            2 => block.cfgxr().modify(|w| {
+                if w.mode(pin % 8) != vals::Mode::INPUT {
+                    w.set_mode(pin % 8, mode);
+                }
+            }),
+            _ => unreachable!(),
+        }
+    }
+
     #[inline]
     fn set_as_output(&self, speed: Speed) {
         self.set_mode_cnf(speed.into(), vals::Cnf::ANALOG_IN__PUSH_PULL_OUT);
@@ -547,8 +860,8 @@ pub(crate) trait SealedPin {
 
     #[inline]
     fn set_as_input(&self, pull: Pull) {
-        self.set_mode_cnf(vals::Mode::INPUT, pull.into());
         self.set_pull(pull);
+        self.set_mode_cnf(vals::Mode::INPUT, pull.into());
     }
 
     #[inline]
@@ -653,6 +966,11 @@ foreach_pin!(
             }
         }
 
+        impl PortPin for peripherals::$pin_name {
+            const PORT: u8 = $port_num;
+            const PIN: u8 = $pin_num;
+        }
+
         impl From<peripherals::$pin_name> for AnyPin {
             fn from(x: peripherals::$pin_name) -> Self {
                 x.degrade()
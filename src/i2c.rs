@@ -55,13 +55,18 @@ pub unsafe fn on_interrupt<T: Instance>() {
 /// I2C error.
 #[derive(Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// Bus error. (BERR)
     Bus,
     /// Arbitration lost (ARLO)
     Arbitration,
-    /// ACK not received (either to the address or to a data byte) (AF)
+    /// ACK not received for a data byte (AF)
     Nack,
+    /// ACK not received for the address, no slave answered (AF)
+    ///
+    /// Reported as [`Error::Nack`] before this variant was added.
+    AddressNack,
     /// Timeout
     Timeout,
     /// CRC error
@@ -72,6 +77,38 @@ pub enum Error {
     ZeroLengthTransfer,
 }
 
+impl Error {
+    /// A NACK while sending the address means no slave answered it.
+    fn in_address_phase(self) -> Self {
+        match self {
+            Self::Nack => Self::AddressNack,
+            e => e,
+        }
+    }
+}
+
+/// I2C slave address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Address {
+    /// 7-bit address.
+    SevenBit(u8),
+    /// 10-bit address.
+    TenBit(u16),
+}
+
+impl From<u8> for Address {
+    fn from(addr: u8) -> Self {
+        Address::SevenBit(addr)
+    }
+}
+
+/// 10-bit addressing header byte: `0b11110xx` with the two address MSBs, plus the R/W bit.
+#[inline]
+fn ten_bit_header(addr: u16, read: bool) -> u8 {
+    0xF0 | ((addr >> 7) as u8 & 0x06) | read as u8
+}
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Duty {
     Duty2_1 = 0,
@@ -82,7 +119,9 @@ pub enum Duty {
 #[non_exhaustive]
 #[derive(Copy, Clone)]
 pub struct Config {
-    /// Timeout.
+    /// Timeout for a whole transfer, blocking or async.
+    ///
+    /// Without the `embassy` feature there is no time source, and transfers wait forever.
     #[cfg(feature = "embassy")]
     pub timeout: embassy_time::Duration,
     pub duty: Duty,
@@ -300,58 +339,89 @@ impl<'d, T: Instance, M: Mode> I2c<'d, T, M> {
             return Err(Error::Arbitration);
         }
 
-        // The errata indicates that BERR may be incorrectly detected. It recommends ignoring and
-        // clearing the BERR bit instead.
+        // Misplaced START/STOP condition, e.g. caused by noise or another master.
         if star1.berr() {
             T::regs().star1().modify(|w| w.set_berr(false));
+            return Err(Error::Bus);
         }
 
         Ok(star1)
     }
 
-    fn write_bytes(&mut self, addr: u8, bytes: &[u8], timeout: Timeout, frame: FrameOptions) -> Result<(), Error> {
-        if frame.send_start() {
-            // Send a START condition
+    fn blocking_start(&self, read: bool, timeout: Timeout) -> Result<(), Error> {
+        // Send a START condition, and set ACK bit for reads
+        T::regs().ctlr1().modify(|reg| {
+            reg.set_start(true);
+            if read {
+                reg.set_ack(true);
+            }
+        });
 
-            T::regs().ctlr1().modify(|reg| {
-                reg.set_start(true);
-            });
+        // Wait until START condition was generated
+        while !Self::check_and_clear_error_flags()?.sb() {
+            timeout.check()?;
+        }
 
-            // Wait until START condition was generated
-            while !Self::check_and_clear_error_flags()?.sb() {
-                timeout.check()?;
-            }
+        // Check if we were the ones to generate START
+        if T::regs().ctlr1().read().start() || !T::regs().star2().read().msl() {
+            return Err(Error::Arbitration);
+        }
 
-            // Check if we were the ones to generate START
-            if T::regs().ctlr1().read().start() || !T::regs().star2().read().msl() {
-                return Err(Error::Arbitration);
-            }
+        Ok(())
+    }
 
-            // Set up current address we're trying to talk to
-            T::regs().datar().write(|reg| reg.set_datar(addr << 1));
+    /// Generate START and send the address, until ADDR is set.
+    ///
+    /// The ADDR flag is left set, callers clear it by reading STAR2.
+    ///
+    /// A 10-bit read sends the full address in write direction, then a repeated START with the
+    /// header in read direction. If `restart` is set, the slave was already addressed in this
+    /// transaction and only the read header is sent.
+    fn blocking_address(&self, address: Address, read: bool, restart: bool, timeout: Timeout) -> Result<(), Error> {
+        let regs = T::regs();
 
-            // Wait until address was sent
-            // Wait for the address to be acknowledged
-            // Check for any I2C errors. If a NACK occurs, the ADDR bit will never be set.
-            while !Self::check_and_clear_error_flags()?.addr() {
-                timeout.check()?;
-            }
+        self.blocking_start(read, timeout)?;
 
-            // Clear condition by reading SR2
-            let _ = T::regs().star2().read();
-        }
+        match address {
+            Address::SevenBit(addr) => regs.datar().write(|reg| reg.set_datar((addr << 1) | read as u8)),
+            Address::TenBit(addr) if read && restart => {
+                regs.datar().write(|reg| reg.set_datar(ten_bit_header(addr, true)))
+            }
+            Address::TenBit(addr) => {
+                regs.datar().write(|reg| reg.set_datar(ten_bit_header(addr, false)));
+                while !Self::check_and_clear_error_flags()
+                    .map_err(Error::in_address_phase)?
+                    .add10()
+                {
+                    timeout.check()?;
+                }
+                regs.datar().write(|reg| reg.set_datar(addr as u8));
+
+                if read {
+                    while !Self::check_and_clear_error_flags()
+                        .map_err(Error::in_address_phase)?
+                        .addr()
+                    {
+                        timeout.check()?;
+                    }
+                    let _ = regs.star2().read();
 
-        // Send bytes
-        for c in bytes {
-            self.send_byte(*c, timeout)?;
+                    self.blocking_start(read, timeout)?;
+                    regs.datar().write(|reg| reg.set_datar(ten_bit_header(addr, true)));
+                }
+            }
         }
 
-        if frame.send_stop() {
-            // Send a STOP condition
-            T::regs().ctlr1().modify(|reg| reg.set_stop(true));
+        // Wait until address was sent
+        // Wait for the address to be acknowledged
+        // Check for any I2C errors. If a NACK occurs, the ADDR bit will never be set.
+        while !Self::check_and_clear_error_flags()
+            .map_err(Error::in_address_phase)?
+            .addr()
+        {
+            timeout.check()?;
         }
 
-        // Fallthrough is success
         Ok(())
     }
 
@@ -394,94 +464,45 @@ impl<'d, T: Instance, M: Mode> I2c<'d, T, M> {
         Ok(value)
     }
 
-    fn blocking_read_timeout(
-        &mut self,
-        addr: u8,
-        buffer: &mut [u8],
-        timeout: Timeout,
-        frame: FrameOptions,
-    ) -> Result<(), Error> {
-        let Some((last, buffer)) = buffer.split_last_mut() else {
-            return Err(Error::Overrun);
-        };
-
-        if frame.send_start() {
-            // Send a START condition and set ACK bit
-            T::regs().ctlr1().modify(|reg| {
-                reg.set_start(true);
-                reg.set_ack(true);
-            });
-
-            // Wait until START condition was generated
-            while !Self::check_and_clear_error_flags()?.sb() {
-                timeout.check()?;
-            }
-
-            // Check if we were the ones to generate START
-            if T::regs().ctlr1().read().start() || !T::regs().star2().read().msl() {
-                return Err(Error::Arbitration);
-            }
-
-            // Set up current address we're trying to talk to
-            T::regs().datar().write(|reg| reg.set_datar((addr << 1) + 1));
-
-            // Wait until address was sent
-            // Wait for the address to be acknowledged
-            while !Self::check_and_clear_error_flags()?.addr() {
-                timeout.check()?;
-            }
-
-            // Clear condition by reading SR2
-            let _ = T::regs().star2().read();
-        }
-
-        // Receive bytes into buffer
-        for c in buffer {
-            *c = self.recv_byte(timeout)?;
+    fn blocking_bus(&self, addr: Address, timeout: Timeout) -> RegisterBus<'_, 'd, T, M> {
+        RegisterBus {
+            i2c: self,
+            addr,
+            timeout,
         }
-
-        // Prepare to send NACK then STOP after next byte
-        T::regs().ctlr1().modify(|reg| {
-            if frame.send_nack() {
-                reg.set_ack(false);
-            }
-            if frame.send_stop() {
-                reg.set_stop(true);
-            }
-        });
-
-        // Receive last byte
-        *last = self.recv_byte(timeout)?;
-
-        // Fallthrough is success
-        Ok(())
     }
 
     /// Blocking read.
-    pub fn blocking_read(&mut self, addr: u8, read: &mut [u8]) -> Result<(), Error> {
-        self.blocking_read_timeout(addr, read, self.timeout(), FrameOptions::FirstAndLastFrame)
+    pub fn blocking_read(&mut self, addr: impl Into<Address>, read: &mut [u8]) -> Result<(), Error> {
+        let mut bus = self.blocking_bus(addr.into(), self.timeout());
+        blocking_read_frame(&mut bus, read, FrameOptions::FirstAndLastFrame, false)
     }
 
     /// Blocking write.
-    pub fn blocking_write(&mut self, addr: u8, write: &[u8]) -> Result<(), Error> {
-        self.write_bytes(addr, write, self.timeout(), FrameOptions::FirstAndLastFrame)?;
+    pub fn blocking_write(&mut self, addr: impl Into<Address>, write: &[u8]) -> Result<(), Error> {
+        let mut bus = self.blocking_bus(addr.into(), self.timeout());
+        blocking_write_frame(&mut bus, write, FrameOptions::FirstAndLastFrame)?;
 
         // Fallthrough is success
         Ok(())
     }
 
     /// Blocking write, restart, read.
-    pub fn blocking_write_read(&mut self, addr: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+    pub fn blocking_write_read(
+        &mut self,
+        addr: impl Into<Address>,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Error> {
         // Check empty read buffer before starting transaction. Otherwise, we would not generate the
         // stop condition below.
         if read.is_empty() {
-            return Err(Error::Overrun);
+            return Err(Error::ZeroLengthTransfer);
         }
 
-        let timeout = self.timeout();
-
-        self.write_bytes(addr, write, timeout, FrameOptions::FirstFrame)?;
-        self.blocking_read_timeout(addr, read, timeout, FrameOptions::FirstAndLastFrame)?;
+        let mut bus = self.blocking_bus(addr.into(), self.timeout());
+        blocking_write_frame(&mut bus, write, FrameOptions::FirstFrame)?;
+        blocking_read_frame(&mut bus, read, FrameOptions::FirstAndLastFrame, true)?;
 
         Ok(())
     }
@@ -491,17 +512,13 @@ impl<'d, T: Instance, M: Mode> I2c<'d, T, M> {
     /// Consecutive operations of same type are merged. See [transaction contract] for details.
     ///
     /// [transaction contract]: embedded_hal_1::i2c::I2c::transaction
-    pub fn blocking_transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
-        let timeout = self.timeout();
-
-        for (op, frame) in operation_frames(operations)? {
-            match op {
-                Operation::Read(read) => self.blocking_read_timeout(addr, read, timeout, frame)?,
-                Operation::Write(write) => self.write_bytes(addr, write, timeout, frame)?,
-            }
-        }
-
-        Ok(())
+    pub fn blocking_transaction(
+        &mut self,
+        addr: impl Into<Address>,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let mut bus = self.blocking_bus(addr.into(), self.timeout());
+        blocking_transaction_frames(&mut bus, operations)
     }
 
     // Async
@@ -518,7 +535,97 @@ impl<'d, T: Instance, M: Mode> I2c<'d, T, M> {
 // ======== Async
 
 impl<'d, T: Instance> I2c<'d, T, Async> {
-    async fn write_frame(&mut self, address: u8, write: &[u8], frame: FrameOptions) -> Result<(), Error> {
+    /// Run `fut` until the configured timeout, so a slave stretching SCL forever can't hang it.
+    ///
+    /// On timeout, a STOP condition is requested to release the bus.
+    async fn with_timeout<R>(timeout: Timeout, fut: impl Future<Output = Result<R, Error>>) -> Result<R, Error> {
+        let res = timeout.with(fut).await;
+        if let Err(Error::Timeout) = res {
+            T::regs().ctlr1().modify(|w| w.set_stop(true));
+        }
+        res
+    }
+
+    /// Wait until `flag` is set in STAR1, or an error occurs.
+    async fn wait_for_flag(flag: impl Fn(&crate::pac::i2c::regs::Star1) -> bool) -> Result<(), Error> {
+        let state = T::state();
+
+        poll_fn(|cx| {
+            state.waker.register(cx.waker());
+
+            match Self::check_and_clear_error_flags() {
+                Err(e) => Poll::Ready(Err(e)),
+                Ok(sr1) => {
+                    if flag(&sr1) {
+                        Poll::Ready(Ok(()))
+                    } else {
+                        // When pending, (re-)enable interrupts to wake us up.
+                        Self::enable_interrupts();
+                        Poll::Pending
+                    }
+                }
+            }
+        })
+        .await
+    }
+
+    async fn start(read: bool) -> Result<(), Error> {
+        // Send a START condition, and set ACK bit for reads
+        T::regs().ctlr1().modify(|reg| {
+            reg.set_start(true);
+            if read {
+                reg.set_ack(true);
+            }
+        });
+
+        // Wait until START condition was generated
+        Self::wait_for_flag(|sr1| sr1.sb()).await?;
+
+        // Check if we were the ones to generate START
+        if T::regs().ctlr1().read().start() || !T::regs().star2().read().msl() {
+            return Err(Error::Arbitration);
+        }
+
+        Ok(())
+    }
+
+    /// Async version of [`I2c::blocking_address`].
+    async fn address(address: Address, read: bool, restart: bool) -> Result<(), Error> {
+        let regs = T::regs();
+
+        Self::start(read).await?;
+
+        match address {
+            Address::SevenBit(addr) => regs.datar().write(|reg| reg.set_datar((addr << 1) | read as u8)),
+            Address::TenBit(addr) if read && restart => {
+                regs.datar().write(|reg| reg.set_datar(ten_bit_header(addr, true)))
+            }
+            Address::TenBit(addr) => {
+                regs.datar().write(|reg| reg.set_datar(ten_bit_header(addr, false)));
+                Self::wait_for_flag(|sr1| sr1.add10())
+                    .await
+                    .map_err(Error::in_address_phase)?;
+                regs.datar().write(|reg| reg.set_datar(addr as u8));
+
+                if read {
+                    Self::wait_for_flag(|sr1| sr1.addr())
+                        .await
+                        .map_err(Error::in_address_phase)?;
+                    regs.star2().read();
+
+                    Self::start(read).await?;
+                    regs.datar().write(|reg| reg.set_datar(ten_bit_header(addr, true)));
+                }
+            }
+        }
+
+        // Wait for the address to be acknowledged
+        Self::wait_for_flag(|sr1| sr1.addr())
+            .await
+            .map_err(Error::in_address_phase)
+    }
+
+    async fn write_frame(&mut self, address: Address, write: &[u8], frame: FrameOptions) -> Result<(), Error> {
         T::regs().ctlr2().modify(|w| {
             // Note: Do not enable the ITBUFEN bit in the I2C_CR2 register if DMA is used for
             // reception.
@@ -543,56 +650,7 @@ impl<'d, T: Instance> I2c<'d, T, Async> {
         let state = T::state();
 
         if frame.send_start() {
-            // Send a START condition
-            T::regs().ctlr1().modify(|reg| {
-                reg.set_start(true);
-            });
-
-            // Wait until START condition was generated
-            poll_fn(|cx| {
-                state.waker.register(cx.waker());
-
-                match Self::check_and_clear_error_flags() {
-                    Err(e) => Poll::Ready(Err(e)),
-                    Ok(sr1) => {
-                        if sr1.sb() {
-                            Poll::Ready(Ok(()))
-                        } else {
-                            // When pending, (re-)enable interrupts to wake us up.
-                            Self::enable_interrupts();
-                            Poll::Pending
-                        }
-                    }
-                }
-            })
-            .await?;
-
-            // Check if we were the ones to generate START
-            if T::regs().ctlr1().read().start() || !T::regs().star2().read().msl() {
-                return Err(Error::Arbitration);
-            }
-
-            // Set up current address we're trying to talk to
-            T::regs().datar().write(|reg| reg.set_datar(address << 1));
-
-            // Wait for the address to be acknowledged
-            poll_fn(|cx| {
-                state.waker.register(cx.waker());
-
-                match Self::check_and_clear_error_flags() {
-                    Err(e) => Poll::Ready(Err(e)),
-                    Ok(sr1) => {
-                        if sr1.addr() {
-                            Poll::Ready(Ok(()))
-                        } else {
-                            // When pending, (re-)enable interrupts to wake us up.
-                            Self::enable_interrupts();
-                            Poll::Pending
-                        }
-                    }
-                }
-            })
-            .await?;
+            Self::address(address, false, false).await?;
 
             // Clear condition by reading SR2
             T::regs().star2().read();
@@ -665,24 +723,42 @@ impl<'d, T: Instance> I2c<'d, T, Async> {
     }
 
     /// Write.
-    pub async fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Error> {
-        self.write_frame(address, write, FrameOptions::FirstAndLastFrame)
-            .await?;
+    ///
+    /// Fails with [`Error::Timeout`] if it doesn't complete within `Config::timeout`.
+    pub async fn write(&mut self, address: impl Into<Address>, write: &[u8]) -> Result<(), Error> {
+        let timeout = self.timeout();
+        Self::with_timeout(
+            timeout,
+            self.write_frame(address.into(), write, FrameOptions::FirstAndLastFrame),
+        )
+        .await?;
 
         Ok(())
     }
 
     /// Read.
-    pub async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
-        self.read_frame(address, buffer, FrameOptions::FirstAndLastFrame)
-            .await?;
+    ///
+    /// Fails with [`Error::Timeout`] if it doesn't complete within `Config::timeout`.
+    pub async fn read(&mut self, address: impl Into<Address>, buffer: &mut [u8]) -> Result<(), Error> {
+        let timeout = self.timeout();
+        Self::with_timeout(
+            timeout,
+            self.read_frame(address.into(), buffer, FrameOptions::FirstAndLastFrame, false),
+        )
+        .await?;
 
         Ok(())
     }
 
-    async fn read_frame(&mut self, address: u8, buffer: &mut [u8], frame: FrameOptions) -> Result<(), Error> {
+    async fn read_frame(
+        &mut self,
+        address: Address,
+        buffer: &mut [u8],
+        frame: FrameOptions,
+        restart: bool,
+    ) -> Result<(), Error> {
         if buffer.is_empty() {
-            return Err(Error::Overrun);
+            return Err(Error::ZeroLengthTransfer);
         }
 
         // Some branches below depend on whether the buffer contains only a single byte.
@@ -714,57 +790,7 @@ impl<'d, T: Instance> I2c<'d, T, Async> {
         let state = T::state();
 
         if frame.send_start() {
-            // Send a START condition and set ACK bit
-            T::regs().ctlr1().modify(|reg| {
-                reg.set_start(true);
-                reg.set_ack(true);
-            });
-
-            // Wait until START condition was generated
-            poll_fn(|cx| {
-                state.waker.register(cx.waker());
-
-                match Self::check_and_clear_error_flags() {
-                    Err(e) => Poll::Ready(Err(e)),
-                    Ok(sr1) => {
-                        if sr1.sb() {
-                            Poll::Ready(Ok(()))
-                        } else {
-                            // When pending, (re-)enable interrupts to wake us up.
-                            Self::enable_interrupts();
-                            Poll::Pending
-                        }
-                    }
-                }
-            })
-            .await?;
-
-            // Check if we were the ones to generate START
-            if T::regs().ctlr1().read().start() || !T::regs().star2().read().msl() {
-                return Err(Error::Arbitration);
-            }
-
-            // Set up current address we're trying to talk to
-            T::regs().datar().write(|reg| reg.set_datar((address << 1) + 1));
-
-            // Wait for the address to be acknowledged
-            poll_fn(|cx| {
-                state.waker.register(cx.waker());
-
-                match Self::check_and_clear_error_flags() {
-                    Err(e) => Poll::Ready(Err(e)),
-                    Ok(sr1) => {
-                        if sr1.addr() {
-                            Poll::Ready(Ok(()))
-                        } else {
-                            // When pending, (re-)enable interrupts to wake us up.
-                            Self::enable_interrupts();
-                            Poll::Pending
-                        }
-                    }
-                }
-            })
-            .await?;
+            Self::address(address, true, restart).await?;
 
             // 18.3.8: When a single byte must be received: the NACK must be programmed during EV6
             // event, i.e. program ACK=0 when ADDR=1, before clearing ADDR flag.
@@ -839,15 +865,27 @@ impl<'d, T: Instance> I2c<'d, T, Async> {
     }
 
     /// Write, restart, read.
-    pub async fn write_read(&mut self, address: u8, write: &[u8], read: &mut [u8]) -> Result<(), Error> {
+    pub async fn write_read(
+        &mut self,
+        address: impl Into<Address>,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Error> {
         // Check empty read buffer before starting transaction. Otherwise, we would not generate the
         // stop condition below.
         if read.is_empty() {
-            return Err(Error::Overrun);
+            return Err(Error::ZeroLengthTransfer);
         }
 
-        self.write_frame(address, write, FrameOptions::FirstFrame).await?;
-        self.read_frame(address, read, FrameOptions::FirstAndLastFrame).await
+        let address = address.into();
+        let timeout = self.timeout();
+        Self::with_timeout(timeout, async {
+            // The read frame generates a repeated START after the write frame.
+            self.write_frame(address, write, FrameOptions::FirstFrame).await?;
+            self.read_frame(address, read, FrameOptions::FirstAndLastFrame, true)
+                .await
+        })
+        .await
     }
 
     /// Transaction with operations.
@@ -855,15 +893,27 @@ impl<'d, T: Instance> I2c<'d, T, Async> {
     /// Consecutive operations of same type are merged. See [transaction contract] for details.
     ///
     /// [transaction contract]: embedded_hal_1::i2c::I2c::transaction
-    pub async fn transaction(&mut self, addr: u8, operations: &mut [Operation<'_>]) -> Result<(), Error> {
-        for (op, frame) in operation_frames(operations)? {
-            match op {
-                Operation::Read(read) => self.read_frame(addr, read, frame).await?,
-                Operation::Write(write) => self.write_frame(addr, write, frame).await?,
+    pub async fn transaction(
+        &mut self,
+        addr: impl Into<Address>,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Error> {
+        let addr = addr.into();
+        let timeout = self.timeout();
+        Self::with_timeout(timeout, async {
+            // Every (repeated) START after the first one re-addresses the same slave.
+            let mut restart = false;
+            for (op, frame) in operation_frames(operations)? {
+                match op {
+                    Operation::Read(read) => self.read_frame(addr, read, frame, restart).await?,
+                    Operation::Write(write) => self.write_frame(addr, write, frame).await?,
+                }
+                restart = true;
             }
-        }
 
-        Ok(())
+            Ok(())
+        })
+        .await
     }
 }
 
@@ -872,6 +922,7 @@ impl<'d, T: Instance> I2c<'d, T, Async> {
 impl<'d, T: Instance, M: Mode> Drop for I2c<'d, T, M> {
     fn drop(&mut self) {
         T::regs().ctlr1().modify(|w| w.set_pe(false));
+        T::disable();
     }
 }
 
@@ -933,7 +984,10 @@ impl embedded_hal::i2c::Error for Error {
         match *self {
             Self::Bus => embedded_hal::i2c::ErrorKind::Bus,
             Self::Arbitration => embedded_hal::i2c::ErrorKind::ArbitrationLoss,
-            Self::Nack => embedded_hal::i2c::ErrorKind::NoAcknowledge(embedded_hal::i2c::NoAcknowledgeSource::Unknown),
+            Self::Nack => embedded_hal::i2c::ErrorKind::NoAcknowledge(embedded_hal::i2c::NoAcknowledgeSource::Data),
+            Self::AddressNack => {
+                embedded_hal::i2c::ErrorKind::NoAcknowledge(embedded_hal::i2c::NoAcknowledgeSource::Address)
+            }
             Self::Timeout => embedded_hal::i2c::ErrorKind::Other,
             Self::Crc => embedded_hal::i2c::ErrorKind::Other,
             Self::Overrun => embedded_hal::i2c::ErrorKind::Overrun,
@@ -964,9 +1018,7 @@ impl<'d, T: Instance, M: Mode> embedded_hal::i2c::I2c for I2c<'d, T, M> {
         address: u8,
         operations: &mut [embedded_hal::i2c::Operation<'_>],
     ) -> Result<(), Self::Error> {
-        let _ = address;
-        let _ = operations;
-        todo!()
+        self.blocking_transaction(address, operations)
     }
 }
 
@@ -988,9 +1040,7 @@ impl<'d, T: Instance> embedded_hal_async::i2c::I2c for I2c<'d, T, Async> {
         address: u8,
         operations: &mut [embedded_hal_async::i2c::Operation<'_>],
     ) -> Result<(), Self::Error> {
-        let _ = address;
-        let _ = operations;
-        todo!()
+        self.transaction(address, operations).await
     }
 }
 
@@ -1108,7 +1158,7 @@ fn operation_frames<'a, 'b: 'a>(
         Read(read) => read.is_empty(),
         Write(_) => false,
     }) {
-        return Err(Error::Overrun);
+        return Err(Error::ZeroLengthTransfer);
     }
 
     let mut operations = operations.iter_mut().peekable();
@@ -1157,3 +1207,320 @@ fn operation_frames<'a, 'b: 'a>(
         Some((op, frame))
     }))
 }
+
+/// Byte level steps of a blocking master transfer to one slave.
+///
+/// The framing below only talks to the bus through this trait, so the tests run it against a
+/// simulated slave.
+trait BlockingBus {
+    /// Generate a (repeated) START and address the slave, in the given direction.
+    ///
+    /// `restart` is set if the slave was already addressed in this transaction.
+    fn address(&mut self, read: bool, restart: bool) -> Result<(), Error>;
+    fn send_byte(&mut self, byte: u8) -> Result<(), Error>;
+    fn recv_byte(&mut self) -> Result<u8, Error>;
+    /// Answer the byte being received with a NACK, and/or generate a STOP after it.
+    fn end_read(&mut self, nack: bool, stop: bool);
+    fn stop(&mut self);
+}
+
+struct RegisterBus<'a, 'd, T: Instance, M: Mode> {
+    i2c: &'a I2c<'d, T, M>,
+    addr: Address,
+    timeout: Timeout,
+}
+
+impl<'a, 'd, T: Instance, M: Mode> BlockingBus for RegisterBus<'a, 'd, T, M> {
+    fn address(&mut self, read: bool, restart: bool) -> Result<(), Error> {
+        self.i2c.blocking_address(self.addr, read, restart, self.timeout)?;
+
+        // Clear condition by reading SR2
+        let _ = T::regs().star2().read();
+        Ok(())
+    }
+
+    fn send_byte(&mut self, byte: u8) -> Result<(), Error> {
+        self.i2c.send_byte(byte, self.timeout)
+    }
+
+    fn recv_byte(&mut self) -> Result<u8, Error> {
+        self.i2c.recv_byte(self.timeout)
+    }
+
+    fn end_read(&mut self, nack: bool, stop: bool) {
+        T::regs().ctlr1().modify(|reg| {
+            if nack {
+                reg.set_ack(false);
+            }
+            if stop {
+                reg.set_stop(true);
+            }
+        });
+    }
+
+    fn stop(&mut self) {
+        T::regs().ctlr1().modify(|reg| reg.set_stop(true));
+    }
+}
+
+fn blocking_write_frame(bus: &mut impl BlockingBus, bytes: &[u8], frame: FrameOptions) -> Result<(), Error> {
+    if frame.send_start() {
+        bus.address(false, false)?;
+    }
+
+    // Send bytes
+    for &c in bytes {
+        bus.send_byte(c)?;
+    }
+
+    if frame.send_stop() {
+        // Send a STOP condition
+        bus.stop();
+    }
+
+    // Fallthrough is success
+    Ok(())
+}
+
+fn blocking_read_frame(
+    bus: &mut impl BlockingBus,
+    buffer: &mut [u8],
+    frame: FrameOptions,
+    restart: bool,
+) -> Result<(), Error> {
+    let Some((last, buffer)) = buffer.split_last_mut() else {
+        return Err(Error::ZeroLengthTransfer);
+    };
+
+    if frame.send_start() {
+        bus.address(true, restart)?;
+    }
+
+    // Receive bytes into buffer
+    for c in buffer {
+        *c = bus.recv_byte()?;
+    }
+
+    // Prepare to send NACK then STOP after next byte
+    bus.end_read(frame.send_nack(), frame.send_stop());
+
+    // Receive last byte
+    *last = bus.recv_byte()?;
+
+    // Fallthrough is success
+    Ok(())
+}
+
+fn blocking_transaction_frames(bus: &mut impl BlockingBus, operations: &mut [Operation<'_>]) -> Result<(), Error> {
+    // Every (repeated) START after the first one re-addresses the same slave.
+    let mut restart = false;
+    for (op, frame) in operation_frames(operations)? {
+        match op {
+            Operation::Read(read) => blocking_read_frame(bus, read, frame, restart)?,
+            Operation::Write(write) => blocking_write_frame(bus, write, frame)?,
+        }
+        restart = true;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use embedded_hal::i2c::{Error as _, ErrorKind, NoAcknowledgeSource, Operation};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Wire {
+        Start { read: bool },
+        Write(u8),
+        Read(u8),
+        Nack,
+        Stop,
+    }
+
+    /// 24Cxx style EEPROM: the first byte written after START sets the address pointer.
+    struct FakeEeprom {
+        present: bool,
+        mem: [u8; 16],
+        ptr: usize,
+        addressing: bool,
+        nack: bool,
+        stop: bool,
+        wire: Vec<Wire>,
+    }
+
+    impl FakeEeprom {
+        fn new() -> Self {
+            Self {
+                present: true,
+                mem: core::array::from_fn(|i| i as u8 * 0x11),
+                ptr: 0,
+                addressing: false,
+                nack: false,
+                stop: false,
+                wire: Vec::new(),
+            }
+        }
+    }
+
+    impl BlockingBus for FakeEeprom {
+        fn address(&mut self, read: bool, _restart: bool) -> Result<(), Error> {
+            self.wire.push(Wire::Start { read });
+            if !self.present {
+                return Err(Error::AddressNack);
+            }
+            self.addressing = !read;
+            Ok(())
+        }
+
+        fn send_byte(&mut self, byte: u8) -> Result<(), Error> {
+            self.wire.push(Wire::Write(byte));
+            if self.addressing {
+                self.ptr = byte as usize;
+                self.addressing = false;
+            } else {
+                *self.mem.get_mut(self.ptr).ok_or(Error::Nack)? = byte;
+                self.ptr += 1;
+            }
+            Ok(())
+        }
+
+        fn recv_byte(&mut self) -> Result<u8, Error> {
+            let byte = self.mem[self.ptr];
+            self.ptr += 1;
+            self.wire.push(Wire::Read(byte));
+            // NACK and STOP apply to the byte received after they were requested
+            if core::mem::take(&mut self.nack) {
+                self.wire.push(Wire::Nack);
+            }
+            if core::mem::take(&mut self.stop) {
+                self.wire.push(Wire::Stop);
+            }
+            Ok(byte)
+        }
+
+        fn end_read(&mut self, nack: bool, stop: bool) {
+            self.nack = nack;
+            self.stop = stop;
+        }
+
+        fn stop(&mut self) {
+            self.wire.push(Wire::Stop);
+        }
+    }
+
+    #[test]
+    fn random_read_restarts_on_direction_change() {
+        let mut eeprom = FakeEeprom::new();
+        let mut buf = [0; 2];
+        blocking_transaction_frames(&mut eeprom, &mut [Operation::Write(&[3]), Operation::Read(&mut buf)]).unwrap();
+
+        assert_eq!(buf, [0x33, 0x44]);
+        assert_eq!(
+            eeprom.wire,
+            [
+                Wire::Start { read: false },
+                Wire::Write(3),
+                Wire::Start { read: true },
+                Wire::Read(0x33),
+                Wire::Read(0x44),
+                Wire::Nack,
+                Wire::Stop
+            ]
+        );
+    }
+
+    #[test]
+    fn same_direction_operations_are_merged() {
+        let mut eeprom = FakeEeprom::new();
+        let (mut a, mut b) = ([0; 1], [0; 2]);
+        blocking_transaction_frames(
+            &mut eeprom,
+            &mut [
+                Operation::Write(&[4]),
+                Operation::Write(&[0xAA, 0xBB]),
+                Operation::Write(&[4]),
+                Operation::Read(&mut a),
+                Operation::Read(&mut b),
+            ],
+        )
+        .unwrap();
+
+        // The second address write is data: the pointer isn't set again without a START
+        assert_eq!(eeprom.mem[4..7], [0xAA, 0xBB, 4]);
+        assert_eq!((a, b), ([0x77], [0x88, 0x99]));
+        // Only the last byte of the merged read is NACKed
+        assert_eq!(
+            eeprom.wire,
+            [
+                Wire::Start { read: false },
+                Wire::Write(4),
+                Wire::Write(0xAA),
+                Wire::Write(0xBB),
+                Wire::Write(4),
+                Wire::Start { read: true },
+                Wire::Read(0x77),
+                Wire::Read(0x88),
+                Wire::Read(0x99),
+                Wire::Nack,
+                Wire::Stop
+            ]
+        );
+    }
+
+    #[test]
+    fn write_after_read_restarts() {
+        let mut eeprom = FakeEeprom::new();
+        let mut buf = [0; 1];
+        blocking_transaction_frames(
+            &mut eeprom,
+            &mut [Operation::Read(&mut buf), Operation::Write(&[1, 0x5A])],
+        )
+        .unwrap();
+
+        assert_eq!(buf, [0x00]);
+        assert_eq!(eeprom.mem[1], 0x5A);
+        assert_eq!(
+            eeprom.wire,
+            [
+                Wire::Start { read: true },
+                Wire::Read(0x00),
+                Wire::Nack,
+                Wire::Start { read: false },
+                Wire::Write(1),
+                Wire::Write(0x5A),
+                Wire::Stop
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_read_is_rejected() {
+        let mut eeprom = FakeEeprom::new();
+        let res = blocking_transaction_frames(&mut eeprom, &mut [Operation::Write(&[0]), Operation::Read(&mut [])]);
+        assert_eq!(res, Err(Error::ZeroLengthTransfer));
+        assert!(eeprom.wire.is_empty());
+    }
+
+    #[test]
+    fn nack_source() {
+        let mut eeprom = FakeEeprom::new();
+        eeprom.present = false;
+        let res = blocking_transaction_frames(&mut eeprom, &mut [Operation::Write(&[0, 1])]);
+        assert_eq!(res, Err(Error::AddressNack));
+        assert_eq!(
+            Error::AddressNack.kind(),
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)
+        );
+
+        // Writing past the end of the memory
+        let mut eeprom = FakeEeprom::new();
+        let res = blocking_transaction_frames(&mut eeprom, &mut [Operation::Write(&[15, 1, 2])]);
+        assert_eq!(res, Err(Error::Nack));
+        assert_eq!(Error::Nack.kind(), ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data));
+    }
+}
@@ -0,0 +1,402 @@
+//! Inter-IC Sound (I2S), on the SPI2 and SPI3 peripherals of CH32V30x
+//!
+//! Master transmitter only, output is streamed from a circular DMA buffer. The pins are the SPI
+//! pins: WS on NSS, CK on SCK and SD on MOSI. The optional master clock MCK runs at 256 times
+//! the sample rate.
+//!
+//! The bit clock is divided from SYSCLK by `2 * DIV + ODD` (I2SPR), with DIV from 2 to 255.
+//! CH32V305/307 can also clock I2S from the PLL3 VCO (RCC_CFGR2.I2SxSRC). PLL3 isn't set up by
+//! [`rcc`](crate::rcc), so the driver selects SYSCLK.
+//! The achieved sample rate is the closest one to [`Config::sample_rate`] this allows, see
+//! [`I2s::sample_rate`].
+
+use core::future::poll_fn;
+use core::task::Poll;
+
+use crate::dma::{OverrunError, TransferOptions, WritableRingBuffer};
+use crate::gpio::{AFType, AnyPin, SealedPin as _, Speed};
+use crate::spi::{CsPin, MckPin, MosiPin, SckPin, TxDma};
+use crate::time::Hertz;
+use crate::{into_ref, peripherals, Peripheral, PeripheralRef};
+
+// I2SCFGR.I2SCFG
+const I2SCFG_MASTER_TX: u8 = 0b10;
+
+/// I2S error
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The DMA played samples that were not written in time, the output had a glitch.
+    Underrun,
+    /// The sample rate can't be divided from SYSCLK, DIV would be outside 2 to 255.
+    InvalidSampleRate,
+}
+
+impl From<OverrunError> for Error {
+    fn from(_: OverrunError) -> Self {
+        Self::Underrun
+    }
+}
+
+/// Data and channel length (DATLEN, CHLEN).
+///
+/// 24 and 32-bit data takes two `u16` words per sample, the most significant first.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// 16-bit data in 16-bit channels
+    Data16Channel16,
+    /// 16-bit data in 32-bit channels
+    Data16Channel32,
+    /// 24-bit data in 32-bit channels
+    Data24Channel32,
+    /// 32-bit data in 32-bit channels
+    Data32Channel32,
+}
+
+impl Format {
+    fn datlen(&self) -> u8 {
+        match self {
+            Format::Data16Channel16 | Format::Data16Channel32 => 0b00,
+            Format::Data24Channel32 => 0b01,
+            Format::Data32Channel32 => 0b10,
+        }
+    }
+
+    fn channel_bits(&self) -> u32 {
+        match self {
+            Format::Data16Channel16 => 16,
+            _ => 32,
+        }
+    }
+}
+
+/// Frame format (I2SSTD, PCMSYNC).
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Standard {
+    /// Philips standard I2S
+    Philips,
+    /// MSB-justified (left-justified)
+    MsbFirst,
+    /// LSB-justified (right-justified)
+    LsbFirst,
+    /// PCM with a short frame sync
+    PcmShortSync,
+    /// PCM with a long frame sync
+    PcmLongSync,
+}
+
+impl Standard {
+    /// I2SSTD and PCMSYNC
+    fn bits(&self) -> (u8, bool) {
+        match self {
+            Standard::Philips => (0b00, false),
+            Standard::MsbFirst => (0b01, false),
+            Standard::LsbFirst => (0b10, false),
+            Standard::PcmShortSync => (0b11, false),
+            Standard::PcmLongSync => (0b11, true),
+        }
+    }
+}
+
+/// I2S configuration.
+#[non_exhaustive]
+#[derive(Copy, Clone)]
+pub struct Config {
+    pub format: Format,
+    pub standard: Standard,
+    /// Sample rate, of frames with both channels
+    pub sample_rate: Hertz,
+    /// CK idles high, instead of low (CKPOL)
+    pub clock_idle_high: bool,
+    /// Speed (slew rate) of the output pins.
+    pub gpio_speed: Speed,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            format: Format::Data16Channel16,
+            standard: Standard::Philips,
+            sample_rate: Hertz::hz(44_100),
+            clock_idle_high: false,
+            gpio_speed: Speed::High,
+        }
+    }
+}
+
+/// I2S driver.
+pub struct I2s<'d, T: Instance> {
+    _peri: PeripheralRef<'d, T>,
+    ws: PeripheralRef<'d, AnyPin>,
+    ck: PeripheralRef<'d, AnyPin>,
+    sd: PeripheralRef<'d, AnyPin>,
+    mck: Option<PeripheralRef<'d, AnyPin>>,
+    tx_ring: WritableRingBuffer<'d, u16>,
+    sample_rate: Hertz,
+}
+
+impl<'d, T: Instance> I2s<'d, T> {
+    /// Create a master transmitter, with master clock output.
+    ///
+    /// `dma_buf` is the circular DMA buffer, written with [`write`](Self::write). Samples are
+    /// interleaved, left channel first. Fails with [`Error::InvalidSampleRate`] if the sample
+    /// rate is out of range for SYSCLK.
+    pub fn new_txonly<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        ws: impl Peripheral<P = impl CsPin<T, REMAP>> + 'd,
+        ck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        sd: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        mck: impl Peripheral<P = impl MckPin<T>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        dma_buf: &'d mut [u16],
+        config: Config,
+    ) -> Result<Self, Error> {
+        into_ref!(mck);
+        Self::new_inner::<REMAP>(peri, ws, ck, sd, Some(mck.map_into()), tx_dma, dma_buf, config)
+    }
+
+    /// Create a master transmitter, without master clock output.
+    pub fn new_txonly_nomck<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        ws: impl Peripheral<P = impl CsPin<T, REMAP>> + 'd,
+        ck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        sd: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        dma_buf: &'d mut [u16],
+        config: Config,
+    ) -> Result<Self, Error> {
+        Self::new_inner::<REMAP>(peri, ws, ck, sd, None, tx_dma, dma_buf, config)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_inner<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        ws: impl Peripheral<P = impl CsPin<T, REMAP>> + 'd,
+        ck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        sd: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        mck: Option<PeripheralRef<'d, AnyPin>>,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        dma_buf: &'d mut [u16],
+        config: Config,
+    ) -> Result<Self, Error> {
+        into_ref!(peri, ws, ck, sd, tx_dma);
+
+        let frame_bits = if mck.is_some() {
+            256
+        } else {
+            2 * config.format.channel_bits()
+        };
+        // Not PCLK1 like SPI, `T::frequency()` doesn't apply
+        let clk = crate::rcc::clocks().sysclk.0;
+        let (div, odd) = calculate_divider(clk, config.sample_rate.0, frame_bits).ok_or(Error::InvalidSampleRate)?;
+        let sample_rate = Hertz(clk / (frame_bits * (2 * div as u32 + odd as u32)));
+
+        T::set_remap(REMAP);
+
+        ws.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        ck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        sd.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        if let Some(mck) = &mck {
+            mck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        }
+
+        T::enable_and_reset();
+        #[cfg(d8c)]
+        T::select_sysclk();
+
+        let regs = regs::<T>();
+        regs.i2spr().write(|w| {
+            w.set_i2sdiv(div);
+            w.set_odd(odd);
+            w.set_mckoe(mck.is_some());
+        });
+
+        let (i2sstd, pcmsync) = config.standard.bits();
+        regs.i2scfgr().write(|w| {
+            w.set_i2smod(true);
+            w.set_i2scfg(I2SCFG_MASTER_TX);
+            w.set_i2sstd(i2sstd);
+            w.set_pcmsync(pcmsync);
+            w.set_ckpol(config.clock_idle_high);
+            w.set_datlen(config.format.datlen());
+            w.set_chlen(config.format.channel_bits() == 32);
+        });
+
+        let request = tx_dma.request();
+        let opts = TransferOptions {
+            half_transfer_ir: true,
+            ..Default::default()
+        };
+        let datar = regs.datar().as_ptr() as *mut u16;
+        let tx_ring = unsafe { WritableRingBuffer::new(tx_dma, request, datar, dma_buf, opts) };
+
+        Ok(Self {
+            _peri: peri,
+            ws: ws.map_into(),
+            ck: ck.map_into(),
+            sd: sd.map_into(),
+            mck,
+            tx_ring,
+            sample_rate,
+        })
+    }
+
+    /// The achieved sample rate.
+    pub fn sample_rate(&self) -> Hertz {
+        self.sample_rate
+    }
+
+    /// Start playing the DMA buffer.
+    ///
+    /// Fill it with [`write`](Self::write) first, or zeros are played until it catches up.
+    pub fn start(&mut self) {
+        self.tx_ring.start();
+        let regs = regs::<T>();
+        regs.ctlr2().modify(|w| w.set_txdmaen(true));
+        regs.i2scfgr().modify(|w| w.set_i2se(true));
+    }
+
+    /// Write samples to the DMA buffer, waiting for the DMA to play older ones to make room.
+    ///
+    /// With `data` of half the buffer length, this returns once per half, so the application
+    /// refills one half while the other is played. Returns [`Error::Underrun`] if the DMA
+    /// caught up with the written samples. The buffer is cleared then, and writing can go on.
+    pub async fn write(&mut self, data: &[u16]) -> Result<(), Error> {
+        self.tx_ring.write_exact(data).await.map_err(|e| {
+            self.tx_ring.clear();
+            e.into()
+        })?;
+        Ok(())
+    }
+
+    /// Play the samples left in the DMA buffer, then stop.
+    pub async fn stop(&mut self) {
+        self.tx_ring.stop().await;
+
+        // Wait for the last frame to be shifted out, at most a frame time, without blocking
+        // other tasks
+        let regs = regs::<T>();
+        poll_fn(|cx| {
+            let sr = regs.statr().read();
+            if sr.txe() && !sr.bsy() {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+
+        regs.i2scfgr().modify(|w| w.set_i2se(false));
+        regs.ctlr2().modify(|w| w.set_txdmaen(false));
+    }
+}
+
+impl<'d, T: Instance> Drop for I2s<'d, T> {
+    fn drop(&mut self) {
+        let regs = regs::<T>();
+        regs.i2scfgr().write(|_| {});
+        regs.ctlr2().write(|_| {});
+
+        self.ws.set_as_disconnected();
+        self.ck.set_as_disconnected();
+        self.sd.set_as_disconnected();
+        self.mck.as_ref().map(|x| x.set_as_disconnected());
+
+        T::disable();
+    }
+}
+
+fn regs<T: Instance>() -> crate::pac::spi::Spi {
+    <T as crate::spi::SealedInstance>::REGS
+}
+
+/// I2SDIV and ODD for the closest sample rate to `sample_rate`, with `frame_bits` bit clocks
+/// (or 256 master clocks) per frame: `clk / (frame_bits * (2 * DIV + ODD))`.
+fn calculate_divider(clk: u32, sample_rate: u32, frame_bits: u32) -> Option<(u8, bool)> {
+    let per_frame = frame_bits as u64 * sample_rate.max(1) as u64;
+    // Rounded to the nearest divider
+    let d = (2 * clk as u64 + per_frame) / (2 * per_frame);
+    let (div, odd) = (d / 2, d & 1 != 0);
+    (2..=255).contains(&div).then_some((div as u8, odd))
+}
+
+trait SealedInstance {
+    /// Clock I2S from SYSCLK, not the PLL3 VCO.
+    #[cfg(d8c)]
+    fn select_sysclk();
+}
+
+/// I2S instance, SPI2 and SPI3.
+#[allow(private_bounds)]
+pub trait Instance: crate::spi::Instance + SealedInstance {}
+
+#[cfg(peri_spi2)]
+impl SealedInstance for peripherals::SPI2 {
+    #[cfg(d8c)]
+    fn select_sysclk() {
+        crate::pac::RCC.cfgr2().modify(|w| w.set_i2s2src(false));
+    }
+}
+#[cfg(peri_spi2)]
+impl Instance for peripherals::SPI2 {}
+#[cfg(peri_spi3)]
+impl SealedInstance for peripherals::SPI3 {
+    #[cfg(d8c)]
+    fn select_sysclk() {
+        crate::pac::RCC.cfgr2().modify(|w| w.set_i2s3src(false));
+    }
+}
+#[cfg(peri_spi3)]
+impl Instance for peripherals::SPI3 {}
+
+// MCK isn't in the pin metadata
+#[cfg(peri_spi2)]
+impl MckPin<peripherals::SPI2> for peripherals::PC6 {}
+#[cfg(peri_spi3)]
+impl MckPin<peripherals::SPI3> for peripherals::PC7 {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(clk: u32, frame_bits: u32, (div, odd): (u8, bool)) -> u32 {
+        clk / (frame_bits * (2 * div as u32 + odd as u32))
+    }
+
+    #[test]
+    fn divider_44k1() {
+        // 72 MHz SYSCLK, 16-bit channels without MCK: 72 MHz / 32 / 44.1 kHz = 51.02
+        let d = calculate_divider(72_000_000, 44_100, 32).unwrap();
+        assert_eq!(d, (25, true));
+        assert_eq!(rate(72_000_000, 32, d), 44_117);
+
+        // With MCK: 144 MHz / 256 / 44.1 kHz = 12.76, rounded up to 13
+        let d = calculate_divider(144_000_000, 44_100, 256).unwrap();
+        assert_eq!(d, (6, true));
+    }
+
+    #[test]
+    fn divider_exact() {
+        // 96 MHz / 64 / 50 kHz = 30, 32-bit channels
+        assert_eq!(calculate_divider(96_000_000, 50_000, 64), Some((15, false)));
+        // 96 MHz / 256 / 46.875 kHz = 8
+        assert_eq!(calculate_divider(96_000_000, 46_875, 256), Some((4, false)));
+    }
+
+    #[test]
+    fn divider_rounds_to_nearest() {
+        // 96 MHz / 64 / 48 kHz = 31.25, rounded down to 31
+        assert_eq!(calculate_divider(96_000_000, 48_000, 64), Some((15, true)));
+        // 96 MHz / 64 / 44.1 kHz = 34.01
+        assert_eq!(calculate_divider(96_000_000, 44_100, 64), Some((17, false)));
+    }
+
+    #[test]
+    fn divider_out_of_range() {
+        // Dividers 3 and 1024 are out of range, 511 is the largest
+        assert_eq!(calculate_divider(8_000_000, 83_333, 32), None);
+        assert_eq!(calculate_divider(72_000_000, 2_197, 32), None);
+        assert_eq!(calculate_divider(72_000_000, 4_404, 32), Some((255, true)));
+    }
+}
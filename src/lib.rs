@@ -51,20 +51,31 @@ mod interrupt_ext;
 
 pub use crate::_generated::{peripherals, Peripherals};
 
-#[cfg(not(time_driver_systick))]
 pub mod delay;
 pub mod dma;
 
 #[cfg(adc)]
 pub mod adc;
+#[cfg(bkp)]
+pub mod backup;
+#[cfg(can)]
+pub mod can;
+#[cfg(crc)]
+pub mod crc;
 #[cfg(dac)]
 pub mod dac;
 pub mod exti;
+pub mod flash;
 pub mod gpio;
 #[cfg(i2c)]
 pub mod i2c;
+#[cfg(all(spi, ch32v3))]
+pub mod i2s;
+pub mod low_power;
 #[cfg(rng)]
 pub mod rng;
+#[cfg(rtc)]
+pub mod rtc;
 #[cfg(sdio_v3)]
 pub mod sdio;
 pub mod signature;
@@ -73,6 +84,8 @@ pub mod spi;
 #[cfg(any(timer_x0, timer_v3))]
 pub mod timer;
 pub mod usart;
+#[cfg(iwdg)]
+pub mod wdg;
 
 /// Common structures for USB drivers
 pub mod usb;
@@ -108,6 +121,16 @@ pub use crate::_generated::interrupt;
 pub struct Config {
     pub rcc: rcc::Config,
     pub dma_interrupt_priority: interrupt::Priority,
+    /// Interrupt priority of the embassy time driver (SysTick or the TIMx capture/compare interrupt).
+    ///
+    /// Lower values are more urgent. The time driver only wakes tasks, so it is safe to keep it below
+    /// the executors it serves, but it must stay above any interrupt executor that relies on it, or
+    /// timer wakeups are delayed until that executor is idle. Nesting between priorities depends on
+    /// the QingKe core's preemption configuration.
+    ///
+    /// Defaults to `P15` (the lowest priority).
+    #[cfg(feature = "embassy")]
+    pub time_interrupt_priority: interrupt::Priority,
 }
 
 impl Default for Config {
@@ -115,6 +138,64 @@ impl Default for Config {
         Self {
             rcc: Default::default(),
             dma_interrupt_priority: interrupt::Priority::P0,
+            #[cfg(feature = "embassy")]
+            time_interrupt_priority: interrupt::Priority::P15,
+        }
+    }
+}
+
+impl Config {
+    /// Highest SYSCLK the chip is specified for, from HSI, so no crystal is needed.
+    ///
+    /// 48 MHz on CH32V0, CH32X0 and CH641, 72 MHz on CH32V1, 96 MHz on CH32L1 and CH32V2, 144 MHz
+    /// on CH32V3 and CH32F2.
+    pub fn fast() -> Self {
+        #[cfg(any(ch32v0, ch32x0, ch641))]
+        let rcc = rcc::Config::SYSCLK_FREQ_48MHZ_HSI;
+        #[cfg(ch32v1)]
+        let rcc = rcc::Config::SYSCLK_FREQ_72MHZ_HSI;
+        #[cfg(ch32l1)]
+        let rcc = rcc::Config::SYSCLK_FREQ_96MHZ_HSI;
+        #[cfg(ch32v2)]
+        let rcc = rcc::Config::SYSCLK_FREQ_96MHZ_HSI;
+        #[cfg(any(ch32v3, ch32f2))]
+        let rcc = rcc::Config::SYSCLK_FREQ_144MHZ_HSI;
+
+        Self::with_rcc(rcc)
+    }
+
+    /// 8 MHz HCLK from HSI, with the PLL off.
+    ///
+    /// This is the reset clock of every chip family, and the same as [`Config::default`].
+    pub fn internal_8mhz() -> Self {
+        Self::with_rcc(rcc::Config::default())
+    }
+
+    /// HSI divided down by the AHB prescaler, with the PLL off.
+    ///
+    /// HCLK is 1 MHz from the 8 MHz HSI of CH32V1, CH32L1, CH32V2, CH32V3 and CH32F2, and 1.5 MHz
+    /// on CH32V0, CH32X0 and CH641. All peripheral clocks and the SysTick counter slow down by
+    /// the same factor, which limits baud rates and timer resolution.
+    pub fn low_power_hsi_div() -> Self {
+        use pac::rcc::vals::Hpre;
+
+        #[cfg(any(ch32v0, ch641))]
+        let ahb_pre = Hpre::DIV16;
+        #[cfg(ch32x0)]
+        let ahb_pre = Hpre::DIV32;
+        #[cfg(any(ch32v1, ch32l1, ch32v2, ch32v3, ch32f2))]
+        let ahb_pre = Hpre::DIV8;
+
+        Self::with_rcc(rcc::Config {
+            ahb_pre,
+            ..Default::default()
+        })
+    }
+
+    fn with_rcc(rcc: rcc::Config) -> Self {
+        Self {
+            rcc,
+            ..Default::default()
         }
     }
 }
@@ -134,7 +215,7 @@ pub fn init(config: Config) -> Peripherals {
         delay::init();
 
         #[cfg(feature = "embassy")]
-        embassy::init();
+        embassy::init(config.time_interrupt_priority);
     }
 
     ::critical_section::with(|cs| unsafe {
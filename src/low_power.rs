@@ -0,0 +1,234 @@
+//! Low-power modes
+//!
+//! - Sleep ([`enter_sleep`]): the core stops, clocks and peripherals keep running. Any interrupt
+//!   wakes it up.
+//! - Stop (`enter_stop`, not on CH32V003): all clocks except LSI and LSE stop, RAM and register
+//!   contents are kept. An EXTI line (interrupt or event) wakes it up.
+//! - Standby ([`enter_standby`]): the core domain is powered off. RAM and registers are lost,
+//!   except the backup domain ([`crate::backup`] registers and RTC). Wakeup, by the WKUP pin, RTC
+//!   alarm or AWU, goes through a reset.
+//!
+//! # Timekeeping
+//!
+//! The embassy TIMx time driver counts from the APB clock, which stops in Stop and Standby.
+//! Embassy time doesn't advance while in Stop, so timers run late by the time spent there.
+//!
+//! # Standby with Auto-Wakeup (AWU), CH32V003
+//!
+//! With the `low-power` feature on CH32V003, `stop_with_awu` enters Standby and resumes
+//! execution after the `WFI` on wakeup, instead of going through a reset. The AWU counts LSI
+//! cycles and raises EXTI line 9 (as an event, so no interrupt handler is needed) when its window
+//! value is reached.
+//!
+//! With a TIMx time driver, the timer doesn't count while in Standby, so `stop_with_awu` advances it
+//! by the slept time before pending alarms are re-evaluated.
+//!
+//! The slept time is computed from the nominal LSI frequency (128 kHz). LSI isn't trimmed and can
+//! deviate by several percent over voltage and temperature, so embassy time can drift by the same
+//! amount during Standby. Sleep durations are always rounded down, so wakeups are never late
+//! relative to the nominal LSI clock.
+
+#[cfg(all(feature = "low-power", ch32v0))]
+use embassy_time::Duration;
+
+#[cfg(all(feature = "low-power", ch32v0))]
+use crate::pac::EXTI;
+use crate::pac::{PFIC, PWR, RCC};
+
+/// Enter Sleep until the next interrupt.
+///
+/// Call it with interrupts enabled, the interrupt handler runs before this returns.
+pub fn enter_sleep() {
+    PFIC.sctlr().modify(|w| w.set_sleepdeep(false));
+    unsafe { qingke::riscv::asm::wfi() };
+}
+
+/// Enter Stop until the next enabled EXTI interrupt, with the voltage regulator in low-power
+/// mode.
+///
+/// The waking interrupt is held until this returns, so that the clock configuration (HSE, PLL
+/// and system clock switch) is restored first: hardware selects HSI on wakeup. PLL2 and PLL3
+/// of CH32V305/307 aren't restored.
+///
+/// Embassy time doesn't advance while in Stop.
+#[cfg(not(ch32v0))]
+pub fn enter_stop() {
+    critical_section::with(|_| {
+        RCC.apb1pcenr().modify(|w| w.set_pwren(true));
+        PWR.ctlr().modify(|w| {
+            w.set_pdds(false);
+            w.set_lpds(true);
+        });
+
+        let ctlr = RCC.ctlr().read();
+        let cfgr0 = RCC.cfgr0().read();
+
+        // WFI wakes on a pending interrupt even with interrupts globally disabled
+        PFIC.sctlr().modify(|w| w.set_sleepdeep(true));
+        unsafe { qingke::riscv::asm::wfi() };
+        PFIC.sctlr().modify(|w| w.set_sleepdeep(false));
+
+        restore_clocks(ctlr, cfgr0);
+    });
+}
+
+/// Enter Standby. Wakeup (WKUP pin rising edge, RTC alarm) resets the chip.
+///
+/// Everything but the backup domain is lost, tell a wakeup from a power-on with the backup
+/// registers or [`rcc::reset_cause`](crate::rcc::reset_cause).
+#[cfg(not(ch32v0))]
+pub fn enter_standby() -> ! {
+    critical_section::with(|_| {
+        RCC.apb1pcenr().modify(|w| w.set_pwren(true));
+        // A stale wakeup flag would wake it up right away
+        PWR.ctlr().modify(|w| {
+            w.set_pdds(true);
+            w.set_cwuf(true);
+        });
+        PFIC.sctlr().modify(|w| w.set_sleepdeep(true));
+    });
+
+    loop {
+        unsafe { qingke::riscv::asm::wfi() };
+    }
+}
+
+/// Enter Standby. Wakeup resets the chip, see `stop_with_awu` to resume execution instead.
+///
+/// Everything but the option bytes and flash is lost.
+#[cfg(ch32v0)]
+pub fn enter_standby() -> ! {
+    critical_section::with(|_| {
+        RCC.apb1pcenr().modify(|w| w.set_pwren(true));
+        PWR.ctlr().modify(|w| w.set_pdds(true));
+        PFIC.sctlr().modify(|w| w.set_sleepdeep(true));
+    });
+
+    loop {
+        unsafe { qingke::riscv::asm::wfi() };
+    }
+}
+
+/// EXTI line connected to the AWU.
+#[cfg(all(feature = "low-power", ch32v0))]
+const AWU_EXTI_LINE: usize = 9;
+
+/// AWU prescaler register values and their LSI division factors.
+#[cfg(all(feature = "low-power", ch32v0))]
+const AWU_PRESCALERS: [(u8, u32); 14] = [
+    (0b0010, 2),
+    (0b0011, 4),
+    (0b0100, 8),
+    (0b0101, 16),
+    (0b0110, 32),
+    (0b0111, 64),
+    (0b1000, 128),
+    (0b1001, 256),
+    (0b1010, 512),
+    (0b1011, 1024),
+    (0b1100, 2048),
+    (0b1101, 4096),
+    (0b1110, 10240),
+    (0b1111, 61440),
+];
+
+/// The AWU window register is 6 bits wide.
+#[cfg(all(feature = "low-power", ch32v0))]
+const AWU_WINDOW_MAX: u32 = 0x3f;
+
+/// Longest sleep a single [`stop_with_awu`] call can do, about 30 seconds.
+#[cfg(all(feature = "low-power", ch32v0))]
+pub const MAX_AWU_DURATION: Duration =
+    Duration::from_micros(AWU_WINDOW_MAX as u64 * 61440 * 1_000_000 / crate::rcc::LSI_FREQUENCY.0 as u64);
+
+/// Pick the finest AWU prescaler that can represent `duration`.
+///
+/// Returns `(prescaler register value, window, LSI cycles)`, with the window rounded down.
+#[cfg(all(feature = "low-power", ch32v0))]
+fn awu_config(duration: Duration) -> Option<(u8, u8, u64)> {
+    let lsi_cycles = duration.as_micros() * crate::rcc::LSI_FREQUENCY.0 as u64 / 1_000_000;
+
+    AWU_PRESCALERS.iter().find_map(|&(psc, div)| {
+        let window = lsi_cycles / div as u64;
+        (window <= AWU_WINDOW_MAX as u64).then_some((psc, window as u8, window * div as u64))
+    })
+}
+
+/// Enter Standby, and wake up after (at most) `duration` using the AWU.
+///
+/// Durations longer than [`MAX_AWU_DURATION`] are clamped. Returns the time actually slept as
+/// estimated from the nominal LSI frequency, which is zero if `duration` is too short for the AWU.
+///
+/// The clock configuration (HSE, PLL and system clock switch) is restored after wakeup, and the
+/// time driver is advanced by the returned duration before any task is woken. Call this from the
+/// idle loop when the next embassy deadline is far away.
+#[cfg(all(feature = "low-power", ch32v0))]
+pub fn stop_with_awu(duration: Duration) -> Duration {
+    let duration = duration.min(MAX_AWU_DURATION);
+
+    let Some((psc, window, lsi_cycles)) = awu_config(duration) else {
+        return Duration::from_ticks(0);
+    };
+    if window == 0 {
+        return Duration::from_ticks(0);
+    }
+
+    let slept = Duration::from_micros(lsi_cycles * 1_000_000 / crate::rcc::LSI_FREQUENCY.0 as u64);
+
+    critical_section::with(|_cs| {
+        // LSI clocks the AWU
+        RCC.rstsckr().modify(|w| w.set_lsion(true));
+        while !RCC.rstsckr().read().lsirdy() {}
+
+        RCC.apb1pcenr().modify(|w| w.set_pwren(true));
+
+        // AWU wakes the core through an EXTI event, interrupts stay masked.
+        EXTI.evenr().modify(|w| w.set_mr(AWU_EXTI_LINE, true));
+        EXTI.rtenr().modify(|w| w.set_tr(AWU_EXTI_LINE, true));
+
+        PWR.awupsc().write(|w| w.set_awupsc(psc));
+        PWR.awuwr().write(|w| w.set_awuwr(window));
+        PWR.awucsr().modify(|w| w.set_awuen(true));
+
+        // Standby instead of Sleep on deep sleep
+        PWR.ctlr().modify(|w| w.set_pdds(true));
+
+        // Clocks fall back to HSI on wakeup
+        let ctlr = RCC.ctlr().read();
+        let cfgr0 = RCC.cfgr0().read();
+
+        PFIC.sctlr().modify(|w| {
+            w.set_sleepdeep(true);
+            w.set_wfitowfe(true);
+        });
+        unsafe { qingke::riscv::asm::wfi() };
+        PFIC.sctlr().modify(|w| {
+            w.set_sleepdeep(false);
+            w.set_wfitowfe(false);
+        });
+
+        PWR.awucsr().modify(|w| w.set_awuen(false));
+        EXTI.intfr().write(|w| w.0 = 1 << AWU_EXTI_LINE);
+
+        restore_clocks(ctlr, cfgr0);
+
+        #[cfg(time_driver_timer)]
+        crate::embassy::time_driver_impl::on_wakeup(_cs, slept.as_ticks());
+    });
+
+    slept
+}
+
+#[cfg(any(not(ch32v0), feature = "low-power"))]
+fn restore_clocks(ctlr: crate::pac::rcc::regs::Ctlr, cfgr0: crate::pac::rcc::regs::Cfgr0) {
+    if ctlr.hseon() {
+        RCC.ctlr().modify(|w| w.set_hseon(true));
+        while !RCC.ctlr().read().hserdy() {}
+    }
+    if ctlr.pllon() {
+        RCC.ctlr().modify(|w| w.set_pllon(true));
+        while !RCC.ctlr().read().pllrdy() {}
+    }
+    RCC.cfgr0().write_value(cfgr0);
+    while RCC.cfgr0().read().sws() != cfgr0.sw() {}
+}
@@ -110,6 +110,9 @@ impl<'d, T, const NR_EP: usize> Driver<'d, T, NR_EP>
 where
     T: Instance,
 {
+    /// Create a new USB driver.
+    ///
+    /// Panics if the USB clock isn't 48 MHz, see `usb_pre` in [`rcc::Config`](crate::rcc::Config).
     pub fn new(
         _usb: impl Peripheral<P = T> + 'd,
         // _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
@@ -118,6 +121,7 @@ where
         ep_buffer: &'d mut [EndpointDataBuffer; NR_EP],
     ) -> Self {
         assert!(ep_buffer.len() > 0);
+        crate::rcc::require_usb_clock();
         let dp = dp.into_ref();
         let dm = dm.into_ref();
 
@@ -252,11 +256,18 @@ impl<'d, T: Instance, const NR_EP: usize> embassy_usb_driver::Driver<'d> for Dri
     }
 }
 
-pub struct Bus<'d, T> {
+pub struct Bus<'d, T: Instance> {
     _phantom: PhantomData<&'d T>,
     inited: bool,
 }
 
+impl<'d, T: Instance> Drop for Bus<'d, T> {
+    fn drop(&mut self) {
+        crate::rcc::release_usb_clock();
+        T::disable();
+    }
+}
+
 impl<'d, T: Instance> Bus<'d, T> {
     fn bus_reset(&mut self) {
         let regs = T::regs();
@@ -1,13 +1,21 @@
 use critical_section::CriticalSection;
 
 pub(crate) trait SealedRccPeripheral {
-    fn frequency() -> crate::time::Hertz;
+    /// Take a reference to the peripheral. The first one enables its clock and resets it.
     fn enable_and_reset_with_cs(cs: CriticalSection);
+    /// Take a reference to the peripheral without resetting it, for using registers another
+    /// driver may have set up.
+    fn enable_with_cs(cs: CriticalSection);
+    /// Release a reference to the peripheral. The last one disables its clock, unless the
+    /// `keep-clocks-on` feature is enabled.
     fn disable_with_cs(cs: CriticalSection);
 
     fn enable_and_reset() {
         critical_section::with(|cs| Self::enable_and_reset_with_cs(cs))
     }
+    fn enable() {
+        critical_section::with(|cs| Self::enable_with_cs(cs))
+    }
     fn disable() {
         critical_section::with(|cs| Self::disable_with_cs(cs))
     }
@@ -18,6 +26,12 @@ pub(crate) trait SealedRemapPeripheral {
 }
 
 #[allow(private_bounds)]
-pub trait RccPeripheral: SealedRccPeripheral + 'static {}
+pub trait RccPeripheral: SealedRccPeripheral + 'static {
+    /// Kernel clock of the peripheral, from [`crate::rcc::clocks()`].
+    ///
+    /// This is the clock of the bus it's on, with the x2 of timers on a divided APB applied,
+    /// or the ADC clock for ADCs.
+    fn frequency() -> crate::time::Hertz;
+}
 #[allow(private_bounds)]
 pub trait RemapPeripheral: SealedRemapPeripheral + 'static {}
@@ -33,6 +33,18 @@ impl Config {
         apb2_pre: APBPrescaler::DIV1,
     };
 }
+impl Config {
+    /// SYSCLK frequency, 0 if the sysclk source is not configured
+    pub(super) const fn sysclk_freq(&self) -> u32 {
+        match self.sys {
+            Sysclk::HSI => HSI_FREQUENCY.0,
+            // MUL2 is the only option
+            Sysclk::PLL => HSI_FREQUENCY.0 * 2,
+            _ => 0,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -65,6 +77,8 @@ pub(crate) unsafe fn init(config: Config) {
 
     if sysclk >= 24_000_000 {
         FLASH.actlr().modify(|w| w.set_latency(0b01)); // 1 等待（24MHz<HCLK≤48MHz）
+    } else {
+        FLASH.actlr().modify(|w| w.set_latency(0b00));
     }
 
     RCC.cfgr0().modify(|w| {
@@ -90,15 +104,31 @@ pub(crate) unsafe fn init(config: Config) {
         _ => panic!(),
     };
 
-    let pclk2 = Hertz(hclk) / config.apb2_pre;
+    // there is no APB prescaler, the divider only feeds the ADC
+    let adcclk = Hertz(hclk) / config.apb2_pre;
 
     super::CLOCKS.sysclk = Hertz(sysclk);
     super::CLOCKS.hclk = Hertz(hclk);
     super::CLOCKS.pclk1 = Hertz(hclk);
-    super::CLOCKS.pclk2 = pclk2;
+    super::CLOCKS.pclk2 = Hertz(hclk);
+
+    super::CLOCKS.pclk1_tim = Hertz(hclk);
+    super::CLOCKS.pclk2_tim = Hertz(hclk);
+
+    super::CLOCKS.adcclk = adcclk;
+
+    super::CLOCKS.pll = (config.sys == Sysclk::PLL).then_some(Hertz(sysclk));
+}
+
+/// Run from HSI, so that the PLL can be reconfigured by [`init`].
+///
+/// The prescalers are left alone, HCLK only goes down.
+pub(super) unsafe fn switch_to_hsi() {
+    // Slowest flash timing, valid for any SYSCLK
+    FLASH.actlr().modify(|w| w.set_latency(0b01));
 
-    super::CLOCKS.pclk1_tim = Hertz(sysclk);
-    super::CLOCKS.pclk2_tim = Hertz(sysclk);
+    RCC.cfgr0().modify(|w| w.set_sw(Sysclk::HSI));
+    while RCC.cfgr0().read().sws() != Sysclk::HSI {}
 }
 
 impl ops::Div<APBPrescaler> for Hertz {
@@ -0,0 +1,122 @@
+//! CSS, Clock Security System
+//!
+//! With [`Config::css`](super::Config::css) set, a failure of the HSE oscillator raises an NMI.
+//! The hardware has already switched SYSCLK to HSI at that point. The NMI handler only clears the
+//! flag, makes sure HSI is selected, and pends the software interrupt. That one turns off HSE and
+//! the PLL, updates [`clocks()`](super::clocks), the delay and the time driver, and then calls
+//! the handler registered with [`set_css_handler`].
+//!
+//! The HAL defines the `NonMaskableInt` and `Software` handlers (with the `rt` feature), so they
+//! can't be defined by the application as well.
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use critical_section::Mutex;
+
+use super::rcc_impl::{calc_pclk, Sysclk, HSI_FREQUENCY};
+use super::{calc_adcclk, Clocks};
+use crate::pac::RCC;
+
+/// Passed to the CSS handler after the system clock fell back to HSI.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ClockFailure {
+    /// Clocks before HSE failed, the new ones are in [`clocks()`](super::clocks)
+    pub previous: Clocks,
+}
+
+static CSS_HANDLER: Mutex<Cell<Option<fn(ClockFailure)>>> = Mutex::new(Cell::new(None));
+static CSS_TRIPPED: AtomicBool = AtomicBool::new(false);
+/// Set by the NMI, taken by the software interrupt
+static CSS_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Core interrupt number of the software interrupt
+const SOFTWARE_IRQ: u8 = 14;
+
+/// Register a function to be called on HSE failure.
+///
+/// The handler runs in the software interrupt, after the clocks have been updated. It should not
+/// block for long, lower priority interrupts wait until it returns.
+pub fn set_css_handler(handler: fn(ClockFailure)) {
+    critical_section::with(|cs| CSS_HANDLER.borrow(cs).set(Some(handler)));
+}
+
+/// Returns `true` if an HSE failure was detected since boot.
+pub fn css_tripped() -> bool {
+    CSS_TRIPPED.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "rt")]
+#[qingke_rt::interrupt(core)]
+fn NonMaskableInt() {
+    on_nmi();
+}
+
+#[cfg(feature = "rt")]
+#[qingke_rt::interrupt(core)]
+fn Software() {
+    on_css();
+}
+
+/// Keep the NMI short: it can't be masked, so it must not touch anything shared with the
+/// interrupted code, like `CLOCKS` or a critical section.
+fn on_nmi() {
+    if !RCC.intr().read().cssf() {
+        return;
+    }
+    RCC.intr().modify(|w| w.set_cssc(true));
+
+    RCC.cfgr0().modify(|w| w.set_sw(Sysclk::HSI));
+    while RCC.cfgr0().read().sws() != Sysclk::HSI {}
+
+    CSS_TRIPPED.store(true, Ordering::Relaxed);
+    CSS_PENDING.store(true, Ordering::Release);
+    unsafe {
+        qingke::pfic::enable_interrupt(SOFTWARE_IRQ);
+        qingke::pfic::pend_interrupt(SOFTWARE_IRQ);
+    }
+}
+
+fn on_css() {
+    if !CSS_PENDING.swap(false, Ordering::Acquire) {
+        return;
+    }
+
+    let previous = *super::clocks();
+
+    // Turn off everything that depends on the failed HSE
+    RCC.ctlr().modify(|w| {
+        w.set_pllon(false);
+        w.set_csson(false);
+        w.set_hseon(false);
+    });
+
+    let cfgr0 = RCC.cfgr0().read();
+    let sys = HSI_FREQUENCY;
+    let hclk = sys / cfgr0.hpre();
+    let (pclk1, pclk1_tim) = calc_pclk(hclk, cfgr0.ppre1());
+    let (pclk2, pclk2_tim) = calc_pclk(hclk, cfgr0.ppre2());
+
+    critical_section::with(|_| unsafe {
+        super::CLOCKS.sysclk = sys;
+        super::CLOCKS.hclk = hclk;
+        super::CLOCKS.pclk1 = pclk1;
+        super::CLOCKS.pclk2 = pclk2;
+        super::CLOCKS.pclk1_tim = pclk1_tim;
+        super::CLOCKS.pclk2_tim = pclk2_tim;
+        super::CLOCKS.adcclk = calc_adcclk(pclk2);
+        super::CLOCKS.hse = None;
+        super::CLOCKS.pll = None;
+        // USB is clocked from the PLL
+        super::CLOCKS.usb = None;
+        // A delay interrupted by the failure may be off, later ones use the new clock
+        crate::delay::init();
+    });
+
+    #[cfg(feature = "embassy")]
+    crate::embassy::on_clock_change();
+
+    if let Some(handler) = critical_section::with(|cs| CSS_HANDLER.borrow(cs).get()) {
+        handler(ClockFailure { previous });
+    }
+}
@@ -0,0 +1,145 @@
+//! MCO, Microcontroller Clock Output on PA8
+
+use super::{clocks, HSI_FREQUENCY};
+use crate::gpio::{AFType, SealedPin, Speed};
+use crate::pac::{GPIOA, RCC};
+use crate::time::Hertz;
+use crate::{into_ref, peripherals, Peripheral, PeripheralRef};
+
+/// MCO clock source, RCC_CFGR0.MCO
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+#[allow(non_camel_case_types)]
+pub enum McoSource {
+    /// System clock
+    SYSCLK = 0b0100,
+    /// HSI
+    HSI = 0b0101,
+    /// HSE
+    HSE = 0b0110,
+    /// PLL clock divided by 2
+    PLL_DIV2 = 0b0111,
+    /// PLL2 clock
+    #[cfg(d8c)]
+    PLL2 = 0b1000,
+    /// PLL3 clock divided by 2
+    #[cfg(d8c)]
+    PLL3_DIV2 = 0b1001,
+    /// XT1 external oscillator (HSE)
+    #[cfg(d8c)]
+    XT1 = 0b1010,
+    /// PLL3 clock, for Ethernet
+    #[cfg(d8c)]
+    PLL3 = 0b1011,
+}
+
+impl McoSource {
+    /// Whether the oscillator or PLL behind this source is running.
+    pub fn is_running(self) -> bool {
+        let ctlr = RCC.ctlr().read();
+        match self {
+            Self::SYSCLK => true,
+            Self::HSI => ctlr.hsirdy(),
+            Self::HSE => ctlr.hserdy(),
+            Self::PLL_DIV2 => ctlr.pllrdy(),
+            #[cfg(d8c)]
+            Self::PLL2 => ctlr.pll2rdy(),
+            #[cfg(d8c)]
+            Self::PLL3_DIV2 | Self::PLL3 => ctlr.pll3rdy(),
+            #[cfg(d8c)]
+            Self::XT1 => ctlr.hserdy(),
+        }
+    }
+
+    /// Output frequency, from [`clocks()`]. `None` if the source isn't running, or its frequency
+    /// isn't tracked (PLL2 and PLL3).
+    pub fn frequency(self) -> Option<Hertz> {
+        if !self.is_running() {
+            return None;
+        }
+        match self {
+            Self::SYSCLK => Some(clocks().sysclk),
+            Self::HSI => Some(HSI_FREQUENCY),
+            Self::HSE => clocks().hse,
+            Self::PLL_DIV2 => clocks().pll.map(|pll| pll / 2u32),
+            #[cfg(d8c)]
+            Self::XT1 => clocks().hse,
+            #[cfg(d8c)]
+            _ => None,
+        }
+    }
+}
+
+/// Clock output on PA8.
+///
+/// The pin is rated for 50 MHz, so the selected source should not be faster. There is no MCO
+/// prescaler, [`McoSource::PLL_DIV2`] is the only divided source.
+pub struct Mco<'d> {
+    _pin: PeripheralRef<'d, peripherals::PA8>,
+    source: McoSource,
+    /// PA8 MODE/CNF and output level before it was taken over
+    saved_cfg: (u8, u8, bool),
+}
+
+impl<'d> Mco<'d> {
+    /// Output `source` on PA8.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the oscillator or PLL behind `source` isn't running, see
+    /// [`McoSource::is_running`].
+    pub fn new(pin: impl Peripheral<P = peripherals::PA8> + 'd, source: McoSource) -> Self {
+        assert!(source.is_running(), "RCC: MCO source is not running");
+        into_ref!(pin);
+
+        let cfghr = GPIOA.cfghr().read();
+        let saved_cfg = (
+            cfghr.mode(0).to_bits(),
+            cfghr.cnf(0).to_bits(),
+            GPIOA.outdr().read().odr(8),
+        );
+
+        pin.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        set_mco(Some(source));
+
+        Self {
+            _pin: pin,
+            source,
+            saved_cfg,
+        }
+    }
+
+    /// Change the clock output on PA8 at runtime.
+    ///
+    /// Panics like [`Mco::new`] if the source isn't running.
+    pub fn set_source(&mut self, source: McoSource) {
+        assert!(source.is_running(), "RCC: MCO source is not running");
+        set_mco(Some(source));
+        self.source = source;
+    }
+
+    /// Frequency on PA8, see [`McoSource::frequency`].
+    pub fn frequency(&self) -> Option<Hertz> {
+        self.source.frequency()
+    }
+}
+
+impl<'d> Drop for Mco<'d> {
+    /// Disable MCO and return PA8 to the configuration it had before [`Mco::new`].
+    fn drop(&mut self) {
+        set_mco(None);
+
+        let (mode, cnf, odr) = self.saved_cfg;
+        GPIOA.outdr().modify(|w| w.set_odr(8, odr));
+        GPIOA.cfghr().modify(|w| {
+            w.set_mode(0, crate::pac::gpio::vals::Mode::from_bits(mode));
+            w.set_cnf(0, crate::pac::gpio::vals::Cnf::from_bits(cnf));
+        });
+    }
+}
+
+fn set_mco(source: Option<McoSource>) {
+    // MCO[3] only exists on CH32V30x
+    RCC.cfgr0().modify(|w| w.set_mco(source.map_or(0, |s| s as u8)));
+}
@@ -1,3 +1,7 @@
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
 use crate::time::Hertz;
 
 const DEFAULT_FREQUENCY: Hertz = Hertz(8_000_000);
@@ -11,6 +15,12 @@ static mut CLOCKS: Clocks = Clocks {
 
     pclk1_tim: DEFAULT_FREQUENCY,
     pclk2_tim: DEFAULT_FREQUENCY,
+    adcclk: DEFAULT_FREQUENCY,
+
+    hse: None,
+    pll: None,
+    usb: None,
+    rtc: None,
 };
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -23,8 +33,25 @@ pub struct Clocks {
     /// APB2 clock
     pub pclk2: Hertz,
 
-    pub(crate) pclk1_tim: Hertz,
-    pub(crate) pclk2_tim: Hertz,
+    /// Timers on APB1, twice `pclk1` if APB1 is divided
+    pub pclk1_tim: Hertz,
+    /// Timers on APB2, twice `pclk2` if APB2 is divided
+    pub pclk2_tim: Hertz,
+    /// ADC clock, after the ADC prescaler
+    pub adcclk: Hertz,
+
+    /// HSE frequency, `None` if HSE is off
+    pub hse: Option<Hertz>,
+    /// PLL output, `None` if the PLL is off
+    pub pll: Option<Hertz>,
+
+    /// USB full-speed clock (USBD, USBFS/OTG), `None` if USB has no usable clock
+    ///
+    /// USB drivers need exactly 48 MHz, see `usb_pre` in [`Config`].
+    pub usb: Option<Hertz>,
+
+    /// RTC clock, `None` if the RTC has no clock source
+    pub rtc: Option<Hertz>,
 }
 
 #[inline]
@@ -32,6 +59,15 @@ pub fn clocks() -> &'static Clocks {
     unsafe { &CLOCKS }
 }
 
+/// Turn off the clock of every peripheral that no driver uses.
+///
+/// Peripheral clocks are turned off when their last driver is dropped, unless the `keep-clocks-on`
+/// feature is set. This also turns off the ones that are still on without a driver, e.g. after
+/// a bootloader. GPIO and DMA clocks, PWR, BKP and AFIO stay on.
+pub fn disable_unused() {
+    critical_section::with(crate::_generated::disable_unused);
+}
+
 #[cfg(ch32v0)]
 #[path = "v0.rs"]
 mod rcc_impl;
@@ -54,6 +90,75 @@ mod rcc_impl;
 
 pub use rcc_impl::*;
 
+#[cfg(any(ch32v2, ch32v3))]
+mod mco;
+#[cfg(any(ch32v2, ch32v3))]
+pub use mco::{Mco, McoSource};
+
+#[cfg(not(any(ch32v0, ch32x0, ch641)))]
+mod css;
+#[cfg(not(any(ch32v0, ch32x0, ch641)))]
+pub use css::{css_tripped, set_css_handler, ClockFailure};
+
+/// Highest SYSCLK allowed by the datasheet
+#[cfg(any(ch32v0, ch32x0, ch641))]
+pub const SYSCLK_MAX_FREQUENCY: Hertz = Hertz(48_000_000);
+/// Highest SYSCLK allowed by the datasheet
+#[cfg(ch32v1)]
+pub const SYSCLK_MAX_FREQUENCY: Hertz = Hertz(80_000_000);
+/// Highest SYSCLK allowed by the datasheet
+#[cfg(ch32l1)]
+pub const SYSCLK_MAX_FREQUENCY: Hertz = Hertz(96_000_000);
+/// Highest SYSCLK allowed by the datasheet
+#[cfg(any(ch32v2, ch32v3, ch32f2))]
+pub const SYSCLK_MAX_FREQUENCY: Hertz = Hertz(144_000_000);
+
+/// Highest ADC clock allowed by the datasheet
+#[cfg(any(ch32v1, ch32l1, ch32v2, ch32v3, ch32f2))]
+pub const ADC_MAX_FREQUENCY: Hertz = Hertz(14_000_000);
+
+/// ADC prescaler from PCLK2, RCC_CFGR0.ADCPRE
+#[cfg(any(ch32v1, ch32l1, ch32v2, ch32v3, ch32f2))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdcPrescaler {
+    DIV2 = 0b00,
+    DIV4 = 0b01,
+    DIV6 = 0b10,
+    DIV8 = 0b11,
+}
+
+#[cfg(any(ch32v1, ch32l1, ch32v2, ch32v3, ch32f2))]
+impl AdcPrescaler {
+    /// Division factor
+    pub const fn divisor(self) -> u32 {
+        (self as u32 + 1) * 2
+    }
+
+    /// The smallest prescaler that keeps the ADC clock within [`ADC_MAX_FREQUENCY`].
+    ///
+    /// Returns `DIV8` if even that is too fast, [`crate::adc::Adc::new`] panics in that case.
+    pub const fn for_pclk2(pclk2: Hertz) -> Self {
+        let all = [Self::DIV2, Self::DIV4, Self::DIV6, Self::DIV8];
+        let mut i = 0;
+        while i < all.len() {
+            if pclk2.0 / all[i].divisor() <= ADC_MAX_FREQUENCY.0 {
+                return all[i];
+            }
+            i += 1;
+        }
+        Self::DIV8
+    }
+}
+
+/// ADC clock from PCLK2 and the current ADCPRE
+#[cfg(any(ch32v1, ch32l1, ch32v2, ch32v3, ch32f2))]
+fn calc_adcclk(pclk2: Hertz) -> Hertz {
+    let adcpre = crate::pac::RCC.cfgr0().read().adcpre();
+    // DIV2, DIV4, DIV6, DIV8
+    Hertz(pclk2.0 / ((adcpre as u32 + 1) * 2))
+}
+
 #[cfg(not(ch32v208))]
 pub const LSI_FREQ: Hertz = Hertz(40_000);
 #[cfg(ch32v208)]
@@ -71,6 +176,7 @@ pub struct LseConfig {
     pub mode: LseMode,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum RtcClockSource {
     LSE,
     LSI,
@@ -79,55 +185,582 @@ pub enum RtcClockSource {
     DISABLE,
 }
 
+/// Low-speed oscillators and RTC clock, in the backup domain
 pub struct LsConfig {
-    pub rtc: RtcClockSource,
+    /// RTC clock source. With `DISABLE` the backup domain is left untouched.
+    pub rtc_mux: RtcClockSource,
+    /// Turn on LSI, even if it's not the RTC clock
     pub lsi: bool,
+    /// Turn on LSE, even if it's not the RTC clock
     pub lse: Option<LseConfig>,
+    /// Allow a backup domain reset if the RTC runs from another clock than `rtc_mux`. The reset
+    /// clears the RTC and the [backup registers](crate::backup). Without it, init panics instead.
+    pub reset_backup_domain: bool,
 }
 
 impl LsConfig {
     pub const fn default_lse() -> Self {
         Self {
-            rtc: RtcClockSource::LSE,
+            rtc_mux: RtcClockSource::LSE,
             lse: Some(LseConfig {
                 frequency: Hertz(32_768),
                 mode: LseMode::Oscillator, // (LseDrive::MediumHigh),
             }),
             lsi: false,
+            reset_backup_domain: false,
         }
     }
 
     pub const fn default_lsi() -> Self {
         Self {
-            rtc: RtcClockSource::LSI,
+            rtc_mux: RtcClockSource::LSI,
             lsi: true,
             lse: None,
+            reset_backup_domain: false,
         }
     }
 
     pub const fn off() -> Self {
         Self {
-            rtc: RtcClockSource::DISABLE,
+            rtc_mux: RtcClockSource::DISABLE,
             lsi: false,
             lse: None,
+            reset_backup_domain: false,
         }
     }
 }
 
+/// Leaves the backup domain alone, select an RTC clock to use the [RTC](crate::rtc).
 impl Default for LsConfig {
     fn default() -> Self {
-        Self::default_lsi()
+        Self::off()
+    }
+}
+
+/// Start HSE, as a crystal oscillator or with an external clock on OSC_IN (`bypass`).
+///
+/// HSEBYP can only be written while HSE is off, so a running HSE is restarted if its mode is
+/// different. Panics if HSE isn't ready after a second or more.
+#[cfg(not(any(ch32x0, ch641)))]
+fn start_hse(bypass: bool) {
+    use crate::pac::RCC;
+
+    let ctlr = RCC.ctlr().read();
+    if !ctlr.hseon() || ctlr.hsebyp() != bypass {
+        RCC.ctlr().modify(|w| w.set_hseon(false));
+        while RCC.ctlr().read().hserdy() {}
+        RCC.ctlr().modify(|w| w.set_hsebyp(bypass));
+        RCC.ctlr().modify(|w| w.set_hseon(true));
+    }
+
+    // An external clock is ready right away, a crystal takes a few ms
+    let mut timeout = clocks().sysclk.0;
+    while !RCC.ctlr().read().hserdy() {
+        timeout = timeout.saturating_sub(1);
+        if timeout == 0 {
+            if bypass {
+                panic!("RCC: HSE did not start, check the external clock on OSC_IN");
+            } else {
+                panic!("RCC: HSE did not start, check the crystal, or use `HseMode::Bypass` for an external clock");
+            }
+        }
     }
 }
 
-// TODO: implement LS clock configuration
-#[allow(unused)]
+/// Number of LSERDY polls before giving up, one second or more at any system clock.
+#[cfg(not(any(ch32v0, ch32x0, ch641)))]
+fn lse_timeout() -> u32 {
+    clocks().sysclk.0
+}
+
+#[cfg(not(any(ch32v0, ch32x0, ch641)))]
 impl LsConfig {
-    pub(crate) fn init(&self) -> Option<Hertz> {
-        todo!()
+    /// Start the configured oscillators and select the RTC clock, returns the RTC frequency.
+    ///
+    /// The backup domain is only reset if the RTC clock source changes and `reset_backup_domain`
+    /// allows it, so a running RTC keeps its time across resets.
+    pub(crate) fn init(&self, hse: Option<Hertz>) -> Option<Hertz> {
+        use crate::pac::{PWR, RCC};
+
+        let rtc_sel: u8 = match self.rtc_mux {
+            RtcClockSource::DISABLE => 0b00,
+            RtcClockSource::LSE => 0b01,
+            RtcClockSource::LSI => 0b10,
+            RtcClockSource::HSE => 0b11,
+        };
+
+        if self.lsi {
+            RCC.rstsckr().modify(|w| w.set_lsion(true));
+            while !RCC.rstsckr().read().lsirdy() {}
+        }
+
+        let rtc_clk = match self.rtc_mux {
+            RtcClockSource::DISABLE => None,
+            RtcClockSource::LSE => Some(
+                self.lse
+                    .as_ref()
+                    .expect("RCC: LSE selected as RTC clock, but `lse` is not configured")
+                    .frequency,
+            ),
+            RtcClockSource::LSI => {
+                if !self.lsi {
+                    panic!("RCC: LSI selected as RTC clock, but `lsi` is not enabled");
+                }
+                Some(LSI_FREQ)
+            }
+            RtcClockSource::HSE => {
+                Some(hse.expect("RCC: HSE selected as RTC clock, but `hse` is not configured") / 128u32)
+            }
+        };
+
+        // Nothing in the backup domain to configure
+        if self.rtc_mux == RtcClockSource::DISABLE && self.lse.is_none() {
+            return None;
+        }
+
+        // Unlock the backup domain
+        RCC.apb1pcenr().modify(|w| {
+            w.set_pwren(true);
+            w.set_bkpen(true);
+        });
+        PWR.ctlr().modify(|w| w.set_dbp(true));
+
+        // RTCSEL can only be changed by a backup domain reset
+        let bdctlr = RCC.bdctlr().read();
+        if self.rtc_mux != RtcClockSource::DISABLE && bdctlr.rtcsel() != 0 && bdctlr.rtcsel() != rtc_sel {
+            assert!(
+                self.reset_backup_domain,
+                "RCC: the RTC runs from another clock, set `ls.reset_backup_domain` to reset the backup domain"
+            );
+            RCC.bdctlr().modify(|w| w.set_bdrst(true));
+            RCC.bdctlr().modify(|w| w.set_bdrst(false));
+        }
+
+        if let Some(lse) = &self.lse {
+            let bypass = matches!(lse.mode, LseMode::Bypass);
+            let bdctlr = RCC.bdctlr().read();
+            // LSEBYP can only be written while LSE is off
+            if !bdctlr.lseon() || bdctlr.lsebyp() != bypass {
+                RCC.bdctlr().modify(|w| w.set_lseon(false));
+                RCC.bdctlr().modify(|w| w.set_lsebyp(bypass));
+                RCC.bdctlr().modify(|w| w.set_lseon(true));
+            }
+
+            let mut timeout = lse_timeout();
+            while !RCC.bdctlr().read().lserdy() {
+                timeout = timeout.saturating_sub(1);
+                if timeout == 0 {
+                    panic!("RCC: LSE did not start, check the 32.768 kHz crystal and its load capacitors");
+                }
+            }
+        }
+
+        if self.rtc_mux != RtcClockSource::DISABLE {
+            RCC.bdctlr().modify(|w| {
+                w.set_rtcsel(rtc_sel);
+                w.set_rtcen(true);
+            });
+        }
+
+        rtc_clk
+    }
+}
+
+/// Largest value accepted by [`set_hsi_trim`], HSITRIM is 5 bits wide.
+pub const HSI_TRIM_MAX: u8 = 31;
+/// Power-on value of HSITRIM, which leaves the factory calibration (HSICAL) unchanged.
+pub const HSI_TRIM_DEFAULT: u8 = 16;
+
+/// Set the HSI trimming value, RCC_CTLR.HSITRIM.
+///
+/// The trim adds to the factory calibration, higher values increase the frequency. One step is
+/// roughly 60 kHz (0.25 %) on the 24 MHz HSI of CH32V003, and 20 kHz (0.25 %) on the 8 MHz HSI
+/// of CH32V1, CH32V2, CH32V3 and CH32L1.
+///
+/// [`clocks()`] keeps reporting the nominal HSI frequency.
+#[cfg(not(any(ch32x0, ch641)))]
+pub fn set_hsi_trim(trim: u8) {
+    assert!(trim <= HSI_TRIM_MAX, "RCC: HSI trim must be at most 31");
+    crate::pac::RCC.ctlr().modify(|w| w.set_hsitrim(trim));
+}
+
+/// Current HSI trimming value.
+#[cfg(not(any(ch32x0, ch641)))]
+pub fn hsi_trim() -> u8 {
+    crate::pac::RCC.ctlr().read().hsitrim()
+}
+
+/// Number of LSE cycles HSI is measured over, 31 ms at 32.768 kHz.
+#[cfg(not(any(ch32v0, ch32x0, ch641)))]
+const HSI_MEASURE_LSE_CYCLES: u32 = 1024;
+
+/// Trim HSI against LSE, and return the measured HSI frequency with the selected trim.
+///
+/// HSI is measured by counting SysTick ticks over a number of RTC clock cycles, which requires
+/// the RTC to be clocked by LSE (see [`LsConfig`]) with a prescaler of at least 1, and SYSCLK to
+/// be derived from HSI, directly or through the PLL. Each measurement takes about 31 ms, and
+/// the search uses 6 of them.
+///
+/// Panics if those conditions aren't met.
+#[cfg(not(any(ch32v0, ch32x0, ch641)))]
+pub fn calibrate_hsi_against_lse() -> Hertz {
+    use crate::pac::RCC;
+
+    let bdctlr = RCC.bdctlr().read();
+    if !(bdctlr.lserdy() && bdctlr.rtcen() && bdctlr.rtcsel() == 0b01) {
+        panic!("RCC: HSI calibration needs the RTC running from LSE");
+    }
+    let cfgr0 = RCC.cfgr0().read();
+    #[cfg(any(ch32v1, ch32l1))]
+    let pll_from_hsi = cfgr0.pllsrc() == PllSource::HSI;
+    // HSI when cleared
+    #[cfg(any(ch32v2, ch32v3, ch32f2))]
+    let pll_from_hsi = !cfgr0.pllsrc();
+    match cfgr0.sws() {
+        Sysclk::HSI => {}
+        Sysclk::PLL if pll_from_hsi => {}
+        _ => panic!("RCC: HSI calibration needs SYSCLK derived from HSI"),
+    }
+
+    // frequency increases with the trim value, find the first trim at or above nominal
+    let (mut lo, mut hi) = (0, HSI_TRIM_MAX);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        set_hsi_trim(mid);
+        if measure_hsi().0 < HSI_FREQUENCY.0 {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    // pick whichever of it and the trim below is closer
+    set_hsi_trim(lo);
+    let mut best = (lo, measure_hsi());
+    if lo > 0 {
+        set_hsi_trim(lo - 1);
+        let below = measure_hsi();
+        if below.0.abs_diff(HSI_FREQUENCY.0) < best.1 .0.abs_diff(HSI_FREQUENCY.0) {
+            best = (lo - 1, below);
+        }
+    }
+
+    set_hsi_trim(best.0);
+    best.1
+}
+
+/// Measure HSI against the RTC clock.
+#[cfg(not(any(ch32v0, ch32x0, ch641)))]
+fn measure_hsi() -> Hertz {
+    use crate::pac::RTC;
+
+    let rtc_clk = clocks().rtc.expect("RCC: RTC clock is not configured").0;
+    // RTC_DIVL counts RTC clock cycles
+    let divl = || RTC.divl().read().0 & 0xffff;
+
+    // wait for an edge of the RTC clock
+    let wait_edge = |last: u32| {
+        let mut timeout = lse_timeout();
+        loop {
+            let now = divl();
+            if now != last {
+                return now;
+            }
+            timeout = timeout.saturating_sub(1);
+            if timeout == 0 {
+                panic!("RCC: RTC prescaler is not counting");
+            }
+        }
+    };
+
+    let mut last = wait_edge(divl());
+    let start = crate::delay::counter();
+    for _ in 0..HSI_MEASURE_LSE_CYCLES {
+        last = wait_edge(last);
     }
+    let ticks = crate::delay::counter().wrapping_sub(start) as u64;
+
+    // the SysTick counter runs at its nominal frequency times the HSI error
+    let measured = ticks * rtc_clk as u64 / HSI_MEASURE_LSE_CYCLES as u64;
+    Hertz((HSI_FREQUENCY.0 as u64 * measured / crate::delay::counter_frequency() as u64) as u32)
 }
 
 pub unsafe fn init(config: Config) {
     rcc_impl::init(config);
 }
+
+/// Error from [`set_sysclk`] and [`reconfigure`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The configuration gives a SYSCLK above [`SYSCLK_MAX_FREQUENCY`]
+    FrequencyTooHigh,
+    /// A USB driver is running from the PLL, which would be restarted
+    UsbActive,
+}
+
+/// Switch to a new clock configuration at runtime, e.g. from the PLL down to HSI and back.
+///
+/// SYSCLK first moves to HSI with the slowest flash timing, then the clock tree is set up like
+/// at init. The flash wait states are set for the new SYSCLK before switching to it, so they
+/// are raised before the frequency goes up and lowered only after it went down.
+///
+/// Afterwards [`clocks()`] reports the new frequencies, and [`crate::delay`] and the embassy
+/// time driver are updated. Drivers that computed dividers from the old clocks (UART baud rate,
+/// SPI and I2C speed, timer prescalers) are not, create them again or reconfigure them.
+///
+/// The low-speed clocks are only set up by [`crate::init`], `config.ls` is ignored here so that
+/// the backup domain and a running RTC are left alone.
+///
+/// Returns [`Error::FrequencyTooHigh`] without touching the clocks if SYSCLK would exceed
+/// [`SYSCLK_MAX_FREQUENCY`]. The PLL is always restarted, so the switch is refused with
+/// [`Error::UsbActive`] while a USB driver runs from it (except on CH32X0, where USB runs from
+/// HSI). Drop the USB driver first. Panics on an inconsistent configuration, like
+/// [`crate::init`] does.
+///
+/// # Safety
+///
+/// Must not be called while a [`Delay`](crate::delay::Delay) runs in another context, or while
+/// DMA transfers or other peripherals that depend on the clocks are active.
+pub unsafe fn set_sysclk(#[allow(unused_mut)] mut config: Config) -> Result<(), Error> {
+    if config.sysclk_freq() > SYSCLK_MAX_FREQUENCY.0 {
+        return Err(Error::FrequencyTooHigh);
+    }
+    if usb_active() {
+        return Err(Error::UsbActive);
+    }
+
+    critical_section::with(|_| {
+        #[cfg(not(any(ch32v0, ch32x0, ch641)))]
+        let rtc = {
+            config.ls = LsConfig::off();
+            clocks().rtc
+        };
+        rcc_impl::switch_to_hsi();
+        rcc_impl::init(config);
+        #[cfg(not(any(ch32v0, ch32x0, ch641)))]
+        {
+            CLOCKS.rtc = rtc;
+        }
+        crate::delay::init();
+    });
+
+    #[cfg(feature = "embassy")]
+    crate::embassy::on_clock_change();
+
+    Ok(())
+}
+
+/// Maximum number of functions registered with [`add_clock_listener`].
+pub const MAX_CLOCK_LISTENERS: usize = 4;
+
+static CLOCK_LISTENERS: Mutex<Cell<[Option<fn(&Clocks)>; MAX_CLOCK_LISTENERS]>> =
+    Mutex::new(Cell::new([None; MAX_CLOCK_LISTENERS]));
+
+/// Number of USB drivers that need their 48 MHz clock.
+#[cfg(any(usbd, otg))]
+static USB_DRIVERS: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
+
+/// Register a function to be called with the new clocks after [`reconfigure`].
+///
+/// Use it for state derived from the clocks that the HAL doesn't update itself, for example to
+/// call `set_config` on a UART so that its baud rate divider is computed again.
+///
+/// Panics if [`MAX_CLOCK_LISTENERS`] functions are registered already.
+pub fn add_clock_listener(listener: fn(&Clocks)) {
+    critical_section::with(|cs| {
+        let cell = CLOCK_LISTENERS.borrow(cs);
+        let mut listeners = cell.get();
+        let slot = listeners
+            .iter_mut()
+            .find(|l| l.is_none())
+            .expect("RCC: too many clock listeners");
+        *slot = Some(listener);
+        cell.set(listeners);
+    });
+}
+
+/// Check that USB has its 48 MHz clock, and keep [`set_sysclk`] from taking it away until
+/// [`release_usb_clock`] is called.
+///
+/// Called by the USB drivers.
+#[cfg(any(usbd, otg))]
+pub(crate) fn require_usb_clock() {
+    assert_eq!(
+        clocks().usb,
+        Some(Hertz(48_000_000)),
+        "USB needs a 48 MHz clock, see `usb_pre` in `rcc::Config`"
+    );
+    critical_section::with(|cs| {
+        let drivers = USB_DRIVERS.borrow(cs);
+        drivers.set(drivers.get() + 1);
+    });
+}
+
+/// Called by a USB driver that no longer needs its clock.
+#[cfg(any(usbd, otg))]
+pub(crate) fn release_usb_clock() {
+    critical_section::with(|cs| {
+        let drivers = USB_DRIVERS.borrow(cs);
+        drivers.set(drivers.get().saturating_sub(1));
+    });
+}
+
+/// Returns `true` if a USB driver runs from a clock that [`set_sysclk`] would restart. On
+/// CH32X0 USB runs from HSI, which stays on.
+fn usb_active() -> bool {
+    #[cfg(all(any(usbd, otg), not(ch32x0)))]
+    return critical_section::with(|cs| USB_DRIVERS.borrow(cs).get() > 0);
+    #[cfg(not(all(any(usbd, otg), not(ch32x0))))]
+    return false;
+}
+
+/// Switch to a new clock configuration at runtime, and notify everything that depends on it.
+///
+/// The switch is done by [`set_sysclk`], which also updates [`clocks()`], [`crate::delay`] and
+/// the embassy time driver. Then the functions registered with [`add_clock_listener`] are
+/// called, and the new clocks are returned.
+///
+/// # Safety
+///
+/// Quiesce everything that runs from the clocks first: finish or stop DMA transfers, so that no
+/// peripheral request is serviced at the wrong rate, and stop UART, SPI, I2C and timer
+/// activity. USB is checked by [`set_sysclk`]. A [`Delay`](crate::delay::Delay) must not be
+/// running in another context.
+pub unsafe fn reconfigure(config: Config) -> Result<Clocks, Error> {
+    set_sysclk(config)?;
+
+    let clocks = *clocks();
+    let listeners = critical_section::with(|cs| CLOCK_LISTENERS.borrow(cs).get());
+    for listener in listeners.into_iter().flatten() {
+        listener(&clocks);
+    }
+
+    Ok(clocks)
+}
+
+/// Cause of the last reset, from the RCC_RSTSCKR flags.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetCause {
+    /// Power-on or power-down reset.
+    PowerOn,
+    /// Independent watchdog timeout.
+    IndependentWatchdog,
+    /// Window watchdog timeout.
+    WindowWatchdog,
+    /// Software reset, PFIC system reset request.
+    Software,
+    /// Entering Standby or Stop mode with the reset option bytes set.
+    LowPower,
+    /// Reset by the OPA (OPA_CTLR2 reset enable).
+    #[cfg(ch32x0)]
+    Opa,
+    /// External reset, NRST pin.
+    Pin,
+    /// No flag set, e.g. after [`clear_reset_flags`] without a reset since.
+    Unknown,
+}
+
+/// Get the cause of the last reset.
+///
+/// The flags are only set by resets and cleared by [`clear_reset_flags`], so without clearing
+/// they accumulate over resets. Read the cause first and clear the flags after, to see only the
+/// next reset on the following boot. The HAL never clears them itself.
+///
+/// A watchdog or software reset also pulses NRST, so the pin flag is only reported when no
+/// other flag is set.
+pub fn reset_cause() -> ResetCause {
+    let r = crate::pac::RCC.rstsckr().read();
+
+    if r.porrstf() {
+        return ResetCause::PowerOn;
+    }
+    if r.iwdgrstf() {
+        return ResetCause::IndependentWatchdog;
+    }
+    if r.wwdgrstf() {
+        return ResetCause::WindowWatchdog;
+    }
+    if r.sftrstf() {
+        return ResetCause::Software;
+    }
+    if r.lpwrrstf() {
+        return ResetCause::LowPower;
+    }
+    #[cfg(ch32x0)]
+    if r.oparstf() {
+        return ResetCause::Opa;
+    }
+    if r.pinrstf() {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Clear the reset flags (RCC_RSTSCKR.RMVF), so that the next boot only sees the next reset.
+pub fn clear_reset_flags() {
+    crate::pac::RCC.rstsckr().modify(|w| w.set_rmvf(true));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Presets and their SYSCLK
+    fn presets() -> impl Iterator<Item = (Config, u32)> {
+        #[cfg(any(ch32v0, ch32x0, ch641))]
+        let presets = [(Config::SYSCLK_FREQ_48MHZ_HSI, 48_000_000)];
+        #[cfg(ch32v1)]
+        let presets = [
+            (Config::SYSCLK_FREQ_48MHZ_HSE, 48_000_000),
+            (Config::SYSCLK_FREQ_72MHZ_HSE, 72_000_000),
+            (Config::SYSCLK_FREQ_72MHZ_HSI, 72_000_000),
+        ];
+        #[cfg(ch32l1)]
+        let presets = [
+            (Config::SYSCLK_FREQ_48MHZ_HSE, 48_000_000),
+            (Config::SYSCLK_FREQ_72MHZ_HSE, 72_000_000),
+            (Config::SYSCLK_FREQ_96MHZ_HSE, 96_000_000),
+            (Config::SYSCLK_FREQ_96MHZ_HSI, 96_000_000),
+        ];
+        #[cfg(any(ch32v2, ch32v3, ch32f2))]
+        let presets = [
+            (Config::SYSCLK_FREQ_96MHZ_HSE, 96_000_000),
+            (Config::SYSCLK_FREQ_96MHZ_HSI, 96_000_000),
+            (Config::SYSCLK_FREQ_144MHZ_HSE, 144_000_000),
+            (Config::SYSCLK_FREQ_144MHZ_HSI, 144_000_000),
+        ];
+        presets.into_iter()
+    }
+
+    #[test]
+    fn presets_within_datasheet_maxima() {
+        for (config, sysclk) in presets() {
+            assert_eq!(config.sysclk_freq(), sysclk);
+            assert!(sysclk <= SYSCLK_MAX_FREQUENCY.0);
+        }
+    }
+
+    #[test]
+    fn hal_configs_within_datasheet_maxima() {
+        for config in [
+            crate::Config::fast(),
+            crate::Config::internal_8mhz(),
+            crate::Config::low_power_hsi_div(),
+        ] {
+            let sysclk = config.rcc.sysclk_freq();
+            assert!(sysclk > 0 && sysclk <= SYSCLK_MAX_FREQUENCY.0);
+        }
+    }
+
+    #[cfg(any(ch32v1, ch32l1, ch32v2, ch32v3, ch32f2))]
+    #[test]
+    fn adc_prescaler_within_adc_max() {
+        for pclk2 in [8_000_000, 48_000_000, 72_000_000, 96_000_000] {
+            let pre = AdcPrescaler::for_pclk2(Hertz(pclk2));
+            assert!(pclk2 / pre.divisor() <= ADC_MAX_FREQUENCY.0);
+        }
+    }
+}
@@ -12,7 +12,7 @@ pub const LSI_FREQUENCY: Hertz = Hertz(128_000);
 pub enum HseMode {
     /// crystal/ceramic oscillator (HSEBYP=0)
     Oscillator,
-    /// external analog clock (low swing) (HSEBYP=1)
+    /// external clock on OSC_IN, e.g. from a TCXO, OSC_OUT is left free (HSEBYP=1)
     Bypass,
 }
 
@@ -35,6 +35,9 @@ pub struct Config {
     pub ahb_pre: AHBPrescaler,
     // ADCPRE is actually splitted from APB2
     pub apb2_pre: APBPrescaler,
+
+    /// HSI trimming value, see [`super::set_hsi_trim`]. `None` keeps the power-on value.
+    pub hsi_trim: Option<u8>,
 }
 
 impl Config {
@@ -44,6 +47,7 @@ impl Config {
         pll_src: PllSource::HSI,
         ahb_pre: AHBPrescaler::DIV1,
         apb2_pre: APBPrescaler::DIV1,
+        hsi_trim: None,
     };
 
     pub const SYSCLK_FREQ_48MHZ_HSE: Config = Config {
@@ -55,6 +59,7 @@ impl Config {
         pll_src: PllSource::HSE,
         ahb_pre: AHBPrescaler::DIV1,
         apb2_pre: APBPrescaler::DIV1,
+        hsi_trim: None,
     };
 
     pub const SYSCLK_FREQ_24MHZ_HSE: Config = Config {
@@ -66,9 +71,30 @@ impl Config {
         pll_src: PllSource::HSE,
         ahb_pre: AHBPrescaler::DIV1,
         apb2_pre: APBPrescaler::DIV1,
+        hsi_trim: None,
     };
 }
 
+impl Config {
+    /// SYSCLK frequency, 0 if the sysclk source is not configured
+    pub(super) const fn sysclk_freq(&self) -> u32 {
+        let hse = match &self.hse {
+            Some(hse) => hse.freq.0,
+            None => 0,
+        };
+        match self.sys {
+            Sysclk::HSI => HSI_FREQUENCY.0,
+            Sysclk::HSE => hse,
+            // MUL2 is the only option
+            Sysclk::PLL => match self.pll_src {
+                PllSource::HSI => HSI_FREQUENCY.0 * 2,
+                _ => hse * 2,
+            },
+            _ => 0,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -78,23 +104,24 @@ impl Default for Config {
             pll_src: PllSource::HSI,
             ahb_pre: AHBPrescaler::DIV3,
             apb2_pre: APBPrescaler::DIV1,
+            hsi_trim: None,
         }
     }
 }
 
 #[allow(unused_variables)]
 pub(crate) unsafe fn init(config: Config) {
+    if let Some(trim) = config.hsi_trim {
+        super::set_hsi_trim(trim);
+    }
+
     if config.sys == Sysclk::HSE || (config.sys == Sysclk::PLL && config.pll_src == PllSource::HSE) {
         // enable HSE pins
         RCC.apb2pcenr().modify(|w| w.set_afioen(true));
         AFIO.pcfr1().modify(|w| w.set_pa12_rm(true));
 
-        // enable HSE
-        RCC.ctlr().modify(|w| {
-            w.set_hsebyp(config.hse.unwrap().mode == HseMode::Bypass);
-            w.set_hseon(true);
-        });
-        while !RCC.ctlr().read().hserdy() {}
+        let hse = config.hse.expect("RCC: HSE selected, but `hse` is not configured");
+        super::start_hse(hse.mode == HseMode::Bypass);
     }
 
     let sysclk = match config.sys {
@@ -109,6 +136,8 @@ pub(crate) unsafe fn init(config: Config) {
             config.hse.unwrap().freq.0
         }
         Sysclk::PLL => {
+            // PLLSRC can only be written while the PLL is off
+            RCC.ctlr().modify(|w| w.set_pllon(false));
             RCC.cfgr0().modify(|w| w.set_pllsrc(config.pll_src));
             RCC.ctlr().modify(|w| w.set_pllon(true));
             while !RCC.ctlr().read().pllrdy() {}
@@ -128,6 +157,8 @@ pub(crate) unsafe fn init(config: Config) {
 
     if sysclk >= 24_000_000 {
         FLASH.actlr().modify(|w| w.set_latency(0b01)); // 1 等待（24MHz<HCLK≤48MHz）
+    } else {
+        FLASH.actlr().modify(|w| w.set_latency(0b00));
     }
 
     RCC.cfgr0().modify(|w| {
@@ -153,15 +184,35 @@ pub(crate) unsafe fn init(config: Config) {
         _ => panic!(),
     };
 
-    let pclk2 = Hertz(hclk) / config.apb2_pre;
+    // there is no APB prescaler, the divider only feeds the ADC
+    let adcclk = Hertz(hclk) / config.apb2_pre;
 
     super::CLOCKS.sysclk = Hertz(sysclk);
     super::CLOCKS.hclk = Hertz(hclk);
     super::CLOCKS.pclk1 = Hertz(hclk);
-    super::CLOCKS.pclk2 = pclk2;
+    super::CLOCKS.pclk2 = Hertz(hclk);
+
+    super::CLOCKS.pclk1_tim = Hertz(hclk);
+    super::CLOCKS.pclk2_tim = Hertz(hclk);
+
+    super::CLOCKS.adcclk = adcclk;
+
+    super::CLOCKS.hse = config.hse.map(|hse| hse.freq).filter(|_| RCC.ctlr().read().hserdy());
+    super::CLOCKS.pll = (config.sys == Sysclk::PLL).then_some(Hertz(sysclk));
+}
+
+/// Run from HSI, so that HSE and the PLL can be reconfigured by [`init`].
+///
+/// The prescalers are left alone, HCLK only goes down.
+pub(super) unsafe fn switch_to_hsi() {
+    // Slowest flash timing, valid for any SYSCLK
+    FLASH.actlr().modify(|w| w.set_latency(0b01));
+
+    RCC.ctlr().modify(|w| w.set_hsion(true));
+    while !RCC.ctlr().read().hsirdy() {}
 
-    super::CLOCKS.pclk1_tim = Hertz(sysclk);
-    super::CLOCKS.pclk2_tim = Hertz(sysclk);
+    RCC.cfgr0().modify(|w| w.set_sw(Sysclk::HSI));
+    while RCC.cfgr0().read().sws() != Sysclk::HSI {}
 }
 
 impl ops::Div<APBPrescaler> for Hertz {
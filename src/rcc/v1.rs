@@ -8,7 +8,7 @@
 
 use core::ops;
 
-use crate::pac::rcc::vals::{
+pub use crate::pac::rcc::vals::{
     Hpre as AHBPrescaler, PllMul, Pllsrc as PllSource, Ppre as APBPrescaler, Sw as Sysclk, Usbpre,
 };
 use crate::pac::{EXTEND, FLASH, RCC};
@@ -21,7 +21,7 @@ pub const LSI_FREQUENCY: Hertz = Hertz(40_000);
 pub enum HseMode {
     /// crystal/ceramic oscillator (HSEBYP=0)
     Oscillator,
-    /// external analog clock (low swing) (HSEBYP=1)
+    /// external clock on OSC_IN, e.g. from a TCXO, OSC_OUT is left free (HSEBYP=1)
     Bypass,
 }
 
@@ -41,6 +41,9 @@ pub enum PllPreDiv {
 
 #[derive(Clone, Copy)]
 pub struct Pll {
+    /// PLL input clock
+    pub src: PllSource,
+
     /// PLL pre-divider
     pub prediv: PllPreDiv,
 
@@ -54,60 +57,250 @@ pub struct Config {
     pub hse: Option<Hse>,
     pub sys: Sysclk,
 
-    pub pll_src: PllSource,
     pub pll: Option<Pll>,
 
     pub ahb_pre: AHBPrescaler,
     pub apb1_pre: APBPrescaler,
     pub apb2_pre: APBPrescaler,
+
+    pub ls: super::LsConfig,
+
+    /// Enable the Clock Security System, see [`super::set_css_handler`]
+    pub css: bool,
+
+    /// HSI trimming value, see [`super::set_hsi_trim`]. `None` keeps the power-on value.
+    pub hsi_trim: Option<u8>,
+
+    /// USB prescaler from the PLL clock, USBD/USBFS need exactly 48 MHz.
+    ///
+    /// `None` picks the prescaler from the PLL frequency, USB is left without a clock if none
+    /// fits. The resulting clock is in [`Clocks::usb`](super::Clocks::usb).
+    pub usb_pre: Option<Usbpre>,
+
+    /// ADC prescaler from PCLK2. `None` picks the smallest one that keeps the ADC clock within
+    /// [`ADC_MAX_FREQUENCY`](super::ADC_MAX_FREQUENCY).
+    pub adc_pre: Option<super::AdcPrescaler>,
 }
 
 impl Config {
-    pub const SYSCLK_FREQ_48MHZ_HSE: Config = Self {
-        hse: Some(Hse {
-            freq: Hertz(8_000_000),
-            mode: HseMode::Oscillator,
-        }),
-        sys: Sysclk::PLL,
-        pll_src: PllSource::HSE,
-        pll: Some(Pll {
-            prediv: PllPreDiv::DIV1,
-            mul: PllMul::MUL6,
-        }),
-        ahb_pre: AHBPrescaler::DIV1,
-        apb1_pre: APBPrescaler::DIV1,
-        apb2_pre: APBPrescaler::DIV1,
-    };
-    pub const SYSCLK_FREQ_72MHZ_HSE: Config = Self {
-        hse: Some(Hse {
-            freq: Hertz(8_000_000),
-            mode: HseMode::Oscillator,
-        }),
-        sys: Sysclk::PLL,
-        pll_src: PllSource::HSE,
-        pll: Some(Pll {
-            prediv: PllPreDiv::DIV1,
-            mul: PllMul::MUL9,
-        }),
-        ahb_pre: AHBPrescaler::DIV1,
-        apb1_pre: APBPrescaler::DIV2,
-        apb2_pre: APBPrescaler::DIV2,
-    };
-    pub const SYSCLK_FREQ_96MHZ_HSE: Config = Self {
-        hse: Some(Hse {
-            freq: Hertz(8_000_000),
-            mode: HseMode::Oscillator,
-        }),
-        sys: Sysclk::PLL,
-        pll_src: PllSource::HSE,
-        pll: Some(Pll {
-            prediv: PllPreDiv::DIV1,
-            mul: PllMul::MUL12,
-        }),
-        ahb_pre: AHBPrescaler::DIV1,
-        apb1_pre: APBPrescaler::DIV2,
-        apb2_pre: APBPrescaler::DIV2,
-    };
+    /// 48 MHz from an 8 MHz HSE crystal
+    pub const fn sysclk_48mhz_hse() -> Config {
+        let config = Config {
+            hse: Some(Hse {
+                freq: Hertz(8_000_000),
+                mode: HseMode::Oscillator,
+            }),
+            sys: Sysclk::PLL,
+            pll: Some(Pll {
+                src: PllSource::HSE,
+                prediv: PllPreDiv::DIV1,
+                mul: PllMul::MUL6,
+            }),
+            ahb_pre: AHBPrescaler::DIV1,
+            apb1_pre: APBPrescaler::DIV1,
+            apb2_pre: APBPrescaler::DIV1,
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
+        };
+        config.validate();
+        config
+    }
+
+    /// 72 MHz from an 8 MHz HSE crystal, APB1/APB2 at 36 MHz
+    pub const fn sysclk_72mhz_hse() -> Config {
+        let config = Config {
+            hse: Some(Hse {
+                freq: Hertz(8_000_000),
+                mode: HseMode::Oscillator,
+            }),
+            sys: Sysclk::PLL,
+            pll: Some(Pll {
+                src: PllSource::HSE,
+                prediv: PllPreDiv::DIV1,
+                mul: PllMul::MUL9,
+            }),
+            ahb_pre: AHBPrescaler::DIV1,
+            apb1_pre: APBPrescaler::DIV2,
+            apb2_pre: APBPrescaler::DIV2,
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
+        };
+        config.validate();
+        config
+    }
+
+    /// 96 MHz from an 8 MHz HSE crystal, APB1/APB2 at 48 MHz
+    ///
+    /// CH32L1 only, it's above the 80 MHz maximum of CH32V1.
+    #[cfg(ch32l1)]
+    pub const fn sysclk_96mhz_hse() -> Config {
+        let config = Config {
+            hse: Some(Hse {
+                freq: Hertz(8_000_000),
+                mode: HseMode::Oscillator,
+            }),
+            sys: Sysclk::PLL,
+            pll: Some(Pll {
+                src: PllSource::HSE,
+                prediv: PllPreDiv::DIV1,
+                mul: PllMul::MUL12,
+            }),
+            ahb_pre: AHBPrescaler::DIV1,
+            apb1_pre: APBPrescaler::DIV2,
+            apb2_pre: APBPrescaler::DIV2,
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
+        };
+        config.validate();
+        config
+    }
+
+    /// 72 MHz from HSI, APB1/APB2 at 36 MHz
+    #[cfg(ch32v1)]
+    pub const fn sysclk_72mhz_hsi() -> Config {
+        let config = Config {
+            hse: None,
+            sys: Sysclk::PLL,
+            pll: Some(Pll {
+                src: PllSource::HSI,
+                prediv: PllPreDiv::DIV1,
+                mul: PllMul::MUL9,
+            }),
+            ahb_pre: AHBPrescaler::DIV1,
+            apb1_pre: APBPrescaler::DIV2,
+            apb2_pre: APBPrescaler::DIV2,
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
+        };
+        config.validate();
+        config
+    }
+
+    /// 96 MHz from HSI, APB1/APB2 at 48 MHz
+    #[cfg(ch32l1)]
+    pub const fn sysclk_96mhz_hsi() -> Config {
+        let config = Config {
+            hse: None,
+            sys: Sysclk::PLL,
+            pll: Some(Pll {
+                src: PllSource::HSI,
+                prediv: PllPreDiv::DIV1,
+                mul: PllMul::MUL12,
+            }),
+            ahb_pre: AHBPrescaler::DIV1,
+            apb1_pre: APBPrescaler::DIV2,
+            apb2_pre: APBPrescaler::DIV2,
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
+        };
+        config.validate();
+        config
+    }
+
+    pub const SYSCLK_FREQ_48MHZ_HSE: Config = Config::sysclk_48mhz_hse();
+    pub const SYSCLK_FREQ_72MHZ_HSE: Config = Config::sysclk_72mhz_hse();
+    #[cfg(ch32l1)]
+    pub const SYSCLK_FREQ_96MHZ_HSE: Config = Config::sysclk_96mhz_hse();
+    #[cfg(ch32v1)]
+    pub const SYSCLK_FREQ_72MHZ_HSI: Config = Config::sysclk_72mhz_hsi();
+    #[cfg(ch32l1)]
+    pub const SYSCLK_FREQ_96MHZ_HSI: Config = Config::sysclk_96mhz_hsi();
+
+    /// Check that the configuration describes a valid clock tree, panics otherwise.
+    ///
+    /// This is called by [`crate::init`]. Evaluating it in a `const` item, as the presets above do,
+    /// turns a bad configuration into a compile error.
+    pub const fn validate(&self) {
+        if let Some(trim) = self.hsi_trim {
+            if trim > super::HSI_TRIM_MAX {
+                panic!("RCC: HSI trim must be at most 31");
+            }
+        }
+
+        if self.css && self.hse.is_none() {
+            panic!("RCC: CSS enabled, but `hse` is not configured");
+        }
+
+        match self.sys {
+            Sysclk::HSE if self.hse.is_none() => panic!("RCC: HSE selected as sysclk, but `hse` is not configured"),
+            Sysclk::PLL if self.pll.is_none() => panic!("RCC: PLL selected as sysclk, but `pll` is not configured"),
+            _ => {}
+        }
+
+        if let Some(pll) = &self.pll {
+            if matches!(pll.src, PllSource::HSE) && self.hse.is_none() {
+                panic!("RCC: HSE selected as PLL source, but `hse` is not configured");
+            }
+        }
+
+        if self.sysclk_freq() > super::SYSCLK_MAX_FREQUENCY.0 {
+            panic!("RCC: sysclk above the maximum frequency of this chip");
+        }
+
+        if let Some(adc_pre) = self.adc_pre {
+            if self.pclk2_freq() / adc_pre.divisor() > super::ADC_MAX_FREQUENCY.0 {
+                panic!("RCC: `adc_pre` gives an ADC clock above 14 MHz");
+            }
+        }
+
+        if let Some(usb_pre) = self.usb_pre {
+            match self.pll_freq() {
+                Some(pll) if pll as u64 * 2 == 48_000_000 * usbpre_div_x2(usb_pre) as u64 => {}
+                _ => panic!("RCC: `usb_pre` must give a 48 MHz USB clock from the PLL"),
+            }
+        }
+    }
+
+    /// PCLK2 frequency, 0 if the sysclk source is not configured
+    const fn pclk2_freq(&self) -> u32 {
+        self.sysclk_freq() >> ahb_shift(self.ahb_pre) >> apb_shift(self.apb2_pre)
+    }
+
+    /// SYSCLK frequency, 0 if the sysclk source is not configured
+    pub(super) const fn sysclk_freq(&self) -> u32 {
+        match self.sys {
+            Sysclk::HSI => HSI_FREQUENCY.0,
+            Sysclk::HSE => match &self.hse {
+                Some(hse) => hse.freq.0,
+                None => 0,
+            },
+            Sysclk::PLL => match self.pll_freq() {
+                Some(pll) => pll,
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    /// PLL output frequency, `None` without PLL
+    const fn pll_freq(&self) -> Option<u32> {
+        let Some(pll) = &self.pll else {
+            return None;
+        };
+        let src = match pll.src {
+            PllSource::HSI => HSI_FREQUENCY.0,
+            PllSource::HSE => match &self.hse {
+                Some(hse) => hse.freq.0,
+                None => return None,
+            },
+        };
+        Some(src / pll.prediv as u32 * pll_mul(pll.mul))
+    }
 }
 
 impl Default for Config {
@@ -116,18 +309,27 @@ impl Default for Config {
             // hsi: true,
             hse: None,
             sys: Sysclk::HSI,
-            pll_src: PllSource::HSI,
             pll: None,
             ahb_pre: AHBPrescaler::DIV1,
             apb1_pre: APBPrescaler::DIV1,
             apb2_pre: APBPrescaler::DIV1,
+            ls: super::LsConfig::default(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
         }
     }
 }
 
 #[allow(unused_variables)]
 pub(crate) unsafe fn init(config: Config) {
+    config.validate();
+
     // Configure HSI
+    if let Some(trim) = config.hsi_trim {
+        super::set_hsi_trim(trim);
+    }
     while !RCC.ctlr().read().hsirdy() {}
     let hsi = Some(HSI_FREQUENCY);
 
@@ -138,22 +340,22 @@ pub(crate) unsafe fn init(config: Config) {
             None
         }
         Some(hse) => {
-            RCC.ctlr().modify(|w| w.set_hsebyp(hse.mode != HseMode::Oscillator));
-            RCC.ctlr().modify(|w| w.set_hseon(true));
-            while !RCC.ctlr().read().hserdy() {}
+            super::start_hse(hse.mode == HseMode::Bypass);
             Some(hse.freq)
         }
     };
+    RCC.ctlr().modify(|w| w.set_csson(config.css));
 
     // Configure PLLs.
     // Configure PLL
+    let mut usb = None;
     let pll_clk = {
         // Disable PLL
         RCC.ctlr().modify(|w| w.set_pllon(false));
         match config.pll {
             None => None,
             Some(pll) => {
-                let pll_src = match config.pll_src {
+                let pll_src = match pll.src {
                     PllSource::HSI => hsi.unwrap(),
                     PllSource::HSE => hse.unwrap(),
                 };
@@ -161,13 +363,14 @@ pub(crate) unsafe fn init(config: Config) {
                 let vco_freq = in_freq * pll.mul;
 
                 // Usb clock must be 48MHz
-                if let Some(usb_pre) = calc_usbpre(vco_freq) {
+                if let Some(usb_pre) = config.usb_pre.or_else(|| calc_usbpre(vco_freq)) {
                     RCC.cfgr0().modify(|w| w.set_usbpre(usb_pre));
+                    usb = Some(Hertz(vco_freq.0 * 2 / usbpre_div_x2(usb_pre)));
                 }
 
                 RCC.cfgr0().modify(|w| w.set_pllmul(pll.mul));
 
-                match config.pll_src {
+                match pll.src {
                     PllSource::HSI => {
                         RCC.cfgr0().modify(|w| w.set_pllsrc(PllSource::HSI)); // use HSI or HSI/2
                         match pll.prediv {
@@ -202,6 +405,7 @@ pub(crate) unsafe fn init(config: Config) {
     let hclk = sys / config.ahb_pre;
     let (pclk1, pclk1_tim) = calc_pclk(hclk, config.apb1_pre);
     let (pclk2, pclk2_tim) = calc_pclk(hclk, config.apb2_pre);
+    let adc_pre = config.adc_pre.unwrap_or(super::AdcPrescaler::for_pclk2(pclk2));
 
     // flash latency
     #[cfg(ch32v1)]
@@ -226,6 +430,7 @@ pub(crate) unsafe fn init(config: Config) {
         w.set_ppre2(config.apb2_pre);
     });
     while RCC.cfgr0().read().sws() != config.sys {}
+    RCC.cfgr0().modify(|w| w.set_adcpre(adc_pre as u8));
 
     super::CLOCKS.sysclk = sys;
     super::CLOCKS.hclk = hclk;
@@ -234,9 +439,33 @@ pub(crate) unsafe fn init(config: Config) {
 
     super::CLOCKS.pclk1_tim = pclk1_tim;
     super::CLOCKS.pclk2_tim = pclk2_tim;
+
+    super::CLOCKS.adcclk = super::calc_adcclk(pclk2);
+    super::CLOCKS.hse = hse;
+    super::CLOCKS.pll = pll_clk;
+    super::CLOCKS.usb = usb;
+
+    super::CLOCKS.rtc = config.ls.init(hse);
+}
+
+/// Run from HSI, so that HSE and the PLL can be reconfigured by [`init`].
+///
+/// The prescalers are left alone, HCLK only goes down.
+pub(super) unsafe fn switch_to_hsi() {
+    // Slowest flash timing, valid for any SYSCLK
+    FLASH.actlr().modify(|w| w.set_latency(2));
+    RCC.ctlr().modify(|w| {
+        w.set_hsion(true);
+        // HSE may be turned off next
+        w.set_csson(false);
+    });
+    while !RCC.ctlr().read().hsirdy() {}
+
+    RCC.cfgr0().modify(|w| w.set_sw(Sysclk::HSI));
+    while RCC.cfgr0().read().sws() != Sysclk::HSI {}
 }
 
-fn calc_pclk<D>(hclk: Hertz, ppre: D) -> (Hertz, Hertz)
+pub(super) fn calc_pclk<D>(hclk: Hertz, ppre: D) -> (Hertz, Hertz)
 where
     Hertz: ops::Div<D, Output = Hertz>,
 {
@@ -256,6 +485,32 @@ fn calc_usbpre(pllclk: Hertz) -> Option<Usbpre> {
     }
 }
 
+/// USB prescaler division factor, times 2
+const fn usbpre_div_x2(usb_pre: Usbpre) -> u32 {
+    match usb_pre {
+        Usbpre::DIV1 => 2,
+        Usbpre::DIV1_5 => 3,
+        #[cfg(ch32l1)]
+        Usbpre::DIV2 => 4,
+        #[allow(unreachable_patterns)]
+        _ => panic!("RCC: unsupported USB prescaler"),
+    }
+}
+
+/// PLL multiplication factor
+const fn pll_mul(mul: PllMul) -> u32 {
+    #[cfg(ch32v1)]
+    match mul {
+        PllMul::MUL16_ALT => 16,
+        _ => mul as u32 + 2,
+    }
+    #[cfg(ch32l1)]
+    match mul {
+        PllMul::MUL18 => 18,
+        _ => mul as u32 + 2,
+    }
+}
+
 impl ops::Div<PllPreDiv> for Hertz {
     type Output = Hertz;
     fn div(self, rhs: PllPreDiv) -> Hertz {
@@ -269,45 +524,44 @@ impl ops::Div<PllPreDiv> for Hertz {
 impl ops::Mul<PllMul> for Hertz {
     type Output = Hertz;
     fn mul(self, rhs: PllMul) -> Hertz {
-        #[cfg(ch32v1)]
-        match rhs {
-            PllMul::MUL16_ALT => Hertz(self.0 * 16),
-            _ => Hertz(self.0 * (rhs as u32 + 2)),
-        }
-        #[cfg(ch32l1)]
-        match rhs {
-            PllMul::MUL18 => Hertz(self.0 * 18),
-            _ => Hertz(self.0 * (rhs as u32 + 2)),
-        }
+        Hertz(self.0 * pll_mul(rhs))
+    }
+}
+
+/// AHB prescaler as a right shift
+const fn ahb_shift(pre: AHBPrescaler) -> u32 {
+    let raw = pre as u32;
+    if raw >= 0b1000 {
+        // 2, 4, 8
+        raw - 0b1000 + 1
+    } else {
+        // DIV1
+        0
+    }
+}
+
+/// APB prescaler as a right shift
+const fn apb_shift(pre: APBPrescaler) -> u32 {
+    let raw = pre as u32;
+    if raw >= 0b100 {
+        // 2, 4, 8, 16
+        raw - 0b100 + 1
+    } else {
+        // DIV1
+        0
     }
 }
 
 impl ops::Div<AHBPrescaler> for Hertz {
     type Output = Hertz;
     fn div(self, rhs: AHBPrescaler) -> Hertz {
-        let raw = rhs as u32;
-        if raw >= 0b1000 {
-            // 2, 4, 8
-            let d = raw - 0b1000 + 1;
-            Hertz(self.0 >> d)
-        } else {
-            // DIV1
-            self
-        }
+        Hertz(self.0 >> ahb_shift(rhs))
     }
 }
 
 impl ops::Div<APBPrescaler> for Hertz {
     type Output = Hertz;
     fn div(self, rhs: APBPrescaler) -> Hertz {
-        let raw = rhs as u32;
-        if raw >= 0b100 {
-            // 2, 4, 8, 16
-            let d = raw - 0b100 + 1;
-            Hertz(self.0 >> d)
-        } else {
-            // DIV1
-            self
-        }
+        Hertz(self.0 >> apb_shift(rhs))
     }
 }
@@ -7,13 +7,13 @@ pub use crate::pac::rcc::vals::{
 use crate::pac::{EXTEND, FLASH, RCC};
 use crate::time::Hertz;
 
-const HSI_FREQUENCY: Hertz = Hertz(8_000_000);
+pub const HSI_FREQUENCY: Hertz = Hertz(8_000_000);
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum HseMode {
     /// crystal/ceramic oscillator (HSEBYP=0)
     Oscillator,
-    /// external analog clock (low swing) (HSEBYP=1)
+    /// external clock on OSC_IN, e.g. from a TCXO, OSC_OUT is left free (HSEBYP=1)
     Bypass,
 }
 
@@ -27,6 +27,9 @@ pub struct Hse {
 
 #[derive(Clone, Copy)]
 pub struct Pll {
+    /// PLL input clock
+    pub src: PllSource,
+
     /// PLL pre-divider
     pub prediv: PllPreDiv,
 
@@ -100,7 +103,6 @@ pub struct Config {
     pub hse: Option<Hse>,
     pub sys: Sysclk,
 
-    pub pll_src: PllSource,
     pub pll: Option<Pll>,
 
     // TODO: optional
@@ -112,6 +114,22 @@ pub struct Config {
 
     pub ls: super::LsConfig,
 
+    /// Enable the Clock Security System, see [`super::set_css_handler`]
+    pub css: bool,
+
+    /// HSI trimming value, see [`super::set_hsi_trim`]. `None` keeps the power-on value.
+    pub hsi_trim: Option<u8>,
+
+    /// USB prescaler from the PLL clock, USBD/USBFS need exactly 48 MHz.
+    ///
+    /// `None` picks the prescaler from the PLL frequency, USB is left without a clock if none
+    /// fits. The resulting clock is in [`Clocks::usb`](super::Clocks::usb).
+    pub usb_pre: Option<Usbpre>,
+
+    /// ADC prescaler from PCLK2. `None` picks the smallest one that keeps the ADC clock within
+    /// [`ADC_MAX_FREQUENCY`](super::ADC_MAX_FREQUENCY).
+    pub adc_pre: Option<super::AdcPrescaler>,
+
     pub hspll_src: HsPllSource,
     pub hspll: Option<HsPll>,
     // /// Per-peripheral kernel clock selection muxes
@@ -119,39 +137,49 @@ pub struct Config {
 }
 
 impl Config {
-    pub const SYSCLK_FREQ_96MHZ_HSE: Config = {
-        Config {
+    /// 96 MHz from an 8 MHz HSE crystal
+    pub const fn sysclk_96mhz_hse() -> Config {
+        let config = Config {
             hse: Some(Hse {
                 freq: Hertz(8_000_000),
                 mode: HseMode::Oscillator,
             }),
             sys: Sysclk::PLL,
-            pll_src: PllSource::HSE,
             pll: Some(Pll {
+                src: PllSource::HSE,
                 prediv: PllPreDiv::DIV1,
-                mul: PllMul::MUL6,
+                mul: PllMul::MUL12,
             }),
-
             pllx: None,
             ahb_pre: AHBPrescaler::DIV1,
             apb1_pre: APBPrescaler::DIV1,
             apb2_pre: APBPrescaler::DIV1,
-            ls: super::LsConfig::default_lsi(),
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
             hspll_src: HsPllSource::HSE,
             hspll: Some(HsPll {
                 pre: HsPllPrescaler::DIV2,
             }),
-        }
-    };
-    pub const SYSCLK_FREQ_144MHZ_HSE: Config = {
-        Config {
+        };
+        config.validate();
+        config
+    }
+
+    /// 144 MHz from an 8 MHz HSE crystal
+    ///
+    /// APB2 runs at 144 MHz, which is too fast for the ADC. Set `apb2_pre` to `DIV2` to use it.
+    pub const fn sysclk_144mhz_hse() -> Config {
+        let config = Config {
             hse: Some(Hse {
                 freq: Hertz(8_000_000),
                 mode: HseMode::Oscillator,
             }),
             sys: Sysclk::PLL,
-            pll_src: PllSource::HSE,
             pll: Some(Pll {
+                src: PllSource::HSE,
                 prediv: PllPreDiv::DIV1,
                 mul: PllMul::MUL18,
             }),
@@ -159,19 +187,29 @@ impl Config {
             ahb_pre: AHBPrescaler::DIV1,
             apb1_pre: APBPrescaler::DIV1,
             apb2_pre: APBPrescaler::DIV1,
-            ls: super::LsConfig::default_lsi(),
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
             hspll_src: HsPllSource::HSE,
             hspll: Some(HsPll {
                 pre: HsPllPrescaler::DIV2,
             }),
-        }
-    };
-    pub const SYSCLK_FREQ_144MHZ_HSI: Config = {
-        Config {
+        };
+        config.validate();
+        config
+    }
+
+    /// 144 MHz from HSI
+    ///
+    /// APB2 runs at 144 MHz, which is too fast for the ADC. Set `apb2_pre` to `DIV2` to use it.
+    pub const fn sysclk_144mhz_hsi() -> Config {
+        let config = Config {
             hse: None,
             sys: Sysclk::PLL,
-            pll_src: PllSource::HSI,
             pll: Some(Pll {
+                src: PllSource::HSI,
                 prediv: PllPreDiv::DIV1,
                 mul: PllMul::MUL18,
             }),
@@ -179,19 +217,27 @@ impl Config {
             ahb_pre: AHBPrescaler::DIV1,
             apb1_pre: APBPrescaler::DIV1,
             apb2_pre: APBPrescaler::DIV1,
-            ls: super::LsConfig::default_lsi(),
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
             hspll_src: HsPllSource::HSI,
             hspll: Some(HsPll {
                 pre: HsPllPrescaler::DIV2,
             }),
-        }
-    };
-    pub const SYSCLK_FREQ_96MHZ_HSI: Config = {
-        Config {
+        };
+        config.validate();
+        config
+    }
+
+    /// 96 MHz from HSI, APB1/APB2 at 24 MHz
+    pub const fn sysclk_96mhz_hsi() -> Config {
+        let config = Config {
             hse: None,
             sys: Sysclk::PLL,
-            pll_src: PllSource::HSI,
             pll: Some(Pll {
+                src: PllSource::HSI,
                 prediv: PllPreDiv::DIV1,
                 mul: PllMul::MUL12,
             }),
@@ -199,13 +245,147 @@ impl Config {
             ahb_pre: AHBPrescaler::DIV1,
             apb1_pre: APBPrescaler::DIV4, // 24MHz
             apb2_pre: APBPrescaler::DIV4,
-            ls: super::LsConfig::default_lsi(),
+            ls: super::LsConfig::off(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
             hspll_src: HsPllSource::HSI,
             hspll: Some(HsPll {
                 pre: HsPllPrescaler::DIV2,
             }),
+        };
+        config.validate();
+        config
+    }
+
+    pub const SYSCLK_FREQ_96MHZ_HSE: Config = Config::sysclk_96mhz_hse();
+    pub const SYSCLK_FREQ_144MHZ_HSE: Config = Config::sysclk_144mhz_hse();
+    pub const SYSCLK_FREQ_144MHZ_HSI: Config = Config::sysclk_144mhz_hsi();
+    pub const SYSCLK_FREQ_96MHZ_HSI: Config = Config::sysclk_96mhz_hsi();
+
+    /// Check that the configuration describes a valid clock tree, panics otherwise.
+    ///
+    /// This is called by [`crate::init`]. Evaluating it in a `const` item, as the presets above do,
+    /// turns a bad configuration into a compile error.
+    pub const fn validate(&self) {
+        if let Some(trim) = self.hsi_trim {
+            if trim > super::HSI_TRIM_MAX {
+                panic!("RCC: HSI trim must be at most 31");
+            }
         }
-    };
+
+        if self.css && self.hse.is_none() {
+            panic!("RCC: CSS enabled, but `hse` is not configured");
+        }
+
+        if let Some(hse) = &self.hse {
+            if hse.freq.0 < 3_000_000 || hse.freq.0 > 25_000_000 {
+                panic!("RCC: HSE frequency must be between 3 MHz and 25 MHz");
+            }
+        }
+
+        match self.sys {
+            Sysclk::HSE if self.hse.is_none() => panic!("RCC: HSE selected as sysclk, but `hse` is not configured"),
+            Sysclk::PLL if self.pll.is_none() => panic!("RCC: PLL selected as sysclk, but `pll` is not configured"),
+            _ => {}
+        }
+
+        if let Some(pll) = &self.pll {
+            match pll.src {
+                PllSource::HSI => {
+                    if !matches!(pll.prediv, PllPreDiv::DIV1 | PllPreDiv::DIV2) {
+                        panic!("RCC: with HSI as PLL source, only prediv DIV1 and DIV2 are available");
+                    }
+                }
+                PllSource::HSE => {
+                    if self.hse.is_none() {
+                        panic!("RCC: HSE selected as PLL source, but `hse` is not configured");
+                    }
+                    #[cfg(any(d6, d8))]
+                    if !matches!(pll.prediv, PllPreDiv::DIV1 | PllPreDiv::DIV2) {
+                        panic!("RCC: with HSE as PLL source, only prediv DIV1 and DIV2 are available");
+                    }
+                    #[cfg(d8w)]
+                    if !matches!(pll.prediv, PllPreDiv::DIV2 | PllPreDiv::DIV4 | PllPreDiv::DIV8) {
+                        panic!("RCC: with HSE as PLL source, only prediv DIV2, DIV4 and DIV8 are available");
+                    }
+                }
+                #[cfg(d8c)]
+                PllSource::PLL2 => panic!("RCC: PLL2 as PLL source is not supported yet"),
+            }
+        }
+
+        if self.sysclk_freq() > super::SYSCLK_MAX_FREQUENCY.0 {
+            panic!("RCC: sysclk above the maximum frequency of this chip");
+        }
+
+        if let Some(adc_pre) = self.adc_pre {
+            if self.pclk2_freq() / adc_pre.divisor() > super::ADC_MAX_FREQUENCY.0 {
+                panic!("RCC: `adc_pre` gives an ADC clock above 14 MHz");
+            }
+        }
+
+        if let Some(usb_pre) = self.usb_pre {
+            match self.pll_freq() {
+                Some(pll) if pll as u64 * 2 == 48_000_000 * usbpre_div_x2(usb_pre) as u64 => {}
+                _ => panic!("RCC: `usb_pre` must give a 48 MHz USB clock from the PLL"),
+            }
+        }
+
+        #[cfg(d8c)]
+        if let Some(hspll) = &self.hspll {
+            let src = match self.hspll_src {
+                HsPllSource::HSI => HSI_FREQUENCY.0,
+                HsPllSource::HSE => match &self.hse {
+                    Some(hse) => hse.freq.0,
+                    None => panic!("RCC: HSE selected as USBHS PLL source, but `hse` is not configured"),
+                },
+            };
+            let ref_freq = src / (hspll.pre as u32 + 1);
+            if !matches!(ref_freq, 3_000_000 | 4_000_000 | 5_000_000 | 8_000_000) {
+                panic!("RCC: USBHS PLL reference must be 3, 4, 5 or 8 MHz");
+            }
+        }
+    }
+
+    /// PCLK2 frequency, 0 if the sysclk source is not configured
+    const fn pclk2_freq(&self) -> u32 {
+        self.sysclk_freq() >> ahb_shift(self.ahb_pre) >> apb_shift(self.apb2_pre)
+    }
+
+    /// SYSCLK frequency, 0 if the sysclk source is not configured
+    pub(super) const fn sysclk_freq(&self) -> u32 {
+        match self.sys {
+            Sysclk::HSI => HSI_FREQUENCY.0,
+            Sysclk::HSE => match &self.hse {
+                Some(hse) => hse.freq.0,
+                None => 0,
+            },
+            Sysclk::PLL => match self.pll_freq() {
+                Some(pll) => pll,
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    /// PLL output frequency, `None` without PLL
+    const fn pll_freq(&self) -> Option<u32> {
+        let Some(pll) = &self.pll else {
+            return None;
+        };
+        let src = match pll.src {
+            PllSource::HSI => HSI_FREQUENCY.0,
+            PllSource::HSE => match &self.hse {
+                Some(hse) => hse.freq.0,
+                None => return None,
+            },
+            #[cfg(d8c)]
+            PllSource::PLL2 => return None,
+        };
+        Some(src / (pll.prediv as u32 + 1) * pll_mul_x2(pll.mul) / 2)
+    }
 }
 
 impl Default for Config {
@@ -214,13 +394,16 @@ impl Default for Config {
             // hsi: true,
             hse: None,
             sys: Sysclk::HSI,
-            pll_src: PllSource::HSI,
             pll: None,
             pllx: None,
             ahb_pre: AHBPrescaler::DIV1,
             apb1_pre: APBPrescaler::DIV1,
             apb2_pre: APBPrescaler::DIV1,
             ls: super::LsConfig::default(),
+            css: false,
+            hsi_trim: None,
+            usb_pre: None,
+            adc_pre: None,
             hspll_src: HsPllSource::HSE,
             hspll: None,
         }
@@ -229,7 +412,12 @@ impl Default for Config {
 
 #[allow(unused_variables)]
 pub(crate) unsafe fn init(config: Config) {
+    config.validate();
+
     // Configure HSI
+    if let Some(trim) = config.hsi_trim {
+        super::set_hsi_trim(trim);
+    }
     while !RCC.ctlr().read().hsirdy() {}
     let hsi = Some(HSI_FREQUENCY);
 
@@ -240,12 +428,11 @@ pub(crate) unsafe fn init(config: Config) {
             None
         }
         Some(hse) => {
-            RCC.ctlr().modify(|w| w.set_hsebyp(hse.mode != HseMode::Oscillator));
-            RCC.ctlr().modify(|w| w.set_hseon(true));
-            while !RCC.ctlr().read().hserdy() {}
+            super::start_hse(hse.mode == HseMode::Bypass);
             Some(hse.freq)
         }
     };
+    RCC.ctlr().modify(|w| w.set_csson(config.css));
 
     // Configure PLLs.
     //
@@ -257,11 +444,12 @@ pub(crate) unsafe fn init(config: Config) {
         (None, None)
     };
     // Configure PLL
+    let mut usb = None;
     let pll_clk = {
         // Disable PLL
         RCC.ctlr().modify(|w| w.set_pllon(false));
         if let Some(pll) = config.pll {
-            let pll_src = match config.pll_src {
+            let pll_src = match pll.src {
                 PllSource::HSI => hsi.unwrap(),
                 PllSource::HSE => hse.unwrap(),
                 #[cfg(d8c)]
@@ -271,15 +459,16 @@ pub(crate) unsafe fn init(config: Config) {
             let vco_freq = in_freq * pll.mul;
 
             // Usb clock must be 48MHz
-            let usb_pre = calc_usbpre(vco_freq);
+            let usb_pre = config.usb_pre.or_else(|| calc_usbpre(vco_freq));
             if let Some(usb_pre) = usb_pre {
                 RCC.cfgr0().modify(|w| w.set_usbpre(usb_pre));
+                usb = Some(Hertz(vco_freq.0 * 2 / usbpre_div_x2(usb_pre)));
             }
             // TODO: handle USBHS clk
 
             RCC.cfgr0().modify(|w| w.set_pllmul(pll.mul));
 
-            match config.pll_src {
+            match pll.src {
                 PllSource::HSI => {
                     RCC.cfgr0().modify(|w| w.set_pllsrc(false)); // use HSI or HSI/2
                     match pll.prediv {
@@ -366,7 +555,7 @@ pub(crate) unsafe fn init(config: Config) {
             Hertz(4_000_000) => HsPllRef::_4M,
             Hertz(8_000_000) => HsPllRef::_8M,
             Hertz(5_000_000) => HsPllRef::_5M,
-            _ => panic!(),
+            _ => unreachable!(), // checked by validate
         };
 
         RCC.cfgr2().modify(|w| {
@@ -392,6 +581,7 @@ pub(crate) unsafe fn init(config: Config) {
     let hclk = sys / config.ahb_pre;
     let (pclk1, pclk1_tim) = calc_pclk(hclk, config.apb1_pre);
     let (pclk2, pclk2_tim) = calc_pclk(hclk, config.apb2_pre);
+    let adc_pre = config.adc_pre.unwrap_or(super::AdcPrescaler::for_pclk2(pclk2));
 
     // 当 AHB 时钟来源的预分频系数大于 1 时，必须开启预取缓冲器
 
@@ -407,6 +597,7 @@ pub(crate) unsafe fn init(config: Config) {
         w.set_ppre2(config.apb2_pre);
     });
     while RCC.cfgr0().read().sws() != config.sys {}
+    RCC.cfgr0().modify(|w| w.set_adcpre(adc_pre as u8));
 
     super::CLOCKS.sysclk = sys;
     super::CLOCKS.hclk = hclk;
@@ -415,9 +606,33 @@ pub(crate) unsafe fn init(config: Config) {
 
     super::CLOCKS.pclk1_tim = pclk1_tim;
     super::CLOCKS.pclk2_tim = pclk2_tim;
+
+    super::CLOCKS.adcclk = super::calc_adcclk(pclk2);
+    super::CLOCKS.hse = hse;
+    super::CLOCKS.pll = pll_clk;
+    super::CLOCKS.usb = usb;
+
+    super::CLOCKS.rtc = config.ls.init(hse);
 }
 
-fn calc_pclk<D>(hclk: Hertz, ppre: D) -> (Hertz, Hertz)
+/// Run from HSI, so that HSE and the PLL can be reconfigured by [`init`].
+///
+/// The prescalers are left alone, HCLK only goes down.
+pub(super) unsafe fn switch_to_hsi() {
+    // Flash clock at HCLK/2, valid for any SYSCLK
+    FLASH.ctlr().modify(|w| w.set_sckmode(false));
+    RCC.ctlr().modify(|w| {
+        w.set_hsion(true);
+        // HSE may be turned off next
+        w.set_csson(false);
+    });
+    while !RCC.ctlr().read().hsirdy() {}
+
+    RCC.cfgr0().modify(|w| w.set_sw(Sysclk::HSI));
+    while RCC.cfgr0().read().sws() != Sysclk::HSI {}
+}
+
+pub(super) fn calc_pclk<D>(hclk: Hertz, ppre: D) -> (Hertz, Hertz)
 where
     Hertz: ops::Div<D, Output = Hertz>,
 {
@@ -438,6 +653,32 @@ fn calc_usbpre(pllclk: Hertz) -> Option<Usbpre> {
     }
 }
 
+/// USB prescaler division factor, times 2
+const fn usbpre_div_x2(usb_pre: Usbpre) -> u32 {
+    match usb_pre {
+        Usbpre::DIV1 => 2,
+        Usbpre::DIV2 => 4,
+        Usbpre::DIV3 => 6,
+        #[cfg(d8w)]
+        Usbpre::DIV5 => 10,
+        #[allow(unreachable_patterns)]
+        _ => panic!("RCC: unsupported USB prescaler"),
+    }
+}
+
+/// PLL multiplication factor, times 2 for the fractional MUL6_5
+const fn pll_mul_x2(mul: PllMul) -> u32 {
+    match mul {
+        PllMul::MUL15 => 30,
+        PllMul::MUL16 => 32,
+        PllMul::MUL18 => 36,
+        #[cfg(d8c)]
+        PllMul::MUL6_5 => 13,
+        // All the others are covered by this case
+        _ => (mul as u32 + 2) * 2,
+    }
+}
+
 impl ops::Div<PllPreDiv> for Hertz {
     type Output = Hertz;
     fn div(self, rhs: PllPreDiv) -> Hertz {
@@ -448,44 +689,44 @@ impl ops::Div<PllPreDiv> for Hertz {
 impl ops::Mul<PllMul> for Hertz {
     type Output = Hertz;
     fn mul(self, rhs: PllMul) -> Hertz {
-        match rhs {
-            PllMul::MUL15 => Hertz(self.0 * 15),
-            PllMul::MUL16 => Hertz(self.0 * 16),
-            PllMul::MUL18 => Hertz(self.0 * 18),
-            #[cfg(d8c)]
-            PllMul::MUL6_5 => Hertz(self.0 * 13 / 2),
-            // All the others are covered by this case
-            _ => Hertz(self.0 * (rhs as u32 + 2)),
-        }
+        Hertz(self.0 * pll_mul_x2(rhs) / 2)
+    }
+}
+
+/// AHB prescaler as a right shift
+const fn ahb_shift(pre: AHBPrescaler) -> u32 {
+    let raw = pre as u32;
+    if raw >= 0b1000 {
+        // 2, 4, 8
+        raw - 0b1000 + 1
+    } else {
+        // DIV1
+        0
+    }
+}
+
+/// APB prescaler as a right shift
+const fn apb_shift(pre: APBPrescaler) -> u32 {
+    let raw = pre as u32;
+    if raw >= 0b100 {
+        // 2, 4, 8, 16
+        raw - 0b100 + 1
+    } else {
+        // DIV1
+        0
     }
 }
 
 impl ops::Div<AHBPrescaler> for Hertz {
     type Output = Hertz;
     fn div(self, rhs: AHBPrescaler) -> Hertz {
-        let raw = rhs as u32;
-        if raw >= 0b1000 {
-            // 2, 4, 8
-            let d = raw - 0b1000 + 1;
-            Hertz(self.0 >> d)
-        } else {
-            // DIV1
-            self
-        }
+        Hertz(self.0 >> ahb_shift(rhs))
     }
 }
 
 impl ops::Div<APBPrescaler> for Hertz {
     type Output = Hertz;
     fn div(self, rhs: APBPrescaler) -> Hertz {
-        let raw = rhs as u32;
-        if raw >= 0b100 {
-            // 2, 4, 8, 16
-            let d = raw - 0b100 + 1;
-            Hertz(self.0 >> d)
-        } else {
-            // DIV1
-            self
-        }
+        Hertz(self.0 >> apb_shift(rhs))
     }
 }
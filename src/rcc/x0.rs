@@ -41,6 +41,13 @@ impl Config {
     };
 }
 
+impl Config {
+    /// SYSCLK frequency, always HSI
+    pub(super) const fn sysclk_freq(&self) -> u32 {
+        HSI_FREQUENCY.0
+    }
+}
+
 #[allow(unused_variables)]
 pub(crate) unsafe fn init(config: Config) {
     RCC.ctlr().modify(|w| w.set_hsion(true));
@@ -81,6 +88,22 @@ pub(crate) unsafe fn init(config: Config) {
 
     super::CLOCKS.pclk1_tim = hclk;
     super::CLOCKS.pclk2_tim = hclk;
+
+    // divided further by ADC_CTLR3
+    super::CLOCKS.adcclk = hclk;
+
+    // USBFS runs from the 48 MHz HSI directly
+    super::CLOCKS.usb = Some(HSI_FREQUENCY);
+}
+
+/// Slow down HCLK, so that [`init`] can set the flash timing before changing it.
+///
+/// SYSCLK is always HSI.
+pub(super) unsafe fn switch_to_hsi() {
+    // Slowest flash timing, valid for any HCLK
+    FLASH.actlr().modify(|w| w.set_latency(0b10));
+    // 6 MHz, within the 0 wait state range
+    RCC.cfgr0().modify(|w| w.set_hpre(AHBPrescaler::DIV8));
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -95,6 +118,7 @@ pub enum ResetReason {
     USBPD,
 }
 
+#[deprecated(note = "use `reset_cause`, which doesn't panic without a reset flag")]
 pub fn reset_resona() -> ResetReason {
     let csr = RCC.rstsckr().read();
 
@@ -183,6 +183,16 @@ impl<'d, T: Instance> RngCore for Rng<'d, T> {
 
 impl<'d, T: Instance> CryptoRng for Rng<'d, T> {}
 
+impl<'d, T: Instance> Drop for Rng<'d, T> {
+    fn drop(&mut self) {
+        T::regs().cr().modify(|reg| {
+            reg.set_rngen(false);
+            reg.set_ie(false);
+        });
+        T::disable();
+    }
+}
+
 trait SealedInstance {
     fn regs() -> pac::rng::Rng;
 }
@@ -0,0 +1,446 @@
+//! Real-time clock (RTC)
+//!
+//! The RTC of CH32V1/V2/V3/L1 is a 32-bit seconds counter in the backup domain, there is no BCD
+//! calendar. [`Rtc`] counts seconds since 1970-01-01 00:00:00 and converts to and from
+//! [`DateTime`], up to the counter overflow in February 2106. CH32V003 and CH32X035 have no RTC,
+//! use the AWU (auto-wakeup) for periodic wakeups there.
+//!
+//! The RTC clock is set up by [`rcc::Config::ls`](crate::rcc::Config), from LSE or LSI. It keeps
+//! counting across resets as long as the backup domain is powered.
+
+use core::future::poll_fn;
+use core::task::Poll;
+use core::time::Duration;
+
+use embassy_sync::waitqueue::AtomicWaker;
+
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::RTC;
+use crate::{interrupt, into_ref, peripherals, Peripheral, PeripheralRef};
+
+static ALARM_WAKER: AtomicWaker = AtomicWaker::new();
+
+// RTC_CTLRH
+const SECIE: u32 = 1 << 0;
+const ALRIE: u32 = 1 << 1;
+const OWIE: u32 = 1 << 2;
+
+// RTC_CTLRL, the flags are cleared by writing 0
+const SECF: u32 = 1 << 0;
+const ALRF: u32 = 1 << 1;
+const OWF: u32 = 1 << 2;
+const RSF: u32 = 1 << 3;
+const CNF: u32 = 1 << 4;
+const RTOFF: u32 = 1 << 5;
+
+/// RTC error
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The date or time is out of range, or not a valid calendar date.
+    InvalidDateTime,
+}
+
+/// Day of the week
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DayOfWeek {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// Calendar date and time, without time zone.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    /// 1970 to 2106
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    /// 0 to 23
+    pub hour: u8,
+    /// 0 to 59
+    pub minute: u8,
+    /// 0 to 59
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Date and time `secs` seconds after 1970-01-01 00:00:00.
+    pub fn from_unix(secs: u32) -> Self {
+        let days = secs / 86_400;
+        let time = secs % 86_400;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    /// Seconds since 1970-01-01 00:00:00, up to 2106-02-07 06:28:15.
+    pub fn to_unix(&self) -> Result<u32, Error> {
+        if !(1970..=2106).contains(&self.year)
+            || !(1..=12).contains(&self.month)
+            || self.day == 0
+            || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+        {
+            return Err(Error::InvalidDateTime);
+        }
+        let days = days_from_civil(self.year, self.month, self.day) as u64;
+        let secs = days * 86_400 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64;
+        u32::try_from(secs).map_err(|_| Error::InvalidDateTime)
+    }
+
+    /// Day of the week, in the Gregorian calendar.
+    ///
+    /// Any year works, also before 1970. The date isn't checked, an invalid one gives a
+    /// meaningless day.
+    pub fn day_of_week(&self) -> DayOfWeek {
+        // 1970-01-01 was a Thursday
+        match (days_from_civil(self.year, self.month, self.day) + 3).rem_euclid(7) {
+            0 => DayOfWeek::Monday,
+            1 => DayOfWeek::Tuesday,
+            2 => DayOfWeek::Wednesday,
+            3 => DayOfWeek::Thursday,
+            4 => DayOfWeek::Friday,
+            5 => DayOfWeek::Saturday,
+            _ => DayOfWeek::Sunday,
+        }
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01, negative before it.
+///
+/// Doesn't overflow for any arguments, so it can be used before validating them.
+fn days_from_civil(year: u16, month: u8, day: u8) -> i64 {
+    // years start in March, so that the leap day is the last day of the year
+    let (y, m) = if month <= 2 {
+        (year as i64 - 1, month as i64 + 9)
+    } else {
+        (year as i64, month as i64 - 3)
+    };
+    let day_of_year = (153 * m + 2) / 5 + day as i64 - 1;
+    // y is -1 for January and February of year 0
+    let days = y * 365 + y.div_euclid(4) - y.div_euclid(100) + y.div_euclid(400) + day_of_year;
+    // days from 0000-03-01 to 1970-01-01
+    days - 719_468
+}
+
+/// Date from days since 1970-01-01.
+fn civil_from_days(days: u32) -> (u16, u8, u8) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let m = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * m + 2) / 5 + 1;
+    let month = if m < 10 { m + 3 } else { m - 9 };
+    let year = era * 400 + year_of_era + (month <= 2) as u32;
+    (year as u16, month as u8, day as u8)
+}
+
+/// EXTI line of the RTC alarm
+const ALARM_EXTI_LINE: usize = 17;
+
+/// RTC alarm interrupt handler.
+///
+/// The alarm reaches the core through EXTI line 17, which also wakes it from Stop mode.
+pub struct InterruptHandler {
+    _private: (),
+}
+
+impl interrupt::typelevel::Handler<interrupt::typelevel::RTCAlarm> for InterruptHandler {
+    unsafe fn on_interrupt() {
+        crate::pac::EXTI.intfr().write(|w| w.0 = 1 << ALARM_EXTI_LINE);
+
+        if RTC.ctlrl().read().0 & ALRF != 0 {
+            // the waiting future sees the interrupt masked
+            RTC.ctlrh().modify(|w| w.0 &= !ALRIE);
+            clear_flags(ALRF);
+            ALARM_WAKER.wake();
+        }
+    }
+}
+
+/// Clear the given RTC_CTLRL flags, leaving the others and CNF unchanged.
+fn clear_flags(flags: u32) {
+    let ctlrl = RTC.ctlrl().read().0;
+    RTC.ctlrl()
+        .write(|w| w.0 = (ctlrl & CNF) | ((SECF | ALRF | OWF | RSF) & !flags));
+}
+
+/// Wait until the APB side of the registers is in sync with the RTC.
+///
+/// After a reset or a wakeup from Stop the registers read stale values until the next RTC
+/// clock edge.
+fn wait_sync() {
+    clear_flags(RSF);
+    while RTC.ctlrl().read().0 & RSF == 0 {}
+}
+
+/// Write the counter, prescaler or alarm registers in configuration mode.
+fn configure(f: impl FnOnce()) {
+    critical_section::with(|_| {
+        while RTC.ctlrl().read().0 & RTOFF == 0 {}
+        RTC.ctlrl().modify(|w| w.0 |= CNF);
+        f();
+        RTC.ctlrl().modify(|w| w.0 &= !CNF);
+        // the registers are updated once the write has crossed into the RTC clock domain
+        while RTC.ctlrl().read().0 & RTOFF == 0 {}
+    });
+}
+
+/// RTC driver.
+pub struct Rtc<'d> {
+    _inner: PeripheralRef<'d, peripherals::RTC>,
+}
+
+impl<'d> Rtc<'d> {
+    /// Create a new RTC driver, counting seconds from the RTC clock.
+    ///
+    /// The counter keeps its value, so the time survives resets. Panics if the RTC has no clock,
+    /// see `ls` in [`rcc::Config`](crate::rcc::Config).
+    pub fn new(
+        inner: impl Peripheral<P = peripherals::RTC> + 'd,
+        _irq: impl interrupt::typelevel::Binding<interrupt::typelevel::RTCAlarm, InterruptHandler> + 'd,
+    ) -> Self {
+        into_ref!(inner);
+        let rtc_clk = crate::rcc::clocks()
+            .rtc
+            .expect("RTC: no clock, set `ls.rtc_mux` in `rcc::Config`");
+
+        wait_sync();
+
+        // 1 Hz counter. PSCR is write-only, but rewriting it doesn't disturb the running count.
+        let prescaler = rtc_clk.0 - 1;
+        configure(|| {
+            RTC.pscrh().write(|w| w.0 = (prescaler >> 16) & 0xf);
+            RTC.pscrl().write(|w| w.0 = prescaler & 0xffff);
+        });
+
+        RTC.ctlrh().modify(|w| w.0 &= !(SECIE | ALRIE | OWIE));
+        clear_flags(SECF | ALRF | OWF);
+
+        // Alarm on the rising edge of EXTI line 17, as an interrupt
+        critical_section::with(|_| {
+            let exti = crate::pac::EXTI;
+            exti.rtenr().modify(|w| w.set_tr(ALARM_EXTI_LINE, true));
+            exti.ftenr().modify(|w| w.set_tr(ALARM_EXTI_LINE, false));
+            exti.intfr().write(|w| w.0 = 1 << ALARM_EXTI_LINE);
+            exti.intenr().modify(|w| w.set_mr(ALARM_EXTI_LINE, true));
+        });
+
+        interrupt::typelevel::RTCAlarm::unpend();
+        unsafe { interrupt::typelevel::RTCAlarm::enable() };
+
+        Self { _inner: inner }
+    }
+
+    /// Seconds since 1970-01-01 00:00:00.
+    pub fn counter(&self) -> u32 {
+        // the low half may wrap between the two reads
+        loop {
+            let high = RTC.cnth().read().0 & 0xffff;
+            let low = RTC.cntl().read().0 & 0xffff;
+            if RTC.cnth().read().0 & 0xffff == high {
+                return (high << 16) | low;
+            }
+        }
+    }
+
+    /// Set the seconds counter.
+    pub fn set_counter(&mut self, secs: u32) {
+        configure(|| {
+            RTC.cnth().write(|w| w.0 = secs >> 16);
+            RTC.cntl().write(|w| w.0 = secs & 0xffff);
+        });
+    }
+
+    /// Current date and time.
+    pub fn now(&self) -> DateTime {
+        DateTime::from_unix(self.counter())
+    }
+
+    /// Set the date and time.
+    pub fn set_datetime(&mut self, datetime: DateTime) -> Result<(), Error> {
+        let secs = datetime.to_unix()?;
+        self.set_counter(secs);
+        Ok(())
+    }
+
+    /// Set the alarm, and wait for it.
+    ///
+    /// Returns right away if `datetime` is not in the future.
+    pub async fn wait_until(&mut self, datetime: DateTime) -> Result<(), Error> {
+        let secs = datetime.to_unix()?;
+        self.wait_counter(secs).await;
+        Ok(())
+    }
+
+    /// Wake up periodically, every `interval`.
+    ///
+    /// The counter ticks at 1 Hz whatever the RTC clock is, so the interval is whole seconds,
+    /// from 1 s up to `u32::MAX` s, and fractions of a second are dropped. The accuracy is that
+    /// of the RTC clock: a 32.768 kHz LSE crystal is within a few seconds per day, LSI can be
+    /// off by several percent.
+    ///
+    /// Each tick is an RTC alarm, which wakes the core from Stop mode through EXTI line 17.
+    /// Panics if `interval` is shorter than a second.
+    pub fn wakeup_interval(&mut self, interval: Duration) -> Wakeup<'_, 'd> {
+        let interval = u32::try_from(interval.as_secs()).unwrap_or(u32::MAX);
+        assert!(interval >= 1, "RTC: wakeup interval must be at least 1 s");
+        let next = self.counter().saturating_add(interval);
+        Wakeup {
+            rtc: self,
+            interval,
+            next,
+        }
+    }
+
+    async fn wait_counter(&mut self, secs: u32) {
+        if secs <= self.counter() {
+            return;
+        }
+
+        // ALRF is set when the counter reaches the alarm value
+        configure(|| {
+            RTC.alrmh().write(|w| w.0 = secs >> 16);
+            RTC.alrml().write(|w| w.0 = secs & 0xffff);
+        });
+        clear_flags(ALRF);
+        RTC.ctlrh().modify(|w| w.0 |= ALRIE);
+
+        // the alarm could have been missed while it was set up
+        if secs <= self.counter() {
+            RTC.ctlrh().modify(|w| w.0 &= !ALRIE);
+            return;
+        }
+
+        poll_fn(|cx| {
+            ALARM_WAKER.register(cx.waker());
+            if RTC.ctlrh().read().0 & ALRIE != 0 {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+
+        // the core may have been in Stop mode
+        wait_sync();
+    }
+}
+
+impl<'d> Drop for Rtc<'d> {
+    /// Disable the RTC interrupts, the counter keeps running.
+    fn drop(&mut self) {
+        RTC.ctlrh().modify(|w| w.0 &= !(SECIE | ALRIE | OWIE));
+        critical_section::with(|_| {
+            crate::pac::EXTI.intenr().modify(|w| w.set_mr(ALARM_EXTI_LINE, false));
+        });
+        interrupt::typelevel::RTCAlarm::disable();
+    }
+}
+
+/// Periodic RTC wakeup, see [`Rtc::wakeup_interval`].
+pub struct Wakeup<'a, 'd> {
+    rtc: &'a mut Rtc<'d>,
+    interval: u32,
+    next: u32,
+}
+
+impl<'a, 'd> Wakeup<'a, 'd> {
+    /// Wait for the next tick.
+    ///
+    /// Ticks stay on the grid set up by [`Rtc::wakeup_interval`]. Ticks that passed while the
+    /// caller was busy are skipped, not made up for.
+    pub async fn wait(&mut self) {
+        let now = self.rtc.counter();
+        if self.next <= now {
+            let missed = (now - self.next) / self.interval + 1;
+            self.next = self.next.saturating_add(missed.saturating_mul(self.interval));
+        }
+        self.rtc.wait_counter(self.next).await;
+        self.next = self.next.saturating_add(self.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn unix_roundtrip() {
+        for secs in [0, 59, 86_399, 86_400, 951_782_400, 1_700_000_000, u32::MAX] {
+            assert_eq!(DateTime::from_unix(secs).to_unix(), Ok(secs));
+        }
+        assert_eq!(datetime(2000, 2, 29, 0, 0, 0).to_unix(), Ok(951_782_400));
+        assert_eq!(DateTime::from_unix(1_700_000_000), datetime(2023, 11, 14, 22, 13, 20));
+        assert_eq!(DateTime::from_unix(u32::MAX), datetime(2106, 2, 7, 6, 28, 15));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert_eq!(datetime(1969, 12, 31, 0, 0, 0).to_unix(), Err(Error::InvalidDateTime));
+        assert_eq!(datetime(2023, 2, 29, 0, 0, 0).to_unix(), Err(Error::InvalidDateTime));
+        assert_eq!(datetime(2100, 2, 29, 0, 0, 0).to_unix(), Err(Error::InvalidDateTime));
+        assert_eq!(datetime(2024, 4, 31, 0, 0, 0).to_unix(), Err(Error::InvalidDateTime));
+        assert_eq!(datetime(2024, 1, 1, 24, 0, 0).to_unix(), Err(Error::InvalidDateTime));
+        assert_eq!(datetime(2106, 2, 7, 6, 28, 16).to_unix(), Err(Error::InvalidDateTime));
+        assert!(datetime(2024, 2, 29, 23, 59, 59).to_unix().is_ok());
+    }
+
+    #[test]
+    fn day_of_week() {
+        assert_eq!(datetime(1970, 1, 1, 0, 0, 0).day_of_week(), DayOfWeek::Thursday);
+        assert_eq!(datetime(2000, 1, 1, 0, 0, 0).day_of_week(), DayOfWeek::Saturday);
+        assert_eq!(datetime(2024, 2, 29, 0, 0, 0).day_of_week(), DayOfWeek::Thursday);
+        // Before 1970, which `to_unix` rejects
+        assert_eq!(datetime(1969, 12, 31, 0, 0, 0).day_of_week(), DayOfWeek::Wednesday);
+        assert_eq!(datetime(1900, 1, 1, 0, 0, 0).day_of_week(), DayOfWeek::Monday);
+        assert_eq!(datetime(0, 1, 1, 0, 0, 0).day_of_week(), DayOfWeek::Saturday);
+        assert_eq!(datetime(0, 3, 1, 0, 0, 0).day_of_week(), DayOfWeek::Wednesday);
+        // Invalid dates don't panic
+        datetime(0, 0, 0, 0, 0, 0).day_of_week();
+        datetime(u16::MAX, 255, 255, 0, 0, 0).day_of_week();
+    }
+}
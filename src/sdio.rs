@@ -1146,6 +1146,8 @@ impl<'d, T: Instance, Dma: SdioDma<T> + 'd> Drop for Sdmmc<'d, T, Dma> {
                 x.set_as_disconnected();
             }
         });
+
+        T::disable();
     }
 }
 
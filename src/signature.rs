@@ -8,12 +8,35 @@ pub fn flash_size_kb() -> u16 {
 }
 
 /// Returns the unique ID
+///
+/// The flash capacity and unique ID are at the same addresses on all supported families.
 pub fn unique_id() -> [u8; 12] {
     const ESIG_UID: *const [u8; 12] = 0x1FFFF7E8 as *const [u8; 12];
 
     unsafe { core::ptr::read_volatile(ESIG_UID) }
 }
 
+/// Returns the unique ID as 3 words, ESIG_UNIID1 to ESIG_UNIID3
+pub fn unique_id_words() -> [u32; 3] {
+    const ESIG_UID: *const u32 = 0x1FFFF7E8 as *const u32;
+
+    unsafe { core::array::from_fn(|i| core::ptr::read_volatile(ESIG_UID.add(i))) }
+}
+
+/// Formats the unique ID as 24 lowercase hex digits into `buf`, in byte order
+///
+/// Useful as a USB serial number.
+pub fn unique_id_hex(buf: &mut [u8; 24]) -> &str {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    for (i, b) in unique_id().iter().enumerate() {
+        buf[2 * i] = HEX[(b >> 4) as usize];
+        buf[2 * i + 1] = HEX[(b & 0xF) as usize];
+    }
+    // only ASCII hex digits were written
+    unsafe { core::str::from_utf8_unchecked(buf) }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct ChipID(u32);
 
@@ -17,12 +17,15 @@ use core::marker::PhantomData;
 use core::ptr;
 
 use embassy_futures::join::join;
-use embedded_hal::spi::{Mode, Phase, Polarity, MODE_0};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::mutex::Mutex;
+use embedded_hal::spi::{Mode, Operation, Phase, Polarity, MODE_0};
 use pac::spi::vals::BaudRate;
 use pac::spi::Spi as Regs;
 
 use crate::dma::{slice_ptr_parts, word, ChannelAndRequest};
-use crate::gpio::{AFType, AnyPin, Pull, Speed};
+use crate::gpio::{AFType, AnyPin, Output, Pull, Speed};
+use crate::internal::drop::OnDrop;
 use crate::mode::{Async, Blocking, Mode as PeriMode};
 use crate::time::Hertz;
 use crate::{into_ref, pac, peripherals, Peripheral, PeripheralRef};
@@ -41,6 +44,7 @@ pub enum Error {
     Overrun,
 }
 
+/// Bit order of the frames, in LSBFIRST.
 #[derive(Copy, Clone)]
 pub enum BitOrder {
     // CH32V003 supports MSB first only
@@ -49,12 +53,31 @@ pub enum BitOrder {
     MsbFirst,
 }
 
+/// SPI configuration.
+///
+/// The frame size isn't part of it: transfers of `u8` words use 8-bit frames, and transfers of
+/// `u16` words 16-bit frames (DFF), with each word written to the data register at once. Both
+/// can be mixed on one bus, the SPI is briefly disabled between frames to switch.
 #[non_exhaustive]
 #[derive(Copy, Clone)]
 pub struct Config {
     pub mode: Mode,
     pub bit_order: BitOrder,
     pub frequency: Hertz,
+    /// Speed (slew rate) of the SCK and data output pins.
+    ///
+    /// Lower it to reduce ringing and EMI when the SPI clock allows.
+    pub gpio_speed: Speed,
+    /// Hardware CRC with this polynomial (CRCR), `None` to disable it
+    ///
+    /// Each transfer is followed by its CRC word, and the CRC word received after the data is
+    /// checked, failing the transfer with [`Error::Crc`] on mismatch. DMA transfers longer than
+    /// 65535 words get a CRC for each 65535 words.
+    pub crc_polynomial: Option<u16>,
+    /// Byte sent while reading, and after the end of the shorter buffer in transfers.
+    ///
+    /// `u16` words send it in both bytes. Some devices, like SD cards, need 0xFF.
+    pub dummy_byte: u8,
 }
 
 impl Default for Config {
@@ -63,6 +86,9 @@ impl Default for Config {
             mode: MODE_0,
             bit_order: BitOrder::MsbFirst,
             frequency: Hertz::hz(1_000_000),
+            gpio_speed: Speed::High,
+            crc_polynomial: None,
+            dummy_byte: 0,
         }
     }
 }
@@ -92,7 +118,7 @@ impl Config {
         }
     }
 
-    fn from_cfgr(cfgr: &pac::spi::regs::Ctlr1, bus_clk: Hertz) -> Self {
+    fn from_cfgr(cfgr: &pac::spi::regs::Ctlr1, bus_clk: Hertz, gpio_speed: Speed, crc_polynomial: Option<u16>) -> Self {
         let polarity = if cfgr.cpol() {
             Polarity::IdleHigh
         } else {
@@ -130,20 +156,34 @@ impl Config {
             mode,
             bit_order,
             frequency: spi_freq,
+            gpio_speed,
+            crc_polynomial,
+            dummy_byte: 0,
         }
     }
 }
 
+/// SPI role.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Role {
+    Master,
+    /// Slave, selected by the NSS pin if one is given, otherwise always selected.
+    Slave,
+}
+
 /// SPI driver.
 pub struct Spi<'d, T: Instance, M: PeriMode> {
     _peri: PeripheralRef<'d, T>,
     sck: Option<PeripheralRef<'d, AnyPin>>,
     mosi: Option<PeripheralRef<'d, AnyPin>>,
     miso: Option<PeripheralRef<'d, AnyPin>>,
+    nss: Option<PeripheralRef<'d, AnyPin>>,
     tx_dma: Option<ChannelAndRequest<'d>>,
     rx_dma: Option<ChannelAndRequest<'d>>,
     _phantom: PhantomData<M>,
     current_word_size: word_impl::Config,
+    gpio_speed: Speed,
+    dummy_byte: u8,
 }
 
 impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
@@ -155,6 +195,21 @@ impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
         tx_dma: Option<ChannelAndRequest<'d>>,
         rx_dma: Option<ChannelAndRequest<'d>>,
         config: Config,
+    ) -> Self {
+        Self::new_inner_with_role(peri, sck, mosi, miso, None, tx_dma, rx_dma, config, Role::Master)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_inner_with_role(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: Option<PeripheralRef<'d, AnyPin>>,
+        mosi: Option<PeripheralRef<'d, AnyPin>>,
+        miso: Option<PeripheralRef<'d, AnyPin>>,
+        nss: Option<PeripheralRef<'d, AnyPin>>,
+        tx_dma: Option<ChannelAndRequest<'d>>,
+        rx_dma: Option<ChannelAndRequest<'d>>,
+        config: Config,
+        role: Role,
     ) -> Self {
         into_ref!(peri);
 
@@ -168,23 +223,39 @@ impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
         T::enable_and_reset();
 
         // high speed mode
-        if config.frequency.0 >= 36_000_000 && div == BaudRate::DIV_2 && miso.is_some() {
+        if role == Role::Master && config.frequency.0 >= 36_000_000 && div == BaudRate::DIV_2 && miso.is_some() {
             regs.hscr().write(|w| w.set_hsrxen(true));
         }
 
-        regs.ctlr2().modify(|w| w.set_ssoe(false));
+        // Master with hardware NSS: drive NSS low while the SPI is enabled
+        let hardware_nss = role == Role::Master && nss.is_some();
+        regs.ctlr2().modify(|w| w.set_ssoe(hardware_nss));
+        if let Some(poly) = config.crc_polynomial {
+            write_crc_polynomial(regs, poly);
+        }
         regs.ctlr1().modify(|w| {
             w.set_cpol(cpol);
             w.set_cpha(cpha);
-            w.set_mstr(true); // master
             w.set_br(div);
             w.set_spe(true);
             w.set_lsbfirst(config.lsb_first());
-            w.set_ssi(true);
-            w.set_ssm(true);
-            w.set_crcen(false);
+            match role {
+                Role::Master => {
+                    w.set_mstr(true);
+                    w.set_ssi(true);
+                    w.set_ssm(nss.is_none());
+                    w.set_rxonly(mosi.is_none());
+                }
+                Role::Slave => {
+                    w.set_mstr(false);
+                    // Software NSS: always selected. Hardware NSS: selected by the NSS pin.
+                    w.set_ssi(false);
+                    w.set_ssm(nss.is_none());
+                    w.set_rxonly(miso.is_none());
+                }
+            }
+            w.set_crcen(config.crc_polynomial.is_some());
             w.set_bidimode(false); // undirectional
-            w.set_rxonly(mosi.is_none());
             w.set_dff(false); // u8
         });
 
@@ -193,14 +264,21 @@ impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
             sck,
             mosi,
             miso,
+            nss,
             tx_dma,
             rx_dma,
             current_word_size: <u8 as SealedWord>::CONFIG,
+            gpio_speed: config.gpio_speed,
+            dummy_byte: config.dummy_byte,
             _phantom: PhantomData,
         }
     }
 
-    /// Reconfigure the SPI peripheral.
+    /// Reconfigure the SPI peripheral, e.g. between transactions to devices on a shared bus.
+    ///
+    /// Waits for the bus to be idle and disables the SPI while CTLR1 is changed, the next
+    /// transfer enables it again. The frequency is rounded down to one the prescaler can do,
+    /// [`get_current_config`](Self::get_current_config) has the achieved one.
     pub fn set_config(&mut self, config: &Config) -> Result<(), ()> {
         let cpha = config.raw_phase();
         let cpol = config.raw_polarity();
@@ -209,27 +287,122 @@ impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
 
         let br = calculate_baud_rate(T::frequency().0, config.frequency.0);
 
-        T::REGS.ctlr1().modify(|w| {
+        self.modify_disabled(|w| {
             w.set_cpol(cpol);
             w.set_cpha(cpha);
             w.set_br(br);
             w.set_lsbfirst(lsbfirst);
         });
 
+        if T::REGS.ctlr1().read().crcen() || config.crc_polynomial.is_some() {
+            // CRCEN may only be changed while the SPI is disabled, transfers enable it again
+            if let Some(poly) = config.crc_polynomial {
+                write_crc_polynomial(T::REGS, poly);
+            }
+            T::REGS.ctlr1().modify(|w| w.set_crcen(config.crc_polynomial.is_some()));
+        }
+
+        if config.gpio_speed != self.gpio_speed {
+            use crate::gpio::SealedPin;
+
+            // Input pins (MISO in master mode) are left alone
+            for pin in [&self.sck, &self.mosi, &self.miso].into_iter().flatten() {
+                pin.set_speed(config.gpio_speed);
+            }
+            self.gpio_speed = config.gpio_speed;
+        }
+        self.dummy_byte = config.dummy_byte;
+
         Ok(())
     }
 
+    /// Set the SPI clock to the highest frequency not above `frequency`, see [`set_config`](Self::set_config).
+    ///
+    /// Returns the achieved frequency.
+    pub fn set_frequency(&mut self, frequency: Hertz) -> Hertz {
+        let br = calculate_baud_rate(T::frequency().0, frequency.0);
+        self.modify_disabled(|w| w.set_br(br));
+        T::frequency() / baud_rate_divider(T::frequency().0, frequency.0)
+    }
+
+    /// Set the clock polarity and phase, see [`set_config`](Self::set_config).
+    pub fn set_mode(&mut self, mode: Mode) {
+        let config = Config {
+            mode,
+            ..Config::default()
+        };
+        let (cpol, cpha) = (config.raw_polarity(), config.raw_phase());
+        self.modify_disabled(|w| {
+            w.set_cpol(cpol);
+            w.set_cpha(cpha);
+        });
+    }
+
+    /// Set the bit order, see [`set_config`](Self::set_config).
+    pub fn set_bit_order(&mut self, bit_order: BitOrder) {
+        let lsbfirst = Config {
+            bit_order,
+            ..Config::default()
+        }
+        .lsb_first();
+        self.modify_disabled(|w| w.set_lsbfirst(lsbfirst));
+    }
+
+    // CTLR1 may only be changed while the SPI is disabled, transfers enable it again
+    fn modify_disabled(&mut self, f: impl FnOnce(&mut pac::spi::regs::Ctlr1)) {
+        while T::REGS.statr().read().bsy() {}
+        T::REGS.ctlr1().modify(|w| w.set_spe(false));
+        T::REGS.ctlr1().modify(f);
+    }
+
+    /// Switch a driver made by `new_inner` to half-duplex, on the MOSI pin.
+    fn into_half_duplex(mut self) -> Self {
+        self.modify_disabled(|w| {
+            w.set_bidimode(true);
+            w.set_bidioe(true);
+            w.set_rxonly(false);
+        });
+        self
+    }
+
+    /// Turn the data line around in half-duplex mode, with the SPI disabled.
+    fn set_half_duplex_output(&mut self, output: bool) {
+        let cr1 = T::REGS.ctlr1().read();
+        if cr1.bidimode() && cr1.bidioe() != output {
+            self.modify_disabled(|w| w.set_bidioe(output));
+        }
+    }
+
+    /// Counter ticks for one SCK cycle, rounded up.
+    fn sck_cycle_ticks(&self) -> u32 {
+        let div = 2_u64 << T::REGS.ctlr1().read().br().to_bits();
+        (div * crate::delay::counter_frequency() as u64 / T::frequency().0 as u64) as u32 + 1
+    }
+
     /// Get current SPI configuration. Useful for get the current baudrate.
     pub fn get_current_config(&self) -> Config {
         let bus_freq = T::frequency();
 
-        Config::from_cfgr(&T::REGS.ctlr1().read(), bus_freq)
+        let cr1 = T::REGS.ctlr1().read();
+        let crc_polynomial = cr1.crcen().then(|| read_crc_polynomial(T::REGS));
+        Config {
+            dummy_byte: self.dummy_byte,
+            ..Config::from_cfgr(&cr1, bus_freq, self.gpio_speed, crc_polynomial)
+        }
     }
 
-    fn set_word_size(&mut self, config: word_impl::Config) {
+    /// Switch between 8 and 16-bit frames (DFF) for words of type `W`.
+    ///
+    /// Transfers do this for their word type, call it to switch before selecting a device.
+    /// DFF may only be changed while the SPI is disabled, so this waits for the bus to be idle,
+    /// and the next transfer enables the SPI again.
+    pub fn set_word_size<W: Word>(&mut self) {
+        let config = W::CONFIG;
         if self.current_word_size == config {
             return;
         }
+        while T::REGS.statr().read().bsy() {}
+        T::REGS.ctlr1().modify(|w| w.set_spe(false));
         T::REGS.ctlr1().modify(|w| {
             w.set_dff(config == <u16 as SealedWord>::CONFIG);
         });
@@ -238,24 +411,108 @@ impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
 
     // blocking functions
 
-    /// Blocking write.
-    pub fn blocking_write<W: Word>(&mut self, words: &[W]) -> Result<(), Error> {
+    /// Set up a blocking transfer of `W` words, returns whether CRC is enabled.
+    fn blocking_begin<W: Word>(&mut self) -> bool {
+        self.set_word_size::<W>();
+        let crc = T::REGS.ctlr1().read().crcen();
+        if crc {
+            reset_crc(T::REGS);
+        }
         T::REGS.ctlr1().modify(|w| w.set_spe(true));
         flush_rx_fifo(T::REGS);
-        self.set_word_size(W::CONFIG);
-        for word in words.iter() {
-            let _ = transfer_word(&T::REGS, *word)?;
+        crc
+    }
+
+    /// Receive and check the CRC word that follows the data.
+    fn blocking_end<W: Word>(&mut self, crc: bool, len: usize) -> Result<(), Error> {
+        if crc && len != 0 {
+            spin_until_rx_ready(&T::REGS)?;
+            let _: W = unsafe { ptr::read_volatile(T::REGS.datar().as_ptr() as _) };
+            check_crc(T::REGS)?;
         }
         Ok(())
     }
 
+    /// Blocking write.
+    pub fn blocking_write<W: Word>(&mut self, words: &[W]) -> Result<(), Error> {
+        if T::REGS.ctlr1().read().bidimode() {
+            return self.blocking_write_half_duplex(words);
+        }
+
+        let crc = self.blocking_begin::<W>();
+        for (i, word) in words.iter().enumerate() {
+            let _ = transfer_word(&T::REGS, *word, crc && i == words.len() - 1)?;
+        }
+        self.blocking_end::<W>(crc, words.len())
+    }
+
     /// Blocking read.
     pub fn blocking_read<W: Word>(&mut self, words: &mut [W]) -> Result<(), Error> {
-        T::REGS.ctlr1().modify(|w| w.set_spe(true));
-        flush_rx_fifo(T::REGS);
-        self.set_word_size(W::CONFIG);
-        for word in words.iter_mut() {
-            *word = transfer_word(&T::REGS, W::default())?;
+        if is_receive_only(T::REGS) {
+            return self.blocking_read_receive_only(words);
+        }
+
+        let crc = self.blocking_begin::<W>();
+        let len = words.len();
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = transfer_word(&T::REGS, W::dummy(self.dummy_byte), crc && i == len - 1)?;
+        }
+        self.blocking_end::<W>(crc, len)
+    }
+
+    // The receiver is off while transmitting, only TXE and BSY tell when the data is out
+    fn blocking_write_half_duplex<W: Word>(&mut self, words: &[W]) -> Result<(), Error> {
+        self.set_half_duplex_output(true);
+        let crc = self.blocking_begin::<W>();
+        for (i, word) in words.iter().enumerate() {
+            spin_until_tx_ready(&T::REGS)?;
+            unsafe {
+                ptr::write_volatile(T::REGS.datar().as_ptr() as _, *word);
+            }
+            if crc && i == words.len() - 1 {
+                T::REGS.ctlr1().modify(|w| w.set_crcnext(true));
+            }
+        }
+        spin_until_tx_ready(&T::REGS)?;
+        while T::REGS.statr().read().bsy() {}
+        Ok(())
+    }
+
+    // The clock runs as long as the SPI is enabled. It is disabled once the last frame has
+    // started, one SCK cycle after the frame before it was received.
+    fn blocking_read_receive_only<W: Word>(&mut self, words: &mut [W]) -> Result<(), Error> {
+        if words.is_empty() {
+            return Ok(());
+        }
+
+        self.set_half_duplex_output(false);
+        let sck_cycle = self.sck_cycle_ticks();
+
+        // Also stops the clock on errors
+        let _stop = OnDrop::new(|| T::REGS.ctlr1().modify(|w| w.set_spe(false)));
+
+        let crc = self.blocking_begin::<W>();
+        let len = words.len();
+        let frames = len + crc as usize;
+        for i in 0..frames {
+            if crc && i == len - 1 {
+                // The CRC is received after the last data word
+                T::REGS.ctlr1().modify(|w| w.set_crcnext(true));
+            }
+            if i == frames - 1 {
+                let start = crate::delay::counter();
+                while crate::delay::counter().wrapping_sub(start) < sck_cycle {}
+                T::REGS.ctlr1().modify(|w| w.set_spe(false));
+            }
+            spin_until_rx_ready(&T::REGS)?;
+            let word: W = unsafe { ptr::read_volatile(T::REGS.datar().as_ptr() as _) };
+            if let Some(w) = words.get_mut(i) {
+                *w = word;
+            }
+        }
+
+        if crc {
+            check_crc(T::REGS)?;
         }
         Ok(())
     }
@@ -264,13 +521,13 @@ impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
     ///
     /// This writes the contents of `data` on MOSI, and puts the received data on MISO in `data`, at the same time.
     pub fn blocking_transfer_in_place<W: Word>(&mut self, words: &mut [W]) -> Result<(), Error> {
-        T::REGS.ctlr1().modify(|w| w.set_spe(true));
-        flush_rx_fifo(T::REGS);
-        self.set_word_size(W::CONFIG);
-        for word in words.iter_mut() {
-            *word = transfer_word(&T::REGS, *word)?;
+        assert_full_duplex(T::REGS);
+        let crc = self.blocking_begin::<W>();
+        let len = words.len();
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = transfer_word(&T::REGS, *word, crc && i == len - 1)?;
         }
-        Ok(())
+        self.blocking_end::<W>(crc, len)
     }
 
     /// Blocking bidirectional transfer.
@@ -278,20 +535,19 @@ impl<'d, T: Instance, M: PeriMode> Spi<'d, T, M> {
     /// This transfers both buffers at the same time, so it is NOT equivalent to `write` followed by `read`.
     ///
     /// The transfer runs for `max(read.len(), write.len())` bytes. If `read` is shorter extra bytes are ignored.
-    /// If `write` is shorter it is padded with zero bytes.
+    /// If `write` is shorter it is padded with [`Config::dummy_byte`].
     pub fn blocking_transfer<W: Word>(&mut self, read: &mut [W], write: &[W]) -> Result<(), Error> {
-        T::REGS.ctlr1().modify(|w| w.set_spe(true));
-        flush_rx_fifo(T::REGS);
-        self.set_word_size(W::CONFIG);
+        assert_full_duplex(T::REGS);
+        let crc = self.blocking_begin::<W>();
         let len = read.len().max(write.len());
         for i in 0..len {
-            let wb = write.get(i).copied().unwrap_or_default();
-            let rb = transfer_word(&T::REGS, wb)?;
+            let wb = write.get(i).copied().unwrap_or(W::dummy(self.dummy_byte));
+            let rb = transfer_word(&T::REGS, wb, crc && i == len - 1)?;
             if let Some(r) = read.get_mut(i) {
                 *r = rb;
             }
         }
-        Ok(())
+        self.blocking_end::<W>(crc, len)
     }
 }
 
@@ -308,8 +564,8 @@ impl<'d, T: Instance> Spi<'d, T, Blocking> {
 
         T::set_remap(REMAP);
 
-        sck.set_as_af_output(AFType::OutputPushPull, Speed::High);
-        mosi.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
         miso.set_as_input(Pull::None);
 
         Self::new_inner(
@@ -323,7 +579,44 @@ impl<'d, T: Instance> Spi<'d, T, Blocking> {
         )
     }
 
+    /// Create a new SPI driver, driving the hardware NSS pin.
+    ///
+    /// NSS is low while the SPI is enabled, from the first transfer on. It only goes high again
+    /// when the SPI is disabled, e.g. to change the frame size or the configuration, so this
+    /// suits a single device that tolerates a select held across transfers.
+    pub fn new_blocking_with_nss<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T, REMAP>> + 'd,
+        nss: impl Peripheral<P = impl CsPin<T, REMAP>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, mosi, miso, nss);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        miso.set_as_input(Pull::None);
+        nss.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+
+        Self::new_inner_with_role(
+            peri,
+            Some(sck.map_into()),
+            Some(mosi.map_into()),
+            Some(miso.map_into()),
+            Some(nss.map_into()),
+            None,
+            None,
+            config,
+            Role::Master,
+        )
+    }
+
     /// Create a new SPI driver, in RX-only mode (only MISO pin, no MOSI).
+    ///
+    /// The clock runs only during reads, and stops after the last word.
     pub fn new_blocking_rxonly<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
         sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
@@ -334,7 +627,7 @@ impl<'d, T: Instance> Spi<'d, T, Blocking> {
 
         T::set_remap(REMAP);
 
-        sck.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
         miso.set_as_input(Pull::None);
 
         Self::new_inner(
@@ -348,6 +641,36 @@ impl<'d, T: Instance> Spi<'d, T, Blocking> {
         )
     }
 
+    /// Create a new SPI driver, in half-duplex mode, on a single bidirectional data line.
+    ///
+    /// The data line is `sda` on the MOSI pin. Writes drive it, reads turn it around and
+    /// receive like [`new_blocking_rxonly`](Self::new_blocking_rxonly). Transfers in both
+    /// directions at once panic.
+    pub fn new_blocking_half_duplex<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        sda: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, sda);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        sda.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+
+        Self::new_inner(
+            peri,
+            Some(sck.map_into()),
+            Some(sda.map_into()),
+            None,
+            None,
+            None,
+            config,
+        )
+        .into_half_duplex()
+    }
+
     /// Create a new SPI driver, in TX-only mode (only MOSI pin, no MISO).
     pub fn new_blocking_txonly<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
@@ -359,8 +682,8 @@ impl<'d, T: Instance> Spi<'d, T, Blocking> {
 
         T::set_remap(REMAP);
 
-        sck.set_as_af_output(AFType::OutputPushPull, Speed::High);
-        mosi.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
 
         Self::new_inner(
             peri,
@@ -385,10 +708,78 @@ impl<'d, T: Instance> Spi<'d, T, Blocking> {
 
         T::set_remap(REMAP);
 
-        mosi.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
 
         Self::new_inner(peri, None, Some(mosi.map_into()), None, None, None, config)
     }
+
+    /// Create a new SPI slave driver, always selected (software NSS).
+    ///
+    /// SCK and MOSI are inputs driven by the external master, MISO is driven by us. Transfers block
+    /// until the master clocks the data. `config.frequency` is ignored.
+    ///
+    /// Underrun isn't detected in SPI mode: if the master clocks a frame before the next word was
+    /// loaded, the last word is sent again.
+    pub fn new_blocking_slave<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T, REMAP>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, mosi, miso);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_input(Pull::None);
+        mosi.set_as_input(Pull::None);
+        miso.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+
+        Self::new_inner_with_role(
+            peri,
+            Some(sck.map_into()),
+            Some(mosi.map_into()),
+            Some(miso.map_into()),
+            None,
+            None,
+            None,
+            config,
+            Role::Slave,
+        )
+    }
+
+    /// Create a new SPI slave driver, selected by the hardware NSS pin.
+    ///
+    /// See [`Spi::new_blocking_slave`].
+    pub fn new_blocking_slave_with_nss<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T, REMAP>> + 'd,
+        nss: impl Peripheral<P = impl CsPin<T, REMAP>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, mosi, miso, nss);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_input(Pull::None);
+        mosi.set_as_input(Pull::None);
+        miso.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        nss.set_as_input(Pull::Up);
+
+        Self::new_inner_with_role(
+            peri,
+            Some(sck.map_into()),
+            Some(mosi.map_into()),
+            Some(miso.map_into()),
+            Some(nss.map_into()),
+            None,
+            None,
+            config,
+            Role::Slave,
+        )
+    }
 }
 
 impl<'d, T: Instance> Spi<'d, T, Async> {
@@ -406,8 +797,8 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
 
         T::set_remap(REMAP);
 
-        sck.set_as_af_output(AFType::OutputPushPull, Speed::High);
-        mosi.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
         miso.set_as_input(Pull::None);
 
         Self::new_inner(
@@ -421,7 +812,45 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
         )
     }
 
+    /// Create a new SPI driver, driving the hardware NSS pin.
+    ///
+    /// The DMA transfers disable the SPI when done, so NSS is low for each transfer and goes
+    /// high in between.
+    pub fn new_with_nss<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T, REMAP>> + 'd,
+        nss: impl Peripheral<P = impl CsPin<T, REMAP>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, mosi, miso, nss);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        miso.set_as_input(Pull::None);
+        nss.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+
+        Self::new_inner_with_role(
+            peri,
+            Some(sck.map_into()),
+            Some(mosi.map_into()),
+            Some(miso.map_into()),
+            Some(nss.map_into()),
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+            Role::Master,
+        )
+    }
+
     /// Create a new SPI driver, in RX-only mode (only MISO pin, no MOSI).
+    ///
+    /// The clock runs only during reads, see [`read`](Self::read).
     pub fn new_rxonly<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
         sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
@@ -433,7 +862,7 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
 
         T::set_remap(REMAP);
 
-        sck.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
         miso.set_as_input(Pull::None);
 
         Self::new_inner(
@@ -447,6 +876,36 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
         )
     }
 
+    /// Create a new SPI driver, in half-duplex mode, on a single bidirectional data line.
+    ///
+    /// See [`new_blocking_half_duplex`](Spi::new_blocking_half_duplex) and [`read`](Self::read).
+    pub fn new_half_duplex<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        sda: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, sda);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        sda.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+
+        Self::new_inner(
+            peri,
+            Some(sck.map_into()),
+            Some(sda.map_into()),
+            None,
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+        )
+        .into_half_duplex()
+    }
+
     /// Create a new SPI driver, in TX-only mode (only MOSI pin, no MISO).
     pub fn new_txonly<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
@@ -459,8 +918,8 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
 
         T::set_remap(REMAP);
 
-        sck.set_as_af_output(AFType::OutputPushPull, Speed::High);
-        mosi.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        sck.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
 
         Self::new_inner(
             peri,
@@ -486,21 +945,106 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
 
         T::set_remap(REMAP);
 
-        mosi.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        mosi.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
 
         Self::new_inner(peri, None, Some(mosi.map_into()), None, new_dma!(tx_dma), None, config)
     }
 
+    /// Create a new SPI slave driver, always selected (software NSS).
+    ///
+    /// See [`Spi::new_blocking_slave`]. DMA transfers complete once the master has clocked all words.
+    pub fn new_slave<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T, REMAP>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, mosi, miso);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_input(Pull::None);
+        mosi.set_as_input(Pull::None);
+        miso.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+
+        Self::new_inner_with_role(
+            peri,
+            Some(sck.map_into()),
+            Some(mosi.map_into()),
+            Some(miso.map_into()),
+            None,
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+            Role::Slave,
+        )
+    }
+
+    /// Create a new SPI slave driver, selected by the hardware NSS pin.
+    ///
+    /// See [`Spi::new_blocking_slave`].
+    pub fn new_slave_with_nss<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        sck: impl Peripheral<P = impl SckPin<T, REMAP>> + 'd,
+        mosi: impl Peripheral<P = impl MosiPin<T, REMAP>> + 'd,
+        miso: impl Peripheral<P = impl MisoPin<T, REMAP>> + 'd,
+        nss: impl Peripheral<P = impl CsPin<T, REMAP>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        into_ref!(sck, mosi, miso, nss);
+
+        T::set_remap(REMAP);
+
+        sck.set_as_input(Pull::None);
+        mosi.set_as_input(Pull::None);
+        miso.set_as_af_output(AFType::OutputPushPull, config.gpio_speed);
+        nss.set_as_input(Pull::Up);
+
+        Self::new_inner_with_role(
+            peri,
+            Some(sck.map_into()),
+            Some(mosi.map_into()),
+            Some(miso.map_into()),
+            Some(nss.map_into()),
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+            Role::Slave,
+        )
+    }
+
     /// SPI write, using DMA.
+    ///
+    /// If the future is dropped, the DMA is stopped after the word being sent.
     pub async fn write<W: Word>(&mut self, data: &[W]) -> Result<(), Error> {
+        for chunk in data.chunks(MAX_DMA_LEN) {
+            self.write_dma(chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_dma<W: Word>(&mut self, data: &[W]) -> Result<(), Error> {
         if data.is_empty() {
             return Ok(());
         }
 
-        self.set_word_size(W::CONFIG);
+        self.set_word_size::<W>();
+        self.set_half_duplex_output(true);
         T::REGS.ctlr1().modify(|w| {
             w.set_spe(false);
         });
+        let crc = T::REGS.ctlr1().read().crcen();
+        if crc {
+            reset_crc(T::REGS);
+        }
+
+        // Also runs when the future is dropped, after the DMA channel was stopped
+        let on_drop = OnDrop::new(|| finish_dma(T::REGS));
 
         let tx_dst = T::REGS.datar().as_ptr();
         let tx_f = unsafe {
@@ -517,24 +1061,54 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
 
         tx_f.await;
 
-        finish_dma(T::REGS);
+        drop(on_drop);
+
+        if crc {
+            // The CRC was sent after the data, and the received one checked, like in
+            // `blocking_write`
+            check_crc(T::REGS)?;
+        }
 
         Ok(())
     }
 
     /// SPI read, using DMA.
+    ///
+    /// [`Config::dummy_byte`] is sent meanwhile. If the future is dropped, the DMA is stopped and the
+    /// received data register is drained.
+    ///
+    /// In RX-only and half-duplex mode nothing is sent, and the clock runs while the SPI is enabled.
+    /// It is stopped from the DMA interrupt during the last frame, which needs the interrupt to be
+    /// served within a frame time.
     pub async fn read<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error> {
+        for chunk in data.chunks_mut(MAX_DMA_LEN) {
+            self.read_dma(chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_dma<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error> {
         if data.is_empty() {
             return Ok(());
         }
+        if is_receive_only(T::REGS) {
+            return self.read_dma_receive_only(data).await;
+        }
 
-        self.set_word_size(W::CONFIG);
+        self.set_word_size::<W>();
         T::REGS.ctlr1().modify(|w| {
             w.set_spe(false);
         });
+        let crc = T::REGS.ctlr1().read().crcen();
+        if crc {
+            reset_crc(T::REGS);
+        }
 
         flush_rx_fifo(T::REGS);
 
+        let on_drop = OnDrop::new(|| finish_dma(T::REGS));
+
+        // RX is set up before TX starts clocking, so no received word is missed
         T::REGS.ctlr2().modify(|w| w.set_rxdmaen(true)); // set rxdma en
 
         let clock_byte_count = data.len();
@@ -542,13 +1116,14 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
         let rx_src = T::REGS.datar().as_ptr() as *mut _;
         let rx_f = unsafe { self.rx_dma.as_mut().unwrap().read(rx_src, data, Default::default()) };
 
+        // Keep the shift register fed with dummy words, the received data is what matters.
         let tx_dst = T::REGS.datar().as_ptr() as *mut _;
-        let clock_byte = 0x00u8;
+        let clock_word = W::dummy(self.dummy_byte);
         let tx_f = unsafe {
             self.tx_dma
                 .as_mut()
                 .unwrap()
-                .write_repeated(&clock_byte, clock_byte_count, tx_dst, Default::default())
+                .write_repeated(&clock_word, clock_byte_count, tx_dst, Default::default())
         };
 
         T::REGS.ctlr2().modify(|w| w.set_txdmaen(true));
@@ -559,27 +1134,90 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
 
         join(tx_f, rx_f).await;
 
-        finish_dma(T::REGS);
+        drop(on_drop);
+
+        // `finish_dma` drained the received CRC word from DATAR
+        if crc {
+            check_crc(T::REGS)?;
+        }
 
         Ok(())
     }
 
+    // Without TX to time the transfer, the clock runs as long as the SPI is enabled. All but the
+    // last word are received by DMA, and the DMA interrupt disables the SPI while the last frame
+    // is received, so it is the last one (the reference manual sequence).
+    async fn read_dma_receive_only<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error> {
+        if data.len() == 1 {
+            return self.blocking_read_receive_only(data);
+        }
+
+        self.set_word_size::<W>();
+        self.set_half_duplex_output(false);
+        T::REGS.ctlr1().modify(|w| {
+            w.set_spe(false);
+        });
+        let crc = T::REGS.ctlr1().read().crcen();
+        if crc {
+            reset_crc(T::REGS);
+        }
+
+        flush_rx_fifo(T::REGS);
+
+        let on_drop = OnDrop::new(|| finish_receive_only_dma(T::REGS));
+
+        T::REGS.ctlr2().modify(|w| w.set_rxdmaen(true));
+
+        let (last, head) = data.split_last_mut().unwrap();
+        let last = last as *mut W as usize;
+        let rx_src = T::REGS.datar().as_ptr() as *mut _;
+        let mut rx_f = unsafe { self.rx_dma.as_mut().unwrap().read(rx_src, head, Default::default()) };
+        rx_f.set_complete_hook(Some((receive_only_last_word::<T, W>, last)));
+
+        T::REGS.ctlr1().modify(|w| {
+            w.set_spe(true);
+        });
+
+        rx_f.await;
+
+        // The last data word, or with CRC the CRC word that the interrupt let through
+        spin_until_rx_ready(&T::REGS)?;
+        let word: W = unsafe { ptr::read_volatile(T::REGS.datar().as_ptr() as _) };
+        if !crc {
+            unsafe { (last as *mut W).write(word) };
+        }
+
+        drop(on_drop);
+
+        if crc {
+            check_crc(T::REGS)?;
+        }
+        Ok(())
+    }
+
     async fn transfer_inner<W: Word>(&mut self, read: *mut [W], write: *const [W]) -> Result<(), Error> {
         let (_, rx_len) = slice_ptr_parts(read);
         let (_, tx_len) = slice_ptr_parts(write);
         assert_eq!(rx_len, tx_len);
+        assert_full_duplex(T::REGS);
         if rx_len == 0 {
             return Ok(());
         }
 
-        self.set_word_size(W::CONFIG);
+        self.set_word_size::<W>();
         T::REGS.ctlr1().modify(|w| {
             w.set_spe(false);
         });
+        let crc = T::REGS.ctlr1().read().crcen();
+        if crc {
+            reset_crc(T::REGS);
+        }
 
         // SPIv3 clears rxfifo on SPE=0
         flush_rx_fifo(T::REGS);
 
+        let on_drop = OnDrop::new(|| finish_dma(T::REGS));
+
         T::REGS.ctlr2().modify(|w| w.set_rxdmaen(true));
 
         let rx_src = T::REGS.datar().as_ptr() as *mut _;
@@ -600,7 +1238,11 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
 
         join(tx_f, rx_f).await;
 
-        finish_dma(T::REGS);
+        drop(on_drop);
+
+        if crc {
+            check_crc(T::REGS)?;
+        }
 
         Ok(())
     }
@@ -610,16 +1252,33 @@ impl<'d, T: Instance> Spi<'d, T, Async> {
     /// This transfers both buffers at the same time, so it is NOT equivalent to `write` followed by `read`.
     ///
     /// The transfer runs for `max(read.len(), write.len())` bytes. If `read` is shorter extra bytes are ignored.
-    /// If `write` is shorter it is padded with zero bytes.
+    /// If `write` is shorter it is padded with [`Config::dummy_byte`].
     pub async fn transfer<W: Word>(&mut self, read: &mut [W], write: &[W]) -> Result<(), Error> {
-        self.transfer_inner(read, write).await
+        let common = read.len().min(write.len());
+        let (read, read_rest) = read.split_at_mut(common);
+        let (write, write_rest) = write.split_at(common);
+
+        for (read, write) in read.chunks_mut(MAX_DMA_LEN).zip(write.chunks(MAX_DMA_LEN)) {
+            self.transfer_inner(read, write).await?;
+        }
+
+        if !read_rest.is_empty() {
+            // padded with dummy words by `read`
+            self.read(read_rest).await
+        } else {
+            // received words are discarded by `write`
+            self.write(write_rest).await
+        }
     }
 
     /// In-place bidirectional transfer, using DMA.
     ///
     /// This writes the contents of `data` on MOSI, and puts the received data on MISO in `data`, at the same time.
     pub async fn transfer_in_place<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error> {
-        self.transfer_inner(data, data).await
+        for chunk in data.chunks_mut(MAX_DMA_LEN) {
+            self.transfer_inner(chunk, chunk).await?;
+        }
+        Ok(())
     }
 }
 
@@ -630,6 +1289,7 @@ impl<'d, T: Instance, M: PeriMode> Drop for Spi<'d, T, M> {
         self.sck.as_ref().map(|x| x.set_as_disconnected());
         self.mosi.as_ref().map(|x| x.set_as_disconnected());
         self.miso.as_ref().map(|x| x.set_as_disconnected());
+        self.nss.as_ref().map(|x| x.set_as_disconnected());
 
         T::disable();
     }
@@ -638,24 +1298,26 @@ impl<'d, T: Instance, M: PeriMode> Drop for Spi<'d, T, M> {
 // Get CTRL1.BR
 #[inline]
 fn calculate_baud_rate(hclk: u32, clk: u32) -> BaudRate {
-    // only div2, div4 to div256 are valid
-    let div = hclk / clk;
-
-    match div {
-        1..=2 => BaudRate::DIV_2,
-        3..=4 => BaudRate::DIV_4,
-        5..=8 => BaudRate::DIV_8,
-        9..=16 => BaudRate::DIV_16,
-        17..=32 => BaudRate::DIV_32,
-        33..=64 => BaudRate::DIV_64,
-        65..=128 => BaudRate::DIV_128,
-        129..=256 => BaudRate::DIV_256,
-        // fallback to the lowest baudrate
-        0 => BaudRate::DIV_2,
+    match baud_rate_divider(hclk, clk) {
+        2 => BaudRate::DIV_2,
+        4 => BaudRate::DIV_4,
+        8 => BaudRate::DIV_8,
+        16 => BaudRate::DIV_16,
+        32 => BaudRate::DIV_32,
+        64 => BaudRate::DIV_64,
+        128 => BaudRate::DIV_128,
         _ => BaudRate::DIV_256,
     }
 }
 
+/// Smallest prescaler, a power of two from 2 to 256, with `hclk / div <= clk`.
+///
+/// Clocks too low for the largest prescaler get 256.
+fn baud_rate_divider(hclk: u32, clk: u32) -> u32 {
+    let div = hclk.div_ceil(clk.max(1));
+    div.clamp(2, 256).next_power_of_two()
+}
+
 fn check_error_flags(sr: &pac::spi::regs::Statr) -> Result<(), Error> {
     if sr.ovr() {
         return Err(Error::Overrun);
@@ -666,7 +1328,6 @@ fn check_error_flags(sr: &pac::spi::regs::Statr) -> Result<(), Error> {
     if sr.crcerr() {
         return Err(Error::Crc);
     }
-
     Ok(())
 }
 
@@ -694,6 +1355,38 @@ fn spin_until_rx_ready(regs: &pac::spi::Spi) -> Result<(), Error> {
     }
 }
 
+fn write_crc_polynomial(regs: Regs, poly: u16) {
+    regs.crcr().write(|w| w.set_crcpoly(poly));
+}
+
+fn read_crc_polynomial(regs: Regs) -> u16 {
+    regs.crcr().read().crcpoly()
+}
+
+/// Restart the CRC calculation, which needs the SPI disabled.
+fn reset_crc(regs: Regs) {
+    while regs.statr().read().bsy() {}
+    regs.ctlr1().modify(|w| w.set_spe(false));
+    regs.ctlr1().modify(|w| w.set_crcen(false));
+    regs.ctlr1().modify(|w| w.set_crcen(true));
+}
+
+/// Check the received CRC, once it has been received after the data.
+fn check_crc(regs: Regs) -> Result<(), Error> {
+    if regs.statr().read().crcerr() {
+        // rc_w0, writing 1 leaves the other flags unchanged
+        regs.statr().write(|w| {
+            w.0 = !0;
+            w.set_crcerr(false);
+        });
+        return Err(Error::Crc);
+    }
+    Ok(())
+}
+
+/// Longest DMA transfer, longer buffers are sent in several transfers.
+const MAX_DMA_LEN: usize = 0xFFFF;
+
 fn flush_rx_fifo(regs: pac::spi::Spi) {
     while regs.statr().read().rxne() {
         let _ = regs.datar().read();
@@ -701,8 +1394,16 @@ fn flush_rx_fifo(regs: pac::spi::Spi) {
 }
 
 fn finish_dma(regs: Regs) {
+    // DMA completion only means the last word was written to DATAR, wait until it has
+    // actually been shifted out.
+    while !regs.statr().read().txe() {}
     while regs.statr().read().bsy() {}
 
+    // TX-only transfers don't read DATAR, drain it and clear the resulting OVR flag
+    // (reading DATAR followed by STATR).
+    flush_rx_fifo(regs);
+    let _ = regs.statr().read();
+
     // Disable the spi peripheral
     regs.ctlr1().modify(|w| {
         w.set_spe(false);
@@ -716,12 +1417,64 @@ fn finish_dma(regs: Regs) {
     });
 }
 
-fn transfer_word<W: Word>(regs: &pac::spi::Spi, tx_word: W) -> Result<W, Error> {
+/// Runs from the RX DMA interrupt of a receive-only read, once all but the last word were
+/// received and the last frame is being clocked in. `last` is the address of the last word.
+fn receive_only_last_word<T: Instance, W: Word>(last: usize) {
+    let regs = T::REGS;
+    if !regs.ctlr1().read().crcen() {
+        // The frame in progress completes, and is the last one
+        regs.ctlr1().modify(|w| w.set_spe(false));
+        return;
+    }
+
+    // The CRC frame follows the last data word, stop the clock while it is received. This waits
+    // for at most a frame.
+    regs.ctlr1().modify(|w| w.set_crcnext(true));
+    while !regs.statr().read().rxne() {}
+    regs.ctlr1().modify(|w| w.set_spe(false));
+    let word: W = unsafe { ptr::read_volatile(regs.datar().as_ptr() as _) };
+    unsafe { (last as *mut W).write(word) };
+}
+
+fn finish_receive_only_dma(regs: Regs) {
+    // Stop the clock first, the frame in progress completes
+    regs.ctlr1().modify(|w| {
+        w.set_spe(false);
+    });
+    while regs.statr().read().bsy() {}
+
+    // Drain the words received after the DMA finished, and the resulting OVR flag
+    flush_rx_fifo(regs);
+    let _ = regs.statr().read();
+
+    regs.ctlr2().modify(|reg| {
+        reg.set_rxdmaen(false);
+    });
+}
+
+/// Master that only receives (RXONLY, or half-duplex): the clock runs while the SPI is enabled.
+fn is_receive_only(regs: Regs) -> bool {
+    let cr1 = regs.ctlr1().read();
+    cr1.mstr() && (cr1.rxonly() || cr1.bidimode())
+}
+
+fn assert_full_duplex(regs: Regs) {
+    assert!(
+        !regs.ctlr1().read().bidimode(),
+        "SPI: half-duplex can't transfer in both directions"
+    );
+}
+
+/// Send and receive one word. With `crc_next`, the CRC is sent after it.
+fn transfer_word<W: Word>(regs: &pac::spi::Spi, tx_word: W, crc_next: bool) -> Result<W, Error> {
     spin_until_tx_ready(regs)?;
 
     unsafe {
         ptr::write_volatile(regs.datar().as_ptr() as _, tx_word);
     }
+    if crc_next {
+        regs.ctlr1().modify(|w| w.set_crcnext(true));
+    }
 
     spin_until_rx_ready(regs)?;
 
@@ -735,6 +1488,8 @@ impl<'d, T: Instance, M: PeriMode> embedded_hal::spi::ErrorType for Spi<'d, T, M
 
 impl<'d, T: Instance, W: Word, M: PeriMode> embedded_hal::spi::SpiBus<W> for Spi<'d, T, M> {
     fn flush(&mut self) -> Result<(), Self::Error> {
+        while !T::REGS.statr().read().txe() {}
+        while T::REGS.statr().read().bsy() {}
         Ok(())
     }
 
@@ -755,6 +1510,88 @@ impl<'d, T: Instance, W: Word, M: PeriMode> embedded_hal::spi::SpiBus<W> for Spi
     }
 }
 
+impl<'d, T: Instance, W: Word> embedded_hal_async::spi::SpiBus<W> for Spi<'d, T, Async> {
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        // The DMA transfers wait for the bus to go idle before returning
+        Ok(())
+    }
+
+    async fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.read(words).await
+    }
+
+    async fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
+        self.write(words).await
+    }
+
+    async fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.transfer(read, write).await
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.transfer_in_place(words).await
+    }
+}
+
+/// A device on a shared SPI bus, selected by its own CS pin.
+///
+/// Each transaction locks the bus, applies the device's [`Config`] and drives CS low around
+/// the operations. CS goes high again when the transaction ends, also if its future is dropped
+/// halfway. For a single device, the hardware NSS output of [`Spi::new_with_nss`] does the same
+/// without a mutex.
+pub struct SpiDeviceWithCs<'a, 'd, M: RawMutex, T: Instance> {
+    bus: &'a Mutex<M, Spi<'d, T, Async>>,
+    cs: Output<'d>,
+    config: Config,
+}
+
+impl<'a, 'd, M: RawMutex, T: Instance> SpiDeviceWithCs<'a, 'd, M, T> {
+    /// Create a device on `bus`, CS is driven high until the first transaction.
+    pub fn new(bus: &'a Mutex<M, Spi<'d, T, Async>>, mut cs: Output<'d>, config: Config) -> Self {
+        cs.set_high();
+        Self { bus, cs, config }
+    }
+
+    /// Change the configuration used for the next transactions.
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+}
+
+impl<'a, 'd, M: RawMutex, T: Instance> embedded_hal::spi::ErrorType for SpiDeviceWithCs<'a, 'd, M, T> {
+    type Error = Error;
+}
+
+impl<'a, 'd, M: RawMutex, T: Instance, W: Word> embedded_hal_async::spi::SpiDevice<W>
+    for SpiDeviceWithCs<'a, 'd, M, T>
+{
+    async fn transaction(&mut self, operations: &mut [Operation<'_, W>]) -> Result<(), Self::Error> {
+        let mut bus = self.bus.lock().await;
+        // Never fails
+        let _ = bus.set_config(&self.config);
+
+        self.cs.set_low();
+        // Dropped before the bus lock, and after a cancelled transfer has stopped its DMA
+        let _deselect = OnDrop::new(|| self.cs.set_high());
+
+        for op in operations {
+            match op {
+                Operation::Read(buf) => bus.read(buf).await?,
+                Operation::Write(buf) => bus.write(buf).await?,
+                Operation::Transfer(read, write) => bus.transfer(read, write).await?,
+                Operation::TransferInPlace(buf) => bus.transfer_in_place(buf).await?,
+                // Yields to the executor while the bus stays locked and CS low
+                #[cfg(feature = "embassy")]
+                Operation::DelayNs(ns) => embassy_time::Timer::after_nanos(*ns as u64).await,
+                #[cfg(not(feature = "embassy"))]
+                Operation::DelayNs(ns) => embedded_hal::delay::DelayNs::delay_ns(&mut crate::delay::Delay, *ns),
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl embedded_hal::spi::Error for Error {
     fn kind(&self) -> embedded_hal::spi::ErrorKind {
         match *self {
@@ -766,8 +1603,11 @@ impl embedded_hal::spi::Error for Error {
     }
 }
 
-trait SealedWord {
+trait SealedWord: Sized {
     const CONFIG: word_impl::Config;
+
+    /// The word with `byte` in each byte.
+    fn dummy(byte: u8) -> Self;
 }
 
 /// Word sizes usable for SPI.
@@ -778,6 +1618,10 @@ macro_rules! impl_word {
     ($T:ty, $config:expr) => {
         impl SealedWord for $T {
             const CONFIG: Config = $config;
+
+            fn dummy(byte: u8) -> Self {
+                <$T>::from_ne_bytes([byte; core::mem::size_of::<$T>()])
+            }
         }
         impl Word for $T {}
     };
@@ -792,7 +1636,7 @@ mod word_impl {
     impl_word!(u16, 1);
 }
 
-trait SealedInstance {
+pub(crate) trait SealedInstance {
     const REGS: Regs;
 }
 
@@ -824,3 +1668,36 @@ pin_trait!(CkPin, Instance);
 
 dma_trait!(RxDma, Instance);
 dma_trait!(TxDma, Instance);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divider_never_exceeds_requested() {
+        // 72 MHz PCLK2: 25 MHz needs DIV_4, not DIV_2 (36 MHz)
+        assert_eq!(baud_rate_divider(72_000_000, 25_000_000), 4);
+        assert_eq!(baud_rate_divider(72_000_000, 36_000_000), 2);
+        assert_eq!(baud_rate_divider(72_000_000, 400_000), 256);
+        assert_eq!(baud_rate_divider(144_000_000, 400_000), 256);
+        assert_eq!(baud_rate_divider(48_000_000, 400_000), 128);
+        assert_eq!(baud_rate_divider(48_000_000, 1_000_000), 64);
+    }
+
+    #[test]
+    fn divider_exact_and_limits() {
+        assert_eq!(baud_rate_divider(64_000_000, 8_000_000), 8);
+        assert_eq!(baud_rate_divider(64_000_000, 7_999_999), 16);
+        // Faster than PCLK / 2 and slower than PCLK / 256
+        assert_eq!(baud_rate_divider(8_000_000, 100_000_000), 2);
+        assert_eq!(baud_rate_divider(8_000_000, 1_000), 256);
+        assert_eq!(baud_rate_divider(8_000_000, 0), 256);
+    }
+
+    #[test]
+    fn dummy_words() {
+        assert_eq!(u8::dummy(0xA5), 0xA5);
+        assert_eq!(u16::dummy(0xFF), 0xFFFF);
+        assert_eq!(u16::dummy(0xA5), 0xA5A5);
+    }
+}
@@ -4,8 +4,11 @@ use core::marker::PhantomData;
 
 use super::low_level::{CountingMode, OutputPolarity, Timer};
 use super::simple_pwm::{Ch1, Ch2, Ch3, Ch4, PwmPin};
-use super::{AdvancedInstance, Channel, Channel1ComplementaryPin, Channel2ComplementaryPin, Channel3ComplementaryPin};
-use crate::gpio::{AFType, AnyPin};
+use super::{
+    AdvancedInstance, BreakInputPin, Channel, Channel1ComplementaryPin, Channel2ComplementaryPin,
+    Channel3ComplementaryPin,
+};
+use crate::gpio::{AFType, AnyPin, Pull, SealedPin, Speed};
 use crate::pac::timer::vals::Ckd;
 use crate::time::Hertz;
 use crate::timer::low_level::OutputCompareMode;
@@ -16,6 +19,7 @@ use crate::{into_ref, Peripheral, PeripheralRef};
 /// This wraps a pin to make it usable with PWM.
 pub struct ComplementaryPwmPin<'d, T, C> {
     _pin: PeripheralRef<'d, AnyPin>,
+    remap: u8,
     phantom: PhantomData<(T, C)>,
 }
 
@@ -29,10 +33,11 @@ macro_rules! complementary_channel_impl {
                 T::set_remap(REMAP);
                 critical_section::with(|_| {
                     pin.set_low();
-                    pin.set_as_af_output(AFType::OutputPushPull, Default::default());
+                    pin.set_as_af_output(AFType::OutputPushPull, Speed::Medium);
                 });
                 ComplementaryPwmPin {
                     _pin: pin.map_into(),
+                    remap: REMAP,
                     phantom: PhantomData,
                 }
             }
@@ -40,13 +45,86 @@ macro_rules! complementary_channel_impl {
     };
 }
 
+impl<'d, T, C> ComplementaryPwmPin<'d, T, C> {
+    /// Change the output speed of the pin, 10 MHz by default.
+    pub fn with_speed(self, speed: Speed) -> Self {
+        critical_section::with(|_| self._pin.set_speed(speed));
+        self
+    }
+}
+
 complementary_channel_impl!(new_ch1, Ch1, Channel1ComplementaryPin);
 complementary_channel_impl!(new_ch2, Ch2, Channel2ComplementaryPin);
 complementary_channel_impl!(new_ch3, Ch3, Channel3ComplementaryPin);
 
+/// Break input pin wrapper.
+pub struct BreakPin<'d, T> {
+    _pin: PeripheralRef<'d, AnyPin>,
+    remap: u8,
+    phantom: PhantomData<T>,
+}
+
+impl<'d, T: AdvancedInstance> BreakPin<'d, T> {
+    /// Create a new break input pin instance.
+    pub fn new<const REMAP: u8>(pin: impl Peripheral<P = impl BreakInputPin<T, REMAP>> + 'd, pull: Pull) -> Self {
+        into_ref!(pin);
+
+        T::set_remap(REMAP);
+        critical_section::with(|_| pin.set_as_input(pull));
+        BreakPin {
+            _pin: pin.map_into(),
+            remap: REMAP,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Break input polarity.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakPolarity {
+    /// Outputs are shut down while the break input is low.
+    ActiveLow,
+    /// Outputs are shut down while the break input is high.
+    ActiveHigh,
+}
+
+/// Break input configuration.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BreakConfig {
+    /// Break input polarity.
+    pub polarity: BreakPolarity,
+    /// Re-enable the outputs at the next update event once the break input is inactive,
+    /// instead of waiting for [`ComplementaryPwm::enable_outputs`].
+    pub automatic_output_enable: bool,
+}
+
+impl Default for BreakConfig {
+    fn default() -> Self {
+        Self {
+            polarity: BreakPolarity::ActiveLow,
+            automatic_output_enable: false,
+        }
+    }
+}
+
+/// Off-state selection, the state of enabled channels while their output is inactive.
+///
+/// With `true` the outputs are driven to their idle level (OISx/OISxN), with `false` they
+/// are released.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct OffStateSelection {
+    /// OSSR, while MOE is set and a channel is disabled by CCxE/CCxNE
+    pub run: bool,
+    /// OSSI, while MOE is cleared, i.e. after a break or [`ComplementaryPwm::disable_outputs`]
+    pub idle: bool,
+}
+
 /// PWM driver with support for standard and complementary outputs.
 pub struct ComplementaryPwm<'d, T: AdvancedInstance> {
     inner: Timer<'d, T>,
+    _brk: Option<BreakPin<'d, T>>,
+    /// Remap of the output pins
+    remap: Option<u8>,
 }
 
 impl<'d, T: AdvancedInstance> ComplementaryPwm<'d, T> {
@@ -66,11 +144,29 @@ impl<'d, T: AdvancedInstance> ComplementaryPwm<'d, T> {
         freq: Hertz,
         counting_mode: CountingMode,
     ) -> Self {
-        Self::new_inner(tim, freq, counting_mode)
+        let remap = super::same_remap([
+            _ch1.as_ref().map(|p| p.remap),
+            _ch1n.as_ref().map(|p| p.remap),
+            _ch2.as_ref().map(|p| p.remap),
+            _ch2n.as_ref().map(|p| p.remap),
+            _ch3.as_ref().map(|p| p.remap),
+            _ch3n.as_ref().map(|p| p.remap),
+            _ch4.as_ref().map(|p| p.remap),
+        ]);
+        Self::new_inner(tim, freq, counting_mode, remap)
     }
 
-    fn new_inner(tim: impl Peripheral<P = T> + 'd, freq: Hertz, counting_mode: CountingMode) -> Self {
-        let mut this = Self { inner: Timer::new(tim) };
+    fn new_inner(
+        tim: impl Peripheral<P = T> + 'd,
+        freq: Hertz,
+        counting_mode: CountingMode,
+        remap: Option<u8>,
+    ) -> Self {
+        let mut this = Self {
+            inner: Timer::new(tim),
+            _brk: None,
+            remap,
+        };
 
         this.inner.set_counting_mode(counting_mode);
         this.set_frequency(freq);
@@ -139,6 +235,54 @@ impl<'d, T: AdvancedInstance> ComplementaryPwm<'d, T> {
         self.inner.set_complementary_output_polarity(channel, polarity);
     }
 
+    /// Set the main output enable (MOE).
+    ///
+    /// Channel outputs are only driven while MOE is set, on top of [`enable`](Self::enable).
+    /// MOE is set by [`new`](Self::new), and cleared by hardware on a break event, so call this
+    /// to resume after a break unless automatic output enable is configured.
+    pub fn enable_outputs(&mut self) {
+        self.inner.set_moe(true);
+    }
+
+    /// Clear the main output enable, all outputs go to their off state.
+    pub fn disable_outputs(&mut self) {
+        self.inner.set_moe(false);
+    }
+
+    /// Whether the main output enable is set, `false` after a break event.
+    pub fn outputs_enabled(&self) -> bool {
+        self.inner.get_moe()
+    }
+
+    /// Enable the break input on `pin`.
+    ///
+    /// An active break input clears MOE asynchronously, independent of the timer clock.
+    pub fn enable_break(&mut self, pin: BreakPin<'d, T>, config: BreakConfig) {
+        super::same_remap([self.remap, Some(pin.remap)]);
+        self.inner
+            .set_break_polarity(config.polarity == BreakPolarity::ActiveHigh);
+        self.inner.set_automatic_output_enable(config.automatic_output_enable);
+        self.inner.clear_break_interrupt();
+        self.inner.set_break_enable(true);
+        self._brk = Some(pin);
+    }
+
+    /// Disable the break input, and release its pin.
+    pub fn disable_break(&mut self) {
+        self.inner.set_break_enable(false);
+        self._brk = None;
+    }
+
+    /// Check and clear the break flag, returns whether a break occurred since the last call.
+    pub fn clear_break_flag(&mut self) -> bool {
+        self.inner.clear_break_interrupt()
+    }
+
+    /// Set the off-state selection.
+    pub fn set_off_state_selection(&mut self, selection: OffStateSelection) {
+        self.inner.set_off_state_selection(selection.run, selection.idle);
+    }
+
     /// Set the dead time as a proportion of max_duty
     pub fn set_dead_time(&mut self, value: u16) {
         let (ckd, value) = compute_dead_time_value(value);
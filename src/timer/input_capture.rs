@@ -0,0 +1,313 @@
+//! Input capture driver.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use super::low_level::{CountingMode, InputCaptureMode, InputTISelection, Timer};
+use super::simple_pwm::{Ch1, Ch2, Ch3, Ch4};
+use super::{Channel, Channel1Pin, Channel2Pin, Channel3Pin, Channel4Pin, GeneralInstance16bit};
+use crate::gpio::{AnyPin, Pull};
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::timer::vals;
+use crate::time::Hertz;
+use crate::{interrupt, into_ref, Peripheral, PeripheralRef};
+
+/// Input capture error.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A capture was overwritten before it was read (CCxOF).
+    ///
+    /// The returned value is lost, the next capture is valid again.
+    Overcapture,
+}
+
+/// Capture pin wrapper.
+///
+/// This wraps a pin to make it usable with capture.
+pub struct CapturePin<'d, T, C> {
+    _pin: PeripheralRef<'d, AnyPin>,
+    remap: u8,
+    phantom: PhantomData<(T, C)>,
+}
+
+macro_rules! channel_impl {
+    ($new_chx:ident, $channel:ident, $pin_trait:ident) => {
+        impl<'d, T: GeneralInstance16bit> CapturePin<'d, T, $channel> {
+            #[doc = concat!("Create a new ", stringify!($channel), " capture pin instance.")]
+            pub fn $new_chx<const REMAP: u8>(
+                pin: impl Peripheral<P = impl $pin_trait<T, REMAP>> + 'd,
+                pull: Pull,
+            ) -> Self {
+                into_ref!(pin);
+                critical_section::with(|_| {
+                    pin.set_as_input(pull);
+                    T::set_remap(REMAP);
+                });
+                CapturePin {
+                    _pin: pin.map_into(),
+                    remap: REMAP,
+                    phantom: PhantomData,
+                }
+            }
+        }
+    };
+}
+
+channel_impl!(new_ch1, Ch1, Channel1Pin);
+channel_impl!(new_ch2, Ch2, Channel2Pin);
+channel_impl!(new_ch3, Ch3, Channel3Pin);
+channel_impl!(new_ch4, Ch4, Channel4Pin);
+
+/// Capture/compare interrupt handler.
+pub struct CaptureCompareInterruptHandler<T: GeneralInstance16bit> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: GeneralInstance16bit> interrupt::typelevel::Handler<T::CaptureCompareInterrupt>
+    for CaptureCompareInterruptHandler<T>
+{
+    unsafe fn on_interrupt() {
+        let regs = crate::pac::timer::Gptm::from_ptr(T::regs());
+
+        let intfr = regs.intfr().read();
+        let dmaintenr = regs.dmaintenr().read();
+
+        for ch in 0..4 {
+            if intfr.ccif(ch) && dmaintenr.ccie(ch) {
+                // the waiting future reads the flag and the value, just mask the interrupt
+                regs.dmaintenr().modify(|w| w.set_ccie(ch, false));
+                T::state().cc_waker[ch].wake();
+            }
+        }
+    }
+}
+
+/// Input capture driver.
+pub struct InputCapture<'d, T: GeneralInstance16bit> {
+    inner: Timer<'d, T>,
+}
+
+impl<'d, T: GeneralInstance16bit> InputCapture<'d, T> {
+    /// Create a new input capture driver.
+    ///
+    /// The counter ticks at `freq` and counts up to its maximum value, so captured values are in
+    /// units of `1 / freq`. Capturing is enabled per channel with
+    /// [`wait_for_capture`](Self::wait_for_capture) or [`enable`](Self::enable).
+    pub fn new(
+        tim: impl Peripheral<P = T> + 'd,
+        _ch1: Option<CapturePin<'d, T, Ch1>>,
+        _ch2: Option<CapturePin<'d, T, Ch2>>,
+        _ch3: Option<CapturePin<'d, T, Ch3>>,
+        _ch4: Option<CapturePin<'d, T, Ch4>>,
+        _irq: impl interrupt::typelevel::Binding<T::CaptureCompareInterrupt, CaptureCompareInterruptHandler<T>> + 'd,
+        freq: Hertz,
+        counting_mode: CountingMode,
+    ) -> Self {
+        super::same_remap([
+            _ch1.as_ref().map(|p| p.remap),
+            _ch2.as_ref().map(|p| p.remap),
+            _ch3.as_ref().map(|p| p.remap),
+            _ch4.as_ref().map(|p| p.remap),
+        ]);
+        let this = Self { inner: Timer::new(tim) };
+
+        this.inner.set_counting_mode(counting_mode);
+        this.inner.set_tick_frequency(freq);
+        this.inner.start();
+
+        T::CaptureCompareInterrupt::unpend();
+        unsafe { T::CaptureCompareInterrupt::enable() };
+
+        this
+    }
+
+    /// Enable capturing on the given channel.
+    pub fn enable(&mut self, channel: Channel) {
+        self.inner.enable_channel(channel, true);
+    }
+
+    /// Disable capturing on the given channel.
+    pub fn disable(&mut self, channel: Channel) {
+        self.inner.enable_channel(channel, false);
+    }
+
+    /// Check whether given channel is enabled
+    pub fn is_enabled(&self, channel: Channel) -> bool {
+        self.inner.get_channel_enable_state(channel)
+    }
+
+    /// Set the input capture prescaler, capture every 1, 2, 4 or 8 edges (0 to 3).
+    pub fn set_input_capture_prescaler(&mut self, channel: Channel, factor: u8) {
+        assert!(factor < 4);
+        self.inner.set_input_capture_prescaler(channel, factor);
+    }
+
+    /// Get the last captured value of the given channel.
+    pub fn get_capture_value(&self, channel: Channel) -> u32 {
+        self.inner.get_capture_value(channel)
+    }
+
+    /// Wait for the next capture on `channel`, and return the captured counter value.
+    ///
+    /// The channel is configured for `mode` on its own input (TIx) and enabled. If the counter
+    /// value from a previous edge wasn't read before another edge was captured, [`Error::Overcapture`]
+    /// is returned.
+    pub async fn wait_for_capture(&mut self, channel: Channel, mode: InputCaptureMode) -> Result<u32, Error> {
+        self.wait_for_capture_on(channel, mode, InputTISelection::Normal).await
+    }
+
+    /// Like [`wait_for_capture`](Self::wait_for_capture), capturing on the input of the neighboring
+    /// channel (TI2 for channel 1, TI1 for channel 2, TI4 for channel 3, TI3 for channel 4).
+    pub async fn wait_for_capture_alternate(&mut self, channel: Channel, mode: InputCaptureMode) -> Result<u32, Error> {
+        self.wait_for_capture_on(channel, mode, InputTISelection::Alternate)
+            .await
+    }
+
+    async fn wait_for_capture_on(
+        &mut self,
+        channel: Channel,
+        mode: InputCaptureMode,
+        tisel: InputTISelection,
+    ) -> Result<u32, Error> {
+        // CCxS can only be written while the channel is disabled, it is enabled again below
+        self.inner.enable_channel(channel, false);
+        self.inner.set_input_ti_selection(channel, tisel);
+        self.inner.set_input_capture_mode(channel, mode);
+
+        let regs = self.inner.regs_gp16();
+        let ch = channel.index();
+
+        // Start from a clean state, so that only edges from now on are reported. INTFR flags are
+        // rc_w0, writing 1 leaves the others unchanged where a read-modify-write could clear one.
+        regs.intfr().write(|w| {
+            w.0 = !0;
+            w.set_ccif(ch, false);
+            w.set_ccof(ch, false);
+        });
+        self.inner.enable_input_interrupt(channel, true);
+        self.enable(channel);
+
+        poll_fn(|cx| {
+            T::state().cc_waker[ch].register(cx.waker());
+
+            if regs.dmaintenr().read().ccie(ch) {
+                return Poll::Pending;
+            }
+
+            // Reading the value clears CCxIF
+            let value = self.inner.get_capture_value(channel);
+            if regs.intfr().read().ccof(ch) {
+                regs.intfr().write(|w| {
+                    w.0 = !0;
+                    w.set_ccof(ch, false);
+                });
+                return Poll::Ready(Err(Error::Overcapture));
+            }
+            Poll::Ready(Ok(value))
+        })
+        .await
+    }
+}
+
+impl<'d, T: GeneralInstance16bit> Drop for InputCapture<'d, T> {
+    fn drop(&mut self) {
+        for channel in [Channel::Ch1, Channel::Ch2, Channel::Ch3, Channel::Ch4] {
+            self.inner.enable_input_interrupt(channel, false);
+        }
+    }
+}
+
+/// PWM input driver, measures period and pulse width of a signal on channel 1.
+///
+/// Channel 1 captures the rising edges of TI1 and resets the counter on each of them (slave
+/// reset mode). Channel 2 captures the falling edges of TI1. So after each period, channel 1
+/// holds the period and channel 2 the pulse width, in counter ticks.
+///
+/// The period must be shorter than the counter range at the tick frequency, longer periods
+/// wrap around and give wrong results.
+pub struct PwmInput<'d, T: GeneralInstance16bit> {
+    inner: Timer<'d, T>,
+}
+
+impl<'d, T: GeneralInstance16bit> PwmInput<'d, T> {
+    /// Create a new PWM input driver on channel 1, with the counter ticking at `freq`.
+    pub fn new(tim: impl Peripheral<P = T> + 'd, _pin: CapturePin<'d, T, Ch1>, freq: Hertz) -> Self {
+        let this = Self { inner: Timer::new(tim) };
+
+        this.inner.set_counting_mode(CountingMode::EdgeAlignedUp);
+        this.inner.set_tick_frequency(freq);
+
+        this.inner
+            .set_input_ti_selection(Channel::Ch1, InputTISelection::Normal);
+        this.inner
+            .set_input_capture_mode(Channel::Ch1, InputCaptureMode::Rising);
+        this.inner
+            .set_input_ti_selection(Channel::Ch2, InputTISelection::Alternate);
+        this.inner
+            .set_input_capture_mode(Channel::Ch2, InputCaptureMode::Falling);
+
+        // Reset the counter on each rising edge of TI1
+        this.inner.regs_gp16().smcfgr().modify(|w| {
+            w.set_ts(vals::Ts::TI1FP1);
+            w.set_sms(vals::Sms::RESETMODE);
+        });
+
+        this.inner.start();
+
+        this
+    }
+
+    /// Start measuring.
+    pub fn enable(&mut self) {
+        self.inner.enable_channel(Channel::Ch1, true);
+        self.inner.enable_channel(Channel::Ch2, true);
+    }
+
+    /// Stop measuring.
+    pub fn disable(&mut self) {
+        self.inner.enable_channel(Channel::Ch1, false);
+        self.inner.enable_channel(Channel::Ch2, false);
+    }
+
+    /// Check whether measuring is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.inner.get_channel_enable_state(Channel::Ch1)
+    }
+
+    /// Period of the last complete cycle, in counter ticks.
+    pub fn get_period_ticks(&self) -> u32 {
+        self.inner.get_capture_value(Channel::Ch1)
+    }
+
+    /// Width of the last high pulse, in counter ticks.
+    pub fn get_width_ticks(&self) -> u32 {
+        self.inner.get_capture_value(Channel::Ch2)
+    }
+
+    /// Duty cycle of the last complete cycle, from 0.0 to 1.0.
+    pub fn get_duty_cycle(&self) -> f32 {
+        let period = self.get_period_ticks();
+        if period == 0 {
+            return 0.0;
+        }
+        self.get_width_ticks() as f32 / period as f32
+    }
+
+    /// Whether capture values were overwritten without being read since the last call.
+    ///
+    /// Unlike event driven capturing this is harmless for PWM input, the registers always hold
+    /// the last complete measurement.
+    pub fn clear_overcapture(&mut self) -> bool {
+        let regs = self.inner.regs_gp16();
+        let intfr = regs.intfr().read();
+        let over = intfr.ccof(0) || intfr.ccof(1);
+        regs.intfr().write(|w| {
+            w.0 = !0;
+            w.set_ccof(0, false);
+            w.set_ccof(1, false);
+        });
+        over
+    }
+}
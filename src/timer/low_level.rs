@@ -265,6 +265,38 @@ impl<'d, T: BasicInstance> Timer<'d, T> {
         }
     }
 
+    /// Set the counter to tick at `freq`, counting up to the maximum value.
+    ///
+    /// `freq` is rounded up to the next frequency the prescaler can divide the timer clock to. Below
+    /// the timer clock divided by 65536, the counter ticks at that lowest frequency instead.
+    pub fn set_tick_frequency(&self, freq: Hertz) {
+        let f = freq.0;
+        assert!(f > 0);
+        let timer_f = T::frequency().0;
+
+        let psc = (timer_f / f).saturating_sub(1).min(u16::MAX as u32) as u16;
+
+        match T::BITS {
+            TimerBits::Bits16 => {
+                let regs = self.regs_basic();
+                regs.psc().write_value(psc);
+                regs.atrlr().write_value(u16::MAX);
+            }
+            #[cfg(any(ch32l1, ch32v208))]
+            TimerBits::Bits32 => {
+                let regs = self.regs_gp32_unchecked();
+                regs.psc().write_value(psc);
+                regs.atrlr().write_value(u32::MAX);
+            }
+        }
+
+        // load the prescaler now, without raising an update interrupt
+        let regs = self.regs_basic();
+        regs.ctlr1().modify(|r| r.set_urs(vals::Urs::COUNTERONLY));
+        regs.swevgr().write(|r| r.set_ug(true));
+        regs.ctlr1().modify(|r| r.set_urs(vals::Urs::ANYEVENT));
+    }
+
     /// Clear update interrupt.
     ///
     /// Returns whether the update interrupt flag was set.
@@ -272,7 +304,9 @@ impl<'d, T: BasicInstance> Timer<'d, T> {
         let regs = self.regs_basic();
         let intfr = regs.intfr().read();
         if intfr.uif() {
-            regs.intfr().modify(|r| {
+            // rc_w0 flags, writing 1 leaves the others unchanged
+            regs.intfr().write(|r| {
+                r.0 = !0;
                 r.set_uif(false);
             });
             true
@@ -286,6 +320,17 @@ impl<'d, T: BasicInstance> Timer<'d, T> {
         self.regs_basic().dmaintenr().modify(|r| r.set_uie(enable));
     }
 
+    /// Output the update event on TRGO, to trigger the DAC, the ADC or other timers.
+    #[cfg(not(timer_x0))] // GPTM_2CH has no CTLR2
+    pub fn set_trgo_update(&self) {
+        self.regs_basic().ctlr2().modify(|r| r.set_mms(vals::Mms::UPDATE));
+    }
+
+    /// Enable/disable one-pulse mode, the counter stops at the next update event.
+    pub fn set_one_pulse_mode(&self, enable: bool) {
+        self.regs_basic().ctlr1().modify(|r| r.set_opm(enable));
+    }
+
     /// Enable/disable autoreload preload.
     pub fn set_autoreload_preload(&self, enable: bool) {
         self.regs_basic().ctlr1().modify(|r| r.set_arpe(enable));
@@ -314,6 +359,11 @@ impl<'d, T: BasicInstance> Timer<'d, T> {
         }
     }
 
+    /// Get the frequency of the counter ticks, the timer clock divided by the prescaler.
+    pub fn get_tick_frequency(&self) -> Hertz {
+        T::frequency() / (self.regs_basic().psc().read() as u32 + 1)
+    }
+
     /// Enable/disable the update dma.
     #[cfg(not(timer_x0))] // GPTM_2CH
     pub fn enable_update_dma(&self, enable: bool) {
@@ -343,6 +393,14 @@ impl<'d, T: GeneralInstance16bit> Timer<'d, T> {
         self.regs_gp16().ctlr1().modify(|r| r.set_ckd(ckd));
     }
 
+    /// Output the compare reference (OCxREF) of `channel` on TRGO, see [`set_trgo_update`](Self::set_trgo_update).
+    #[cfg(not(timer_x0))] // no CTLR2
+    pub fn set_trgo_compare(&self, channel: Channel) {
+        // OC1REF to OC4REF follow each other
+        let mms = vals::Mms::from_bits(vals::Mms::COMPAREOC1.to_bits() + channel.index() as u8);
+        self.regs_gp16().ctlr2().modify(|r| r.set_mms(mms));
+    }
+
     /// Get max compare value. This depends on the timer frequency and the clock frequency from RCC.
     pub fn get_max_compare_value(&self) -> u32 {
         match T::BITS {
@@ -388,7 +446,11 @@ impl<'d, T: GeneralInstance16bit> Timer<'d, T> {
 
     /// Clear input interrupt.
     pub fn clear_input_interrupt(&self, channel: Channel) {
-        self.regs_gp16().intfr().modify(|r| r.set_ccif(channel.index(), false));
+        // rc_w0 flags, writing 1 leaves the others unchanged
+        self.regs_gp16().intfr().write(|r| {
+            r.0 = !0;
+            r.set_ccif(channel.index(), false);
+        });
     }
 
     /// Enable input interrupt.
@@ -550,6 +612,47 @@ impl<'d, T: AdvancedInstance> Timer<'d, T> {
         self.regs_advanced().bdtr().modify(|w| w.set_moe(enable));
     }
 
+    /// Get state of MOE-bit in BDTR register, cleared by hardware on a break event.
+    pub fn get_moe(&self) -> bool {
+        self.regs_advanced().bdtr().read().moe()
+    }
+
+    /// Enable/disable the break input.
+    pub fn set_break_enable(&self, enable: bool) {
+        self.regs_advanced().bdtr().modify(|w| w.set_bke(enable));
+    }
+
+    /// Set break input polarity, `true` for active high.
+    pub fn set_break_polarity(&self, active_high: bool) {
+        self.regs_advanced().bdtr().modify(|w| w.set_bkp(active_high));
+    }
+
+    /// Set automatic output enable, MOE is set again at the next update event after a break.
+    pub fn set_automatic_output_enable(&self, enable: bool) {
+        self.regs_advanced().bdtr().modify(|w| w.set_aoe(enable));
+    }
+
+    /// Set off-state selection for run mode (OSSR) and idle mode (OSSI).
+    pub fn set_off_state_selection(&self, run: bool, idle: bool) {
+        self.regs_advanced().bdtr().modify(|w| {
+            w.set_ossr(run);
+            w.set_ossi(idle);
+        });
+    }
+
+    /// Clear the break interrupt flag, returns whether it was set.
+    pub fn clear_break_interrupt(&self) -> bool {
+        let regs = self.regs_advanced();
+        let set = regs.intfr().read().bif();
+        if set {
+            regs.intfr().write(|w| {
+                w.0 = !0;
+                w.set_bif(false);
+            });
+        }
+        set
+    }
+
     /// Set complementary output polarity.
     pub fn set_complementary_output_polarity(&self, channel: Channel, polarity: OutputPolarity) {
         self.regs_advanced()
@@ -568,6 +671,18 @@ impl<'d, T: AdvancedInstance> Timer<'d, T> {
 // GPTM 2CH does not have these features
 #[cfg(timer_x0)]
 impl<'d, T: AdvancedInstance> Timer<'d, T> {
+    /// Output the update event on TRGO, to trigger the ADC or other timers.
+    pub fn set_trgo_update(&self) {
+        self.regs_advanced().ctlr2().modify(|r| r.set_mms(vals::Mms::UPDATE));
+    }
+
+    /// Output the compare reference (OCxREF) of `channel` on TRGO, see [`set_trgo_update`](Self::set_trgo_update).
+    pub fn set_trgo_compare(&self, channel: Channel) {
+        // OC1REF to OC4REF follow each other
+        let mms = vals::Mms::from_bits(vals::Mms::COMPAREOC1.to_bits() + channel.index() as u8);
+        self.regs_advanced().ctlr2().modify(|r| r.set_mms(mms));
+    }
+
     pub fn get_cc_dma_selection(&self) -> vals::Ccds {
         self.regs_advanced().ctlr2().read().ccds()
     }
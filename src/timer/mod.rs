@@ -11,13 +11,36 @@
 //! - No too many levels of abstraction
 //! - 2CH GPTM instances are also have helper functions defined
 
+use embassy_sync::waitqueue::AtomicWaker;
+
 use crate::peripheral::RccPeripheral;
 use crate::{interrupt, RemapPeripheral};
 
 pub mod complementary_pwm;
+#[cfg(not(timer_x0))]
+pub mod input_capture;
 pub mod low_level;
+#[cfg(not(timer_x0))]
+pub mod one_pulse;
+#[cfg(not(timer_x0))]
+pub mod qei;
 pub mod simple_pwm;
 
+/// Check that the pins of a driver use the same remap, and return it.
+///
+/// The remap is one AFIO field per timer, written by each pin constructor, so with different
+/// remaps only the pins matching the last one would be connected.
+fn same_remap<const N: usize>(remaps: [Option<u8>; N]) -> Option<u8> {
+    let mut remap = None;
+    for r in remaps.into_iter().flatten() {
+        match remap {
+            Some(prev) if prev != r => panic!("TIM: pins with different remaps, {} and {}", prev, r),
+            _ => remap = Some(r),
+        }
+    }
+    remap
+}
+
 /// Timer channel.
 #[derive(Clone, Copy, PartialEq)]
 pub enum Channel {
@@ -80,9 +103,30 @@ trait SealedGeneralInstance: BasicInstance {
     }
 }
 
+/// Per-timer driver state.
+pub(crate) struct State {
+    /// Woken when a capture/compare interrupt of the channel fires
+    pub(crate) cc_waker: [AtomicWaker; 4],
+    /// Woken when the update interrupt fires
+    pub(crate) up_waker: AtomicWaker,
+}
+
+impl State {
+    pub(crate) const fn new() -> Self {
+        Self {
+            cc_waker: [const { AtomicWaker::new() }; 4],
+            up_waker: AtomicWaker::new(),
+        }
+    }
+}
+
+trait SealedGeneralInstance16bit {
+    fn state() -> &'static State;
+}
+
 /// General-purpose 16-bit timer with 4 channels instance.
 #[allow(private_bounds)]
-pub trait GeneralInstance16bit: SealedGeneralInstance {
+pub trait GeneralInstance16bit: SealedGeneralInstance + SealedGeneralInstance16bit {
     /// Capture compare interrupt for this timer.
     type CaptureCompareInterrupt: interrupt::typelevel::Interrupt;
 
@@ -141,6 +185,12 @@ macro_rules! impl_core_timer {
 #[allow(unused)]
 macro_rules! impl_general_16bit {
     ($inst:ident) => {
+        impl SealedGeneralInstance16bit for crate::peripherals::$inst {
+            fn state() -> &'static State {
+                static STATE: State = State::new();
+                &STATE
+            }
+        }
         impl GeneralInstance16bit for crate::peripherals::$inst {
             type CaptureCompareInterrupt = crate::_generated::peripheral_interrupts::$inst::CC;
             type TriggerInterrupt = crate::_generated::peripheral_interrupts::$inst::TRG;
@@ -0,0 +1,386 @@
+//! One-pulse mode, a single pulse after a delay.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use super::low_level::{InputTISelection, OutputCompareMode, OutputPolarity, Timer};
+use super::simple_pwm::{Ch1, Ch2, Ch3, Ch4, PwmPin};
+use super::{Channel, Channel1Pin, Channel2Pin, ExternalTriggerPin, GeneralInstance16bit};
+use crate::gpio::{AnyPin, Pull};
+use crate::interrupt::typelevel::Interrupt;
+use crate::pac::timer::vals;
+use crate::{interrupt, into_ref, Peripheral, PeripheralRef};
+
+/// Edge of the trigger input that starts the pulse.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerEdge {
+    /// Rising edge.
+    Rising,
+    /// Falling edge.
+    Falling,
+}
+
+/// Trigger input of the slave mode controller
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TriggerSource {
+    Ti1,
+    Ti2,
+    Etr,
+}
+
+impl TriggerSource {
+    fn ts(self) -> vals::Ts {
+        match self {
+            TriggerSource::Ti1 => vals::Ts::TI1FP1,
+            TriggerSource::Ti2 => vals::Ts::TI2FP2,
+            TriggerSource::Etr => vals::Ts::ETRF,
+        }
+    }
+}
+
+/// Trigger pin wrapper.
+///
+/// This wraps a pin to make it start the pulse in hardware, through the slave mode controller.
+pub struct TriggerPin<'d, T> {
+    _pin: PeripheralRef<'d, AnyPin>,
+    remap: u8,
+    source: TriggerSource,
+    edge: TriggerEdge,
+    phantom: PhantomData<T>,
+}
+
+impl<'d, T: GeneralInstance16bit> TriggerPin<'d, T> {
+    /// Trigger from the channel 1 input.
+    pub fn new_ch1<const REMAP: u8>(
+        pin: impl Peripheral<P = impl Channel1Pin<T, REMAP>> + 'd,
+        pull: Pull,
+        edge: TriggerEdge,
+    ) -> Self {
+        into_ref!(pin);
+        critical_section::with(|_| {
+            pin.set_as_input(pull);
+            T::set_remap(REMAP);
+        });
+        Self::new_inner(pin.map_into(), REMAP, TriggerSource::Ti1, edge)
+    }
+
+    /// Trigger from the channel 2 input.
+    pub fn new_ch2<const REMAP: u8>(
+        pin: impl Peripheral<P = impl Channel2Pin<T, REMAP>> + 'd,
+        pull: Pull,
+        edge: TriggerEdge,
+    ) -> Self {
+        into_ref!(pin);
+        critical_section::with(|_| {
+            pin.set_as_input(pull);
+            T::set_remap(REMAP);
+        });
+        Self::new_inner(pin.map_into(), REMAP, TriggerSource::Ti2, edge)
+    }
+
+    /// Trigger from the external trigger input (ETR).
+    pub fn new_etr<const REMAP: u8>(
+        pin: impl Peripheral<P = impl ExternalTriggerPin<T, REMAP>> + 'd,
+        pull: Pull,
+        edge: TriggerEdge,
+    ) -> Self {
+        into_ref!(pin);
+        critical_section::with(|_| {
+            pin.set_as_input(pull);
+            T::set_remap(REMAP);
+        });
+        Self::new_inner(pin.map_into(), REMAP, TriggerSource::Etr, edge)
+    }
+
+    fn new_inner(pin: PeripheralRef<'d, AnyPin>, remap: u8, source: TriggerSource, edge: TriggerEdge) -> Self {
+        Self {
+            _pin: pin,
+            remap,
+            source,
+            edge,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Channel of a [`PwmPin`] marker type.
+trait OutputChannel {
+    const CHANNEL: Channel;
+}
+
+impl OutputChannel for Ch1 {
+    const CHANNEL: Channel = Channel::Ch1;
+}
+impl OutputChannel for Ch2 {
+    const CHANNEL: Channel = Channel::Ch2;
+}
+impl OutputChannel for Ch3 {
+    const CHANNEL: Channel = Channel::Ch3;
+}
+impl OutputChannel for Ch4 {
+    const CHANNEL: Channel = Channel::Ch4;
+}
+
+/// Update interrupt handler.
+pub struct UpdateInterruptHandler<T: GeneralInstance16bit> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: GeneralInstance16bit> interrupt::typelevel::Handler<T::UpdateInterrupt> for UpdateInterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let regs = crate::pac::timer::Bctm::from_ptr(T::regs());
+
+        if regs.intfr().read().uif() && regs.dmaintenr().read().uie() {
+            // the waiting future reads and clears the flag, just mask the interrupt
+            regs.dmaintenr().modify(|w| w.set_uie(false));
+            T::state().up_waker.wake();
+        }
+    }
+}
+
+/// One-pulse driver.
+///
+/// Each pulse starts `delay_us` after the counter is started, by [`fire`](Self::fire) or by an
+/// edge on the [`TriggerPin`], and lasts `width_us`. The counter then stops and the output stays
+/// inactive until the next start.
+///
+/// The output channel runs in PWM mode 2: it is inactive while the counter is below the compare
+/// value (the delay), and active from there up to the reload value (the pulse). Both are counted
+/// at the same prescaled tick, chosen as fast as possible so that delay plus width fit in the 16
+/// bit counter. The longest delay plus width is about 29 s at a 144 MHz timer clock.
+pub struct OnePulse<'d, T: GeneralInstance16bit> {
+    inner: Timer<'d, T>,
+    channel: Channel,
+    _trigger: Option<TriggerPin<'d, T>>,
+}
+
+impl<'d, T: GeneralInstance16bit> OnePulse<'d, T> {
+    /// Create a new one-pulse driver, outputting on the channel of `pin`.
+    ///
+    /// Without `trigger`, pulses are only started by [`fire`](Self::fire). The trigger must be on
+    /// a different channel than the output.
+    #[allow(private_bounds)]
+    pub fn new<C: OutputChannel>(
+        tim: impl Peripheral<P = T> + 'd,
+        _pin: PwmPin<'d, T, C>,
+        trigger: Option<TriggerPin<'d, T>>,
+        _irq: impl interrupt::typelevel::Binding<T::UpdateInterrupt, UpdateInterruptHandler<T>> + 'd,
+        delay_us: u32,
+        width_us: u32,
+    ) -> Self {
+        super::same_remap([Some(_pin.remap), trigger.as_ref().map(|t| t.remap)]);
+        let mut this = Self {
+            inner: Timer::new(tim),
+            channel: C::CHANNEL,
+            _trigger: None,
+        };
+
+        this.inner.set_one_pulse_mode(true);
+        this.inner.set_autoreload_preload(true);
+        this.inner
+            .set_output_compare_mode(this.channel, OutputCompareMode::PwmMode2);
+        this.inner.set_output_compare_preload(this.channel, true);
+        this.set_timing(delay_us, width_us);
+
+        if let Some(trigger) = trigger {
+            this.set_trigger(&trigger);
+            this._trigger = Some(trigger);
+        }
+
+        // INTFR flags are rc_w0, writing 1 leaves the others unchanged
+        this.inner.regs_basic().intfr().write(|w| {
+            w.0 = !0;
+            w.set_uif(false);
+        });
+        this.inner.enable_channel(this.channel, true);
+        this.inner.enable_outputs(); // Required for advanced timers, see SealedGeneralInstance for details
+
+        T::UpdateInterrupt::unpend();
+        unsafe { T::UpdateInterrupt::enable() };
+
+        this
+    }
+
+    fn set_trigger(&mut self, trigger: &TriggerPin<'d, T>) {
+        let regs = self.inner.regs_gp16();
+        let falling = trigger.edge == TriggerEdge::Falling;
+
+        let etp = match trigger.source {
+            TriggerSource::Ti1 | TriggerSource::Ti2 => {
+                let channel = if trigger.source == TriggerSource::Ti1 {
+                    Channel::Ch1
+                } else {
+                    Channel::Ch2
+                };
+                assert!(channel != self.channel, "trigger and output on the same channel");
+
+                self.inner.set_input_ti_selection(channel, InputTISelection::Normal);
+                regs.ccer().modify(|w| w.set_ccp(channel.index(), falling));
+                false
+            }
+            TriggerSource::Etr => falling,
+        };
+
+        // Start the counter on the trigger edge
+        regs.smcfgr().modify(|w| {
+            w.set_etp(etp);
+            w.set_ts(trigger.source.ts());
+            w.set_sms(vals::Sms::TRIGGERMODE);
+        });
+    }
+
+    /// Change the delay and the width of the following pulses.
+    ///
+    /// Must not be called while a pulse is running.
+    pub fn set_timing(&mut self, delay_us: u32, width_us: u32) {
+        assert!(!self.is_running());
+
+        let timing = pulse_timing(T::frequency().0, delay_us, width_us).expect("pulse too long for the timer");
+
+        let regs = self.inner.regs_gp16();
+        regs.psc().write_value(timing.psc);
+        regs.atrlr().write_value(timing.arr);
+        self.inner.set_compare_value(self.channel, timing.ccr as u32);
+
+        // load the preloaded values now, without raising an update interrupt
+        regs.ctlr1().modify(|r| r.set_urs(vals::Urs::COUNTERONLY));
+        regs.swevgr().write(|r| r.set_ug(true));
+        regs.ctlr1().modify(|r| r.set_urs(vals::Urs::ANYEVENT));
+    }
+
+    /// Set the output polarity, active high is a high pulse on a low output.
+    pub fn set_polarity(&mut self, polarity: OutputPolarity) {
+        self.inner.set_output_polarity(self.channel, polarity);
+    }
+
+    /// Start a pulse from software.
+    ///
+    /// Does nothing if a pulse is already running.
+    pub fn fire(&mut self) {
+        if self.is_running() {
+            return;
+        }
+        self.inner.regs_basic().intfr().write(|w| {
+            w.0 = !0;
+            w.set_uif(false);
+        });
+        self.inner.start();
+    }
+
+    /// Whether a pulse is running, the counter is between the start and the end of the pulse.
+    pub fn is_running(&self) -> bool {
+        self.inner.regs_basic().ctlr1().read().cen()
+    }
+
+    /// Wait until the pulse has ended.
+    ///
+    /// Returns when a pulse ended since the last [`fire`](Self::fire) or `wait_done`, which
+    /// can be right away. With a trigger, this waits for the next triggered pulse.
+    pub async fn wait_done(&mut self) {
+        let regs = self.inner.regs_basic();
+
+        poll_fn(|cx| {
+            T::state().up_waker.register(cx.waker());
+
+            if regs.intfr().read().uif() {
+                regs.intfr().write(|w| {
+                    w.0 = !0;
+                    w.set_uif(false);
+                });
+                return Poll::Ready(());
+            }
+            self.inner.enable_update_interrupt(true);
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl<'d, T: GeneralInstance16bit> Drop for OnePulse<'d, T> {
+    fn drop(&mut self) {
+        self.inner.enable_update_interrupt(false);
+        self.inner.enable_channel(self.channel, false);
+    }
+}
+
+/// Register values for a pulse.
+#[derive(Debug, PartialEq, Eq)]
+struct PulseTiming {
+    psc: u16,
+    /// Ticks from the counter start to the pulse start
+    ccr: u16,
+    /// Last tick of the pulse
+    arr: u16,
+}
+
+/// Compute the timing of a pulse of `width_us` starting `delay_us` after the counter start, for a
+/// timer clock of `timer_f`.
+///
+/// The counter counts `0..=arr` at `timer_f / (psc + 1)`, the output is active from `ccr` on. Both
+/// delay and width are at least one tick, a compare value of 0 would make the output active
+/// while the counter is stopped. Returns `None` if delay plus width don't fit the counter at the
+/// largest prescaler.
+fn pulse_timing(timer_f: u32, delay_us: u32, width_us: u32) -> Option<PulseTiming> {
+    const COUNTER_RANGE: u64 = 1 << 16;
+
+    // timer clocks, rounded to the nearest tick of the given divider
+    let ticks = |us: u32, div: u64| ((us as u64 * timer_f as u64 + div * 500_000) / (div * 1_000_000)).max(1);
+
+    let total_clk = (delay_us as u64 + width_us as u64) * timer_f as u64 / 1_000_000;
+    let mut div = total_clk.div_ceil(COUNTER_RANGE).max(1);
+
+    // rounding can make delay plus width one tick longer than the counter range
+    while div <= COUNTER_RANGE {
+        let delay = ticks(delay_us, div);
+        let width = ticks(width_us, div);
+        if delay + width <= COUNTER_RANGE {
+            return Some(PulseTiming {
+                psc: (div - 1) as u16,
+                ccr: delay as u16,
+                arr: (delay + width - 1) as u16,
+            });
+        }
+        div += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(psc: u16, ccr: u16, arr: u16) -> Option<PulseTiming> {
+        Some(PulseTiming { psc, ccr, arr })
+    }
+
+    #[test]
+    fn no_prescaler_when_it_fits() {
+        assert_eq!(pulse_timing(144_000_000, 100, 50), timing(0, 14_400, 21_599));
+        assert_eq!(pulse_timing(1_000_000, 65_535, 1), timing(0, 65_535, 65_535));
+    }
+
+    #[test]
+    fn prescaled() {
+        // 216000 clocks, divided by 4
+        assert_eq!(pulse_timing(144_000_000, 1_000, 500), timing(3, 36_000, 53_999));
+        // 1 s delay, 10 ms pulse at 48 MHz: divided by 740
+        let t = pulse_timing(48_000_000, 1_000_000, 10_000).unwrap();
+        assert_eq!(t.psc, 739);
+        assert_eq!(t.ccr, 64_865);
+        assert_eq!(t.arr, 64_865 + 649 - 1);
+    }
+
+    #[test]
+    fn at_least_one_tick() {
+        assert_eq!(pulse_timing(8_000_000, 0, 1), timing(0, 1, 8));
+        assert_eq!(pulse_timing(8_000_000, 5, 0), timing(0, 40, 40));
+    }
+
+    #[test]
+    fn too_long() {
+        assert_eq!(pulse_timing(144_000_000, 30_000_000, 0), None);
+        assert!(pulse_timing(144_000_000, 29_000_000, 0).is_some());
+    }
+}
@@ -0,0 +1,259 @@
+//! Quadrature encoder interface.
+
+use core::marker::PhantomData;
+
+use super::low_level::{InputTISelection, Timer};
+use super::simple_pwm::{Ch1, Ch2};
+use super::{Channel, Channel1Pin, Channel2Pin, GeneralInstance16bit};
+use crate::gpio::{AnyPin, Pull};
+use crate::pac::timer::vals;
+use crate::{into_ref, Peripheral, PeripheralRef};
+
+/// Counting direction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// Counting up.
+    Upcounting,
+    /// Counting down.
+    Downcounting,
+}
+
+/// Encoder counting mode, SMCFGR.SMS
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QeiMode {
+    /// Count both edges of TI2, 2 counts per encoder cycle.
+    X2Ti2,
+    /// Count both edges of TI1, 2 counts per encoder cycle.
+    X2Ti1,
+    /// Count both edges of TI1 and TI2, 4 counts per encoder cycle.
+    X4,
+}
+
+impl QeiMode {
+    fn sms(self) -> vals::Sms {
+        match self {
+            QeiMode::X2Ti2 => vals::Sms::ENCODERMODE1,
+            QeiMode::X2Ti1 => vals::Sms::ENCODERMODE2,
+            QeiMode::X4 => vals::Sms::ENCODERMODE3,
+        }
+    }
+}
+
+/// Quadrature decoder pin wrapper.
+///
+/// This wraps a pin to make it usable as an encoder input.
+pub struct QeiPin<'d, T, C> {
+    _pin: PeripheralRef<'d, AnyPin>,
+    remap: u8,
+    phantom: PhantomData<(T, C)>,
+}
+
+macro_rules! channel_impl {
+    ($new_chx:ident, $channel:ident, $pin_trait:ident) => {
+        impl<'d, T: GeneralInstance16bit> QeiPin<'d, T, $channel> {
+            #[doc = concat!("Create a new ", stringify!($channel), " QEI pin instance.")]
+            pub fn $new_chx<const REMAP: u8>(
+                pin: impl Peripheral<P = impl $pin_trait<T, REMAP>> + 'd,
+                pull: Pull,
+            ) -> Self {
+                into_ref!(pin);
+                critical_section::with(|_| {
+                    pin.set_as_input(pull);
+                    T::set_remap(REMAP);
+                });
+                QeiPin {
+                    _pin: pin.map_into(),
+                    remap: REMAP,
+                    phantom: PhantomData,
+                }
+            }
+        }
+    };
+}
+
+channel_impl!(new_ch1, Ch1, Channel1Pin);
+channel_impl!(new_ch2, Ch2, Channel2Pin);
+
+/// Quadrature decoder driver.
+///
+/// The two encoder signals go to channel 1 and 2, and the timer counter follows the encoder
+/// position. The counter is 16 bits wide and wraps around, [`position`](Self::position) extends
+/// it to 32 bits as long as it's called at least once every 32767 counts.
+///
+/// For encoders with an index signal, call [`reset`](Self::reset) from the index pin edge.
+pub struct Qei<'d, T: GeneralInstance16bit> {
+    inner: Timer<'d, T>,
+    last_count: u16,
+    position: i32,
+}
+
+impl<'d, T: GeneralInstance16bit> Qei<'d, T> {
+    /// Create a new quadrature decoder driver.
+    pub fn new(
+        tim: impl Peripheral<P = T> + 'd,
+        _ch1: QeiPin<'d, T, Ch1>,
+        _ch2: QeiPin<'d, T, Ch2>,
+        mode: QeiMode,
+    ) -> Self {
+        super::same_remap([Some(_ch1.remap), Some(_ch2.remap)]);
+        let inner = Timer::new(tim);
+        let r = inner.regs_gp16();
+
+        // Both inputs on their own channel, not inverted
+        inner.set_input_ti_selection(Channel::Ch1, InputTISelection::Normal);
+        inner.set_input_ti_selection(Channel::Ch2, InputTISelection::Normal);
+        r.ccer().modify(|w| {
+            w.set_ccp(0, false);
+            w.set_ccp(1, false);
+        });
+
+        r.smcfgr().modify(|w| w.set_sms(mode.sms()));
+
+        r.psc().write_value(0);
+        r.atrlr().write_value(u16::MAX);
+        r.cnt().write_value(0);
+
+        inner.start();
+
+        Self {
+            inner,
+            last_count: 0,
+            position: 0,
+        }
+    }
+
+    /// Set the digital filter of both encoder inputs, to reject contact bounce.
+    pub fn set_input_filter(&mut self, filter: vals::FilterValue) {
+        self.inner.set_input_capture_filter(Channel::Ch1, filter);
+        self.inner.set_input_capture_filter(Channel::Ch2, filter);
+    }
+
+    /// Get the direction the encoder last moved in.
+    pub fn read_direction(&self) -> Direction {
+        match self.inner.regs_gp16().ctlr1().read().dir() {
+            vals::Dir::DOWN => Direction::Downcounting,
+            vals::Dir::UP => Direction::Upcounting,
+        }
+    }
+
+    /// Get the raw 16-bit counter value.
+    pub fn count(&self) -> u16 {
+        self.inner.regs_gp16().cnt().read()
+    }
+
+    /// Get the position, the counter value extended to 32 bits.
+    ///
+    /// Moves of up to 32767 counts between calls are accounted for correctly in both directions.
+    pub fn position(&mut self) -> i32 {
+        let count = self.count();
+        self.position = extend_count(self.position, self.last_count, count);
+        self.last_count = count;
+        self.position
+    }
+
+    /// Reset the counter and the position to 0.
+    pub fn reset(&mut self) {
+        self.inner.regs_gp16().cnt().write_value(0);
+        self.last_count = 0;
+        self.position = 0;
+    }
+}
+
+/// Add the movement from `last` to `count` of the 16-bit counter to `position`.
+fn extend_count(position: i32, last: u16, count: u16) -> i32 {
+    position.wrapping_add(count.wrapping_sub(last) as i16 as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed counter values of an encoder moving by `steps` counts, in chunks of `chunk`.
+    fn simulate(start: u16, steps: i32, chunk: i32) -> i32 {
+        let mut position = 0;
+        let mut counter = start;
+        let mut moved = 0;
+        while moved != steps {
+            let step = (steps - moved).clamp(-chunk, chunk);
+            let last = counter;
+            counter = counter.wrapping_add(step as u16);
+            position = extend_count(position, last, counter);
+            moved += step;
+        }
+        position
+    }
+
+    #[test]
+    fn counts_up_and_down() {
+        assert_eq!(simulate(0, 1, 1), 1);
+        assert_eq!(simulate(0, -1, 1), -1);
+        assert_eq!(simulate(100, 4 * 250, 3), 1000);
+        assert_eq!(simulate(100, -4 * 250, 3), -1000);
+    }
+
+    /// Counter change of the encoder interface on a transition of (TI1, TI2) from `prev` to `now`,
+    /// after the counting direction table of the reference manual.
+    fn encoder_step(mode: QeiMode, prev: (bool, bool), now: (bool, bool)) -> i16 {
+        if prev.0 != now.0 && mode != QeiMode::X2Ti2 {
+            // Rising TI1 counts up while TI2 is low
+            if now.0 != now.1 {
+                1
+            } else {
+                -1
+            }
+        } else if prev.1 != now.1 && mode != QeiMode::X2Ti1 {
+            // Rising TI2 counts up while TI1 is high
+            if now.0 == now.1 {
+                1
+            } else {
+                -1
+            }
+        } else {
+            0
+        }
+    }
+
+    /// Turn an encoder by `cycles` full A/B cycles, A leading B when positive, and return the
+    /// position read after every transition.
+    fn turn(mode: QeiMode, start: u16, cycles: i32) -> i32 {
+        const FORWARD: [(bool, bool); 4] = [(true, false), (true, true), (false, true), (false, false)];
+        let mut inputs = (false, false);
+        let mut counter = start;
+        let mut position = 0;
+        for i in 0..4 * cycles.unsigned_abs() as usize {
+            let next = if cycles > 0 {
+                FORWARD[i % 4]
+            } else {
+                FORWARD[3 - (i + 1) % 4]
+            };
+            let last = counter;
+            counter = counter.wrapping_add(encoder_step(mode, inputs, next) as u16);
+            inputs = next;
+            position = extend_count(position, last, counter);
+        }
+        position
+    }
+
+    #[test]
+    fn decodes_quadrature() {
+        assert_eq!(turn(QeiMode::X4, 0, 10), 40);
+        assert_eq!(turn(QeiMode::X4, 0, -10), -40);
+        assert_eq!(turn(QeiMode::X2Ti1, 0, 10), 20);
+        assert_eq!(turn(QeiMode::X2Ti1, 0, -10), -20);
+        assert_eq!(turn(QeiMode::X2Ti2, 0, 10), 20);
+        assert_eq!(turn(QeiMode::X2Ti2, 0, -10), -20);
+        // Across the 16-bit wrap
+        assert_eq!(turn(QeiMode::X4, u16::MAX - 5, 3), 12);
+        assert_eq!(turn(QeiMode::X4, 5, -3), -12);
+    }
+
+    #[test]
+    fn wraps_around_16_bits() {
+        assert_eq!(simulate(u16::MAX - 2, 10, 1), 10);
+        assert_eq!(simulate(2, -10, 1), -10);
+        assert_eq!(simulate(0, 200_000, 30_000), 200_000);
+        assert_eq!(simulate(0, -200_000, 32_767), -200_000);
+    }
+}
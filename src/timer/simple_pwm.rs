@@ -4,7 +4,7 @@ use core::marker::PhantomData;
 
 use super::low_level::{CountingMode, OutputCompareMode, OutputPolarity, Timer};
 use super::{Channel, Channel1Pin, Channel2Pin, Channel3Pin, Channel4Pin, GeneralInstance16bit};
-use crate::gpio::{AFType, AnyPin};
+use crate::gpio::{AFType, AnyPin, SealedPin, Speed};
 use crate::time::Hertz;
 use crate::{into_ref, Peripheral, PeripheralRef};
 
@@ -22,6 +22,7 @@ pub enum Ch4 {}
 /// This wraps a pin to make it usable with PWM.
 pub struct PwmPin<'d, T, C> {
     _pin: PeripheralRef<'d, AnyPin>,
+    pub(super) remap: u8,
     phantom: PhantomData<(T, C)>,
 }
 
@@ -32,11 +33,12 @@ macro_rules! channel_impl {
             pub fn $new_chx<const REMAP: u8>(pin: impl Peripheral<P = impl $pin_trait<T, REMAP>> + 'd) -> Self {
                 into_ref!(pin);
                 critical_section::with(|_| {
-                    pin.set_as_af_output(AFType::OutputPushPull, Default::default());
+                    pin.set_as_af_output(AFType::OutputPushPull, Speed::Medium);
                     T::set_remap(REMAP);
                 });
                 PwmPin {
                     _pin: pin.map_into(),
+                    remap: REMAP,
                     phantom: PhantomData,
                 }
             }
@@ -44,6 +46,14 @@ macro_rules! channel_impl {
     };
 }
 
+impl<'d, T, C> PwmPin<'d, T, C> {
+    /// Change the output speed of the pin, 10 MHz by default.
+    pub fn with_speed(self, speed: Speed) -> Self {
+        critical_section::with(|_| self._pin.set_speed(speed));
+        self
+    }
+}
+
 channel_impl!(new_ch1, Ch1, Channel1Pin);
 channel_impl!(new_ch2, Ch2, Channel2Pin);
 channel_impl!(new_ch3, Ch3, Channel3Pin);
@@ -65,6 +75,12 @@ impl<'d, T: GeneralInstance16bit> SimplePwm<'d, T> {
         freq: Hertz,
         counting_mode: CountingMode,
     ) -> Self {
+        super::same_remap([
+            _ch1.as_ref().map(|p| p.remap),
+            _ch2.as_ref().map(|p| p.remap),
+            _ch3.as_ref().map(|p| p.remap),
+            _ch4.as_ref().map(|p| p.remap),
+        ]);
         Self::new_inner(tim, freq, counting_mode)
     }
 
@@ -128,6 +144,11 @@ impl<'d, T: GeneralInstance16bit> SimplePwm<'d, T> {
         self.inner.get_max_compare_value() + 1
     }
 
+    /// Get the frequency of the counter ticks, the duty resolution.
+    pub fn get_tick_frequency(&self) -> Hertz {
+        self.inner.get_tick_frequency()
+    }
+
     /// Set the duty for a given channel.
     ///
     /// The value ranges from 0 for 0% duty, to [`get_max_duty`](Self::get_max_duty) for 100% duty, both included.
@@ -222,25 +243,49 @@ impl<'d, T: GeneralInstance16bit> SimplePwm<'d, T> {
     }
 }
 
-/*
 macro_rules! impl_waveform_chx {
     ($fn_name:ident, $dma_ch:ident, $cc_ch:ident) => {
+        #[cfg(not(timer_x0))]
         impl<'d, T: GeneralInstance16bit> SimplePwm<'d, T> {
-            /// Generate a sequence of PWM waveform
+            /// Generate a PWM waveform, with a new duty from `duty` for each PWM period.
+            ///
+            /// The TIMx_CHy DMA request is moved to the update event, so the DMA writes the next
+            /// duty to the compare register in each period, and the preload applies it at the
+            /// start of the next one. Duty values are in ticks, up to
+            /// [`get_max_duty`](Self::get_max_duty), see [`get_tick_frequency`](Self::get_tick_frequency)
+            /// for the resolution. For WS2812 LEDs, 800 kHz PWM with a duty of about 1/3 for a 0
+            /// and 2/3 for a 1 bit.
+            ///
+            /// After the last value, the output is held low: the duty becomes 0 or the maximum,
+            /// depending on the polarity and on PWM mode 1 or 2. The last value may be repeated
+            /// for some periods before that, if this task runs late. End `duty` with the low
+            /// duty where that matters, like the reset (latch) of WS2812. The channel enable and
+            /// DMA settings are restored afterwards.
             ///
-            /// Note:
-            /// you will need to provide corresponding TIMx_CHy DMA channel to use this method.
+            /// Panics if the counter isn't running, the DMA would never be triggered.
             pub async fn $fn_name(&mut self, dma: impl Peripheral<P = impl super::$dma_ch<T>>, duty: &[u16]) {
-                use crate::pac::timer::vals::Ccds;
+                use crate::pac::timer::vals::{Ccds, Ocm};
 
                 into_ref!(dma);
 
-                #[allow(clippy::let_unit_value)] // eg. stm32f334
                 let req = dma.request();
 
                 let cc_channel = Channel::$cc_ch;
 
-                let original_duty_state = self.get_duty(cc_channel);
+                let regs = self.inner.regs_gp16();
+                assert!(regs.ctlr1().read().cen(), "TIM: counter stopped");
+
+                // OCxREF inactive for an active high output, active for an active low one. 0
+                // keeps OCxREF inactive in PWM mode 1 and active in PWM mode 2.
+                let idx = cc_channel.index();
+                let ref_active = regs.ccer().read().ccp(idx);
+                let pwm_mode2 = regs.chctlr_output(idx / 2).read().ocm(idx % 2) == Ocm::PWMMODE2;
+                let low_duty = if ref_active != pwm_mode2 {
+                    self.get_max_duty().min(u16::MAX as u32) as u16
+                } else {
+                    0
+                };
+
                 let original_enable_state = self.is_enabled(cc_channel);
                 let original_cc_dma_on_update = self.inner.get_cc_dma_selection() == Ccds::ONUPDATE;
                 let original_cc_dma_enabled = self.inner.get_cc_dma_enable_state(cc_channel);
@@ -258,45 +303,26 @@ macro_rules! impl_waveform_chx {
                     self.enable(cc_channel);
                 }
 
+                // The update that makes the last value active preloads the low duty, the next
+                // one makes it active and completes the second transfer
+                let low = [low_duty; 2];
                 unsafe {
-                    #[cfg(not(any(bdma, gpdma)))]
-                    use crate::dma::{Burst, FifoThreshold};
                     use crate::dma::{Transfer, TransferOptions};
 
-                    let dma_transfer_option = TransferOptions {
-                        #[cfg(not(any(bdma, gpdma)))]
-                        fifo_threshold: Some(FifoThreshold::Full),
-                        #[cfg(not(any(bdma, gpdma)))]
-                        mburst: Burst::Incr8,
-                        ..Default::default()
-                    };
-
-                    Transfer::new_write(
-                        &mut dma,
-                        req,
-                        duty,
-                        self.inner.regs_gp16().ccr(cc_channel.index()).as_ptr() as *mut _,
-                        dma_transfer_option,
-                    )
-                    .await
+                    let chcvr = regs.chcvr(idx).as_ptr() as *mut _;
+                    Transfer::new_write(&mut dma, req, duty, chcvr, TransferOptions::default()).await;
+                    Transfer::new_write(&mut dma, req, &low[..], chcvr, TransferOptions::default()).await;
                 };
 
+                if !original_cc_dma_enabled {
+                    self.inner.set_cc_dma_enable_state(cc_channel, false);
+                }
+
                 // restore output compare state
                 if !original_enable_state {
                     self.disable(cc_channel);
                 }
 
-                self.set_duty(cc_channel, original_duty_state);
-
-                // Since DMA is closed before timer Capture Compare Event trigger DMA is turn off,
-                // this can almost always trigger a DMA FIFO error.
-                //
-                // optional TODO:
-                // clean FEIF after disable UDE
-                if !original_cc_dma_enabled {
-                    self.inner.set_cc_dma_enable_state(cc_channel, false);
-                }
-
                 if !original_cc_dma_on_update {
                     self.inner.set_cc_dma_selection(Ccds::ONCOMPARE)
                 }
@@ -309,4 +335,3 @@ impl_waveform_chx!(waveform_ch1, Ch1Dma, Ch1);
 impl_waveform_chx!(waveform_ch2, Ch2Dma, Ch2);
 impl_waveform_chx!(waveform_ch3, Ch3Dma, Ch3);
 impl_waveform_chx!(waveform_ch4, Ch4Dma, Ch4);
-*/
@@ -0,0 +1,510 @@
+//! Interrupt-driven buffered USART.
+//!
+//! Bytes are moved between the data register and two ring buffers by the USART interrupt, so
+//! reception goes on while no `read` is pending. Bind [`BufferedInterruptHandler`] instead of
+//! [`InterruptHandler`](super::InterruptHandler) for the instance.
+//!
+//! With hardware flow control ([`BufferedUart::new_with_rtscts`]), reception pauses when the
+//! receive buffer is full: the last byte stays in the data register, which keeps RTS deasserted
+//! until the buffer has room again, so no byte is dropped.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::slice;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::task::Poll;
+
+use embassy_hal_internal::atomic_ring_buffer::RingBuffer;
+use embassy_sync::waitqueue::AtomicWaker;
+
+use super::{clear_lbd, reconfigure, Config, ConfigError, CtsPin, Error, Instance, RtsPin, RxPin, TxPin};
+use crate::gpio::{AFType, AnyPin, Pull, SealedPin, Speed};
+use crate::interrupt::typelevel::Interrupt;
+use crate::{interrupt, into_ref, Peripheral, PeripheralRef};
+
+/// Interrupt handler for the buffered driver.
+pub struct BufferedInterruptHandler<T: Instance> {
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for BufferedInterruptHandler<T> {
+    unsafe fn on_interrupt() {
+        let r = T::regs();
+        let state = T::buffered_state();
+
+        let (sr, cr1) = (r.statr().read(), r.ctlr1().read());
+
+        // RX. Reading DATAR after STATR clears RXNE and the error flags
+        if cr1.rxneie() && sr.rxne() && r.ctlr3().read().rtse() && state.rx_buf.is_full() {
+            // With flow control, leave the byte in DATAR so that RTS stays deasserted, until the
+            // reader makes room
+            r.ctlr1().modify(|w| w.set_rxneie(false));
+            state.rx_waker.wake();
+        } else if cr1.rxneie() && (sr.rxne() || sr.ore()) {
+            let byte = r.datar().read().dr() as u8;
+            let is_break = sr.lbd();
+            if is_break {
+                clear_lbd(&r);
+                state.set_error(Error::Break);
+            } else if sr.pe() {
+                state.set_error(Error::Parity);
+            } else if sr.fe() {
+                state.set_error(Error::Framing);
+            } else if sr.ne() {
+                state.set_error(Error::Noise);
+            }
+            if sr.ore() {
+                state.set_error(Error::Overrun);
+            }
+            // A LIN break arrives with an all-zero frame with a framing error, it isn't data
+            if sr.rxne() && !(is_break && sr.fe()) {
+                let mut rx_writer = state.rx_buf.writer();
+                if !rx_writer.push_one(byte) {
+                    // Buffer full, the byte is lost
+                    state.set_error(Error::Overrun);
+                }
+            }
+            state.rx_waker.wake();
+        }
+
+        // TX
+        if cr1.txeie() && sr.txe() {
+            let mut tx_reader = state.tx_buf.reader();
+            if let Some(byte) = tx_reader.pop_byte() {
+                r.datar().write(|w| w.set_dr(byte as u16));
+            } else {
+                // Buffer drained, wait for the last byte to leave the shift register
+                r.ctlr1().modify(|w| {
+                    w.set_txeie(false);
+                    w.set_tcie(true);
+                });
+            }
+            state.tx_waker.wake();
+        } else if cr1.tcie() && sr.tc() {
+            r.ctlr1().modify(|w| w.set_tcie(false));
+            // A write may have queued more bytes in the meantime
+            if state.tx_buf.is_empty() {
+                state.tx_done.store(true, Ordering::Relaxed);
+            }
+            state.tx_waker.wake();
+        }
+    }
+}
+
+pub(super) struct State {
+    rx_waker: AtomicWaker,
+    rx_buf: RingBuffer,
+    /// Receive error for the next read, 0 for none
+    error: AtomicU8,
+    tx_waker: AtomicWaker,
+    tx_buf: RingBuffer,
+    tx_done: AtomicBool,
+}
+
+impl State {
+    fn set_error(&self, error: Error) {
+        let code = match error {
+            Error::Overrun => 1,
+            Error::Parity => 2,
+            Error::Framing => 3,
+            Error::Noise => 4,
+            Error::Break => 5,
+            Error::BufferTooLong => return,
+        };
+        self.error.store(code, Ordering::Relaxed);
+    }
+
+    /// Returns the pending receive error, and clears it.
+    fn take_error(&self) -> Option<Error> {
+        let code = critical_section::with(|_| {
+            let code = self.error.load(Ordering::Relaxed);
+            self.error.store(0, Ordering::Relaxed);
+            code
+        });
+        match code {
+            1 => Some(Error::Overrun),
+            2 => Some(Error::Parity),
+            3 => Some(Error::Framing),
+            4 => Some(Error::Noise),
+            5 => Some(Error::Break),
+            _ => None,
+        }
+    }
+
+    pub(super) const fn new() -> Self {
+        Self {
+            rx_waker: AtomicWaker::new(),
+            rx_buf: RingBuffer::new(),
+            error: AtomicU8::new(0),
+            tx_waker: AtomicWaker::new(),
+            tx_buf: RingBuffer::new(),
+            tx_done: AtomicBool::new(true),
+        }
+    }
+}
+
+/// Bidirectional buffered UART
+pub struct BufferedUart<'d, T: Instance> {
+    rx: BufferedUartRx<'d, T>,
+    tx: BufferedUartTx<'d, T>,
+}
+
+/// Rx-only buffered UART
+///
+/// Created with [`BufferedUart::split`]
+pub struct BufferedUartRx<'d, T: Instance> {
+    _phantom: PhantomData<T>,
+    rx: Option<PeripheralRef<'d, AnyPin>>,
+    rts: Option<PeripheralRef<'d, AnyPin>>,
+}
+
+/// Tx-only buffered UART
+///
+/// Created with [`BufferedUart::split`]
+pub struct BufferedUartTx<'d, T: Instance> {
+    _phantom: PhantomData<T>,
+    tx: Option<PeripheralRef<'d, AnyPin>>,
+    cts: Option<PeripheralRef<'d, AnyPin>>,
+}
+
+impl<'d, T: Instance> BufferedUart<'d, T> {
+    /// Create a new bidirectional buffered UART
+    ///
+    /// Received bytes are kept in `rx_buffer` until read, bytes to send are queued in
+    /// `tx_buffer`. When `rx_buffer` is full, further bytes are dropped and the next read
+    /// returns [`Error::Overrun`]. Use [`new_with_rtscts`](Self::new_with_rtscts) to pause the
+    /// sender instead.
+    pub fn new<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, BufferedInterruptHandler<T>> + 'd,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        into_ref!(peri, rx, tx);
+
+        rx.set_as_input(Pull::None);
+        tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        T::set_remap(REMAP);
+
+        Self::new_inner(
+            peri,
+            Some(rx.map_into()),
+            Some(tx.map_into()),
+            None,
+            None,
+            tx_buffer,
+            rx_buffer,
+            config,
+        )
+    }
+
+    /// Create a new bidirectional buffered UART with request-to-send and clear-to-send pins
+    ///
+    /// See [`Uart::new_with_rtscts`](super::Uart::new_with_rtscts) for the flow control behaviour.
+    pub fn new_with_rtscts<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, BufferedInterruptHandler<T>> + 'd,
+        rts: impl Peripheral<P = impl RtsPin<T, REMAP>> + 'd,
+        cts: impl Peripheral<P = impl CtsPin<T, REMAP>> + 'd,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        into_ref!(peri, rx, tx, rts, cts);
+
+        rx.set_as_input(Pull::None);
+        tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        rts.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        cts.set_as_input(Pull::None);
+        T::set_remap(REMAP);
+
+        Self::new_inner(
+            peri,
+            Some(rx.map_into()),
+            Some(tx.map_into()),
+            Some(rts.map_into()),
+            Some(cts.map_into()),
+            tx_buffer,
+            rx_buffer,
+            config,
+        )
+    }
+
+    fn new_inner(
+        _peri: impl Peripheral<P = T> + 'd,
+        rx: Option<PeripheralRef<'d, AnyPin>>,
+        tx: Option<PeripheralRef<'d, AnyPin>>,
+        rts: Option<PeripheralRef<'d, AnyPin>>,
+        cts: Option<PeripheralRef<'d, AnyPin>>,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        assert!(!tx_buffer.is_empty() && !rx_buffer.is_empty());
+
+        // BufferedUartRx and BufferedUartTx have one refcount each, released in their Drop.
+        T::enable_and_reset();
+        T::enable_and_reset();
+
+        let state = T::buffered_state();
+        let len = tx_buffer.len();
+        unsafe { state.tx_buf.init(tx_buffer.as_mut_ptr(), len) };
+        let len = rx_buffer.len();
+        unsafe { state.rx_buf.init(rx_buffer.as_mut_ptr(), len) };
+        state.error.store(0, Ordering::Relaxed);
+        state.tx_done.store(true, Ordering::Relaxed);
+
+        let r = T::regs();
+        r.ctlr3().write(|w| {
+            w.set_rtse(rts.is_some());
+            w.set_ctse(cts.is_some());
+        });
+        super::configure(&r, &config, T::frequency(), true, true)?;
+
+        // RXNEIE also covers ORE
+        r.ctlr1().modify(|w| w.set_rxneie(true));
+
+        T::Interrupt::unpend();
+        unsafe { T::Interrupt::enable() };
+
+        Ok(Self {
+            rx: BufferedUartRx {
+                _phantom: PhantomData,
+                rx,
+                rts,
+            },
+            tx: BufferedUartTx {
+                _phantom: PhantomData,
+                tx,
+                cts,
+            },
+        })
+    }
+
+    /// Reconfigure the driver
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        reconfigure::<T>(config)
+    }
+
+    /// Split the UART into a transmitter and receiver, e.g. for use in two tasks
+    pub fn split(self) -> (BufferedUartTx<'d, T>, BufferedUartRx<'d, T>) {
+        (self.tx, self.rx)
+    }
+}
+
+impl<'d, T: Instance> BufferedUartRx<'d, T> {
+    /// Reconfigure the driver
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        reconfigure::<T>(config)
+    }
+
+    /// Read at least one byte into `buf`, waiting until a byte is received
+    ///
+    /// Returns [`Error::Overrun`] once if bytes were lost since the last read, either because
+    /// the buffer was full or the data register wasn't read in time. Parity, framing and noise
+    /// errors are reported the same way, the byte that had it is kept in the buffer.
+    async fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        poll_fn(|cx| {
+            let state = T::buffered_state();
+            state.rx_waker.register(cx.waker());
+
+            if let Some(e) = state.take_error() {
+                return Poll::Ready(Err(e));
+            }
+
+            let mut rx_reader = unsafe { state.rx_buf.reader() };
+            let data = rx_reader.pop_slice();
+            if data.is_empty() {
+                return Poll::Pending;
+            }
+
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            rx_reader.pop_done(len);
+            Self::resume_rx();
+            Poll::Ready(Ok(len))
+        })
+        .await
+    }
+
+    async fn fill_buf(&self) -> Result<&[u8], Error> {
+        poll_fn(|cx| {
+            let state = T::buffered_state();
+            state.rx_waker.register(cx.waker());
+
+            if let Some(e) = state.take_error() {
+                return Poll::Ready(Err(e));
+            }
+
+            let mut rx_reader = unsafe { state.rx_buf.reader() };
+            let (p, n) = rx_reader.pop_buf();
+            if n == 0 {
+                return Poll::Pending;
+            }
+
+            // Safety: the interrupt only writes to the free part of the buffer
+            let buf = unsafe { slice::from_raw_parts(p, n) };
+            Poll::Ready(Ok(buf))
+        })
+        .await
+    }
+
+    fn consume(&self, amt: usize) {
+        let state = T::buffered_state();
+        let mut rx_reader = unsafe { state.rx_buf.reader() };
+        rx_reader.pop_done(amt);
+        Self::resume_rx();
+    }
+
+    /// Take bytes again after the interrupt paused on a full buffer (flow control).
+    fn resume_rx() {
+        critical_section::with(|_| T::regs().ctlr1().modify(|w| w.set_rxneie(true)));
+    }
+}
+
+impl<'d, T: Instance> BufferedUartTx<'d, T> {
+    /// Reconfigure the driver
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        reconfigure::<T>(config)
+    }
+
+    /// Queue bytes from `buf`, waiting until there is room for at least one
+    async fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        poll_fn(|cx| {
+            let state = T::buffered_state();
+            state.tx_waker.register(cx.waker());
+
+            let mut tx_writer = unsafe { state.tx_buf.writer() };
+            let data = tx_writer.push_slice();
+            if data.is_empty() {
+                return Poll::Pending;
+            }
+
+            let len = data.len().min(buf.len());
+            data[..len].copy_from_slice(&buf[..len]);
+            tx_writer.push_done(len);
+
+            state.tx_done.store(false, Ordering::Relaxed);
+            // The interrupt takes it from here
+            critical_section::with(|_| T::regs().ctlr1().modify(|w| w.set_txeie(true)));
+
+            Poll::Ready(Ok(len))
+        })
+        .await
+    }
+
+    /// Wait until all queued bytes are sent
+    async fn flush(&self) -> Result<(), Error> {
+        poll_fn(|cx| {
+            let state = T::buffered_state();
+            state.tx_waker.register(cx.waker());
+
+            if state.tx_done.load(Ordering::Relaxed) {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<'d, T: Instance> Drop for BufferedUartRx<'d, T> {
+    fn drop(&mut self) {
+        let state = T::buffered_state();
+        T::regs().ctlr1().modify(|w| w.set_rxneie(false));
+        unsafe { state.rx_buf.deinit() };
+
+        self.rx.as_ref().map(|x| x.set_as_disconnected());
+        self.rts.as_ref().map(|x| x.set_as_disconnected());
+        T::disable();
+    }
+}
+
+impl<'d, T: Instance> Drop for BufferedUartTx<'d, T> {
+    fn drop(&mut self) {
+        let state = T::buffered_state();
+        T::regs().ctlr1().modify(|w| {
+            w.set_txeie(false);
+            w.set_tcie(false);
+        });
+        unsafe { state.tx_buf.deinit() };
+
+        self.tx.as_ref().map(|x| x.set_as_disconnected());
+        self.cts.as_ref().map(|x| x.set_as_disconnected());
+        T::disable();
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::ErrorType for BufferedUart<'d, T> {
+    type Error = Error;
+}
+
+impl<'d, T: Instance> embedded_io_async::ErrorType for BufferedUartRx<'d, T> {
+    type Error = Error;
+}
+
+impl<'d, T: Instance> embedded_io_async::ErrorType for BufferedUartTx<'d, T> {
+    type Error = Error;
+}
+
+impl<'d, T: Instance> embedded_io_async::Read for BufferedUart<'d, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.rx.read(buf).await
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::Read for BufferedUartRx<'d, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Self::read(self, buf).await
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::BufRead for BufferedUart<'d, T> {
+    async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+        self.rx.fill_buf().await
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.rx.consume(amt)
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::BufRead for BufferedUartRx<'d, T> {
+    async fn fill_buf(&mut self) -> Result<&[u8], Self::Error> {
+        Self::fill_buf(self).await
+    }
+
+    fn consume(&mut self, amt: usize) {
+        Self::consume(self, amt)
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::Write for BufferedUart<'d, T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.tx.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.tx.flush().await
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::Write for BufferedUartTx<'d, T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Self::write(self, buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Self::flush(self).await
+    }
+}
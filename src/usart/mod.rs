@@ -11,6 +11,7 @@ Supports DMA
 Multiple interrupt sources
  */
 
+use core::cell::Cell;
 use core::future::poll_fn;
 use core::marker::PhantomData;
 use core::sync::atomic::{compiler_fence, Ordering};
@@ -20,13 +21,18 @@ use embassy_sync::waitqueue::AtomicWaker;
 use futures::future::{select, Either};
 
 use crate::dma::ChannelAndRequest;
-use crate::gpio::{AFType, AnyPin, Pull, SealedPin, Speed};
+use crate::gpio::{AFType, AnyPin, Pin, Pull, SealedPin, Speed};
 use crate::internal::drop::OnDrop;
 use crate::interrupt::typelevel::Interrupt;
 use crate::mode::{Async, Blocking, Mode};
 use crate::time::Hertz;
 use crate::{interrupt, into_ref, pac, peripherals, Peripheral, PeripheralRef};
 
+mod buffered;
+pub use buffered::{BufferedInterruptHandler, BufferedUart, BufferedUartRx, BufferedUartTx};
+mod ringbuffered;
+pub use ringbuffered::RingBufferedUartRx;
+
 /// Interrupt handler.
 pub struct InterruptHandler<T: Instance> {
     _phantom: PhantomData<T>,
@@ -37,9 +43,16 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
         let r = T::regs();
         let s = T::state();
 
-        let (sr, cr1, cr3) = (r.statr().read(), r.ctlr1().read(), r.ctlr3().read());
+        let (sr, cr1, cr2, cr3) = (r.statr().read(), r.ctlr1().read(), r.ctlr2().read(), r.ctlr3().read());
+
+        if cr1.tcie() && sr.tc() {
+            // Last stop bit sent, see `wait_transmission_complete`
+            r.ctlr1().modify(|w| w.set_tcie(false));
+            s.tx_waker.wake();
+        }
 
-        let has_errors = (sr.pe() && cr1.peie()) || ((sr.fe() || sr.ne() || sr.ore()) && cr3.eie());
+        let has_errors =
+            (sr.pe() && cr1.peie()) || ((sr.fe() || sr.ne() || sr.ore()) && cr3.eie()) || (sr.lbd() && cr2.lbdie());
         if has_errors {
             // clear all interrupts and DMA Rx Request
             r.ctlr1().modify(|w| {
@@ -50,6 +63,8 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
                 // disable idle line interrupt
                 w.set_idleie(false);
             });
+            // disable LIN break interrupt, the flag is cleared by the reader
+            r.ctlr2().modify(|w| w.set_lbdie(false));
             r.ctlr3().modify(|w| {
                 // disable Error Interrupt: (Frame error, Noise error, Overrun error)
                 w.set_eie(false);
@@ -66,6 +81,10 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
             // We cannot check the RXNE flag as it is auto-cleared by the DMA controller
 
             // It is up to the listener to determine if this in fact was a RX event and disable the RXNE detection
+            if !cr3.dmar() {
+                // Nothing reads the data register before the task runs, see `wait_for_address`
+                r.ctlr1().modify(|w| w.set_rxneie(false));
+            }
         } else {
             return;
         }
@@ -75,11 +94,29 @@ impl<T: Instance> interrupt::typelevel::Handler<T::Interrupt> for InterruptHandl
     }
 }
 
+/// Echo handling in half-duplex mode
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HalfDuplexReadback {
+    /// The receiver is disabled while transmitting, so the echo of sent bytes is discarded
+    NoReadback,
+    /// Sent bytes are received back, e.g. to detect collisions on the bus
+    Readback,
+}
+
+/// Data bits, not counting the parity bit
+///
+/// The USART has 8 or 9-bit frames (M), and with parity it sends the parity bit in place of
+/// the last bit of the frame. So 8E1 uses the 9-bit frame, and 7E1 the 8-bit one. 9 data bits
+/// with parity, and 7 data bits without, aren't supported.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum DataBits {
-    DataBits8 = 0,
-    DataBits9 = 1,
+    /// Only with parity. Received bytes keep the parity bit in bit 7.
+    DataBits7,
+    DataBits8,
+    /// Use [`UartTx::blocking_write_u16`] and [`UartRx::blocking_read_u16`] for the ninth bit
+    DataBits9,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -90,6 +127,24 @@ pub enum Parity {
     ParityOdd,
 }
 
+/// How a muted receiver wakes up, see [`UartRx::enter_mute`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeupMethod {
+    /// Wake up on an idle line
+    IdleLine,
+    /// Wake up on an address byte, the MSB of the frame set, whose low 4 bits match `addr`
+    AddressMark { addr: u8 },
+}
+
+/// Length of the low level detected as a LIN break
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BreakDetectLength {
+    Bits10,
+    Bits11,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum StopBits {
@@ -117,6 +172,20 @@ pub struct Config {
     /// If false: the error is ignored and cleared
     pub detect_previous_overrun: bool,
 
+    /// RS-485: bit times between asserting the driver enable pin and the first start bit
+    pub de_assertion_time: u8,
+    /// RS-485: bit times between the last stop bit and deasserting the driver enable pin
+    pub de_deassertion_time: u8,
+
+    /// Enable LIN mode, detecting a break of the given length as [`Error::Break`]
+    ///
+    /// LIN mode needs [`StopBits::STOP1`], other stop bits are rejected with
+    /// [`ConfigError::LinStopBits`]. Without LIN mode, a break shows up as [`Error::Framing`].
+    pub lin_break_detection: Option<BreakDetectLength>,
+
+    /// Multiprocessor communication: how the receiver leaves mute mode
+    pub wakeup: WakeupMethod,
+
     half_duplex: bool,
 }
 impl Default for Config {
@@ -130,6 +199,13 @@ impl Default for Config {
 
             detect_previous_overrun: false,
 
+            de_assertion_time: 0,
+            de_deassertion_time: 0,
+
+            lin_break_detection: None,
+
+            wakeup: WakeupMethod::IdleLine,
+
             half_duplex: false,
         }
     }
@@ -151,6 +227,9 @@ pub enum Error {
     /// Parity check error
     // PE
     Parity,
+    /// Break received, the line was held low for longer than a frame
+    // LBD
+    Break,
     /// Buffer too large for DMA
     BufferTooLong,
 }
@@ -160,6 +239,10 @@ pub enum Error {
 pub enum ConfigError {
     BaudrateTooLow,
     BaudrateTooHigh,
+    /// 9 data bits with parity would need a 10-bit frame, 7 data bits without parity a 7-bit one
+    DataParityNotSupported,
+    /// LIN mode needs 1 stop bit
+    LinStopBits,
 }
 
 enum ReadCompletionEvent {
@@ -178,10 +261,15 @@ pub struct UartTx<'d, T: Instance, M: Mode> {
     tx: Option<PeripheralRef<'d, AnyPin>>,
     cts: Option<PeripheralRef<'d, AnyPin>>,
     tx_dma: Option<ChannelAndRequest<'d>>,
+    half_duplex: Option<HalfDuplexReadback>,
+    de: Option<PeripheralRef<'d, AnyPin>>,
+    /// RS-485 driver enable assertion and deassertion times, in microseconds
+    de_times: (u32, u32),
 }
 
 impl<'d, T: Instance, M: Mode> UartTx<'d, T, M> {
     fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        self.de_times = de_times(config);
         reconfigure::<T>(config)
     }
 
@@ -206,17 +294,58 @@ impl<'d, T: Instance, M: Mode> UartTx<'d, T, M> {
             tx,
             cts,
             tx_dma,
+            half_duplex: None,
+            de: None,
+            de_times: de_times(&config),
         })
     }
 
     /// Perform a blocking UART write
+    ///
+    /// In half-duplex mode without readback, this waits for the last byte to be sent and
+    /// switches back to receiving before returning. With an RS-485 driver enable pin, it waits
+    /// for the last stop bit and deasserts the pin.
     pub fn blocking_write(&mut self, buffer: &[u8]) -> Result<(), Error> {
         let rb = T::regs();
 
+        begin_half_duplex_tx::<T>(self.half_duplex);
+        begin_de(self.de.as_deref(), self.de_times.0);
         for &c in buffer {
             while !rb.statr().read().tc() {} // wait tx complete
             rb.datar().write(|w| w.set_dr(c as u16));
         }
+        end_de::<T>(self.de.as_deref(), self.de_times.1);
+        end_half_duplex_tx::<T>(self.half_duplex);
+        Ok(())
+    }
+
+    /// Perform a blocking UART write of 9-bit words, for [`DataBits::DataBits9`]
+    ///
+    /// Bits above the ninth are ignored.
+    pub fn blocking_write_u16(&mut self, buffer: &[u16]) -> Result<(), Error> {
+        self.blocking_write_words(buffer.iter().copied())
+    }
+
+    /// Multiprocessor communication: send an address byte, with the ninth bit set, then `payload`
+    ///
+    /// Needs [`DataBits::DataBits9`] without parity. Receivers muted with
+    /// [`WakeupMethod::AddressMark`] and a matching address wake up, the others stay muted.
+    pub fn blocking_write_addressed(&mut self, addr: u8, payload: &[u8]) -> Result<(), Error> {
+        let words = core::iter::once(addr as u16 | 0x100).chain(payload.iter().map(|&b| b as u16));
+        self.blocking_write_words(words)
+    }
+
+    fn blocking_write_words(&mut self, words: impl Iterator<Item = u16>) -> Result<(), Error> {
+        let rb = T::regs();
+
+        begin_half_duplex_tx::<T>(self.half_duplex);
+        begin_de(self.de.as_deref(), self.de_times.0);
+        for c in words {
+            while !rb.statr().read().tc() {} // wait tx complete
+            rb.datar().write(|w| w.set_dr(c & 0x1FF));
+        }
+        end_de::<T>(self.de.as_deref(), self.de_times.1);
+        end_half_duplex_tx::<T>(self.half_duplex);
         Ok(())
     }
 
@@ -224,9 +353,74 @@ impl<'d, T: Instance, M: Mode> UartTx<'d, T, M> {
     pub fn blocking_flush(&mut self) -> Result<(), Error> {
         let rb = T::regs();
 
-        while !rb.statr().read().txe() {} // wait tx ends
+        while !rb.statr().read().tc() {} // wait tx ends
         Ok(())
     }
+
+    /// Send a break, after the byte being transmitted
+    ///
+    /// Blocks until the break has been sent. In half-duplex LIN mode, the receiver sees the break as
+    /// [`Error::Break`].
+    pub fn send_break(&mut self) {
+        let rb = T::regs();
+
+        rb.ctlr1().modify(|w| w.set_sbk(true));
+        // cleared by hardware during the stop bit of the break
+        while rb.ctlr1().read().sbk() {}
+    }
+}
+
+/// In half-duplex mode without readback, mute the receiver while transmitting.
+fn begin_half_duplex_tx<T: Instance>(half_duplex: Option<HalfDuplexReadback>) {
+    if half_duplex == Some(HalfDuplexReadback::NoReadback) {
+        T::regs().ctlr1().modify(|w| w.set_re(false));
+    }
+}
+
+/// Wait until the last byte has left the shift register, then unmute the receiver.
+fn end_half_duplex_tx<T: Instance>(half_duplex: Option<HalfDuplexReadback>) {
+    if half_duplex == Some(HalfDuplexReadback::NoReadback) {
+        while !T::regs().statr().read().tc() {}
+        T::regs().ctlr1().modify(|w| w.set_re(true));
+    }
+}
+
+/// RS-485 driver enable assertion and deassertion times from `config`, in microseconds.
+fn de_times(config: &Config) -> (u32, u32) {
+    let us = |bits: u8| (bits as u32 * 1_000_000).div_ceil(config.baudrate);
+    (us(config.de_assertion_time), us(config.de_deassertion_time))
+}
+
+/// RS-485: turn the transceiver's driver on, and give it `guard_us` before the first start bit.
+fn begin_de(de: Option<&AnyPin>, guard_us: u32) {
+    if let Some(de) = de {
+        de.set_high();
+        crate::delay::Delay.delay_us(guard_us);
+    }
+}
+
+/// RS-485: wait for the last stop bit, not just an empty data register, then turn the driver off
+/// after `guard_us`.
+fn end_de<T: Instance>(de: Option<&AnyPin>, guard_us: u32) {
+    if let Some(de) = de {
+        while !T::regs().statr().read().tc() {}
+        crate::delay::Delay.delay_us(guard_us);
+        de.set_low();
+    }
+}
+
+/// Wait for transmission complete (TC) with the TC interrupt.
+async fn wait_transmission_complete<T: Instance>() {
+    poll_fn(|cx| {
+        T::state().tx_waker.register(cx.waker());
+        if T::regs().statr().read().tc() {
+            Poll::Ready(())
+        } else {
+            T::regs().ctlr1().modify(|w| w.set_tcie(true));
+            Poll::Pending
+        }
+    })
+    .await
 }
 
 impl<'d, T: Instance> UartTx<'d, T, Async> {
@@ -269,8 +463,37 @@ impl<'d, T: Instance> UartTx<'d, T, Async> {
     }
 
     /// Initiate an asynchronous UART write
+    ///
+    /// Returns when the DMA has moved the last byte to the data register, the last two bytes
+    /// may still be in transmission. Use [`blocking_flush`](Self::blocking_flush) to wait for
+    /// them.
+    ///
+    /// If the future is dropped, the DMA transfer is stopped: the bytes already taken by the
+    /// USART are still sent, the rest of `buffer` is not.
+    ///
+    /// In half-duplex mode without readback, this waits for the last byte to be sent and
+    /// switches back to receiving before returning. With an RS-485 driver enable pin, it waits
+    /// for the last stop bit, using the TC interrupt, and deasserts the pin.
     pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        let half_duplex = self.half_duplex;
+        let de = self.de.as_deref();
+        let (de_assertion_us, de_deassertion_us) = self.de_times;
+        begin_half_duplex_tx::<T>(half_duplex);
+        begin_de(de, de_assertion_us);
+        // Also runs if the write is cancelled, after the transfer is stopped
+        let _on_drop = OnDrop::new(move || {
+            T::regs().ctlr3().modify(|w| w.set_dmat(false));
+            end_de::<T>(de, de_deassertion_us);
+            end_half_duplex_tx::<T>(half_duplex);
+        });
+
         let ch = self.tx_dma.as_mut().unwrap();
+        // DMA writes don't clear TC, it would stay set from the previous transmission.
+        // STATR flags are rc_w0, write 1 to the others so a flag set meanwhile isn't lost.
+        T::regs().statr().write(|w| {
+            w.0 = !0;
+            w.set_tc(false);
+        });
         T::regs().ctlr3().modify(|reg| {
             reg.set_dmat(true);
         });
@@ -278,6 +501,17 @@ impl<'d, T: Instance> UartTx<'d, T, Async> {
         // is held across an await and makes the future non-Send.
         let transfer = unsafe { ch.write(buffer, T::regs().datar().as_ptr() as _, Default::default()) };
         transfer.await;
+
+        if de.is_some() {
+            // Don't block on TC in `_on_drop`
+            wait_transmission_complete::<T>().await;
+        }
+        Ok(())
+    }
+
+    /// Wait until the last byte has been sent, including its stop bits
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        wait_transmission_complete::<T>().await;
         Ok(())
     }
 }
@@ -327,6 +561,7 @@ pub struct UartRx<'d, T: Instance, M: Mode> {
     rx_dma: Option<ChannelAndRequest<'d>>,
     detect_previous_overrun: bool,
     buffered_sr: ch32_metapac::usart::regs::Statr,
+    received_before_error: usize,
 }
 
 impl<'d, T: Instance, M: Mode> UartRx<'d, T, M> {
@@ -358,6 +593,7 @@ impl<'d, T: Instance, M: Mode> UartRx<'d, T, M> {
             rx_dma,
             detect_previous_overrun: config.detect_previous_overrun,
             buffered_sr: ch32_metapac::usart::regs::Statr(0),
+            received_before_error: 0,
         })
     }
 
@@ -366,6 +602,56 @@ impl<'d, T: Instance, M: Mode> UartRx<'d, T, M> {
         reconfigure::<T>(config)
     }
 
+    /// Multiprocessor communication: ignore received bytes until the wakeup condition of
+    /// [`Config::wakeup`]
+    ///
+    /// With [`WakeupMethod::AddressMark`], the matching address byte is the first one received
+    /// after the hardware unmutes. [`blocking_read_addressed`](Self::blocking_read_addressed) and
+    /// [`read_addressed`](Self::read_addressed) wait for it and drop it.
+    pub fn enter_mute(&mut self) {
+        T::regs().ctlr1().modify(|w| w.set_rwu(true));
+    }
+
+    /// Whether the receiver is still muted, the hardware unmutes it on wakeup
+    pub fn is_muted(&self) -> bool {
+        T::regs().ctlr1().read().rwu()
+    }
+
+    /// Mute the receiver and drop what was received before, for the address filtered reads.
+    fn mute_for_address(&mut self) {
+        let r = T::regs();
+        assert!(
+            r.ctlr1().read().wake(),
+            "USART: address filtered reads need `WakeupMethod::AddressMark`"
+        );
+        self.buffered_sr = ch32_metapac::usart::regs::Statr(0);
+        if r.statr().read().rxne() {
+            let _ = r.datar().read();
+        }
+        self.enter_mute();
+    }
+
+    /// Multiprocessor communication: mute the receiver and wait until it's woken up by its
+    /// address byte, see [`WakeupMethod::AddressMark`]
+    ///
+    /// Frames to other addresses are ignored by the hardware meanwhile. The address byte is
+    /// dropped, the payload follows.
+    pub fn blocking_wait_for_address(&mut self) -> Result<(), Error> {
+        self.mute_for_address();
+        while self.is_muted() {}
+
+        while !self.check_rx_flags()? {}
+        let _ = T::regs().datar().read();
+        Ok(())
+    }
+
+    /// Multiprocessor communication: read `buffer.len()` bytes of payload that follow the next
+    /// byte addressed to this receiver, see [`blocking_wait_for_address`](Self::blocking_wait_for_address)
+    pub fn blocking_read_addressed(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.blocking_wait_for_address()?;
+        self.blocking_read(buffer)
+    }
+
     // The same as embassy-stm32's usart_v1
     // checks rxne
     fn check_rx_flags(&mut self) -> Result<bool, Error> {
@@ -390,6 +676,14 @@ impl<'d, T: Instance, M: Mode> UartRx<'d, T, M> {
             } else {
                 // No error flags from previous iterations were set: Check the actual status register
                 let sr = r.statr().read();
+                if sr.lbd() {
+                    clear_lbd(&r);
+                    // LIN break, drop the all-zero frame with a framing error that comes with it
+                    if sr.rxne() && sr.fe() {
+                        let _ = r.datar().read().dr();
+                    }
+                    return Err(Error::Break);
+                }
                 if !sr.rxne() {
                     return Ok(false);
                 }
@@ -420,6 +714,16 @@ impl<'d, T: Instance, M: Mode> UartRx<'d, T, M> {
         }
         Ok(())
     }
+
+    /// Perform a blocking read of 9-bit words into `buffer`, for [`DataBits::DataBits9`]
+    pub fn blocking_read_u16(&mut self, buffer: &mut [u16]) -> Result<(), Error> {
+        let r = T::regs();
+        for b in buffer {
+            while !self.check_rx_flags()? {}
+            *b = r.datar().read().dr() & 0x1FF
+        }
+        Ok(())
+    }
 }
 
 impl<'d, T: Instance> UartRx<'d, T, Async> {
@@ -466,6 +770,10 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
     }
 
     /// Initiate an asynchronous UART read
+    ///
+    /// Completes when the DMA has filled `buffer`, or on the first receive error. If the future
+    /// is dropped, the DMA transfer is stopped and the USART is ready for the next read, bytes
+    /// already received stay in `buffer`.
     pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
         self.inner_read(buffer, false).await?;
 
@@ -473,16 +781,91 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
     }
 
     /// Initiate an asynchronous read with idle line detection enabled
+    ///
+    /// Completes with the number of bytes received when `buffer` is full, or when the line goes
+    /// idle for one frame time after at least one byte, which delimits frames in protocols like
+    /// Modbus RTU.
+    ///
+    /// A received break also ends the frame, it isn't reported as an error here.
+    ///
+    /// On an error such as [`Error::Overrun`], reception stops and the bytes received before it
+    /// stay at the start of `buffer`, see [`received_before_error`](Self::received_before_error).
     pub async fn read_until_idle(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
         self.inner_read(buffer, true).await
     }
 
+    /// Number of bytes the last [`read`](Self::read) or [`read_until_idle`](Self::read_until_idle)
+    /// received before it failed with an error.
+    pub fn received_before_error(&self) -> usize {
+        self.received_before_error
+    }
+
+    /// Wait for a LIN break, needs [`Config::lin_break_detection`]
+    ///
+    /// Bytes received meanwhile are not read. A break that was detected before the call
+    /// completes it right away.
+    pub async fn wait_for_break(&mut self) {
+        let r = T::regs();
+
+        poll_fn(|cx| {
+            T::state().rx_waker.register(cx.waker());
+
+            if r.statr().read().lbd() {
+                clear_lbd(&r);
+                Poll::Ready(())
+            } else {
+                // The interrupt handler disables the interrupt when it fires
+                r.ctlr2().modify(|w| w.set_lbdie(true));
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Multiprocessor communication: mute the receiver and wait until it's woken up by its
+    /// address byte, on the RXNE interrupt
+    ///
+    /// Like [`blocking_wait_for_address`](Self::blocking_wait_for_address), the address byte is
+    /// dropped.
+    pub async fn wait_for_address(&mut self) -> Result<(), Error> {
+        let r = T::regs();
+        self.mute_for_address();
+
+        // RXNE is only set once the hardware unmuted on the address byte
+        let _on_drop = OnDrop::new(|| r.ctlr1().modify(|w| w.set_rxneie(false)));
+        poll_fn(|cx| {
+            T::state().rx_waker.register(cx.waker());
+
+            match self.check_rx_flags() {
+                Ok(true) => Poll::Ready(Ok(())),
+                Ok(false) => {
+                    // The interrupt handler disables the interrupt when it fires
+                    r.ctlr1().modify(|w| w.set_rxneie(true));
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await?;
+
+        let _ = r.datar().read();
+        Ok(())
+    }
+
+    /// Multiprocessor communication: read `buffer` with the payload that follows the next byte
+    /// addressed to this receiver, see [`wait_for_address`](Self::wait_for_address)
+    pub async fn read_addressed(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.wait_for_address().await?;
+        self.read(buffer).await
+    }
+
     async fn inner_read_run(
         &mut self,
         buffer: &mut [u8],
         enable_idle_line_detection: bool,
     ) -> Result<ReadCompletionEvent, Error> {
         let r = T::regs();
+        self.received_before_error = 0;
 
         // make sure USART state is restored to neutral state when this future is dropped
         let on_drop = OnDrop::new(move || {
@@ -496,6 +879,7 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
                 // disable idle line interrupt
                 w.set_idleie(false);
             });
+            r.ctlr2().modify(|w| w.set_lbdie(false));
             r.ctlr3().modify(|w| {
                 // disable Error Interrupt: (Frame error, Noise error, Overrun error)
                 w.set_eie(false);
@@ -521,6 +905,13 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
             // clear_interrupt_flags(r, sr);
         }
 
+        if enable_idle_line_detection {
+            // Clear a stale idle flag with the STATR then DATAR read sequence. This has to happen
+            // before the DMA request is enabled, or the DATAR read could take a received byte.
+            let _sr = r.statr().read();
+            let _ = r.datar().read().dr();
+        }
+
         r.ctlr1().modify(|w| {
             // disable RXNE interrupt
             w.set_rxneie(false);
@@ -528,6 +919,9 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
             w.set_peie(w.pce());
         });
 
+        // enable LIN break interrupt in LIN mode
+        r.ctlr2().modify(|w| w.set_lbdie(w.linen()));
+
         r.ctlr3().modify(|w| {
             // enable Error Interrupt: (Frame error, Noise error, Overrun error)
             w.set_eie(true);
@@ -571,12 +965,6 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
         }
 
         if enable_idle_line_detection {
-            // clear idle flag
-            let _sr = r.statr().read();
-            // This read also clears the error and idle interrupt flags on v1.
-            let _ = r.datar().read().dr();
-            // clear_interrupt_flags(r, sr);
-
             // enable idle interrupt
             r.ctlr1().modify(|w| {
                 w.set_idleie(true);
@@ -585,19 +973,28 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
 
         compiler_fence(Ordering::SeqCst);
 
+        // Set when a LIN break came with its all-zero frame, which the DMA moved into the buffer
+        let break_frame = Cell::new(false);
+
         // future which completes when idle line or error is detected
-        let abort = poll_fn(move |cx| {
+        let abort = poll_fn(|cx| {
             let s = T::state();
 
             s.rx_waker.register(cx.waker());
 
             let sr = r.statr().read();
-
-            // This read also clears the error and idle interrupt flags on v1.
-            let _ = r.datar().read().dr();
-            // clear_interrupt_flags(r, sr);
-
-            if enable_idle_line_detection {
+            let has_errors = sr.pe() || sr.fe() || sr.ne() || sr.ore() || sr.lbd();
+            let is_break = sr.lbd();
+
+            if has_errors || (enable_idle_line_detection && sr.idle()) {
+                // This read also clears the error and idle interrupt flags on v1. Only done once
+                // a flag is set, as it would take a byte from the DMA otherwise.
+                let _ = r.datar().read().dr();
+                if is_break {
+                    clear_lbd(&r);
+                    break_frame.set(sr.fe());
+                }
+            } else if enable_idle_line_detection {
                 // enable idle interrupt
                 r.ctlr1().modify(|w| {
                     w.set_idleie(true);
@@ -606,11 +1003,12 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
 
             compiler_fence(Ordering::SeqCst);
 
-            let has_errors = sr.pe() || sr.fe() || sr.ne() || sr.ore();
-
             if has_errors {
                 // all Rx interrupts and Rx DMA Request have already been cleared in interrupt handler
 
+                if is_break {
+                    return Poll::Ready(Err(Error::Break));
+                }
                 if sr.pe() {
                     return Poll::Ready(Err(Error::Parity));
                 }
@@ -645,8 +1043,24 @@ impl<'d, T: Instance> UartRx<'d, T, Async> {
                 buffer_len - transfer.get_remaining_transfers() as usize,
             )),
 
-            // error occurred
-            Either::Right((Err(e), _)) => Err(e),
+            // A break ends the frame like an idle line
+            Either::Right((Err(Error::Break), transfer)) if enable_idle_line_detection => {
+                let n = buffer_len - transfer.get_remaining_transfers() as usize;
+                drop(transfer);
+                // The zero frame of the break isn't data
+                let n = if break_frame.get() && n > 0 && buffer[n - 1] == 0 {
+                    n - 1
+                } else {
+                    n
+                };
+                Ok(ReadCompletionEvent::Idle(n))
+            }
+
+            // error occurred, the bytes received so far stay in the buffer
+            Either::Right((Err(e), transfer)) => {
+                self.received_before_error = buffer_len - transfer.get_remaining_transfers() as usize;
+                Err(e)
+            }
         };
 
         drop(on_drop);
@@ -714,6 +1128,7 @@ impl<'d, T: Instance, M: Mode> Drop for UartTx<'d, T, M> {
     fn drop(&mut self) {
         self.tx.as_ref().map(|x| x.set_as_disconnected());
         self.cts.as_ref().map(|x| x.set_as_disconnected());
+        self.de.as_ref().map(|x| x.set_as_disconnected());
         T::disable();
     }
 }
@@ -733,10 +1148,10 @@ impl<'d, T: Instance, M: Mode> Drop for UartRx<'d, T, M> {
 ///
 /// ### Notes on [`embedded_io::Read`]
 ///
-/// `embedded_io::Read` requires guarantees that the base [`UartRx`] cannot provide.
+/// The base [`UartRx`] only receives while a read is pending, bytes arriving between reads are
+/// lost to an overrun after the first one. Async reads complete on an idle line.
 ///
-/// See [`UartRx`] for more details, and see [`BufferedUart`] and [`RingBufferedUartRx`]
-/// as alternatives that do provide the necessary guarantees for `embedded_io::Read`.
+/// See [`BufferedUart`] and [`RingBufferedUartRx`] for readers that keep receiving in between.
 pub struct Uart<'d, T: Instance, M: Mode> {
     tx: UartTx<'d, T, M>,
     rx: UartRx<'d, T, M>,
@@ -758,9 +1173,9 @@ impl<'d, T: Instance, M: Mode> Uart<'d, T, M> {
         rx_dma: Option<ChannelAndRequest<'d>>,
         config: Config,
     ) -> Result<Self, ConfigError> {
-        // UartRx and UartTx have one refcount each.
+        // UartRx and UartTx have one refcount each, released in their Drop.
+        T::enable_and_reset();
         T::enable_and_reset();
-        T::enable_and_reset(); // CH32: rfcnt is not used yet
 
         let r = T::regs();
 
@@ -782,6 +1197,9 @@ impl<'d, T: Instance, M: Mode> Uart<'d, T, M> {
                 tx,
                 cts,
                 tx_dma,
+                half_duplex: None,
+                de: None,
+                de_times: de_times(&config),
             },
             rx: UartRx {
                 _phantom: PhantomData,
@@ -790,6 +1208,7 @@ impl<'d, T: Instance, M: Mode> Uart<'d, T, M> {
                 rx_dma,
                 detect_previous_overrun: config.detect_previous_overrun,
                 buffered_sr: ch32_metapac::usart::regs::Statr(0),
+                received_before_error: 0,
             },
         })
     }
@@ -815,16 +1234,54 @@ impl<'d, T: Instance, M: Mode> Uart<'d, T, M> {
         self.rx.blocking_read(buffer)
     }
 
+    /// Send a break, after the byte being transmitted
+    pub fn send_break(&mut self) {
+        self.tx.send_break()
+    }
+
+    /// Perform a blocking write of 9-bit words
+    pub fn blocking_write_u16(&mut self, buffer: &[u16]) -> Result<(), Error> {
+        self.tx.blocking_write_u16(buffer)
+    }
+
+    /// Perform a blocking read of 9-bit words into `buffer`
+    pub fn blocking_read_u16(&mut self, buffer: &mut [u16]) -> Result<(), Error> {
+        self.rx.blocking_read_u16(buffer)
+    }
+
+    /// Send an address byte, with the ninth bit set, then `payload`
+    pub fn blocking_write_addressed(&mut self, addr: u8, payload: &[u8]) -> Result<(), Error> {
+        self.tx.blocking_write_addressed(addr, payload)
+    }
+
+    /// Ignore received bytes until the wakeup condition of [`Config::wakeup`]
+    pub fn enter_mute(&mut self) {
+        self.rx.enter_mute()
+    }
+
+    /// Read the payload after the next byte addressed to this receiver, see
+    /// [`UartRx::blocking_read_addressed`]
+    pub fn blocking_read_addressed(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.rx.blocking_read_addressed(buffer)
+    }
+
     /// Split the Uart into a transmitter and receiver, which is
     /// particularly useful when having two tasks correlating to
     /// transmitting and receiving.
+    ///
+    /// Panics for a half-duplex UART, so that reads and writes on the shared pin can't overlap.
     pub fn split(self) -> (UartTx<'d, T, M>, UartRx<'d, T, M>) {
+        assert!(self.tx.half_duplex.is_none(), "USART: half-duplex UART can't be split");
         (self.tx, self.rx)
     }
 }
 
 impl<'d, T: Instance> Uart<'d, T, Async> {
-    /// Create a new bidirectional UARTUart
+    /// Create a new bidirectional UART
+    ///
+    /// [`write`](Self::write) and [`read`](Self::read) are driven by the DMA channels, and
+    /// complete on their transfer complete interrupts. The USART interrupt only reports receive
+    /// errors and idle line. Use [`split`](Self::split) to read and write from different tasks.
     pub fn new<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
         rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
@@ -838,6 +1295,7 @@ impl<'d, T: Instance> Uart<'d, T, Async> {
 
         rx.set_as_input(Pull::None);
         tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        T::set_remap(REMAP);
 
         Self::new_inner(
             peri,
@@ -851,7 +1309,45 @@ impl<'d, T: Instance> Uart<'d, T, Async> {
         )
     }
 
+    /// Create a new bidirectional UART in LIN mode, for a LIN transceiver
+    ///
+    /// The frame format of `config` is replaced by the 8N1 of LIN, and breaks of `break_length`
+    /// are detected. Send the break of the header with [`send_break`](Self::send_break), and wait
+    /// for one with [`wait_for_break`](Self::wait_for_break).
+    pub fn new_lin<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        break_length: BreakDetectLength,
+        mut config: Config,
+    ) -> Result<Self, ConfigError> {
+        config.data_bits = DataBits::DataBits8;
+        config.parity = Parity::ParityNone;
+        config.stop_bits = StopBits::STOP1;
+        config.lin_break_detection = Some(break_length);
+
+        Self::new(peri, rx, tx, _irq, tx_dma, rx_dma, config)
+    }
+
+    /// Wait for a LIN break, see [`UartRx::wait_for_break`]
+    pub async fn wait_for_break(&mut self) {
+        self.rx.wait_for_break().await
+    }
+
+    /// Read the payload after the next byte addressed to this receiver, see
+    /// [`UartRx::read_addressed`]
+    pub async fn read_addressed(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.rx.read_addressed(buffer).await
+    }
+
     /// Create a new bidirectional UART with request-to-send and clear-to-send pins
+    ///
+    /// Hardware flow control is enabled (CTSE/RTSE): transmission pauses while CTS is high, and
+    /// RTS goes high while the receive data register is full. The pin traits only accept the
+    /// RTS/CTS pins of this USART under the same `REMAP` as RX/TX, which is applied through AFIO.
     pub fn new_with_rtscts<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
         rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
@@ -869,6 +1365,7 @@ impl<'d, T: Instance> Uart<'d, T, Async> {
         tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
         rts.set_as_af_output(AFType::OutputPushPull, Speed::High);
         cts.set_as_input(Pull::None);
+        T::set_remap(REMAP);
 
         Self::new_inner(
             peri,
@@ -882,25 +1379,108 @@ impl<'d, T: Instance> Uart<'d, T, Async> {
         )
     }
 
-    /// Half-duplex
+    /// Create a new bidirectional UART for an RS-485 transceiver, with a driver enable pin
     ///
-    /// Note: Half duplex requires TX pin to have a pull-up resistor
+    /// `de` is a plain GPIO, the USART has no driver enable output. It goes high before
+    /// transmission, and low only once the last stop bit is out (TC), not just when the data
+    /// register is empty, so the transceiver doesn't cut the last byte. The guard times around
+    /// it are [`Config::de_assertion_time`] and [`Config::de_deassertion_time`].
+    pub fn new_with_de<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        de: impl Peripheral<P = impl Pin> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        into_ref!(peri, rx, tx, de, tx_dma, rx_dma);
+
+        rx.set_as_input(Pull::None);
+        tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        set_as_de_pin(&*de);
+        T::set_remap(REMAP);
+
+        let mut this = Self::new_inner(
+            peri,
+            Some(rx.map_into()),
+            Some(tx.map_into()),
+            None,
+            None,
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+        )?;
+        this.tx.de = Some(de.map_into());
+        Ok(this)
+    }
+
+    /// Create a new single-wire half-duplex UART
+    ///
+    /// Transmission and reception both happen on the TX pin (HDSEL), RX is not used and stays
+    /// free as a GPIO. The TX pin is configured as open-drain (push-pull on CH32X0, which has no
+    /// open-drain alternate function) and the line needs a pull-up resistor.
+    ///
+    /// For a request/response protocol, `write` the request and then `read` the response.
+    /// With [`HalfDuplexReadback::NoReadback`], the receiver is muted while transmitting and
+    /// `write` only returns once the last byte is out, so the next `read` only sees the other
+    /// side. With [`HalfDuplexReadback::Readback`], every sent byte is also received and must be
+    /// read back before the response.
     pub fn new_half_duplex<const REMAP: u8>(
-        _peri: impl Peripheral<P = T> + 'd,
+        peri: impl Peripheral<P = T> + 'd,
         tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
+        _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
+        tx_dma: impl Peripheral<P = impl TxDma<T>> + 'd,
+        rx_dma: impl Peripheral<P = impl RxDma<T>> + 'd,
         mut config: Config,
+        readback: HalfDuplexReadback,
     ) -> Result<Self, ConfigError> {
-        into_ref!(_peri, tx);
+        into_ref!(peri, tx, tx_dma, rx_dma);
 
-        tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        set_as_half_duplex_pin(&*tx);
         T::set_remap(REMAP);
 
         config.half_duplex = true;
 
-        Self::new_inner(_peri, None, Some(tx.map_into()), None, None, None, None, config)
+        let mut this = Self::new_inner(
+            peri,
+            None,
+            Some(tx.map_into()),
+            None,
+            None,
+            new_dma!(tx_dma),
+            new_dma!(rx_dma),
+            config,
+        )?;
+        this.tx.half_duplex = Some(readback);
+        Ok(this)
+    }
+
+    /// Initiate an asynchronous UART write
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        self.tx.write(buffer).await
+    }
+
+    /// Initiate an asynchronous UART read
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.rx.read(buffer).await
     }
 }
 
+fn set_as_de_pin(pin: &impl SealedPin) {
+    // Driver off
+    pin.set_low();
+    pin.set_as_output(Speed::High);
+}
+
+fn set_as_half_duplex_pin(pin: &impl SealedPin) {
+    #[cfg(not(gpio_x0))]
+    pin.set_as_af_output(AFType::OutputOpenDrain, Speed::High);
+    #[cfg(gpio_x0)]
+    pin.set_as_af_output(AFType::OutputPushPull, Speed::High);
+}
+
 impl<'d, T: Instance> Uart<'d, T, Blocking> {
     /// Create a new blocking bidirectional UART.
     pub fn new_blocking<const REMAP: u8>(
@@ -928,6 +1508,8 @@ impl<'d, T: Instance> Uart<'d, T, Blocking> {
     }
 
     /// Create a new bidirectional UART with request-to-send and clear-to-send pins
+    ///
+    /// See [`Uart::new_with_rtscts`] for the flow control behaviour.
     pub fn new_blocking_with_rtscts<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
         rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
@@ -956,22 +1538,69 @@ impl<'d, T: Instance> Uart<'d, T, Blocking> {
         )
     }
 
+    /// Create a new blocking bidirectional UART for an RS-485 transceiver, with a driver enable
+    /// pin
+    ///
+    /// See [`Uart::new_with_de`] for the driver enable behaviour.
+    pub fn new_blocking_with_de<const REMAP: u8>(
+        peri: impl Peripheral<P = T> + 'd,
+        rx: impl Peripheral<P = impl RxPin<T, REMAP>> + 'd,
+        tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
+        de: impl Peripheral<P = impl Pin> + 'd,
+        config: Config,
+    ) -> Result<Self, ConfigError> {
+        into_ref!(peri, rx, tx, de);
+
+        rx.set_as_input(Pull::None);
+        tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        set_as_de_pin(&*de);
+        T::set_remap(REMAP);
+
+        let mut this = Self::new_inner(
+            peri,
+            Some(rx.map_into()),
+            Some(tx.map_into()),
+            None,
+            None,
+            None,
+            None,
+            config,
+        )?;
+        this.tx.de = Some(de.map_into());
+        Ok(this)
+    }
+
+    /// Create a new blocking single-wire half-duplex UART
+    ///
+    /// See [`Uart::new_half_duplex`] for pin usage and how `readback` affects reads.
     pub fn new_blocking_half_duplex<const REMAP: u8>(
         peri: impl Peripheral<P = T> + 'd,
         tx: impl Peripheral<P = impl TxPin<T, REMAP>> + 'd,
         mut config: Config,
+        readback: HalfDuplexReadback,
     ) -> Result<Self, ConfigError> {
         into_ref!(peri, tx);
 
-        tx.set_as_af_output(AFType::OutputPushPull, Speed::High);
+        set_as_half_duplex_pin(&*tx);
         T::set_remap(REMAP);
 
         config.half_duplex = true;
 
-        Self::new_inner(peri, None, Some(tx.map_into()), None, None, None, None, config)
+        let mut this = Self::new_inner(peri, None, Some(tx.map_into()), None, None, None, None, config)?;
+        this.tx.half_duplex = Some(readback);
+        Ok(this)
     }
 }
 
+/// Clear the LIN break flag. STATR flags are rc_w0, writing 1 leaves the others unchanged where a
+/// read-modify-write could clear one set in between.
+fn clear_lbd(r: &pac::usart::Usart) {
+    r.statr().write(|w| {
+        w.0 = !0;
+        w.set_lbd(false);
+    });
+}
+
 fn reconfigure<T: Instance>(config: &Config) -> Result<(), ConfigError> {
     T::Interrupt::disable();
     let r = T::regs();
@@ -996,10 +1625,30 @@ fn configure(
         panic!("USART: At least one of RX or TX should be enabled");
     }
 
-    rb.ctlr2().modify(|w| w.set_stop(config.stop_bits as u8));
+    // The parity bit takes the place of the last data bit of the frame
+    let word_bits = match (config.data_bits, config.parity) {
+        (DataBits::DataBits7, Parity::ParityNone) => return Err(ConfigError::DataParityNotSupported),
+        (DataBits::DataBits7, _) | (DataBits::DataBits8, Parity::ParityNone) => 8,
+        (DataBits::DataBits8, _) | (DataBits::DataBits9, Parity::ParityNone) => 9,
+        (DataBits::DataBits9, _) => return Err(ConfigError::DataParityNotSupported),
+    };
+
+    if config.lin_break_detection.is_some() && config.stop_bits != StopBits::STOP1 {
+        return Err(ConfigError::LinStopBits);
+    }
+
+    rb.ctlr2().modify(|w| {
+        w.set_stop(config.stop_bits as u8);
+        w.set_linen(config.lin_break_detection.is_some());
+        w.set_lbdl(config.lin_break_detection == Some(BreakDetectLength::Bits11));
+        if let WakeupMethod::AddressMark { addr } = config.wakeup {
+            w.set_add(addr & 0x0F);
+        }
+    });
 
     rb.ctlr1().modify(|w| {
-        w.set_m(config.data_bits as u8 != 0);
+        w.set_wake(matches!(config.wakeup, WakeupMethod::AddressMark { .. }));
+        w.set_m(word_bits == 9);
         w.set_pce(config.parity != Parity::ParityNone);
         w.set_ps(config.parity == Parity::ParityOdd); // 1 for odd parity, 0 for even parity
         w.set_te(enable_tx);
@@ -1028,6 +1677,147 @@ fn configure(
     Ok(())
 }
 
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match *self {
+            Self::Framing | Self::Noise | Self::Parity | Self::Break => embedded_io::ErrorKind::InvalidData,
+            Self::Overrun => embedded_io::ErrorKind::Other,
+            Self::BufferTooLong => embedded_io::ErrorKind::InvalidInput,
+        }
+    }
+}
+
+impl embedded_hal_nb::serial::Error for Error {
+    fn kind(&self) -> embedded_hal_nb::serial::ErrorKind {
+        match *self {
+            Self::Framing | Self::Break => embedded_hal_nb::serial::ErrorKind::FrameFormat,
+            Self::Noise => embedded_hal_nb::serial::ErrorKind::Noise,
+            Self::Overrun => embedded_hal_nb::serial::ErrorKind::Overrun,
+            Self::Parity => embedded_hal_nb::serial::ErrorKind::Parity,
+            Self::BufferTooLong => embedded_hal_nb::serial::ErrorKind::Other,
+        }
+    }
+}
+
+impl<'d, T: Instance, M: Mode> embedded_io::ErrorType for UartTx<'d, T, M> {
+    type Error = Error;
+}
+
+impl<'d, T: Instance, M: Mode> embedded_io::ErrorType for UartRx<'d, T, M> {
+    type Error = Error;
+}
+
+impl<'d, T: Instance, M: Mode> embedded_io::ErrorType for Uart<'d, T, M> {
+    type Error = Error;
+}
+
+impl<'d, T: Instance, M: Mode> embedded_io::Write for UartTx<'d, T, M> {
+    /// Writes the bytes the USART takes without blocking, at least one.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.de.is_some() || self.half_duplex.is_some() {
+            // The bus is turned around once per write, send everything
+            self.blocking_write(buf)?;
+            return Ok(buf.len());
+        }
+
+        let rb = T::regs();
+        while !rb.statr().read().txe() {}
+        let mut n = 0;
+        for &c in buf {
+            if n > 0 && !rb.statr().read().txe() {
+                break;
+            }
+            rb.datar().write(|w| w.set_dr(c as u16));
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.blocking_flush()
+    }
+}
+
+impl<'d, T: Instance, M: Mode> embedded_io::Read for UartRx<'d, T, M> {
+    /// Waits for the first byte, then also returns the ones already received.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let r = T::regs();
+        while !self.check_rx_flags()? {}
+        buf[0] = r.datar().read().dr() as u8;
+
+        let mut n = 1;
+        while n < buf.len() {
+            let sr = r.statr().read();
+            // Leave errors to the next read, so that the bytes before them are returned
+            let has_errors = sr.pe() || sr.fe() || sr.ne() || sr.ore() || sr.lbd();
+            if self.buffered_sr.0 != 0 || has_errors || !sr.rxne() {
+                break;
+            }
+            buf[n] = r.datar().read().dr() as u8;
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl<'d, T: Instance, M: Mode> embedded_io::Write for Uart<'d, T, M> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(&mut self.tx, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.tx.blocking_flush()
+    }
+}
+
+impl<'d, T: Instance, M: Mode> embedded_io::Read for Uart<'d, T, M> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        embedded_io::Read::read(&mut self.rx, buf)
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::Write for UartTx<'d, T, Async> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf).await?;
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush().await
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::Read for UartRx<'d, T, Async> {
+    /// Completes on an idle line, see [`UartRx::read_until_idle`].
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read_until_idle(buf).await
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::Write for Uart<'d, T, Async> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.tx.write(buf).await?;
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.tx.flush().await
+    }
+}
+
+impl<'d, T: Instance> embedded_io_async::Read for Uart<'d, T, Async> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.rx.read_until_idle(buf).await
+    }
+}
+
 impl<'d, T: Instance> core::fmt::Write for UartTx<'d, T, Blocking> {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         self.blocking_write(s.as_bytes()).map_err(|_| core::fmt::Error)?;
@@ -1044,12 +1834,14 @@ impl<'d, T: Instance> core::fmt::Write for Uart<'d, T, Blocking> {
 // Peripheral traits
 struct State {
     rx_waker: AtomicWaker,
+    tx_waker: AtomicWaker,
 }
 
 impl State {
     const fn new() -> Self {
         Self {
             rx_waker: AtomicWaker::new(),
+            tx_waker: AtomicWaker::new(),
         }
     }
 }
@@ -1057,8 +1849,7 @@ impl State {
 trait SealedInstance: crate::peripheral::RccPeripheral + crate::peripheral::RemapPeripheral {
     fn regs() -> crate::pac::usart::Usart;
     fn state() -> &'static State;
-
-    // fn buffered_state() -> &'static buffered::State;
+    fn buffered_state() -> &'static buffered::State;
 }
 
 #[allow(private_bounds)]
@@ -1078,6 +1869,11 @@ foreach_peripheral!(
                 static STATE: State = State::new();
                 &STATE
             }
+
+            fn buffered_state() -> &'static buffered::State {
+                static BUFFERED_STATE: buffered::State = buffered::State::new();
+                &BUFFERED_STATE
+            }
         }
 
         impl Instance for peripherals::$inst {
@@ -0,0 +1,256 @@
+//! Ring-buffered DMA reception for USART.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::mem;
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::Poll;
+
+use futures::future::{select, Either};
+
+use super::{clear_lbd, reconfigure, Config, ConfigError, Error, Instance, UartRx};
+use crate::dma::{ReadableRingBuffer, TransferOptions};
+use crate::gpio::{AnyPin, SealedPin};
+use crate::mode::Async;
+use crate::PeripheralRef;
+
+/// Rx-only Ring-buffered UART Driver
+///
+/// Created with [`UartRx::into_ring_buffered`]
+///
+/// The DMA channel runs in circular mode and keeps receiving while no `read` is pending, so no
+/// data is lost as long as the buffer is drained faster than it fills up.
+pub struct RingBufferedUartRx<'d, T: Instance> {
+    _phantom: PhantomData<T>,
+    rx: Option<PeripheralRef<'d, AnyPin>>,
+    rts: Option<PeripheralRef<'d, AnyPin>>,
+    ring_buf: ReadableRingBuffer<'d, u8>,
+}
+
+impl<'d, T: Instance> UartRx<'d, T, Async> {
+    /// Turn the `UartRx` into a buffered uart which can continously receive in the background
+    /// without the possibility of losing bytes. The `dma_buf` is a buffer registered to the
+    /// DMA controller, and must be large enough to prevent overflows.
+    pub fn into_ring_buffered(mut self, dma_buf: &'d mut [u8]) -> RingBufferedUartRx<'d, T> {
+        assert!(!dma_buf.is_empty() && dma_buf.len() <= 0xFFFF);
+
+        // Wake on half and full buffer, the IDLE interrupt covers the rest
+        let opts = TransferOptions {
+            half_transfer_ir: true,
+            ..Default::default()
+        };
+
+        let rx_dma = self.rx_dma.as_mut().unwrap();
+        let request = rx_dma.request;
+        // Safety: `self` is forgotten below, so the channel is never used through it again.
+        let rx_dma = unsafe { rx_dma.channel.clone_unchecked() };
+        let ring_buf =
+            unsafe { ReadableRingBuffer::new(rx_dma, request, T::regs().datar().as_ptr() as _, dma_buf, opts) };
+
+        let rx = self.rx.take();
+        let rts = self.rts.take();
+
+        // Don't disable the clock
+        mem::forget(self);
+
+        RingBufferedUartRx {
+            _phantom: PhantomData,
+            rx,
+            rts,
+            ring_buf,
+        }
+    }
+}
+
+impl<'d, T: Instance> RingBufferedUartRx<'d, T> {
+    /// Reconfigure the driver
+    ///
+    /// Reception is stopped and restarts with the next [`read`](Self::read).
+    pub fn set_config(&mut self, config: &Config) -> Result<(), ConfigError> {
+        self.stop_uart();
+        reconfigure::<T>(config)
+    }
+
+    /// Start receiving in the background into the previously passed `dma_buf` buffer.
+    ///
+    /// Called implicitly by [`read`](Self::read) if reception isn't running yet.
+    pub fn start_uart(&mut self) {
+        // Drop whatever was received before, and restart the DMA at the start of the buffer.
+        // The DMA may still be running if the interrupt handler stopped reception on an error.
+        self.ring_buf.request_pause();
+        self.ring_buf.clear();
+        compiler_fence(Ordering::SeqCst);
+        self.ring_buf.start();
+
+        let r = T::regs();
+        // clear all interrupts and DMA Rx Request
+        r.ctlr1().modify(|w| {
+            // disable RXNE interrupt
+            w.set_rxneie(false);
+            // enable parity interrupt if not ParityNone
+            w.set_peie(w.pce());
+            // enable idle line interrupt
+            w.set_idleie(true);
+        });
+        r.ctlr3().modify(|w| {
+            // enable Error Interrupt: (Frame error, Noise error, Overrun error)
+            w.set_eie(true);
+            // enable DMA Rx Request
+            w.set_dmar(true);
+        });
+    }
+
+    /// Stop DMA backed UART receiver
+    fn stop_uart(&mut self) {
+        self.ring_buf.request_pause();
+
+        let r = T::regs();
+        // clear all interrupts and DMA Rx Request
+        r.ctlr1().modify(|w| {
+            // disable RXNE interrupt
+            w.set_rxneie(false);
+            // disable parity interrupt
+            w.set_peie(false);
+            // disable idle line interrupt
+            w.set_idleie(false);
+        });
+        r.ctlr3().modify(|w| {
+            // disable Error Interrupt: (Frame error, Noise error, Overrun error)
+            w.set_eie(false);
+            // disable DMA Rx Request
+            w.set_dmar(false);
+        });
+
+        compiler_fence(Ordering::SeqCst);
+    }
+
+    /// Read bytes that are readily available in the ring buffer.
+    /// If no bytes are currently available in the buffer the call waits until some bytes are
+    /// available (at least one byte and at most half the buffer size).
+    ///
+    /// Returns [`Error::Overrun`] if the DMA wrapped past data that wasn't read yet. Reception is
+    /// stopped on any error, and restarted with an empty buffer by the next call.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let r = T::regs();
+
+        // Start DMA and Uart if it was not already started,
+        // otherwise check for errors in status register.
+        let sr = clear_idle_flag::<T>();
+        if !r.ctlr3().read().dmar() {
+            self.start_uart();
+        } else {
+            check_for_errors(sr)?;
+        }
+
+        loop {
+            match self.ring_buf.read(buf) {
+                Ok((0, _)) => {}
+                Ok((len, _)) => {
+                    return Ok(len);
+                }
+                Err(_) => {
+                    self.stop_uart();
+                    return Err(Error::Overrun);
+                }
+            }
+
+            match self.wait_for_data_or_idle().await {
+                Ok(_) => {}
+                Err(err) => {
+                    self.stop_uart();
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Wait for uart idle or dma half-full or full
+    async fn wait_for_data_or_idle(&mut self) -> Result<(), Error> {
+        compiler_fence(Ordering::SeqCst);
+
+        // Future which completes when idle line or error is detected
+        let uart = poll_fn(|cx| {
+            T::state().rx_waker.register(cx.waker());
+
+            compiler_fence(Ordering::SeqCst);
+
+            // Critical section is needed so that IDLE isn't set after
+            // our read but before we clear it.
+            let sr = critical_section::with(|_| clear_idle_flag::<T>());
+
+            check_for_errors(sr)?;
+
+            if sr.idle() {
+                // Idle line is detected
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        });
+
+        let mut dma_init = false;
+        // Future which completes when the DMA controller indicates it
+        // has written to the ring buffer's middle byte, or last byte
+        let dma = poll_fn(|cx| {
+            self.ring_buf.set_waker(cx.waker());
+
+            let status = match dma_init {
+                false => Poll::Pending,
+                true => Poll::Ready(()),
+            };
+
+            dma_init = true;
+            status
+        });
+
+        match select(uart, dma).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Ok(()),
+        }
+    }
+}
+
+impl<'d, T: Instance> Drop for RingBufferedUartRx<'d, T> {
+    fn drop(&mut self) {
+        self.stop_uart();
+        self.rx.as_ref().map(|x| x.set_as_disconnected());
+        self.rts.as_ref().map(|x| x.set_as_disconnected());
+        T::disable();
+    }
+}
+
+/// Return the status register, clearing the idle and error flags.
+fn clear_idle_flag<T: Instance>() -> ch32_metapac::usart::regs::Statr {
+    let r = T::regs();
+
+    let sr = r.statr().read();
+    if sr.idle() || sr.pe() || sr.fe() || sr.ne() || sr.ore() {
+        // This read also clears the error and idle interrupt flags on v1. Only done once a flag
+        // is set, as it would take a byte from the DMA otherwise.
+        let _ = r.datar().read().dr();
+    }
+    if sr.lbd() {
+        clear_lbd(&r);
+    }
+
+    // The interrupt handler disables the idle interrupt, re-enable it
+    r.ctlr1().modify(|w| w.set_idleie(true));
+
+    sr
+}
+
+fn check_for_errors(s: ch32_metapac::usart::regs::Statr) -> Result<(), Error> {
+    if s.lbd() {
+        Err(Error::Break)
+    } else if s.pe() {
+        Err(Error::Parity)
+    } else if s.fe() {
+        Err(Error::Framing)
+    } else if s.ne() {
+        Err(Error::Noise)
+    } else if s.ore() {
+        Err(Error::Overrun)
+    } else {
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
 //! Universal Serial Bus Device(USBD)
 //!
 //! This peripheral is almost the same as usb_v1 in embassy-stm32.
+//!
+//! Endpoints are single-buffered: the hardware only double-buffers bulk and isochronous
+//! endpoints, not interrupt ones. For an interrupt IN endpoint (HID reports), the next report
+//! can be written as soon as the previous one was taken by the host, and the host polls with
+//! the `interval_ms` given at allocation, which goes to the descriptor as bInterval.
 
 #![macro_use]
 
@@ -224,12 +229,15 @@ pub struct Driver<'d, T: Instance> {
 
 impl<'d, T: Instance> Driver<'d, T> {
     /// Create a new USB driver.
+    ///
+    /// Panics if the USB clock isn't 48 MHz, see `usb_pre` in [`rcc::Config`](crate::rcc::Config).
     pub fn new(
         _usb: impl Peripheral<P = T> + 'd,
         _irq: impl interrupt::typelevel::Binding<T::Interrupt, InterruptHandler<T>> + 'd,
         dp: impl Peripheral<P = impl DpPin<T, 0>> + 'd,
         dm: impl Peripheral<P = impl DmPin<T, 0>> + 'd,
     ) -> Self {
+        crate::rcc::require_usb_clock();
         into_ref!(dp, dm);
 
         {
@@ -447,6 +455,13 @@ pub struct Bus<'d, T: Instance> {
     inited: bool,
 }
 
+impl<'d, T: Instance> Drop for Bus<'d, T> {
+    fn drop(&mut self) {
+        crate::rcc::release_usb_clock();
+        T::disable();
+    }
+}
+
 impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
     async fn poll(&mut self) -> Event {
         poll_fn(move |cx| {
@@ -574,11 +589,14 @@ impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
                         true => Stat::NAK,
                     };
                     let r = reg.read();
-                    if r.stat_tx() == want_stat {
+                    // Enabling also resets the data toggle to DATA0, as required after SET_CONFIGURATION
+                    let reset_dtog = enabled && r.dtog_tx();
+                    if r.stat_tx() == want_stat && !reset_dtog {
                         break;
                     }
                     let mut w = invariant(r);
                     w.set_stat_tx(Stat::from_bits(r.stat_tx().to_bits() ^ want_stat.to_bits()));
+                    w.set_dtog_tx(reset_dtog);
                     reg.write_value(w);
                 }
                 EP_IN_WAKERS[ep_addr.index()].wake();
@@ -590,11 +608,14 @@ impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
                         true => Stat::VALID,
                     };
                     let r = reg.read();
-                    if r.stat_rx() == want_stat {
+                    // Enabling also resets the data toggle to DATA0, as required after SET_CONFIGURATION
+                    let reset_dtog = enabled && r.dtog_rx();
+                    if r.stat_rx() == want_stat && !reset_dtog {
                         break;
                     }
                     let mut w = invariant(r);
                     w.set_stat_rx(Stat::from_bits(r.stat_rx().to_bits() ^ want_stat.to_bits()));
+                    w.set_dtog_rx(reset_dtog);
                     reg.write_value(w);
                 }
                 EP_OUT_WAKERS[ep_addr.index()].wake();
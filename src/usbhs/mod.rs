@@ -286,6 +286,12 @@ pub struct Bus<'d, T: Instance> {
     fake_power_on: bool,
 }
 
+impl<'d, T: Instance> Drop for Bus<'d, T> {
+    fn drop(&mut self) {
+        T::disable();
+    }
+}
+
 impl<'d, T: Instance> Bus<'d, T> {
     fn bus_reset(&mut self) {
         let regs = T::regs();
@@ -136,6 +136,8 @@ impl<'d, T: Instance> UsbPdPhy<'d, T> {
     }
 
     pub fn reset(&mut self) -> Result<(), Error> {
+        // Keep the reference count of this driver at one
+        T::disable();
         T::enable_and_reset();
 
         T::REGS.config().write(|w| {
@@ -358,6 +360,12 @@ impl<'d, T: Instance> UsbPdPhy<'d, T> {
     }
 }
 
+impl<'d, T: Instance> Drop for UsbPdPhy<'d, T> {
+    fn drop(&mut self) {
+        T::disable();
+    }
+}
+
 struct State {
     waker: AtomicWaker,
     // Inverted logic for a default state of 0 so that the data goes into the .bss section.
@@ -0,0 +1,119 @@
+//! Independent watchdog (IWDG)
+//!
+//! The watchdog runs from LSI and resets the chip unless it's fed ([`Iwdg::pet`]) before the
+//! timeout. Once started, it can't be stopped until the next reset.
+//!
+//! LSI varies a lot between parts and with temperature, so the real timeout can be quite far from
+//! the requested one. For accurate timeouts, measure LSI (for example with a timer capturing it)
+//! and pass the result to [`Iwdg::new_with_lsi`].
+
+use core::time::Duration;
+
+use crate::pac::{IWDG, RCC};
+use crate::time::Hertz;
+use crate::{into_ref, peripherals, Peripheral, PeripheralRef};
+
+/// Nominal LSI frequency.
+#[cfg(any(ch32v0, ch641))]
+const LSI: Hertz = crate::rcc::LSI_FREQUENCY;
+#[cfg(not(any(ch32v0, ch641)))]
+const LSI: Hertz = crate::rcc::LSI_FREQ;
+
+// IWDG_CTLR keys
+const KEY_RELOAD: u32 = 0xAAAA;
+const KEY_ENABLE: u32 = 0xCCCC;
+const KEY_UNLOCK: u32 = 0x5555;
+
+// IWDG_STATR bits
+const STATR_PVU: u32 = 1 << 0;
+const STATR_RVU: u32 = 1 << 1;
+
+/// Largest reload value, 12 bits.
+const MAX_RELOAD: u32 = 0xFFF;
+
+/// Independent watchdog driver.
+pub struct Iwdg<'d> {
+    _peri: PeripheralRef<'d, peripherals::IWDG>,
+}
+
+impl<'d> Iwdg<'d> {
+    /// Create a watchdog with a timeout of `timeout`, for the nominal LSI frequency.
+    ///
+    /// The watchdog isn't started, see [`unleash`](Self::unleash).
+    pub fn new(iwdg: impl Peripheral<P = peripherals::IWDG> + 'd, timeout: Duration) -> Self {
+        Self::new_with_lsi(iwdg, timeout, LSI)
+    }
+
+    /// Create a watchdog with a timeout of `timeout`, for a measured LSI frequency `lsi`.
+    ///
+    /// Panics if the timeout is too long, the longest is 4096 * 256 LSI cycles.
+    pub fn new_with_lsi(iwdg: impl Peripheral<P = peripherals::IWDG> + 'd, timeout: Duration, lsi: Hertz) -> Self {
+        into_ref!(iwdg);
+
+        let (pr, rl) = prescaler_reload(timeout, lsi).expect("IWDG: timeout too long");
+
+        // Wait for earlier updates, writes are ignored while one is in progress
+        while IWDG.statr().read().0 & (STATR_PVU | STATR_RVU) != 0 {}
+        IWDG.ctlr().write(|w| w.0 = KEY_UNLOCK);
+        IWDG.pscr().write(|w| w.0 = pr);
+        IWDG.rldr().write(|w| w.0 = rl);
+        // Load the new reload value, in case the watchdog already runs (IWDG_SW option byte)
+        IWDG.ctlr().write(|w| w.0 = KEY_RELOAD);
+
+        Self { _peri: iwdg }
+    }
+
+    /// Start the watchdog, it can't be stopped again.
+    pub fn unleash(&mut self) {
+        IWDG.ctlr().write(|w| w.0 = KEY_ENABLE);
+    }
+
+    /// Feed the watchdog, restarting the timeout.
+    pub fn pet(&mut self) {
+        IWDG.ctlr().write(|w| w.0 = KEY_RELOAD);
+    }
+}
+
+/// Returns `true` if the last reset was caused by the independent watchdog.
+///
+/// The reset flags in RCC_RSTSCKR are kept over resets, until they are cleared with RMVF.
+pub fn reset_by_watchdog() -> bool {
+    RCC.rstsckr().read().iwdgrstf()
+}
+
+/// Prescaler (IWDG_PSCR) and reload (IWDG_RLDR) values for `timeout`, with the finest resolution.
+fn prescaler_reload(timeout: Duration, lsi: Hertz) -> Option<(u32, u32)> {
+    let ticks = timeout.as_micros() * lsi.0 as u128 / 1_000_000;
+
+    // Divider 4 << pr
+    (0..=6u32).find_map(|pr| {
+        let cycles = ticks / (4 << pr);
+        (cycles <= MAX_RELOAD as u128 + 1).then(|| (pr, (cycles as u32).max(1) - 1))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_finest_prescaler() {
+        let lsi = Hertz(40_000);
+        // 40 ticks, divider 4
+        assert_eq!(prescaler_reload(Duration::from_millis(1), lsi), Some((0, 9)));
+        // 40000 ticks, divider 16
+        assert_eq!(prescaler_reload(Duration::from_secs(1), lsi), Some((2, 2499)));
+        // Exactly 4096 periods of divider 256
+        assert_eq!(
+            prescaler_reload(Duration::from_micros(26_214_400), lsi),
+            Some((6, 4095))
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let lsi = Hertz(40_000);
+        assert_eq!(prescaler_reload(Duration::from_secs(27), lsi), None);
+        assert_eq!(prescaler_reload(Duration::ZERO, lsi), Some((0, 0)));
+    }
+}